@@ -0,0 +1,159 @@
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use libp2p::futures::io::Cursor;
+use modius::{
+    net::{
+        bus::Subscription,
+        command::CommandKind,
+        event::Event,
+        nat::NatReport,
+        session::DeliveryGuarantee,
+        wire::{read_frame, write_frame, Envelope, Frame},
+    },
+    Node, NodeBuilder,
+};
+use tokio::runtime::Runtime;
+
+const SIZES: [usize; 3] = [1024, 64 * 1024, 1024 * 1024];
+const BATCH: u64 = 32;
+
+async fn pair(port: usize) -> (Node, Node) {
+    let mut server = NodeBuilder::default().port(port).build().unwrap();
+    server.start().unwrap();
+
+    let mut builder = NodeBuilder::default();
+    builder.port(port + 1);
+    builder
+        .try_bootstrap(server.id(), format!("/ip4/127.0.0.1/tcp/{port}"))
+        .unwrap();
+    let mut client = builder.build().unwrap();
+    client.start().unwrap();
+    client.await_ready().await.unwrap();
+    (server, client)
+}
+
+async fn receive(events: &mut Subscription, count: u64) {
+    let mut received = 0;
+    while received < count {
+        if let Some(Event::Message { .. }) = events.recv().await {
+            received += 1;
+        }
+    }
+}
+
+fn frame_codec(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("frame_codec");
+    for size in SIZES {
+        let frame = Frame::Message(Envelope {
+            session: 0,
+            seq: 0,
+            id: None,
+            nonce: None,
+            channel: None,
+            tag: None,
+            #[cfg(feature = "opentelemetry")]
+            trace: None,
+            #[cfg(feature = "ratchet")]
+            ratchet: None,
+            payload: vec![7u8; size].into(),
+        });
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &frame, |b, frame| {
+            b.to_async(&rt).iter(|| async {
+                let mut buffer = Cursor::new(Vec::with_capacity(size + 64));
+                write_frame(&mut buffer, frame).await.unwrap();
+                buffer.set_position(0);
+                read_frame(&mut buffer).await.unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn command_round_trip(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let (server, client) = rt.block_on(pair(9301));
+    c.bench_function("command_round_trip", |b| {
+        b.to_async(&rt).iter(|| async {
+            client
+                .command::<NatReport>(CommandKind::NatReport)
+                .await
+                .unwrap()
+        })
+    });
+    server.stop();
+    client.stop();
+}
+
+fn message_throughput(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let (server, client) = rt.block_on(pair(9311));
+    let events = server.subscribe().unwrap();
+    let mut group = c.benchmark_group("message_throughput");
+    group.measurement_time(Duration::from_secs(10));
+    for size in SIZES {
+        let payload = bytes::Bytes::from(vec![7u8; size]);
+        group.throughput(Throughput::Bytes(size as u64 * BATCH));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &payload, |b, payload| {
+            b.to_async(&rt).iter(|| async {
+                let mut events = events.clone();
+                for _ in 0..BATCH {
+                    client
+                        .send(
+                            server.peer_id(),
+                            payload.clone(),
+                            DeliveryGuarantee::Ordered,
+                        )
+                        .await
+                        .unwrap();
+                }
+                receive(&mut events, BATCH).await;
+            })
+        });
+    }
+    group.finish();
+    server.stop();
+    client.stop();
+}
+
+fn stream_open_latency(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let mut port = 9321;
+    let mut group = c.benchmark_group("stream_open");
+    group.sample_size(10);
+    group.bench_function("latency", |b| {
+        b.to_async(&rt).iter_custom(|iterations| {
+            let base = port;
+            port += 2 * iterations as usize;
+            async move {
+                let mut total = Duration::ZERO;
+                for i in 0..iterations as usize {
+                    let (server, client) = pair(base + 2 * i).await;
+                    let mut events = server.subscribe().unwrap();
+                    let start = Instant::now();
+                    client
+                        .send(server.peer_id(), "ping", DeliveryGuarantee::Ordered)
+                        .await
+                        .unwrap();
+                    receive(&mut events, 1).await;
+                    total += start.elapsed();
+                    server.stop();
+                    client.stop();
+                }
+                total
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    frame_codec,
+    command_round_trip,
+    message_throughput,
+    stream_open_latency
+);
+criterion_main!(benches);
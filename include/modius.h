@@ -0,0 +1,53 @@
+#ifndef MODIUS_H
+#define MODIUS_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define MODIUS_OK 0
+
+#define MODIUS_ERROR -1
+
+typedef enum ModiusGuarantee {
+  MODIUS_GUARANTEE_ORDERED = 0,
+  MODIUS_GUARANTEE_EXACTLY_ONCE = 1,
+} ModiusGuarantee;
+
+typedef struct ModiusNode ModiusNode;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+ModiusNode *modius_node_new(const char *name, const char *group, uint16_t port);
+
+void modius_node_free(ModiusNode *node);
+
+int modius_node_start(ModiusNode *node);
+
+int modius_node_stop(ModiusNode *node);
+
+bool modius_node_active(const ModiusNode *node);
+
+char *modius_node_id(const ModiusNode *node);
+
+int modius_node_send(ModiusNode *node,
+                     const char *peer,
+                     const uint8_t *data,
+                     size_t length,
+                     ModiusGuarantee guarantee);
+
+char *modius_node_poll_event(ModiusNode *node, uint64_t timeout_ms);
+
+char *modius_last_error(void);
+
+void modius_string_free(char *value);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* MODIUS_H */
@@ -0,0 +1,207 @@
+use std::{collections::BTreeMap, error::Error, str::FromStr};
+
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::util::{Peer, PeerType};
+
+const CSV_HEADER: &str = "id,kind,name,group,pinned,tags,addresses";
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AddressBookFormat {
+    #[default]
+    Json,
+    Csv,
+    Multiaddrs,
+}
+
+fn with_peer_id(address: &Multiaddr, peer: PeerId) -> Multiaddr {
+    match address.iter().last() {
+        Some(Protocol::P2p(_)) => address.clone(),
+        _ => address.clone().with(Protocol::P2p(peer)),
+    }
+}
+
+fn split_peer_id(address: Multiaddr) -> Option<(PeerId, Multiaddr)> {
+    let mut transport = address;
+    match transport.pop()? {
+        Protocol::P2p(peer) => Some((peer, transport)),
+        _ => None,
+    }
+}
+
+fn kind_name(kind: &PeerType) -> &'static str {
+    match kind {
+        PeerType::Bootstrap => "bootstrap",
+        PeerType::Discovered => "discovered",
+        PeerType::Relay => "relay",
+    }
+}
+
+fn parse_kind(kind: &str) -> Result<PeerType, Box<dyn Error + Send + Sync>> {
+    match kind.to_ascii_lowercase().as_str() {
+        "bootstrap" => Ok(PeerType::Bootstrap),
+        "discovered" | "" => Ok(PeerType::Discovered),
+        "relay" => Ok(PeerType::Relay),
+        other => Err(format!("Unknown peer kind {other:?}").into()),
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_string(),
+    }
+}
+
+fn csv_record(line: &str) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            (c, _) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field".into());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+fn addresses(peer: &Peer) -> Vec<Multiaddr> {
+    let mut addresses = Vec::new();
+    for address in std::iter::once(peer.address.clone()).chain(peer.dial_addresses()) {
+        let address = match split_peer_id(address.clone()) {
+            Some((id, transport)) if id == peer.id => transport,
+            _ => address,
+        };
+        if !addresses.contains(&address) {
+            addresses.push(address);
+        }
+    }
+    addresses
+}
+
+pub fn export(
+    peers: &[Peer],
+    format: AddressBookFormat,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    match format {
+        AddressBookFormat::Json => Ok(serde_json::to_string_pretty(peers)?),
+        AddressBookFormat::Csv => {
+            let mut out = format!("{CSV_HEADER}\n");
+            for peer in peers {
+                let tags: Vec<String> = peer
+                    .tags
+                    .iter()
+                    .map(|(key, value)| format!("{key}={value}"))
+                    .collect();
+                let addresses: Vec<String> =
+                    addresses(peer).iter().map(|a| a.to_string()).collect();
+                let record = [
+                    peer.id.to_string(),
+                    kind_name(&peer.kind).to_string(),
+                    peer.name.clone().unwrap_or_default(),
+                    peer.group.clone().unwrap_or_default(),
+                    peer.pinned.to_string(),
+                    tags.join(";"),
+                    addresses.join(" "),
+                ];
+                let record: Vec<String> = record.iter().map(|field| csv_field(field)).collect();
+                out.push_str(&record.join(","));
+                out.push('\n');
+            }
+            Ok(out)
+        }
+        AddressBookFormat::Multiaddrs => {
+            let mut out = String::new();
+            for peer in peers {
+                for address in addresses(peer) {
+                    out.push_str(&with_peer_id(&address, peer.id).to_string());
+                    out.push('\n');
+                }
+            }
+            Ok(out)
+        }
+    }
+}
+
+pub fn import(
+    data: &str,
+    format: AddressBookFormat,
+) -> Result<Vec<Peer>, Box<dyn Error + Send + Sync>> {
+    match format {
+        AddressBookFormat::Json => Ok(serde_json::from_str(data)?),
+        AddressBookFormat::Csv => {
+            let mut peers = Vec::new();
+            for (index, line) in data.lines().enumerate() {
+                let line = line.trim_end_matches('\r');
+                if line.trim().is_empty() || (index == 0 && line == CSV_HEADER) {
+                    continue;
+                }
+
+                let fields = csv_record(line)?;
+                let [id, kind, name, group, pinned, tags, addresses] = fields.as_slice() else {
+                    return Err(format!(
+                        "Line {} has {} fields, expected 7",
+                        index + 1,
+                        fields.len()
+                    )
+                    .into());
+                };
+                let id = PeerId::from_str(id)?;
+                let mut addresses = addresses
+                    .split_whitespace()
+                    .map(Multiaddr::from_str)
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter();
+                let Some(primary) = addresses.next() else {
+                    return Err(format!("Peer {id} has no addresses").into());
+                };
+
+                let mut peer = Peer::new(parse_kind(kind)?, id, primary);
+                for address in addresses {
+                    peer.add_address(address);
+                }
+                peer.name = (!name.is_empty()).then(|| name.clone());
+                peer.group = (!group.is_empty()).then(|| group.clone());
+                peer.pinned = pinned.parse().unwrap_or(false);
+                for tag in tags.split(';').filter(|tag| !tag.is_empty()) {
+                    let (key, value) = tag.split_once('=').unwrap_or((tag, ""));
+                    peer.tags.insert(key.to_string(), value.to_string());
+                }
+                peers.push(peer);
+            }
+            Ok(peers)
+        }
+        AddressBookFormat::Multiaddrs => {
+            let mut peers: BTreeMap<PeerId, Peer> = BTreeMap::new();
+            for line in data.lines().map(str::trim) {
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                let address = Multiaddr::from_str(line)?;
+                let (id, transport) = split_peer_id(address)
+                    .ok_or_else(|| format!("Address {line} has no trailing /p2p component"))?;
+                match peers.get_mut(&id) {
+                    Some(peer) => peer.add_address(transport),
+                    None => {
+                        peers.insert(id, Peer::new(PeerType::Discovered, id, transport));
+                    }
+                }
+            }
+            Ok(peers.into_values().collect())
+        }
+    }
+}
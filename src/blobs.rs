@@ -0,0 +1,191 @@
+use std::{collections::HashSet, error::Error, fmt::Write, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::storage::Storage;
+
+const BLOBS: &str = "blobs";
+const CHUNKS: &str = "blob_chunks";
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+pub fn digest(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .fold(String::with_capacity(64), |mut out, byte| {
+            let _ = write!(out, "{byte:02x}");
+            out
+        })
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BlobRecord {
+    pub id: String,
+    pub size: u64,
+    pub chunks: Vec<String>,
+    pub pinned: bool,
+    pub stored: DateTime<Utc>,
+    pub last_access: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StorageStats {
+    pub blobs: usize,
+    pub pinned: usize,
+    pub chunks: usize,
+    pub bytes: u64,
+    pub pinned_bytes: u64,
+    pub quota: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct BlobStore {
+    storage: Arc<dyn Storage>,
+    quota: Option<u64>,
+}
+
+impl BlobStore {
+    pub fn new(storage: Arc<dyn Storage>, quota: Option<u64>) -> Self {
+        BlobStore { storage, quota }
+    }
+
+    pub fn record(&self, id: &str) -> Result<Option<BlobRecord>, Box<dyn Error + Send + Sync>> {
+        self.storage.get_value::<BlobRecord>(BLOBS, id.as_bytes())
+    }
+
+    pub fn list(&self) -> Result<Vec<BlobRecord>, Box<dyn Error + Send + Sync>> {
+        self.storage.values::<BlobRecord>(BLOBS)
+    }
+
+    fn save(&self, record: &BlobRecord) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.storage.put_value(BLOBS, record.id.as_bytes(), record)
+    }
+
+    pub fn contains(&self, id: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(self.record(id)?.is_some())
+    }
+
+    pub fn put(&self, data: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.put_with(data, false)
+    }
+
+    pub fn put_pinned(&self, data: &[u8]) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.put_with(data, true)
+    }
+
+    fn put_with(&self, data: &[u8], pinned: bool) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let id = digest(data);
+        let now = Utc::now();
+        let record = match self.record(&id)? {
+            Some(mut record) => {
+                record.pinned |= pinned;
+                record.last_access = now;
+                record
+            }
+            None => {
+                let mut chunks = Vec::new();
+                for chunk in data.chunks(CHUNK_SIZE) {
+                    let hash = digest(chunk);
+                    self.storage.put(CHUNKS, hash.as_bytes(), chunk)?;
+                    chunks.push(hash);
+                }
+                BlobRecord {
+                    id: id.clone(),
+                    size: data.len() as u64,
+                    chunks,
+                    pinned,
+                    stored: now,
+                    last_access: now,
+                }
+            }
+        };
+        self.save(&record)?;
+        self.enforce_quota()?;
+        Ok(id)
+    }
+
+    pub fn get(&self, id: &str) -> Result<Option<Vec<u8>>, Box<dyn Error + Send + Sync>> {
+        let Some(mut record) = self.record(id)? else {
+            return Ok(None);
+        };
+
+        let mut data = Vec::with_capacity(record.size as usize);
+        for hash in record.chunks.iter() {
+            match self.storage.get(CHUNKS, hash.as_bytes())? {
+                Some(chunk) => data.extend_from_slice(&chunk),
+                None => return Err(format!("Missing chunk {hash} of blob {id}").into()),
+            }
+        }
+
+        record.last_access = Utc::now();
+        self.save(&record)?;
+        Ok(Some(data))
+    }
+
+    pub fn pin(&self, id: &str, pinned: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut record = self.record(id)?.ok_or("Unknown blob")?;
+        record.pinned = pinned;
+        self.save(&record)?;
+        if !pinned {
+            self.enforce_quota()?;
+        }
+        Ok(())
+    }
+
+    pub fn remove(&self, id: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let Some(record) = self.record(id)? else {
+            return Ok(false);
+        };
+
+        self.storage.delete(BLOBS, id.as_bytes())?;
+        let referenced: HashSet<String> = self
+            .list()?
+            .into_iter()
+            .flat_map(|other| other.chunks)
+            .collect();
+        for hash in record
+            .chunks
+            .iter()
+            .filter(|hash| !referenced.contains(*hash))
+        {
+            self.storage.delete(CHUNKS, hash.as_bytes())?;
+        }
+        Ok(true)
+    }
+
+    pub fn enforce_quota(&self) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let Some(quota) = self.quota else {
+            return Ok(Vec::new());
+        };
+
+        let mut records = self.list()?;
+        let mut total: u64 = records.iter().map(|record| record.size).sum();
+        records.retain(|record| !record.pinned);
+        records.sort_by_key(|record| record.last_access);
+
+        let mut evicted = Vec::new();
+        for record in records {
+            if total <= quota {
+                break;
+            }
+            self.remove(&record.id)?;
+            total = total.saturating_sub(record.size);
+            evicted.push(record.id);
+        }
+        Ok(evicted)
+    }
+
+    pub fn stats(&self) -> Result<StorageStats, Box<dyn Error + Send + Sync>> {
+        let records = self.list()?;
+        let pinned: Vec<&BlobRecord> = records.iter().filter(|record| record.pinned).collect();
+        Ok(StorageStats {
+            blobs: records.len(),
+            pinned: pinned.len(),
+            chunks: self.storage.iterate(CHUNKS)?.len(),
+            bytes: records.iter().map(|record| record.size).sum(),
+            pinned_bytes: pinned.iter().map(|record| record.size).sum(),
+            quota: self.quota,
+        })
+    }
+}
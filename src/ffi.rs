@@ -0,0 +1,252 @@
+#![allow(clippy::missing_safety_doc)]
+
+use std::{
+    cell::RefCell,
+    error::Error,
+    ffi::{c_char, c_int, CStr, CString},
+    ptr,
+    str::FromStr,
+    time::Duration,
+};
+
+use libp2p::PeerId;
+
+use crate::{
+    net::{bus::Subscription, session::DeliveryGuarantee},
+    Node, NodeBuilder,
+};
+
+pub const MODIUS_OK: c_int = 0;
+pub const MODIUS_ERROR: c_int = -1;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModiusGuarantee {
+    Ordered = 0,
+    ExactlyOnce = 1,
+}
+
+impl From<ModiusGuarantee> for DeliveryGuarantee {
+    fn from(value: ModiusGuarantee) -> Self {
+        match value {
+            ModiusGuarantee::Ordered => DeliveryGuarantee::Ordered,
+            ModiusGuarantee::ExactlyOnce => DeliveryGuarantee::ExactlyOnce,
+        }
+    }
+}
+
+pub struct ModiusNode {
+    runtime: tokio::runtime::Runtime,
+    node: Node,
+    events: Option<Subscription>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error<E: ToString>(error: E) {
+    let message = CString::new(error.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn status(result: Result<(), Box<dyn Error + Send + Sync>>) -> c_int {
+    match result {
+        Ok(()) => MODIUS_OK,
+        Err(e) => {
+            set_error(e);
+            MODIUS_ERROR
+        }
+    }
+}
+
+unsafe fn string(value: *const c_char) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(CStr::from_ptr(value).to_str()?.to_string()))
+}
+
+fn owned(value: String) -> *mut c_char {
+    match CString::new(value) {
+        Ok(value) => value.into_raw(),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+unsafe fn create(
+    name: *const c_char,
+    group: *const c_char,
+    port: u16,
+) -> Result<ModiusNode, Box<dyn Error + Send + Sync>> {
+    let mut builder = NodeBuilder::default();
+    if let Some(name) = string(name)? {
+        builder.name(name);
+    }
+    if let Some(group) = string(group)? {
+        builder.group(group);
+    }
+    if port != 0 {
+        builder.port(port as usize);
+    }
+    Ok(ModiusNode {
+        runtime: tokio::runtime::Runtime::new()?,
+        node: builder.build()?,
+        events: None,
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn modius_node_new(
+    name: *const c_char,
+    group: *const c_char,
+    port: u16,
+) -> *mut ModiusNode {
+    match create(name, group, port) {
+        Ok(node) => Box::into_raw(Box::new(node)),
+        Err(e) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn modius_node_free(node: *mut ModiusNode) {
+    if node.is_null() {
+        return;
+    }
+    let node = *Box::from_raw(node);
+    node.node.stop();
+    node.runtime.shutdown_timeout(Duration::from_secs(1));
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn modius_node_start(node: *mut ModiusNode) -> c_int {
+    let Some(node) = node.as_mut() else {
+        set_error("Node handle is null");
+        return MODIUS_ERROR;
+    };
+    let _runtime = node.runtime.enter();
+    status(node.node.start().and_then(|_| {
+        node.events = Some(node.node.subscribe()?);
+        Ok(())
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn modius_node_stop(node: *mut ModiusNode) -> c_int {
+    let Some(node) = node.as_mut() else {
+        set_error("Node handle is null");
+        return MODIUS_ERROR;
+    };
+    node.node.stop();
+    node.events = None;
+    MODIUS_OK
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn modius_node_active(node: *const ModiusNode) -> bool {
+    node.as_ref().is_some_and(|node| node.node.active())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn modius_node_id(node: *const ModiusNode) -> *mut c_char {
+    match node.as_ref() {
+        Some(node) => owned(node.node.id()),
+        None => {
+            set_error("Node handle is null");
+            ptr::null_mut()
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn modius_node_send(
+    node: *mut ModiusNode,
+    peer: *const c_char,
+    data: *const u8,
+    length: usize,
+    guarantee: ModiusGuarantee,
+) -> c_int {
+    let Some(node) = node.as_ref() else {
+        set_error("Node handle is null");
+        return MODIUS_ERROR;
+    };
+    if data.is_null() && length > 0 {
+        set_error("Payload is null");
+        return MODIUS_ERROR;
+    }
+    let peer = match string(peer) {
+        Ok(Some(peer)) => peer,
+        Ok(None) => {
+            set_error("Peer id is null");
+            return MODIUS_ERROR;
+        }
+        Err(e) => {
+            set_error(e);
+            return MODIUS_ERROR;
+        }
+    };
+    let payload = match length {
+        0 => Vec::new(),
+        _ => std::slice::from_raw_parts(data, length).to_vec(),
+    };
+    status(node.runtime.block_on(async {
+        let peer = PeerId::from_str(&peer)?;
+        node.node
+            .send(peer, payload, guarantee.into())
+            .await
+            .map(|_| ())
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn modius_node_poll_event(
+    node: *mut ModiusNode,
+    timeout_ms: u64,
+) -> *mut c_char {
+    let Some(node) = node.as_mut() else {
+        set_error("Node handle is null");
+        return ptr::null_mut();
+    };
+    let Some(events) = node.events.as_mut() else {
+        set_error("Node is not running");
+        return ptr::null_mut();
+    };
+    let event = match timeout_ms {
+        0 => events.try_recv(),
+        _ => node.runtime.block_on(async {
+            tokio::time::timeout(Duration::from_millis(timeout_ms), events.recv())
+                .await
+                .ok()
+                .flatten()
+        }),
+    };
+    match event.map(|event| serde_json::to_string(&event)) {
+        Some(Ok(json)) => owned(json),
+        Some(Err(e)) => {
+            set_error(e);
+            ptr::null_mut()
+        }
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn modius_last_error() -> *mut c_char {
+    LAST_ERROR.with(|last| match last.borrow_mut().take() {
+        Some(message) => message.into_raw(),
+        None => ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn modius_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
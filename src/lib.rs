@@ -1,18 +1,39 @@
-use std::{error::Error, sync::Arc};
+use std::{error::Error, future::Future, sync::Arc, time::Duration};
 
 use async_channel::{Receiver, Sender};
-use chrono::Utc;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use derive_builder::Builder;
-use libp2p::{identity::{Keypair, PublicKey}, PeerId};
-use net::{command::CommandWrapper, event::Event};
-use serde::{Deserialize, Serialize};
-use tokio::task::JoinHandle;
+use libp2p::{futures::{future, stream, Stream, StreamExt}, identity::{Keypair, PublicKey}, multiaddr::Protocol, Multiaddr, PeerId};
+use net::{ack::AckQueue, acl::Acl, admin::AdminRequest, audit::{AuditConfig, AuditEntry, AuditLog}, breaker::BreakerConfig, broadcast::{Audience, BroadcastResult, FanOut}, budget::{BandwidthBudget, BudgetUsage}, bus::{EventBus, Subscription}, channel::{self, ChannelPair, Channels}, codec::{Codec, JsonCodec}, client::Client, command::{CommandKind, CommandWrapper}, fairness::SendScheduling, horizon::Advertise, replicate::{ReplicationPolicy, ShardPlacement}, room::{Room, Rooms}, routing::{RoutingEntry, RoutingTable}, rpc::{Balance, CallOptions, Caller, Callbacks, RpcError, RpcErrorKind, RpcStream, Service, Services}, schema::{MessageTag, MessageType, Schemas}, session::{Backlog, DeliveryGuarantee, PendingMessage, Receipt}, signing::Signature, stats::NodeStats, sync::{Shares, SyncReport, SyncSource}, feed::{FeedEntry, Feeds}, listen::ListenerInfo, multipath::Redundancy, probe::ThroughputReport, queue::{self, Job, JobRequest, LeasedJob, QueueConfig, QueueStats, Queues, RenewRequest, ClaimRequest}, transport::{CustomTransport, DnsResolver, MuxerConfig, Security}, watchdog::WatchdogConfig, update::UpdateManifest};
+use addressbook::AddressBookFormat;
+use blobs::BlobStore;
+use peers::{PeerSet, PeerStore, PrunePolicy};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use runtime::Task;
+use storage::{MemoryStorage, Storage};
 use util::Peer;
+use uuid::Uuid;
 
-mod util;
-mod net;
+pub mod addressbook;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+pub mod blobs;
+pub mod util;
+pub mod validate;
+pub mod net;
+pub mod peers;
+pub mod runtime;
+pub mod storage;
+
+pub use net::wire;
+pub use validate::{NodeBuilderError, ValidationIssue};
+
+pub type NodeThread = Arc<Task<Result<(), Box<dyn Error + Send + Sync>>>>;
 
 #[derive(Clone, Debug, Builder)]
+#[builder(build_fn(validate = "Self::validate", error = "NodeBuilderError"))]
 pub struct Node {
     #[builder(default = "Keypair::generate_ed25519()")]
     pub key: Keypair,
@@ -26,33 +47,231 @@ pub struct Node {
     #[builder(default = "String::from(\"modius.generic\")")]
     pub group: String,
 
+    #[builder(default = "Uuid::new_v4()")]
+    pub device_id: Uuid,
+
     #[builder(default = "8000")]
     pub port: usize,
 
+    #[builder(default = "Arc::new(MemoryStorage::new())")]
+    pub storage: Arc<dyn Storage>,
+
+    #[builder(default = "None", setter(into, strip_option))]
+    pub socks5_proxy: Option<String>,
+
+    #[builder(default = "false")]
+    pub proxy_only: bool,
+
+    #[builder(default = "false")]
+    pub port_fallback: bool,
+
+    #[builder(default = "false")]
+    pub ipfs_dht: bool,
+
+    #[builder(default = "None", setter(strip_option))]
+    pub dns_resolver: Option<DnsResolver>,
+
+    #[builder(default = "String::from(\"1.0\")", setter(into))]
+    pub app_version: String,
+
+    #[builder(default = "true")]
+    pub listen: bool,
+
+    #[builder(default = "false", setter(custom))]
+    pub ephemeral: bool,
+
+    #[builder(default = "None", setter(strip_option))]
+    pub coalesce_discovery: Option<Duration>,
+
+    #[builder(default = "false", setter(custom))]
+    pub auto_relay: bool,
+
+    #[builder(default = "1", setter(custom))]
+    pub min_reservations: usize,
+
+    #[builder(default = "false")]
+    pub group_admin: bool,
+
+    #[builder(default = "Vec::new()")]
+    pub group_admins: Vec<PeerId>,
+
+    #[builder(default = "Duration::from_secs(60 * 60)")]
+    pub group_key_rotation: Duration,
+
+    #[builder(default = "Vec::new()")]
+    pub update_publishers: Vec<PeerId>,
+
+    #[builder(default = "Acl::default()")]
+    pub acl: Acl,
+
+    #[builder(default = "None", setter(strip_option))]
+    pub storage_quota: Option<u64>,
+
+    #[builder(default = "ReplicationPolicy::default()")]
+    pub replication: ReplicationPolicy,
+
+    #[builder(default = "false")]
+    pub causal_rooms: bool,
+
+    #[builder(default = "MuxerConfig::default()")]
+    pub muxer: MuxerConfig,
+
+    #[builder(default = "Vec::new()", setter(into))]
+    pub noise_prologue: Vec<u8>,
+
+    #[builder(default = "Security::default()")]
+    pub security: Security,
+
+    #[builder(default = "Advertise::default()")]
+    pub advertise: Advertise,
+
+    #[builder(default = "false")]
+    pub address_privacy: bool,
+
+    #[builder(default = "wire::MAX_FRAME - wire::FRAME_OVERHEAD")]
+    pub max_message_size: usize,
+
+    #[builder(default = "Some(WatchdogConfig::default())")]
+    pub watchdog: Option<WatchdogConfig>,
+
+    #[builder(default = "Some(BreakerConfig::default())")]
+    pub circuit_breaker: Option<BreakerConfig>,
+
+    #[builder(default = "SendScheduling::default()")]
+    pub send_scheduling: SendScheduling,
+
+    #[builder(default = "None", setter(strip_option))]
+    pub bandwidth_budget: Option<BandwidthBudget>,
+
+    #[builder(default = "false")]
+    pub trace_commands: bool,
+
+    #[builder(default = "false")]
+    pub journal_commands: bool,
+
+    #[builder(default = "Vec::new()")]
+    pub acknowledged_events: Vec<String>,
+
+    #[builder(default = "1024")]
+    pub event_buffer: usize,
+
+    #[builder(default = "Duration::from_secs(60)")]
+    pub lookup_cache_ttl: Duration,
+
+    #[builder(default = "Vec::new()")]
+    pub custom_transports: Vec<Arc<dyn CustomTransport>>,
+
+    #[cfg(all(unix, feature = "tokio"))]
+    #[builder(default = "Vec::new()")]
+    pub unix_sockets: Vec<std::path::PathBuf>,
+
+    #[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+    #[builder(default = "false")]
+    pub webrtc: bool,
+
+    #[builder(default = "PrunePolicy::default()")]
+    pub prune: PrunePolicy,
+
+    #[builder(default = "QueueConfig::default()")]
+    pub work_queue: QueueConfig,
+
+    #[builder(default = "64")]
+    pub max_inbound_streams: usize,
+
+    #[builder(default = "16")]
+    pub command_concurrency: usize,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[builder(default = "None", setter(into, strip_option))]
+    pub event_log_path: Option<std::path::PathBuf>,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[builder(default = "10 * 1024 * 1024")]
+    pub event_log_max_bytes: u64,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[builder(default = "chrono::TimeDelta::days(1)")]
+    pub event_log_max_age: chrono::TimeDelta,
+
+    #[builder(default = "None", setter(strip_option))]
+    pub audit_log: Option<AuditConfig>,
+
+    #[cfg(all(feature = "webhooks", not(target_arch = "wasm32")))]
+    #[builder(default)]
+    pub webhooks: Vec<net::webhook::Webhook>,
+
     #[builder(setter(skip))]
     pub commands: Option<Sender<CommandWrapper>>,
 
     #[builder(setter(skip))]
-    pub events: Option<Receiver<Event>>,
+    pub events: Option<EventBus>,
+
+    #[builder(setter(skip))]
+    pub thread: Option<NodeThread>,
 
     #[builder(setter(skip))]
-    pub thread: Option<Arc<JoinHandle<Result<(), Box<dyn Error>>>>>
+    pub channels: Channels,
+
+    #[builder(setter(skip))]
+    pub rooms: Rooms,
+
+    #[builder(setter(skip))]
+    pub schemas: Schemas,
+
+    #[builder(setter(skip))]
+    pub shares: Shares,
+
+    #[builder(setter(skip))]
+    pub services: Services,
+
+    #[builder(setter(skip))]
+    pub queues: Option<Queues>
 }
 
 impl NodeBuilder {
-    pub fn try_bootstrap<I: AsRef<str>, A: AsRef<str>>(&mut self, id: I, addr: A) -> Result<(), Box<dyn Error>> {
-        self.with_peer(Peer::try_new(util::PeerType::Bootstrap, id, addr)?);
+    fn validate(&self) -> Result<(), NodeBuilderError> {
+        let persistent = self.storage.as_ref().is_some_and(|storage| storage.is_persistent());
+        validate::validate(self.port, self.group.as_deref(), self.peers.as_deref().unwrap_or_default(), self.ephemeral.unwrap_or(false), persistent)
+    }
+
+    fn try_peer<I: AsRef<str>, A: AsRef<str>>(&mut self, kind: util::PeerType, id: I, addr: A) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut peer = Peer::try_new(kind, id, addr)?;
+        if validate::address_peer(&peer.address).is_none() {
+            peer.address.push(Protocol::P2p(peer.id));
+        }
+        self.with_peer(peer);
 
         Ok(())
     }
 
-    pub fn try_relay<I: AsRef<str>, A: AsRef<str>>(&mut self, id: I, addr: A) -> Result<(), Box<dyn Error>> {
-        self.with_peer(Peer::try_new(util::PeerType::Relay, id, addr)?);
+    pub fn try_bootstrap<I: AsRef<str>, A: AsRef<str>>(&mut self, id: I, addr: A) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.try_peer(util::PeerType::Bootstrap, id, addr)
+    }
 
-        Ok(())
+    pub fn try_relay<I: AsRef<str>, A: AsRef<str>>(&mut self, id: I, addr: A) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.try_peer(util::PeerType::Relay, id, addr)
+    }
+
+    pub fn ephemeral(&mut self) -> &mut Self {
+        self.ephemeral = Some(true);
+        self.storage = Some(Arc::new(MemoryStorage::new()));
+        self
+    }
+
+    pub fn auto_relay(&mut self, enabled: bool, min_reservations: usize) -> &mut Self {
+        self.auto_relay = Some(enabled);
+        self.min_reservations = Some(min_reservations);
+        self
     }
 
-    pub fn with_peer(&mut self, peer: Peer) -> () {
+    pub fn with_transport<T: CustomTransport + 'static>(&mut self, transport: T) -> &mut Self {
+        self.custom_transports
+            .get_or_insert_with(Vec::new)
+            .push(Arc::new(transport));
+        self
+    }
+
+    pub fn with_peer(&mut self, peer: Peer) {
         if let Some(ref mut peers) = self.peers {
             peers.push(peer);
         } else {
@@ -67,32 +286,75 @@ pub struct SavedNode {
     pub peers: Vec<util::Peer>,
     pub name: String,
     pub group: String,
-    pub port: usize
+    pub port: usize,
+
+    #[serde(default)]
+    pub rooms: Vec<String>,
+
+    #[serde(default = "Uuid::new_v4")]
+    pub device_id: Uuid
 }
 
 impl SavedNode {
-    pub fn hydrate(&self) -> Result<Node, Box<dyn Error>> {
-        let key = Keypair::from_protobuf_encoding(&self.key.as_slice())?;
-        Ok(Node {
-            key: key.clone(),
-            peers: self.peers.clone(),
-            name: self.name.clone(),
-            group: self.group.clone(),
-            port: self.port,
-            commands: None,
-            events: None,
-            thread: None
-        })
+    pub fn hydrate(&self) -> Result<Node, Box<dyn Error + Send + Sync>> {
+        let key = Keypair::from_protobuf_encoding(self.key.as_slice())?;
+        let node = NodeBuilder::default().key(key).peers(validate::complete_peers(&self.peers)).name(self.name.clone()).group(self.group.clone()).port(self.port).device_id(self.device_id).build()?;
+        for room in self.rooms.iter() {
+            node.rooms.open(room);
+        }
+
+        Ok(node)
     }
 
-    pub fn save(node: &Node) -> Self {
-        SavedNode {
-            key: node.key.to_protobuf_encoding().expect("Failed to parse Keypair"),
+    pub fn save(node: &Node) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        if node.ephemeral {
+            return Err("Ephemeral nodes cannot export their key".into());
+        }
+
+        Ok(SavedNode {
+            key: node.key.to_protobuf_encoding()?,
             peers: node.peers.clone(),
             name: node.name.clone(),
             group: node.group.clone(),
-            port: node.port
+            port: node.port,
+            rooms: node.rooms.names(),
+            device_id: node.device_id
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NodeSnapshot {
+    pub node: SavedNode,
+    pub known_peers: Vec<util::Peer>,
+
+    #[serde(default)]
+    pub routing: Vec<RoutingEntry>,
+
+    #[serde(default)]
+    pub outbox: Vec<PendingMessage>,
+
+    pub taken: DateTime<Utc>
+}
+
+impl NodeSnapshot {
+    pub fn restore(&self, storage: Arc<dyn Storage>) -> Result<Node, Box<dyn Error + Send + Sync>> {
+        let mut node = self.node.hydrate()?;
+        node.storage = storage;
+
+        let store = node.peer_store();
+        for peer in self.known_peers.clone() {
+            store.insert(peer)?;
         }
+
+        let routing = node.routing_table();
+        for entry in self.routing.iter() {
+            routing.record(entry)?;
+        }
+
+        Backlog::for_node(node.storage.clone(), &node.key).seed(&self.outbox)?;
+
+        Ok(node)
     }
 }
 
@@ -109,6 +371,22 @@ impl Node {
         self.peer_id().to_string()
     }
 
+    pub fn sign(&self, data: &[u8]) -> Result<Signature, Box<dyn Error + Send + Sync>> {
+        self.sign_in("", data)
+    }
+
+    pub fn sign_in(&self, domain: &str, data: &[u8]) -> Result<Signature, Box<dyn Error + Send + Sync>> {
+        Ok(Signature::sign(&self.key, domain, data)?)
+    }
+
+    pub fn verify(&self, peer: &PeerId, data: &[u8], signature: &Signature) -> bool {
+        self.verify_in("", peer, data, signature)
+    }
+
+    pub fn verify_in(&self, domain: &str, peer: &PeerId, data: &[u8], signature: &Signature) -> bool {
+        signature.verify(peer, domain, data)
+    }
+
     pub fn active(&self) -> bool {
         if let Some(commands) = &self.commands {
             if commands.is_closed() {
@@ -133,11 +411,547 @@ impl Node {
         false
     }
 
-    pub fn save(&self) -> SavedNode {
+    pub fn subscribe(&self) -> Result<Subscription, Box<dyn Error + Send + Sync>> {
+        match &self.events {
+            Some(events) => Ok(events.subscribe()),
+            None => Err("Node is not running".into())
+        }
+    }
+
+    pub fn peer_store(&self) -> PeerStore {
+        PeerStore::new(self.storage.clone())
+    }
+
+    pub fn routing_table(&self) -> RoutingTable {
+        RoutingTable::new(self.storage.clone())
+    }
+
+    pub fn blob_store(&self) -> BlobStore {
+        BlobStore::new(self.storage.clone(), self.storage_quota)
+    }
+
+    pub fn audit_entries(&self) -> Result<Vec<AuditEntry>, Box<dyn Error + Send + Sync>> {
+        AuditLog::entries(&self.storage)
+    }
+
+    pub fn ack_event(&self, id: Uuid) -> Result<(), Box<dyn Error + Send + Sync>> {
+        AckQueue::new(self.storage.clone(), &self.acknowledged_events).ack(id)
+    }
+
+    pub fn feeds(&self) -> Feeds {
+        Feeds::new(self.storage.clone(), &self.group)
+    }
+
+    pub fn start(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.active() {
+            return Err("Node is already running".into());
+        }
+
+        if self.ephemeral && self.storage.is_persistent() {
+            return Err("Ephemeral nodes cannot use persistent storage".into());
+        }
+
+        let store = self.peer_store();
+        for peer in self.peers.clone() {
+            store.insert(peer)?;
+        }
+
+        let queues = Queues::new(self.storage.clone(), self.work_queue.clone());
+        queues.serve(&self.services);
+        self.queues = Some(queues);
+
+        let (client, commands, events) = Client::create(self)?;
+        self.commands = Some(commands);
+        self.events = Some(events);
+        self.thread = Some(Arc::new(Task::spawn(client.run())));
+
+        Ok(())
+    }
+
+    pub async fn command<T: Serialize + DeserializeOwned>(&self, command: CommandKind) -> Result<T, Box<dyn Error + Send + Sync>> {
+        match &self.commands {
+            Some(commands) => command.send::<T>(commands.clone()).await,
+            None => Err("Node is not running".into())
+        }
+    }
+
+    pub async fn command_traced<T: Serialize + DeserializeOwned>(&self, id: Uuid, command: CommandKind) -> Result<T, Box<dyn Error + Send + Sync>> {
+        match &self.commands {
+            Some(commands) => command.send_traced::<T>(id, commands.clone()).await,
+            None => Err("Node is not running".into())
+        }
+    }
+
+    pub async fn command_idempotent<T: Serialize + DeserializeOwned>(&self, key: &str, command: CommandKind) -> Result<T, Box<dyn Error + Send + Sync>> {
+        match &self.commands {
+            Some(commands) => command.send_idempotent::<T>(key, commands.clone()).await,
+            None => Err("Node is not running".into())
+        }
+    }
+
+    pub async fn send<P: Into<Bytes>>(&self, peer: PeerId, payload: P, guarantee: DeliveryGuarantee) -> Result<Receipt, Box<dyn Error + Send + Sync>> {
+        self.send_redundant(peer, payload, guarantee, Redundancy::Single).await
+    }
+
+    pub async fn send_redundant<P: Into<Bytes>>(&self, peer: PeerId, payload: P, guarantee: DeliveryGuarantee, redundancy: Redundancy) -> Result<Receipt, Box<dyn Error + Send + Sync>> {
+        self.command::<Receipt>(CommandKind::Send {
+            peer,
+            payload: payload.into(),
+            guarantee,
+            tag: None,
+            redundancy,
+            #[cfg(feature = "opentelemetry")]
+            trace: net::trace::TraceContext::current()
+        }).await
+    }
+
+    pub fn register<M: MessageType>(&self) {
+        self.schemas.register::<M>();
+    }
+
+    pub async fn send_typed<M: MessageType>(&self, peer: PeerId, message: &M, guarantee: DeliveryGuarantee) -> Result<Receipt, Box<dyn Error + Send + Sync>> {
+        self.command::<Receipt>(CommandKind::Send {
+            peer,
+            payload: serde_json::to_vec(message)?.into(),
+            guarantee,
+            tag: Some(MessageTag::of::<M>()),
+            redundancy: Redundancy::Single,
+            #[cfg(feature = "opentelemetry")]
+            trace: net::trace::TraceContext::current()
+        }).await
+    }
+
+    pub async fn broadcast<P: Into<Bytes>>(&self, payload: P, fanout: FanOut) -> Result<Vec<BroadcastResult>, Box<dyn Error + Send + Sync>> {
+        self.broadcast_to(payload, fanout, Audience::Group).await
+    }
+
+    pub async fn broadcast_to<P: Into<Bytes>>(&self, payload: P, fanout: FanOut, to: Audience) -> Result<Vec<BroadcastResult>, Box<dyn Error + Send + Sync>> {
+        self.command::<Vec<BroadcastResult>>(CommandKind::Broadcast { payload: payload.into(), fanout, to }).await
+    }
+
+    pub async fn define_peer_set(&self, name: &str, peers: Vec<PeerId>) -> Result<PeerSet, Box<dyn Error + Send + Sync>> {
+        self.command::<PeerSet>(CommandKind::DefinePeerSet { name: name.to_string(), peers }).await
+    }
+
+    pub async fn update_peer_set(&self, name: &str, add: Vec<PeerId>, remove: Vec<PeerId>) -> Result<PeerSet, Box<dyn Error + Send + Sync>> {
+        self.command::<PeerSet>(CommandKind::UpdatePeerSet { name: name.to_string(), add, remove }).await
+    }
+
+    pub async fn delete_peer_set(&self, name: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        self.command::<bool>(CommandKind::DeletePeerSet(name.to_string())).await
+    }
+
+    pub async fn peer_sets(&self) -> Result<Vec<PeerSet>, Box<dyn Error + Send + Sync>> {
+        self.command::<Vec<PeerSet>>(CommandKind::ListPeerSets).await
+    }
+
+    pub async fn disconnect_set(&self, set: &str) -> Result<Vec<PeerId>, Box<dyn Error + Send + Sync>> {
+        self.command::<Vec<PeerId>>(CommandKind::Disconnect { set: set.to_string() }).await
+    }
+
+    pub fn open_channel<T: Serialize + DeserializeOwned + Send + 'static>(&self, peer: PeerId, label: &str) -> Result<(Sender<T>, Receiver<T>), Box<dyn Error + Send + Sync>> {
+        self.open_channel_with(peer, label, JsonCodec::<T>::new())
+    }
+
+    pub fn open_channel_with<C: Codec>(&self, peer: PeerId, label: &str, codec: C) -> Result<ChannelPair<C::Item>, Box<dyn Error + Send + Sync>> {
+        match &self.commands {
+            Some(commands) => Ok(channel::bridge(&self.channels, commands.clone(), peer, label.to_string(), codec)),
+            None => Err("Node is not running".into())
+        }
+    }
+
+    pub async fn join_room(&self, name: &str) -> Result<Room, Box<dyn Error + Send + Sync>> {
+        let events = self.rooms.open(name);
+        if let Err(e) = self.command::<bool>(CommandKind::JoinRoom(name.to_string())).await {
+            self.rooms.close(name);
+            return Err(e);
+        }
+
+        match &self.commands {
+            Some(commands) => Ok(Room::new(name.to_string(), commands.clone(), events)),
+            None => Err("Node is not running".into())
+        }
+    }
+
+    #[cfg(all(feature = "mqtt", not(target_arch = "wasm32")))]
+    pub async fn bridge_mqtt(&self, config: net::mqtt::MqttBridgeConfig) -> Result<net::mqtt::MqttBridge, Box<dyn Error + Send + Sync>> {
+        let mut rooms = Vec::new();
+        for room in config.rooms.iter() {
+            rooms.push(self.join_room(room).await?);
+        }
+        Ok(net::mqtt::MqttBridge::start(config, rooms))
+    }
+
+    pub async fn await_ready(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.command::<()>(CommandKind::AwaitReady).await
+    }
+
+    pub async fn schedule(&self, interval: Duration, command: CommandKind) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        self.command::<u64>(CommandKind::Schedule { interval, command: Box::new(command) }).await
+    }
+
+    pub async fn unschedule(&self, id: u64) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        self.command::<bool>(CommandKind::Unschedule(id)).await
+    }
+
+    pub async fn replicate(&self, blob: &str) -> Result<ShardPlacement, Box<dyn Error + Send + Sync>> {
+        self.command::<ShardPlacement>(CommandKind::Replicate(blob.to_string())).await
+    }
+
+    pub async fn restore_blob(&self, blob: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.command::<String>(CommandKind::RestoreBlob(blob.to_string())).await
+    }
+
+    pub async fn append_feed(&self, name: &str, data: Value) -> Result<FeedEntry, Box<dyn Error + Send + Sync>> {
+        self.command::<FeedEntry>(CommandKind::AppendFeed { name: name.to_string(), data }).await
+    }
+
+    pub async fn catch_up_feed(&self, feed: Option<&str>) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        self.command::<usize>(CommandKind::CatchUpFeed(feed.map(|feed| feed.to_string()))).await
+    }
+
+    pub async fn export_peers(&self, format: AddressBookFormat) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.command::<String>(CommandKind::ExportPeers { format }).await
+    }
+
+    pub async fn import_peers(&self, format: AddressBookFormat, data: &str) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        self.command::<usize>(CommandKind::ImportPeers { format, data: data.to_string() }).await
+    }
+
+    pub async fn listeners(&self) -> Result<Vec<ListenerInfo>, Box<dyn Error + Send + Sync>> {
+        self.command::<Vec<ListenerInfo>>(CommandKind::GetListeners).await
+    }
+
+    pub async fn add_listener(&self, address: Multiaddr) -> Result<u64, Box<dyn Error + Send + Sync>> {
+        self.command::<u64>(CommandKind::AddListener(address)).await
+    }
+
+    pub async fn remove_listener(&self, id: u64) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        self.command::<bool>(CommandKind::RemoveListener(id)).await
+    }
+
+    pub async fn pause(&self) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        self.command::<bool>(CommandKind::Pause).await
+    }
+
+    pub async fn resume(&self) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        self.command::<bool>(CommandKind::Resume).await
+    }
+
+    pub async fn probe_throughput(&self, peer: PeerId, duration: Duration) -> Result<ThroughputReport, Box<dyn Error + Send + Sync>> {
+        self.command::<ThroughputReport>(CommandKind::ProbeThroughput { peer, duration }).await
+    }
+
+    pub async fn flush_caches(&self) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        self.command::<usize>(CommandKind::FlushCaches).await
+    }
+
+    pub async fn bandwidth_usage(&self) -> Result<Option<BudgetUsage>, Box<dyn Error + Send + Sync>> {
+        self.command::<Option<BudgetUsage>>(CommandKind::BandwidthUsage).await
+    }
+
+    pub async fn stats(&self) -> Result<NodeStats, Box<dyn Error + Send + Sync>> {
+        self.command::<NodeStats>(CommandKind::Stats).await
+    }
+
+    pub fn share(&self, name: &str, source: Arc<dyn SyncSource>) {
+        self.shares.share(name, source);
+    }
+
+    pub async fn sync(&self, peer: PeerId, share: &str) -> Result<SyncReport, Box<dyn Error + Send + Sync>> {
+        self.command::<SyncReport>(CommandKind::Sync { peer, share: share.to_string() }).await
+    }
+
+    pub async fn publish_update(&self, manifest: UpdateManifest) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.command::<()>(CommandKind::PublishUpdate(manifest)).await
+    }
+
+    pub fn serve<P, R, F, Fut>(&self, method: &str, handler: F)
+    where
+        P: DeserializeOwned + Send + 'static,
+        R: Serialize,
+        F: Fn(PeerId, P) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R, RpcError>> + Send + 'static
+    {
+        self.serve_with_callbacks(method, move |caller: Caller, params| handler(caller.peer, params));
+    }
+
+    pub fn serve_with_callbacks<P, R, F, Fut>(&self, method: &str, handler: F)
+    where
+        P: DeserializeOwned + Send + 'static,
+        R: Serialize,
+        F: Fn(Caller, P) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R, RpcError>> + Send + 'static
+    {
+        let handler = Arc::new(handler);
+        let name = method.to_string();
+        self.services.serve(method, Arc::new(move |caller, params| {
+            let handler = handler.clone();
+            let name = name.clone();
+            Box::pin(async move {
+                let params = serde_json::from_value::<P>(params).map_err(|e| RpcError::invalid_params(&name, e))?;
+                let result = handler(caller, params).await?;
+                serde_json::to_value(result).map_err(|e| RpcError::failed(e.to_string()))
+            })
+        }));
+    }
+
+    pub fn serve_streaming<P, R, F, S>(&self, method: &str, handler: F)
+    where
+        P: DeserializeOwned + Send + 'static,
+        R: Serialize,
+        F: Fn(PeerId, P) -> S + Send + Sync + 'static,
+        S: Stream<Item = Result<R, RpcError>> + Send + 'static
+    {
+        let name = method.to_string();
+        self.services.serve_streaming(method, Arc::new(move |peer, params| match serde_json::from_value::<P>(params) {
+            Ok(params) => handler(peer, params)
+                .map(|item| item.and_then(|value| serde_json::to_value(value).map_err(|e| RpcError::failed(e.to_string()))))
+                .boxed(),
+            Err(e) => stream::once(future::ready(Err(RpcError::invalid_params(&name, e)))).boxed()
+        }));
+    }
+
+    pub fn register_service(&self, service: Arc<dyn Service>) {
+        self.services.register(service);
+    }
+
+    pub fn unserve(&self, method: &str) -> bool {
+        self.services.unserve(method)
+    }
+
+    pub async fn call<P: Serialize, R: DeserializeOwned>(&self, peer: PeerId, method: &str, params: P) -> Result<R, RpcError> {
+        self.call_with(peer, method, params, Callbacks::new(), CallOptions::default()).await
+    }
+
+    pub async fn call_with_callbacks<P: Serialize, R: DeserializeOwned>(&self, peer: PeerId, method: &str, params: P, callbacks: Callbacks) -> Result<R, RpcError> {
+        self.call_with(peer, method, params, callbacks, CallOptions::default()).await
+    }
+
+    pub async fn call_with_options<P: Serialize, R: DeserializeOwned>(&self, peer: PeerId, method: &str, params: P, options: CallOptions) -> Result<R, RpcError> {
+        self.call_with(peer, method, params, Callbacks::new(), options).await
+    }
+
+    pub async fn call_with<P: Serialize, R: DeserializeOwned>(&self, peer: PeerId, method: &str, params: P, callbacks: Callbacks, options: CallOptions) -> Result<R, RpcError> {
+        let params = serde_json::to_value(params).map_err(|e| RpcError::invalid_params(method, e))?;
+        let callbacks = (!callbacks.is_empty()).then(|| self.services.attach(callbacks));
+        let result = self.command::<Value>(CommandKind::Call { peer, method: method.to_string(), params, callbacks, options }).await;
+        if let Some(call) = callbacks {
+            self.services.detach(call);
+        }
+        let value = result.map_err(|e| RpcError::from_command(method, e))?;
+        serde_json::from_value::<R>(value).map_err(|e| RpcError::new(RpcErrorKind::Failed, method, e.to_string()))
+    }
+
+    pub async fn call_any<P: Serialize, R: DeserializeOwned>(&self, service: &str, method: &str, params: P) -> Result<R, RpcError> {
+        self.call_any_with(service, method, params, Balance::default(), CallOptions::default()).await
+    }
+
+    pub async fn call_any_with<P: Serialize, R: DeserializeOwned>(&self, service: &str, method: &str, params: P, balance: Balance, options: CallOptions) -> Result<R, RpcError> {
+        let params = serde_json::to_value(params).map_err(|e| RpcError::invalid_params(method, e))?;
+        let value = self
+            .command::<Value>(CommandKind::CallAny { service: service.to_string(), method: method.to_string(), params, balance, options })
+            .await
+            .map_err(|e| RpcError::from_command(method, e))?;
+        serde_json::from_value::<R>(value).map_err(|e| RpcError::new(RpcErrorKind::Failed, method, e.to_string()))
+    }
+
+    pub async fn call_streaming<P: Serialize, R: DeserializeOwned>(&self, peer: PeerId, method: &str, params: P) -> Result<RpcStream<R>, RpcError> {
+        let params = serde_json::to_value(params).map_err(|e| RpcError::invalid_params(method, e))?;
+        let (call, stream) = self.services.open_stream::<R>(method);
+        let opened = self.command::<()>(CommandKind::CallStreaming { peer, method: method.to_string(), params, call }).await;
+        if let Err(e) = opened {
+            self.services.close_stream(call);
+            return Err(RpcError::from_command(method, e));
+        }
+        Ok(stream)
+    }
+
+    pub async fn barrier(&self, name: &str, count: usize) -> Result<Vec<PeerId>, Box<dyn Error + Send + Sync>> {
+        self.command::<Vec<PeerId>>(CommandKind::Barrier { name: name.to_string(), count }).await
+    }
+
+    pub async fn propose<T: Serialize + DeserializeOwned>(&self, key: &str, value: T, quorum: usize, timeout: Duration) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let value = serde_json::to_value(value)?;
+        self.command::<T>(CommandKind::Propose { key: key.to_string(), value, quorum, timeout }).await
+    }
+
+    pub async fn accept<T: Serialize + DeserializeOwned>(&self, key: &str) -> Result<T, Box<dyn Error + Send + Sync>> {
+        self.command::<T>(CommandKind::AwaitDecision(key.to_string())).await
+    }
+
+    fn work_queues(&self) -> Result<&Queues, Box<dyn Error + Send + Sync>> {
+        self.queues.as_ref().ok_or_else(|| "Node is not running".into())
+    }
+
+    pub fn enqueue<T: Serialize>(&self, queue: &str, payload: T) -> Result<Uuid, Box<dyn Error + Send + Sync>> {
+        self.work_queues()?.enqueue(queue, serde_json::to_value(payload)?)
+    }
+
+    pub fn queue_stats(&self, queue: &str) -> Result<QueueStats, Box<dyn Error + Send + Sync>> {
+        self.work_queues()?.stats(queue)
+    }
+
+    pub fn dead_jobs(&self, queue: &str) -> Result<Vec<Job>, Box<dyn Error + Send + Sync>> {
+        self.work_queues()?.dead(queue)
+    }
+
+    pub async fn claim_job(&self, producer: PeerId, queue: &str, lease: Duration) -> Result<Option<LeasedJob>, RpcError> {
+        self.call(producer, queue::CLAIM, ClaimRequest { queue: queue.to_string(), lease }).await
+    }
+
+    pub async fn renew_job(&self, producer: PeerId, job: &LeasedJob, lease: Duration) -> Result<bool, RpcError> {
+        self.call(producer, queue::RENEW, RenewRequest { queue: job.job.queue.clone(), job: job.job.id, lease }).await
+    }
+
+    pub async fn complete_job(&self, producer: PeerId, job: &LeasedJob) -> Result<bool, RpcError> {
+        self.call(producer, queue::COMPLETE, JobRequest { queue: job.job.queue.clone(), job: job.job.id }).await
+    }
+
+    pub async fn fail_job(&self, producer: PeerId, job: &LeasedJob) -> Result<bool, RpcError> {
+        self.call(producer, queue::FAIL, JobRequest { queue: job.job.queue.clone(), job: job.job.id }).await
+    }
+
+    pub async fn admin(&self, peer: PeerId, request: AdminRequest) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        self.command::<Value>(CommandKind::Admin { peer, request }).await
+    }
+
+    pub async fn change_group(&mut self, group: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let changed = self.command::<bool>(CommandKind::ChangeGroup(group.to_string())).await?;
+        self.group = group.to_string();
+        Ok(changed)
+    }
+
+    pub async fn leave_group(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.command::<()>(CommandKind::LeaveGroup).await
+    }
+
+    pub fn stop(&self) {
+        if let Some(commands) = &self.commands {
+            commands.close();
+        }
+    }
+
+    pub fn save(&self) -> Result<SavedNode, Box<dyn Error + Send + Sync>> {
         SavedNode::save(self)
     }
 
-    pub fn load(state: SavedNode) -> Result<Node, Box<dyn Error>> {
+    pub fn load(state: SavedNode) -> Result<Node, Box<dyn Error + Send + Sync>> {
         state.hydrate()
     }
-}
\ No newline at end of file
+
+    pub fn snapshot(&self) -> Result<NodeSnapshot, Box<dyn Error + Send + Sync>> {
+        Ok(NodeSnapshot {
+            node: self.save()?,
+            known_peers: self.peer_store().list()?,
+            routing: self.routing_table().entries()?,
+            outbox: Backlog::for_node(self.storage.clone(), &self.key).pending()?,
+            taken: Utc::now()
+        })
+    }
+
+    pub fn restore(snapshot: NodeSnapshot, storage: Arc<dyn Storage>) -> Result<Node, Box<dyn Error + Send + Sync>> {
+        snapshot.restore(storage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn hydrates_pre_series_saved_nodes() {
+        let key = Keypair::generate_ed25519();
+        let bootstrap = Keypair::generate_ed25519().public().to_peer_id();
+        let saved: SavedNode = serde_json::from_value(json!({
+            "key": key.to_protobuf_encoding().unwrap(),
+            "peers": [{
+                "id": bootstrap.to_string(),
+                "address": format!("/ip4/10.0.0.1/tcp/8000/p2p/{bootstrap}"),
+                "last_seen": null,
+                "name": null,
+                "kind": "Bootstrap"
+            }],
+            "name": "legacy",
+            "group": "modius.generic",
+            "port": 8000
+        })).unwrap();
+        assert!(saved.rooms.is_empty());
+
+        let node = saved.hydrate().unwrap();
+        assert_eq!(node.peer_id(), key.public().to_peer_id());
+        assert_eq!(node.name, "legacy");
+        assert_eq!(node.port, 8000);
+        assert_eq!(node.peers.len(), 1);
+        assert_eq!(node.device_id, saved.device_id);
+    }
+
+    #[test]
+    fn hydrate_completes_bootstrap_addresses_without_a_peer_id() {
+        let key = Keypair::generate_ed25519();
+        let bootstrap = Keypair::generate_ed25519().public().to_peer_id();
+        let saved = SavedNode {
+            key: key.to_protobuf_encoding().unwrap(),
+            peers: vec![Peer::new(util::PeerType::Bootstrap, bootstrap, Multiaddr::from_str("/ip4/10.0.0.2/tcp/8000").unwrap())],
+            name: String::from("legacy"),
+            group: String::from("modius.generic"),
+            port: 8000,
+            rooms: vec![String::from("lobby")],
+            device_id: Uuid::new_v4()
+        };
+
+        let node = saved.hydrate().unwrap();
+        assert_eq!(node.peers[0].address, Multiaddr::from_str(&format!("/ip4/10.0.0.2/tcp/8000/p2p/{bootstrap}")).unwrap());
+        assert_eq!(node.rooms.names(), vec![String::from("lobby")]);
+    }
+
+    #[test]
+    fn hydrate_reports_invalid_fields() {
+        let key = Keypair::generate_ed25519();
+        let bootstrap = Keypair::generate_ed25519().public().to_peer_id();
+        let stranger = Keypair::generate_ed25519().public().to_peer_id();
+        let peer = |address: String| Peer::new(util::PeerType::Bootstrap, bootstrap, Multiaddr::from_str(&address).unwrap());
+        let mismatched = format!("/ip4/10.0.0.1/tcp/8000/p2p/{stranger}");
+        let saved = SavedNode {
+            key: key.to_protobuf_encoding().unwrap(),
+            peers: vec![peer(mismatched.clone()), peer(format!("/ip4/10.0.0.3/tcp/8000/p2p/{bootstrap}"))],
+            name: String::from("legacy"),
+            group: String::from("my group!"),
+            port: 70000,
+            rooms: Vec::new(),
+            device_id: Uuid::new_v4()
+        };
+
+        let error = saved.hydrate().unwrap_err();
+        assert_eq!(error.downcast_ref::<NodeBuilderError>(), Some(&NodeBuilderError::Invalid(vec![
+            ValidationIssue::InvalidPort(70000),
+            ValidationIssue::InvalidGroup(String::from("my group!")),
+            ValidationIssue::MismatchedPeerId { peer: bootstrap, address: Multiaddr::from_str(&mismatched).unwrap() },
+            ValidationIssue::DuplicatePeer(bootstrap),
+        ])));
+    }
+
+    #[test]
+    fn snapshot_restores_routing_rooms_into_the_given_storage() {
+        let node = NodeBuilder::default().build().unwrap();
+        node.rooms.open("lobby");
+        let peer = Keypair::generate_ed25519().public().to_peer_id();
+        let entry = RoutingEntry { peer, addresses: vec![Multiaddr::from_str("/ip4/10.0.0.1/tcp/4001").unwrap()] };
+        node.routing_table().record(&entry).unwrap();
+
+        let snapshot = node.snapshot().unwrap();
+        assert_eq!(snapshot.routing, vec![entry.clone()]);
+        assert!(snapshot.outbox.is_empty());
+
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let restored = Node::restore(snapshot, storage.clone()).unwrap();
+        assert!(Arc::ptr_eq(&restored.storage, &storage));
+        assert_eq!(restored.peer_id(), node.peer_id());
+        assert_eq!(restored.rooms.names(), vec![String::from("lobby")]);
+        assert_eq!(restored.routing_table().entries().unwrap(), vec![entry]);
+    }
+}
@@ -0,0 +1,92 @@
+use std::{collections::HashSet, error::Error, sync::Arc};
+
+use async_channel::{Receiver, Sender};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    runtime::{Executor, Runtime},
+    storage::Storage,
+};
+
+use super::event::Event;
+
+const PENDING_EVENTS: &str = "pending_events";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct PendingEvent {
+    id: Uuid,
+    event: Event,
+    queued: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug)]
+pub struct AckQueue {
+    storage: Arc<dyn Storage>,
+    classes: HashSet<String>,
+}
+
+impl AckQueue {
+    pub fn new(storage: Arc<dyn Storage>, classes: &[String]) -> Self {
+        AckQueue {
+            storage,
+            classes: classes.iter().cloned().collect(),
+        }
+    }
+
+    pub fn ack(&self, id: Uuid) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.storage.delete(PENDING_EVENTS, id.as_bytes())
+    }
+
+    pub fn pending(&self) -> Result<Vec<Event>, Box<dyn Error + Send + Sync>> {
+        let mut pending = self.storage.values::<PendingEvent>(PENDING_EVENTS)?;
+        pending.sort_by_key(|pending| pending.queued);
+        Ok(pending
+            .into_iter()
+            .map(|pending| Event::Critical {
+                id: pending.id,
+                event: Box::new(pending.event),
+            })
+            .collect())
+    }
+
+    fn hold(&self, event: Event) -> Event {
+        if !self.classes.contains(event.name()) {
+            return event;
+        }
+
+        let id = Uuid::new_v4();
+        let pending = PendingEvent {
+            id,
+            event,
+            queued: Utc::now(),
+        };
+        match self
+            .storage
+            .put_value(PENDING_EVENTS, id.as_bytes(), &pending)
+        {
+            Ok(()) => Event::Critical {
+                id,
+                event: Box::new(pending.event),
+            },
+            Err(_) => pending.event,
+        }
+    }
+
+    pub fn spawn(self, events: Receiver<Event>, forward: Sender<Event>) {
+        Runtime::spawn(async move {
+            for event in self.pending().unwrap_or_default() {
+                if forward.send(event).await.is_err() {
+                    return;
+                }
+            }
+            while let Ok(event) = events.recv().await {
+                if forward.send(self.hold(event)).await.is_err() {
+                    break;
+                }
+            }
+            forward.close();
+        });
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use async_channel::{Receiver, Sender};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use crate::peers::PeerStore;
+
+const WILDCARD: &str = "*";
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PeerMatcher {
+    Any,
+    Peer(PeerId),
+    Tag(String),
+    Group(String),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum Permission {
+    Protocol(String),
+    MessageType(String),
+    Command(String),
+}
+
+impl Permission {
+    fn covers(&self, requested: &Permission) -> bool {
+        let matches = |rule: &str, requested: &str| rule == WILDCARD || rule == requested;
+        match (self, requested) {
+            (Permission::Protocol(rule), Permission::Protocol(requested))
+            | (Permission::MessageType(rule), Permission::MessageType(requested))
+            | (Permission::Command(rule), Permission::Command(requested)) => {
+                matches(rule, requested)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AclAction {
+    #[default]
+    Allow,
+    Deny,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AclRule {
+    pub peers: PeerMatcher,
+    pub permission: Permission,
+    pub action: AclAction,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Acl {
+    #[serde(default)]
+    pub default: AclAction,
+    #[serde(default)]
+    pub rules: Vec<AclRule>,
+    #[serde(default)]
+    pub max_violations: Option<u32>,
+}
+
+impl Acl {
+    pub fn deny_by_default() -> Self {
+        Acl {
+            default: AclAction::Deny,
+            ..Acl::default()
+        }
+    }
+
+    pub fn allow(mut self, peers: PeerMatcher, permission: Permission) -> Self {
+        self.rules.push(AclRule {
+            peers,
+            permission,
+            action: AclAction::Allow,
+        });
+        self
+    }
+
+    pub fn deny(mut self, peers: PeerMatcher, permission: Permission) -> Self {
+        self.rules.push(AclRule {
+            peers,
+            permission,
+            action: AclAction::Deny,
+        });
+        self
+    }
+
+    pub fn max_violations(mut self, limit: u32) -> Self {
+        self.max_violations = Some(limit);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Violation {
+    pub peer: PeerId,
+    pub permission: Permission,
+}
+
+#[derive(Clone)]
+pub struct AccessControl {
+    acl: Arc<Acl>,
+    peers: PeerStore,
+    violations: Sender<Violation>,
+}
+
+impl AccessControl {
+    pub fn new(acl: Acl, peers: PeerStore) -> (Self, Receiver<Violation>) {
+        let (violations, receiver) = async_channel::unbounded::<Violation>();
+        (
+            AccessControl {
+                acl: Arc::new(acl),
+                peers,
+                violations,
+            },
+            receiver,
+        )
+    }
+
+    pub fn max_violations(&self) -> Option<u32> {
+        self.acl.max_violations
+    }
+
+    fn matches(&self, matcher: &PeerMatcher, peer: &PeerId) -> bool {
+        match matcher {
+            PeerMatcher::Any => true,
+            PeerMatcher::Peer(id) => id == peer,
+            PeerMatcher::Tag(tag) => self
+                .peers
+                .get(peer)
+                .ok()
+                .flatten()
+                .is_some_and(|known| known.has_tag(tag)),
+            PeerMatcher::Group(group) => self
+                .peers
+                .get(peer)
+                .ok()
+                .flatten()
+                .is_some_and(|known| known.group.as_ref() == Some(group)),
+        }
+    }
+
+    pub fn allows(&self, peer: &PeerId, permission: &Permission) -> bool {
+        let action = self
+            .acl
+            .rules
+            .iter()
+            .find(|rule| rule.permission.covers(permission) && self.matches(&rule.peers, peer))
+            .map(|rule| rule.action)
+            .unwrap_or(match permission {
+                Permission::Command(_) => AclAction::Deny,
+                _ => self.acl.default,
+            });
+        action == AclAction::Allow
+    }
+
+    pub fn check(&self, peer: PeerId, permission: Permission) -> bool {
+        if self.allows(&peer, &permission) {
+            return true;
+        }
+
+        let _ = self.violations.try_send(Violation { peer, permission });
+        false
+    }
+}
@@ -0,0 +1,164 @@
+use std::{io, time::Duration};
+
+use async_channel::Sender;
+use chrono::{DateTime, TimeDelta, Utc};
+use libp2p::{
+    futures::AsyncWriteExt,
+    identity::{Keypair, PublicKey},
+    PeerId, Stream, StreamProtocol,
+};
+use libp2p_stream::Control;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    peers::{PeerFilter, PrunePolicy},
+    runtime::{Executor, Runtime},
+};
+
+use super::{
+    command::CommandWrapper,
+    wire::{read_frame, write_frame, Frame},
+};
+
+pub const ADMIN_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/admin/1.0.0");
+const MAX_SKEW: TimeDelta = TimeDelta::seconds(60);
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Reconfiguration {
+    pub prune: Option<PrunePolicy>,
+    pub min_reservations: Option<usize>,
+    pub group_key_rotation: Option<Duration>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum AdminRequest {
+    Metrics,
+    Peers(PeerFilter),
+    Reconfigure(Reconfiguration),
+}
+
+impl AdminRequest {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AdminRequest::Metrics => "metrics",
+            AdminRequest::Peers(_) => "peers",
+            AdminRequest::Reconfigure(_) => "reconfigure",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdminMetrics {
+    pub connected: usize,
+    pub known: usize,
+    pub reservations: usize,
+    pub rooms: Vec<String>,
+    pub dead_letters: usize,
+    pub group_key_epoch: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdminCommand {
+    pub request: AdminRequest,
+    pub at: DateTime<Utc>,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+fn message(request: &AdminRequest, at: &DateTime<Utc>) -> Option<Vec<u8>> {
+    let mut message = serde_json::to_vec(request).ok()?;
+    message.extend_from_slice(format!(":{}", at.timestamp_micros()).as_bytes());
+    Some(message)
+}
+
+impl AdminCommand {
+    pub fn sign(key: &Keypair, request: AdminRequest) -> Option<Self> {
+        let at = Utc::now();
+        let signature = key.sign(&message(&request, &at)?).ok()?;
+        Some(AdminCommand {
+            request,
+            at,
+            public_key: key.public().encode_protobuf(),
+            signature,
+        })
+    }
+
+    pub fn verify(&self, peer: &PeerId) -> bool {
+        let Ok(key) = PublicKey::try_decode_protobuf(&self.public_key) else {
+            return false;
+        };
+        let Some(message) = message(&self.request, &self.at) else {
+            return false;
+        };
+
+        key.to_peer_id() == *peer
+            && (Utc::now() - self.at).abs() <= MAX_SKEW
+            && key.verify(&message, &self.signature)
+    }
+}
+
+pub type AdminReply = Result<Value, String>;
+
+pub struct AdminCall {
+    pub peer: PeerId,
+    pub request: AdminRequest,
+    pub reply: Sender<AdminReply>,
+}
+
+pub fn call(command: CommandWrapper, mut control: Control, peer: PeerId, signed: AdminCommand) {
+    Runtime::spawn(async move {
+        let result = async {
+            let mut stream = control
+                .open_stream(peer, ADMIN_PROTOCOL)
+                .await
+                .map_err(|e| io::Error::new(io::ErrorKind::ConnectionRefused, e))?;
+            write_frame(&mut stream, &Frame::AdminCommand(signed)).await?;
+            match read_frame(&mut stream).await? {
+                Frame::AdminReply(reply) => Ok(reply),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unexpected admin reply",
+                )),
+            }
+        }
+        .await;
+
+        let _ = match result {
+            Ok(Ok(value)) => command.reply(value).await,
+            Ok(Err(e)) => command.respond::<Value, _>(Err(e)).await,
+            Err(e) => command.respond::<Value, _>(Err(e)).await,
+        };
+    });
+}
+
+pub fn receive(peer: PeerId, mut stream: Stream, calls: Sender<AdminCall>) {
+    Runtime::spawn(async move {
+        let reply = match read_frame(&mut stream).await {
+            Ok(Frame::AdminCommand(signed)) if signed.verify(&peer) => {
+                let (reply, replied) = async_channel::bounded::<AdminReply>(1);
+                let call = AdminCall {
+                    peer,
+                    request: signed.request,
+                    reply,
+                };
+                match calls.send(call).await {
+                    Ok(()) => replied
+                        .recv()
+                        .await
+                        .unwrap_or_else(|_| Err(String::from("Node is shutting down"))),
+                    Err(_) => Err(String::from("Node is shutting down")),
+                }
+            }
+            Ok(Frame::AdminCommand(_)) => Err(String::from("Invalid admin signature")),
+            _ => return,
+        };
+
+        if write_frame(&mut stream, &Frame::AdminReply(reply))
+            .await
+            .is_ok()
+        {
+            let _ = stream.close().await;
+        }
+    });
+}
@@ -0,0 +1,173 @@
+use std::{error::Error, sync::Arc, time::Duration};
+
+use async_channel::{Receiver, Sender};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    runtime::{Executor, Runtime},
+    storage::Storage,
+};
+
+use super::event::Event;
+
+const AUDIT_LOG: &str = "audit_log";
+const PRUNE_EVERY: u64 = 64;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditConfig {
+    pub max_entries: usize,
+    pub max_age: Duration,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            max_entries: 10_000,
+            max_age: Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    pub event: Event,
+}
+
+#[derive(Debug)]
+pub struct AuditLog {
+    storage: Arc<dyn Storage>,
+    config: AuditConfig,
+    last: u64,
+    written: u64,
+}
+
+fn stamp(key: &[u8]) -> u64 {
+    key.try_into().map(u64::from_be_bytes).unwrap_or_default()
+}
+
+impl AuditLog {
+    pub fn new(storage: Arc<dyn Storage>, config: AuditConfig) -> Self {
+        AuditLog {
+            storage,
+            config,
+            last: 0,
+            written: 0,
+        }
+    }
+
+    pub fn entries(
+        storage: &Arc<dyn Storage>,
+    ) -> Result<Vec<AuditEntry>, Box<dyn Error + Send + Sync>> {
+        let mut entries = storage.values::<AuditEntry>(AUDIT_LOG)?;
+        entries.sort_by_key(|entry| entry.timestamp);
+        Ok(entries)
+    }
+
+    pub fn write(&mut self, event: &Event) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let timestamp = Utc::now();
+        self.last = (self.last + 1).max(timestamp.timestamp_micros() as u64);
+        self.storage.put_value(
+            AUDIT_LOG,
+            &self.last.to_be_bytes(),
+            &AuditEntry {
+                timestamp,
+                event: event.clone(),
+            },
+        )?;
+
+        self.written += 1;
+        if self.written.is_multiple_of(PRUNE_EVERY) {
+            self.prune()?;
+        }
+        Ok(())
+    }
+
+    pub fn prune(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut keys: Vec<Vec<u8>> = self
+            .storage
+            .iterate(AUDIT_LOG)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        keys.sort();
+
+        let cutoff = Utc::now().timestamp_micros() as u64;
+        let cutoff = cutoff.saturating_sub(self.config.max_age.as_micros() as u64);
+        let excess = keys.len().saturating_sub(self.config.max_entries);
+        for (index, key) in keys.iter().enumerate() {
+            if index >= excess && stamp(key) >= cutoff {
+                break;
+            }
+            self.storage.delete(AUDIT_LOG, key)?;
+        }
+        Ok(())
+    }
+
+    pub fn spawn(mut self, events: Receiver<Event>, forward: Sender<Event>) {
+        Runtime::spawn(async move {
+            while let Ok(event) = events.recv().await {
+                let _ = self.write(&event);
+                if forward.send(event).await.is_err() {
+                    break;
+                }
+            }
+            forward.close();
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[test]
+    fn prunes_beyond_max_entries() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let mut log = AuditLog::new(
+            storage.clone(),
+            AuditConfig {
+                max_entries: 3,
+                ..AuditConfig::default()
+            },
+        );
+        for _ in 0..5 {
+            log.write(&Event::OutboundOnly).unwrap();
+        }
+        assert_eq!(AuditLog::entries(&storage).unwrap().len(), 5);
+
+        log.prune().unwrap();
+        assert_eq!(AuditLog::entries(&storage).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn prunes_expired_entries() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let old = (Utc::now().timestamp_micros() as u64 - 10_000_000).to_be_bytes();
+        storage
+            .put_value(
+                AUDIT_LOG,
+                &old,
+                &AuditEntry {
+                    timestamp: Utc::now(),
+                    event: Event::OutboundOnly,
+                },
+            )
+            .unwrap();
+        let mut log = AuditLog::new(
+            storage.clone(),
+            AuditConfig {
+                max_age: Duration::from_secs(5),
+                ..AuditConfig::default()
+            },
+        );
+        log.write(&Event::OutboundOnly).unwrap();
+
+        log.prune().unwrap();
+        assert_eq!(AuditLog::entries(&storage).unwrap().len(), 1);
+        assert!(storage.get(AUDIT_LOG, &old).unwrap().is_none());
+    }
+}
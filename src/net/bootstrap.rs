@@ -0,0 +1,180 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use super::command::CommandWrapper;
+
+const MAX_BACKOFF: i64 = 60;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BootstrapStage {
+    Dialing,
+    Connected,
+    Registered,
+    DhtBootstrapped,
+    Ready,
+    Failed,
+}
+
+#[derive(Default)]
+struct Retry {
+    attempts: u32,
+    due: Option<DateTime<Utc>>,
+}
+
+#[derive(Default)]
+pub struct Bootstrap {
+    peers: HashMap<PeerId, Retry>,
+    connected: bool,
+    registering: HashSet<PeerId>,
+    registered: bool,
+    dht: Option<bool>,
+    finished: Option<BootstrapStage>,
+    waiters: Vec<CommandWrapper>,
+}
+
+impl Bootstrap {
+    pub fn new(peers: Vec<PeerId>) -> Self {
+        Bootstrap {
+            peers: peers
+                .into_iter()
+                .map(|peer| (peer, Retry::default()))
+                .collect(),
+            ..Bootstrap::default()
+        }
+    }
+
+    pub fn start(&mut self) -> Vec<BootstrapStage> {
+        if self.peers.is_empty() {
+            self.finished = Some(BootstrapStage::Ready);
+            return vec![BootstrapStage::Ready];
+        }
+
+        vec![BootstrapStage::Dialing]
+    }
+
+    pub fn is_bootstrap(&self, peer: &PeerId) -> bool {
+        self.peers.contains_key(peer)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.finished == Some(BootstrapStage::Ready)
+    }
+
+    pub fn on_connected(&mut self, peer: &PeerId) -> Option<BootstrapStage> {
+        if self.connected || !self.is_bootstrap(peer) {
+            return None;
+        }
+
+        self.connected = true;
+        Some(BootstrapStage::Connected)
+    }
+
+    pub fn on_dial_failed(&mut self, peer: &PeerId) {
+        if self.connected {
+            return;
+        }
+
+        if let Some(retry) = self.peers.get_mut(peer) {
+            let backoff = 2i64.saturating_pow(retry.attempts).min(MAX_BACKOFF);
+            retry.attempts += 1;
+            retry.due = Some(Utc::now() + TimeDelta::seconds(backoff));
+        }
+    }
+
+    pub fn due(&mut self) -> Vec<PeerId> {
+        if self.connected {
+            return Vec::new();
+        }
+
+        let now = Utc::now();
+        self.peers
+            .iter_mut()
+            .filter(|(_, retry)| retry.due.is_some_and(|due| due <= now))
+            .map(|(peer, retry)| {
+                retry.due = None;
+                *peer
+            })
+            .collect()
+    }
+
+    pub fn joining(&mut self, registering: HashSet<PeerId>, dht: bool) -> Vec<BootstrapStage> {
+        self.registering = registering;
+        if !dht {
+            self.dht = Some(false);
+        }
+        self.check()
+    }
+
+    pub fn on_registered(&mut self, server: &PeerId) -> Vec<BootstrapStage> {
+        self.registering.remove(server);
+        if self.registered {
+            return self.check();
+        }
+
+        self.registered = true;
+        let mut stages = vec![BootstrapStage::Registered];
+        stages.extend(self.check());
+        stages
+    }
+
+    pub fn on_register_failed(&mut self, server: &PeerId) -> Vec<BootstrapStage> {
+        self.registering.remove(server);
+        self.check()
+    }
+
+    pub fn on_dht(&mut self, success: bool) -> Vec<BootstrapStage> {
+        if self.dht.is_some() {
+            return Vec::new();
+        }
+
+        self.dht = Some(success);
+        let mut stages = Vec::new();
+        if success {
+            stages.push(BootstrapStage::DhtBootstrapped);
+        }
+        stages.extend(self.check());
+        stages
+    }
+
+    fn check(&mut self) -> Vec<BootstrapStage> {
+        if self.is_ready() {
+            return Vec::new();
+        }
+
+        if self.registered || self.dht == Some(true) {
+            self.finished = Some(BootstrapStage::Ready);
+            return vec![BootstrapStage::Ready];
+        }
+
+        if self.finished.is_none()
+            && self.connected
+            && self.registering.is_empty()
+            && self.dht == Some(false)
+        {
+            self.finished = Some(BootstrapStage::Failed);
+            return vec![BootstrapStage::Failed];
+        }
+
+        Vec::new()
+    }
+
+    pub fn wait(&mut self, command: CommandWrapper) -> Option<CommandWrapper> {
+        match self.finished {
+            Some(_) => Some(command),
+            None => {
+                self.waiters.push(command);
+                None
+            }
+        }
+    }
+
+    pub fn take_waiters(&mut self) -> Vec<CommandWrapper> {
+        match self.finished {
+            Some(_) => self.waiters.drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+}
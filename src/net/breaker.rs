@@ -0,0 +1,135 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_channel::Sender;
+use chrono::{DateTime, TimeDelta, Utc};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use super::event::Event;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BreakerConfig {
+    pub threshold: u32,
+    pub cooldown: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        BreakerConfig {
+            threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+fn after(now: DateTime<Utc>, cooldown: TimeDelta) -> DateTime<Utc> {
+    now.checked_add_signed(cooldown)
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+#[derive(Default)]
+struct Circuit {
+    failures: u32,
+    open_until: Option<DateTime<Utc>>,
+    probe_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Default)]
+pub struct CircuitBreaker {
+    config: Option<(u32, TimeDelta)>,
+    circuits: Arc<Mutex<HashMap<PeerId, Circuit>>>,
+    events: Option<Sender<Event>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: Option<BreakerConfig>, events: Sender<Event>) -> Self {
+        CircuitBreaker {
+            config: config.map(|config| {
+                (
+                    config.threshold.max(1),
+                    TimeDelta::from_std(config.cooldown).unwrap_or(TimeDelta::MAX),
+                )
+            }),
+            circuits: Arc::new(Mutex::new(HashMap::new())),
+            events: Some(events),
+        }
+    }
+
+    fn emit(&self, event: Event) {
+        if let Some(events) = &self.events {
+            let _ = events.try_send(event);
+        }
+    }
+
+    pub fn is_open(&self, peer: &PeerId) -> bool {
+        let circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+        circuits
+            .get(peer)
+            .and_then(|circuit| circuit.open_until)
+            .is_some_and(|until| Utc::now() < until)
+    }
+
+    pub fn allow(&self, peer: &PeerId) -> bool {
+        let Some((_, cooldown)) = self.config else {
+            return true;
+        };
+
+        let mut circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(circuit) = circuits.get_mut(peer) else {
+            return true;
+        };
+        let Some(open_until) = circuit.open_until else {
+            return true;
+        };
+
+        let now = Utc::now();
+        if now < open_until || circuit.probe_until.is_some_and(|until| now < until) {
+            return false;
+        }
+
+        circuit.probe_until = Some(after(now, cooldown));
+        true
+    }
+
+    pub fn success(&self, peer: &PeerId) {
+        if self.config.is_none() {
+            return;
+        }
+
+        let closed = self
+            .circuits
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(peer)
+            .is_some_and(|circuit| circuit.open_until.is_some());
+        if closed {
+            self.emit(Event::CircuitClosed { peer: *peer });
+        }
+    }
+
+    pub fn failure(&self, peer: &PeerId) {
+        let Some((threshold, cooldown)) = self.config else {
+            return;
+        };
+
+        let opened = {
+            let mut circuits = self.circuits.lock().unwrap_or_else(|e| e.into_inner());
+            let circuit = circuits.entry(*peer).or_default();
+            circuit.failures += 1;
+            let probing = circuit.probe_until.take().is_some();
+            if probing || (circuit.open_until.is_none() && circuit.failures >= threshold) {
+                circuit.open_until = Some(after(Utc::now(), cooldown));
+                true
+            } else {
+                false
+            }
+        };
+        if opened {
+            self.emit(Event::CircuitOpened { peer: *peer });
+        }
+    }
+}
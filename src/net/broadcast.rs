@@ -0,0 +1,77 @@
+use std::time::Duration;
+
+use async_channel::Receiver;
+use libp2p::{
+    futures::{select_biased, FutureExt},
+    PeerId,
+};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::{sleep, Executor, Runtime};
+
+use super::{command::CommandWrapper, session::Receipt};
+
+const BROADCAST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum FanOut {
+    All,
+    Sample(usize),
+    Gossip,
+}
+
+impl FanOut {
+    pub fn select(&self, mut members: Vec<PeerId>) -> Vec<PeerId> {
+        let count = match self {
+            FanOut::All => return members,
+            FanOut::Sample(n) => *n,
+            FanOut::Gossip => (members.len() as f64).sqrt().ceil() as usize,
+        };
+
+        members.shuffle(&mut rand::thread_rng());
+        members.truncate(count);
+        members
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Audience {
+    #[default]
+    Group,
+    Set(String),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BroadcastResult {
+    pub peer: PeerId,
+    pub receipt: Receipt,
+    pub delivered: bool,
+}
+
+pub fn collect(command: CommandWrapper, pending: Vec<(PeerId, Receipt, Receiver<()>)>) {
+    Runtime::spawn(async move {
+        let mut results = Vec::with_capacity(pending.len());
+        let mut deadline = Box::pin(sleep(BROADCAST_TIMEOUT).fuse());
+        let mut expired = false;
+        for (peer, receipt, acked) in pending {
+            let delivered = match expired {
+                true => acked.try_recv().is_ok(),
+                false => select_biased! {
+                    result = acked.recv().fuse() => result.is_ok(),
+                    _ = deadline => {
+                        expired = true;
+                        false
+                    }
+                },
+            };
+            results.push(BroadcastResult {
+                peer,
+                receipt,
+                delivered,
+            });
+        }
+
+        let _ = command.reply(results).await;
+    });
+}
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+#[allow(deprecated)]
+use libp2p::bandwidth::BandwidthSinks;
+use serde::{Deserialize, Serialize};
+
+use super::command::CommandKind;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BudgetPeriod {
+    Hourly,
+    Daily,
+}
+
+impl BudgetPeriod {
+    fn length(&self) -> TimeDelta {
+        match self {
+            BudgetPeriod::Hourly => TimeDelta::hours(1),
+            BudgetPeriod::Daily => TimeDelta::days(1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BandwidthBudget {
+    pub limit: u64,
+    pub period: BudgetPeriod,
+    pub near_ratio: f64,
+}
+
+impl BandwidthBudget {
+    pub fn hourly(limit: u64) -> Self {
+        BandwidthBudget {
+            limit,
+            period: BudgetPeriod::Hourly,
+            near_ratio: 0.8,
+        }
+    }
+
+    pub fn daily(limit: u64) -> Self {
+        BandwidthBudget {
+            limit,
+            period: BudgetPeriod::Daily,
+            near_ratio: 0.8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BudgetLevel {
+    Normal,
+    Near,
+    Exceeded,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BudgetUsage {
+    pub used: u64,
+    pub limit: u64,
+    pub level: BudgetLevel,
+    pub resets_at: DateTime<Utc>,
+}
+
+pub struct BudgetMeter {
+    budget: BandwidthBudget,
+    #[allow(deprecated)]
+    sinks: Arc<BandwidthSinks>,
+    period_start: DateTime<Utc>,
+    baseline: u64,
+    level: BudgetLevel,
+}
+
+impl BudgetMeter {
+    #[allow(deprecated)]
+    pub fn new(budget: BandwidthBudget, sinks: Arc<BandwidthSinks>) -> Self {
+        let mut meter = BudgetMeter {
+            budget,
+            sinks,
+            period_start: Utc::now(),
+            baseline: 0,
+            level: BudgetLevel::Normal,
+        };
+        meter.reset(Utc::now());
+        meter
+    }
+
+    fn total(&self) -> u64 {
+        self.sinks.total_inbound() + self.sinks.total_outbound()
+    }
+
+    fn reset(&mut self, now: DateTime<Utc>) {
+        let length = self.budget.period.length();
+        self.period_start = now.duration_trunc(length).unwrap_or(now);
+        self.baseline = self.total();
+    }
+
+    fn used(&self) -> u64 {
+        self.total().saturating_sub(self.baseline)
+    }
+
+    pub fn update(&mut self) -> Option<BudgetLevel> {
+        let now = Utc::now();
+        if now >= self.period_start + self.budget.period.length() {
+            self.reset(now);
+        }
+
+        let used = self.used();
+        let level = if used >= self.budget.limit {
+            BudgetLevel::Exceeded
+        } else if used as f64 >= self.budget.limit as f64 * self.budget.near_ratio {
+            BudgetLevel::Near
+        } else {
+            BudgetLevel::Normal
+        };
+        if level == self.level {
+            return None;
+        }
+
+        self.level = level;
+        Some(level)
+    }
+
+    pub fn level(&self) -> BudgetLevel {
+        self.level
+    }
+
+    pub fn usage(&self) -> BudgetUsage {
+        BudgetUsage {
+            used: self.used(),
+            limit: self.budget.limit,
+            level: self.level,
+            resets_at: self.period_start + self.budget.period.length(),
+        }
+    }
+
+    pub fn denies(&self, command: &CommandKind) -> Option<&'static str> {
+        match (self.level, command) {
+            (BudgetLevel::Exceeded, CommandKind::Send { .. } | CommandKind::ChannelSend { .. }) => {
+                Some("Bandwidth budget exceeded")
+            }
+            (
+                BudgetLevel::Near | BudgetLevel::Exceeded,
+                CommandKind::RoomSend { .. }
+                | CommandKind::Broadcast { .. }
+                | CommandKind::Replicate(_)
+                | CommandKind::RestoreBlob(_)
+                | CommandKind::Sync { .. }
+                | CommandKind::CatchUpFeed(_)
+                | CommandKind::ProbeThroughput { .. },
+            ) => Some("Bandwidth budget nearly exhausted, bulk transfers are deferred"),
+            _ => None,
+        }
+    }
+}
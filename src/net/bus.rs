@@ -0,0 +1,124 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use async_channel::{Receiver, Sender};
+
+use crate::runtime::{Executor, Runtime};
+
+use super::event::Event;
+
+#[derive(Debug)]
+struct Subscriber {
+    sender: Sender<Event>,
+    lagged: Arc<AtomicU64>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EventBus {
+    capacity: usize,
+    subscribers: Arc<Mutex<Option<Vec<Subscriber>>>>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        EventBus {
+            capacity: capacity.max(1),
+            subscribers: Arc::new(Mutex::new(Some(Vec::new()))),
+        }
+    }
+
+    pub fn subscribe(&self) -> Subscription {
+        let (sender, receiver) = async_channel::bounded::<Event>(self.capacity);
+        let lagged = Arc::new(AtomicU64::new(0));
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        match subscribers.as_mut() {
+            Some(subscribers) => subscribers.push(Subscriber {
+                sender,
+                lagged: lagged.clone(),
+            }),
+            None => drop(sender),
+        }
+        Subscription {
+            bus: self.clone(),
+            receiver,
+            lagged,
+        }
+    }
+
+    pub fn subscribers(&self) -> usize {
+        let mut subscribers = self.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+        subscribers.as_mut().map_or(0, |subscribers| {
+            subscribers.retain(|subscriber| !subscriber.sender.is_closed());
+            subscribers.len()
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_none()
+    }
+
+    pub fn pump(&self, events: Receiver<Event>) {
+        let bus = self.clone();
+        Runtime::spawn(async move {
+            while let Ok(event) = events.recv().await {
+                let mut subscribers = bus.subscribers.lock().unwrap_or_else(|e| e.into_inner());
+                if let Some(subscribers) = subscribers.as_mut() {
+                    subscribers.retain(|subscriber| {
+                        match subscriber.sender.force_send(event.clone()) {
+                            Ok(Some(_)) => {
+                                subscriber.lagged.fetch_add(1, Ordering::Relaxed);
+                                true
+                            }
+                            Ok(None) => true,
+                            Err(_) => false,
+                        }
+                    });
+                }
+            }
+            bus.close();
+        });
+    }
+
+    pub fn close(&self) {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+    }
+}
+
+#[derive(Debug)]
+pub struct Subscription {
+    bus: EventBus,
+    receiver: Receiver<Event>,
+    lagged: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    pub async fn recv(&mut self) -> Option<Event> {
+        self.receiver.recv().await.ok()
+    }
+
+    pub fn try_recv(&mut self) -> Option<Event> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub fn pending(&self) -> usize {
+        self.receiver.len()
+    }
+
+    pub fn lagged(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
+    }
+}
+
+impl Clone for Subscription {
+    fn clone(&self) -> Self {
+        self.bus.subscribe()
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufReader, Read, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::wire::Frame;
+
+const CAPTURE_MAGIC: &[u8; 4] = b"MCAP";
+const CAPTURE_VERSION: u16 = 1;
+
+static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Redaction {
+    #[default]
+    None,
+    Payloads,
+    Full,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RecordHeader {
+    timestamp: DateTime<Utc>,
+    direction: Direction,
+    kind: String,
+    frame: Option<Value>,
+    payload_length: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct CapturedFrame {
+    pub timestamp: DateTime<Utc>,
+    pub direction: Direction,
+    pub kind: String,
+    pub frame: Option<Value>,
+    pub payload_length: usize,
+    pub payload: Option<Bytes>,
+}
+
+struct Recorder {
+    file: File,
+    redaction: Redaction,
+}
+
+pub fn start<P: AsRef<Path>>(path: P, redaction: Redaction) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(path)?;
+    file.write_all(CAPTURE_MAGIC)?;
+    file.write_all(&CAPTURE_VERSION.to_be_bytes())?;
+
+    let mut recorder = RECORDER.lock().unwrap_or_else(|e| e.into_inner());
+    *recorder = Some(Recorder { file, redaction });
+    Ok(())
+}
+
+pub fn stop() {
+    let mut recorder = RECORDER.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(mut recorder) = recorder.take() {
+        let _ = recorder.file.flush();
+    }
+}
+
+pub fn is_active() -> bool {
+    RECORDER.lock().unwrap_or_else(|e| e.into_inner()).is_some()
+}
+
+pub(crate) fn record(direction: Direction, frame: &Frame, payload: &[u8]) {
+    let mut recorder = RECORDER.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(active) = recorder.as_mut() else {
+        return;
+    };
+
+    let Ok(value) = serde_json::to_value(frame) else {
+        return;
+    };
+    let kind = match &value {
+        Value::String(kind) => kind.clone(),
+        Value::Object(fields) => fields.keys().next().cloned().unwrap_or_default(),
+        _ => String::new(),
+    };
+    let header = RecordHeader {
+        timestamp: Utc::now(),
+        direction,
+        kind,
+        frame: (active.redaction != Redaction::Full).then_some(value),
+        payload_length: payload.len(),
+    };
+    let payload = match active.redaction {
+        Redaction::None => payload,
+        Redaction::Payloads | Redaction::Full => &[],
+    };
+    let Ok(header) = serde_json::to_vec(&header) else {
+        return;
+    };
+
+    let mut record = Vec::with_capacity(8 + header.len() + payload.len());
+    record.extend_from_slice(&(header.len() as u32).to_be_bytes());
+    record.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    record.extend_from_slice(&header);
+    record.extend_from_slice(payload);
+    if active.file.write_all(&record).is_err() {
+        *recorder = None;
+    }
+}
+
+pub struct CaptureReader {
+    reader: BufReader<File>,
+}
+
+impl CaptureReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut preamble = [0u8; 6];
+        reader.read_exact(&mut preamble)?;
+        if &preamble[..4] != CAPTURE_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a modius capture file",
+            ));
+        }
+        if u16::from_be_bytes([preamble[4], preamble[5]]) != CAPTURE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unsupported capture version",
+            ));
+        }
+        Ok(CaptureReader { reader })
+    }
+
+    fn next_frame(&mut self) -> io::Result<Option<CapturedFrame>> {
+        let mut lengths = [0u8; 8];
+        match self.reader.read_exact(&mut lengths) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let header_length =
+            u32::from_be_bytes([lengths[0], lengths[1], lengths[2], lengths[3]]) as usize;
+        let payload_length =
+            u32::from_be_bytes([lengths[4], lengths[5], lengths[6], lengths[7]]) as usize;
+        let mut header = vec![0u8; header_length];
+        self.reader.read_exact(&mut header)?;
+        let mut payload = vec![0u8; payload_length];
+        self.reader.read_exact(&mut payload)?;
+
+        let header: RecordHeader = serde_json::from_slice(&header)?;
+        Ok(Some(CapturedFrame {
+            timestamp: header.timestamp,
+            direction: header.direction,
+            kind: header.kind,
+            frame: header.frame,
+            payload_length: header.payload_length,
+            payload: (payload_length > 0 || header.payload_length == 0)
+                .then(|| Bytes::from(payload)),
+        }))
+    }
+}
+
+impl Iterator for CaptureReader {
+    type Item = io::Result<CapturedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
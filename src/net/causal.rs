@@ -0,0 +1,300 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    io,
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+};
+
+use async_channel::{Receiver, Sender};
+use bytes::{BufMut, Bytes, BytesMut};
+use libp2p::{futures::AsyncWriteExt, PeerId, Stream, StreamProtocol};
+use libp2p_stream::Control;
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::{Executor, Runtime};
+
+use super::wire::{read_frame, write_frame, Frame};
+
+pub const CAUSAL_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/causal/1.0.0");
+const CAUSAL_MAGIC: &[u8; 4] = b"MVC1";
+const RETAINED_MESSAGES: usize = 1024;
+
+pub type VectorClock = BTreeMap<PeerId, u64>;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CausalHeader {
+    pub sender: PeerId,
+    pub seq: u64,
+    pub clock: VectorClock,
+}
+
+impl CausalHeader {
+    pub fn wrap(&self, payload: &[u8]) -> Option<Bytes> {
+        let header = serde_json::to_vec(self).ok()?;
+        let mut out = BytesMut::with_capacity(4 + 4 + header.len() + payload.len());
+        out.put_slice(CAUSAL_MAGIC);
+        out.put_u32(u32::try_from(header.len()).ok()?);
+        out.put_slice(&header);
+        out.put_slice(payload);
+        Some(out.freeze())
+    }
+
+    pub fn unwrap(data: Bytes) -> Result<(Option<CausalHeader>, Bytes), &'static str> {
+        if !data.starts_with(CAUSAL_MAGIC) {
+            return Ok((None, data));
+        }
+        if data.len() < 8 {
+            return Err("Causal header is truncated");
+        }
+
+        let length = u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let end = 8usize
+            .checked_add(length)
+            .filter(|end| *end <= data.len())
+            .ok_or("Causal header is truncated")?;
+        let header = serde_json::from_slice::<CausalHeader>(&data[8..end])
+            .map_err(|_| "Invalid causal header")?;
+        Ok((Some(header), data.slice(end..)))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CausalMessage {
+    pub header: CausalHeader,
+    pub data: Bytes,
+}
+
+#[derive(Clone, Debug, Default)]
+struct SenderState {
+    delivered: u64,
+    requested: u64,
+    pending: BTreeSet<u64>,
+}
+
+impl SenderState {
+    fn baseline(seq: u64) -> Self {
+        SenderState {
+            delivered: seq,
+            requested: seq,
+            pending: BTreeSet::new(),
+        }
+    }
+
+    fn missing(&mut self, upto: u64) -> Option<RangeInclusive<u64>> {
+        let start = self.delivered.max(self.requested) + 1;
+        if upto < start {
+            return None;
+        }
+
+        self.requested = upto;
+        Some(start..=upto)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct RoomClock {
+    own: u64,
+    senders: HashMap<PeerId, SenderState>,
+}
+
+pub struct Observation {
+    pub fresh: bool,
+    pub gaps: Vec<(PeerId, RangeInclusive<u64>)>,
+}
+
+type Retained = Arc<Mutex<HashMap<(String, PeerId), VecDeque<CausalMessage>>>>;
+
+#[derive(Clone)]
+pub struct Causality {
+    enabled: bool,
+    local: PeerId,
+    rooms: HashMap<String, RoomClock>,
+    retained: Retained,
+    retransmitted: Sender<(String, CausalMessage)>,
+}
+
+impl Causality {
+    pub fn new(local: PeerId, enabled: bool) -> (Self, Receiver<(String, CausalMessage)>) {
+        let (retransmitted, receiver) = async_channel::unbounded::<(String, CausalMessage)>();
+        (
+            Causality {
+                enabled,
+                local,
+                rooms: HashMap::new(),
+                retained: Arc::new(Mutex::new(HashMap::new())),
+                retransmitted,
+            },
+            receiver,
+        )
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn stamp(&mut self, room: &str) -> CausalHeader {
+        let clock = self.rooms.entry(room.to_string()).or_default();
+        clock.own += 1;
+
+        let mut vector: VectorClock = clock
+            .senders
+            .iter()
+            .map(|(peer, state)| (*peer, state.delivered))
+            .collect();
+        vector.insert(self.local, clock.own);
+        CausalHeader {
+            sender: self.local,
+            seq: clock.own,
+            clock: vector,
+        }
+    }
+
+    pub fn observe(&mut self, room: &str, header: &CausalHeader) -> Observation {
+        let clock = self.rooms.entry(room.to_string()).or_default();
+        let mut gaps = Vec::new();
+
+        let state = clock
+            .senders
+            .entry(header.sender)
+            .or_insert_with(|| SenderState::baseline(header.seq.saturating_sub(1)));
+        let fresh = header.seq > state.delivered && state.pending.insert(header.seq);
+        if fresh {
+            while state.pending.remove(&(state.delivered + 1)) {
+                state.delivered += 1;
+            }
+            if let Some(range) = state.missing(header.seq.saturating_sub(1)) {
+                gaps.push((header.sender, range));
+            }
+        }
+
+        for (peer, seen) in header.clock.iter() {
+            if *peer == self.local || *peer == header.sender {
+                continue;
+            }
+
+            let state = clock
+                .senders
+                .entry(*peer)
+                .or_insert_with(|| SenderState::baseline(*seen));
+            if let Some(range) = state.missing(*seen) {
+                gaps.push((*peer, range));
+            }
+        }
+
+        Observation { fresh, gaps }
+    }
+
+    pub fn forget(&mut self, room: &str) {
+        self.rooms.remove(room);
+        let mut retained = self.retained.lock().unwrap_or_else(|e| e.into_inner());
+        retained.retain(|(retained_room, _), _| retained_room != room);
+    }
+
+    pub fn retain(&self, room: &str, message: CausalMessage) {
+        let mut retained = self.retained.lock().unwrap_or_else(|e| e.into_inner());
+        let messages = retained
+            .entry((room.to_string(), message.header.sender))
+            .or_default();
+        messages.push_back(message);
+        while messages.len() > RETAINED_MESSAGES {
+            messages.pop_front();
+        }
+    }
+
+    fn lookup(
+        &self,
+        room: &str,
+        sender: PeerId,
+        range: &RangeInclusive<u64>,
+    ) -> Vec<CausalMessage> {
+        let retained = self.retained.lock().unwrap_or_else(|e| e.into_inner());
+        retained
+            .get(&(room.to_string(), sender))
+            .map(|messages| {
+                messages
+                    .iter()
+                    .filter(|message| range.contains(&message.header.seq))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn serve(&self, stream: Stream) {
+        let causality = self.clone();
+        Runtime::spawn(async move {
+            let _ = causality.respond(stream).await;
+        });
+    }
+
+    async fn respond(&self, mut stream: Stream) -> io::Result<()> {
+        if let Frame::CausalRequest {
+            room,
+            sender,
+            range,
+        } = read_frame(&mut stream).await?
+        {
+            let messages = self.lookup(&room, sender, &range);
+            write_frame(&mut stream, &Frame::CausalMessages(messages)).await?;
+        }
+        stream.close().await
+    }
+
+    pub fn repair(
+        &self,
+        control: Control,
+        peers: Vec<PeerId>,
+        room: String,
+        sender: PeerId,
+        range: RangeInclusive<u64>,
+    ) {
+        let retransmitted = self.retransmitted.clone();
+        Runtime::spawn(async move {
+            let mut missing: BTreeSet<u64> = range.clone().collect();
+            for peer in peers {
+                if missing.is_empty() {
+                    break;
+                }
+
+                let Ok(messages) = fetch(control.clone(), peer, &room, sender, range.clone()).await
+                else {
+                    continue;
+                };
+                for message in messages {
+                    if message.header.sender == sender && missing.remove(&message.header.seq) {
+                        let _ = retransmitted.send((room.clone(), message)).await;
+                    }
+                }
+            }
+        });
+    }
+}
+
+async fn fetch(
+    mut control: Control,
+    peer: PeerId,
+    room: &str,
+    sender: PeerId,
+    range: RangeInclusive<u64>,
+) -> io::Result<Vec<CausalMessage>> {
+    let mut stream = control
+        .open_stream(peer, CAUSAL_PROTOCOL)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::ConnectionRefused, e))?;
+    write_frame(
+        &mut stream,
+        &Frame::CausalRequest {
+            room: room.to_string(),
+            sender,
+            range,
+        },
+    )
+    .await?;
+    match read_frame(&mut stream).await? {
+        Frame::CausalMessages(messages) => Ok(messages),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unexpected causal response",
+        )),
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::runtime::{Executor, Runtime};
+use async_channel::{Receiver, Sender};
+use bytes::Bytes;
+use libp2p::PeerId;
+
+use super::{
+    codec::Codec,
+    command::{CommandKind, CommandWrapper},
+    session::Receipt,
+};
+
+pub type ChannelPair<T> = (Sender<T>, Receiver<T>);
+
+#[derive(Clone, Debug)]
+struct Slot {
+    sender: Sender<Bytes>,
+    receiver: Receiver<Bytes>,
+}
+
+impl Slot {
+    fn new() -> Self {
+        let (sender, receiver) = async_channel::unbounded::<Bytes>();
+        Slot { sender, receiver }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Channels {
+    slots: Arc<Mutex<HashMap<(PeerId, String), Slot>>>,
+}
+
+impl Channels {
+    pub fn new() -> Self {
+        Channels::default()
+    }
+
+    fn slot(&self, peer: PeerId, label: String) -> Slot {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots.entry((peer, label)).or_insert_with(Slot::new).clone()
+    }
+
+    pub fn open(&self, peer: PeerId, label: impl Into<String>) -> Receiver<Bytes> {
+        self.slot(peer, label.into()).receiver
+    }
+
+    pub fn deliver(&self, peer: PeerId, label: String, payload: Bytes) {
+        let _ = self.slot(peer, label).sender.try_send(payload);
+    }
+
+    pub fn close(&self, peer: PeerId, label: &str) {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(slot) = slots.remove(&(peer, label.to_string())) {
+            slot.sender.close();
+        }
+    }
+}
+
+pub fn bridge<C: Codec>(
+    channels: &Channels,
+    commands: Sender<CommandWrapper>,
+    peer: PeerId,
+    label: String,
+    codec: C,
+) -> ChannelPair<C::Item> {
+    let inbound = channels.open(peer, label.clone());
+    let (outgoing, pending) = async_channel::unbounded::<C::Item>();
+    let (received, incoming) = async_channel::unbounded::<C::Item>();
+
+    let encoder = codec.clone();
+    Runtime::spawn(async move {
+        while let Ok(value) = pending.recv().await {
+            let Ok(payload) = encoder.encode(&value) else {
+                continue;
+            };
+            let command = CommandKind::ChannelSend {
+                peer,
+                label: label.clone(),
+                payload,
+            };
+            if command.send::<Receipt>(commands.clone()).await.is_err() {
+                break;
+            }
+        }
+    });
+    Runtime::spawn(async move {
+        while let Ok(payload) = inbound.recv().await {
+            if let Ok(value) = codec.decode(payload) {
+                if received.send(value).await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    (outgoing, incoming)
+}
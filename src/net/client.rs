@@ -1,187 +1,2904 @@
 use std::{
+    collections::{HashMap, HashSet},
     error::Error,
-    sync::{Arc, Mutex},
+    pin::pin,
+    sync::{Arc, Mutex, MutexGuard},
     time::Duration,
 };
 
 use async_channel::{Receiver, Sender};
+use bytes::Bytes;
+use chrono::{DateTime, TimeDelta, Utc};
+#[cfg(not(target_arch = "wasm32"))]
 use libp2p::{
-    futures::StreamExt,
+    core::transport::OptionalTransport, mdns, quic, swarm::behaviour::toggle::Toggle, tcp,
+};
+use libp2p::{
+    core::{transport::ListenerId, ConnectedPoint},
+    futures::{select, select_biased, stream::SelectAll, FutureExt, StreamExt},
+    gossipsub::{self, TopicHash},
     identity::Keypair,
-    noise,
-    rendezvous::Namespace,
-    swarm::{DialError, NetworkBehaviour, SwarmEvent},
-    tcp, yamux, PeerId, Stream, StreamProtocol, Swarm, SwarmBuilder,
+    kad::RecordKey,
+    multiaddr::Protocol,
+    rendezvous::{Namespace, Registration},
+    swarm::{
+        dial_opts::{DialOpts, PeerCondition},
+        ConnectionDenied, DialError, NetworkBehaviour, SwarmEvent,
+    },
+    Multiaddr, PeerId, Stream, StreamProtocol, Swarm, SwarmBuilder, Transport,
+};
+use libp2p_stream::{Control, IncomingStreams};
+use serde_json::Value;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::runtime::{dns, Mdns, Quic, Tcp};
+use crate::{
+    addressbook,
+    blobs::BlobStore,
+    peers::{PeerStore, PrunePolicy},
+    runtime::{sleep, Executor, Runtime},
+    util::{agent_version, parse_agent_version, Peer, PeerType},
+    validate::valid_group,
+    Node,
 };
 
+#[cfg(feature = "ratchet")]
+use super::ratchet::{Ratchets, RATCHET_PROTOCOL};
+#[cfg(all(unix, feature = "tokio"))]
+use super::unix::{self, UnixTransport};
+#[cfg(all(feature = "webhooks", not(target_arch = "wasm32")))]
+use super::webhook::Webhooks;
+#[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+use super::webrtc;
 use super::{
+    ack::AckQueue,
+    acl::{AccessControl, Permission, Violation},
+    admin::{self, AdminCall, AdminCommand, AdminMetrics, AdminRequest, ADMIN_PROTOCOL},
+    audit::AuditLog,
+    bootstrap::{Bootstrap, BootstrapStage},
+    breaker::CircuitBreaker,
+    broadcast::{self, Audience},
+    budget::{BudgetLevel, BudgetMeter},
+    bus::EventBus,
+    causal::{CausalHeader, CausalMessage, Causality, CAUSAL_PROTOCOL},
+    coalesce::Coalescer,
     command::{CommandKind, CommandWrapper},
-    event::Event,
+    coordination::{self, Coordinator},
+    dead::{DeadLetters, DeadReason},
+    dial::{transport_of, Dialer},
+    dispatch::Dispatcher,
+    doctor::{Check, Doctor, Outcome},
+    event::{DisconnectReason, Event},
+    feed::{Appended, FeedEntry, Feeds, FEED_PROTOCOL},
+    groupkey::{self, GroupKey, Keyring, GROUP_KEY_PROTOCOL},
+    history::{History, HISTORY_PROTOCOL},
+    horizon::{Advertise, Identify},
+    idempotency::Idempotency,
+    ipfs::{self, IpfsDiscovery, IPFS_KAD_PROTOCOL},
+    journal::Journal,
+    lan::LanIdentity,
+    leave::{self, Departure, DEPARTURE_TIMEOUT, LEAVE_PROTOCOL},
+    listen::Listeners,
+    liveness::{LivenessGossip, Sighting},
+    lookup::LookupCache,
+    multipath::RelayPath,
+    nat::NatState,
+    privacy::Redactor,
+    probe::{self, PROBE_PROTOCOL},
+    relay::RelaySelector,
+    replicate::{Replicator, ShardStore, SHARD_PROTOCOL},
+    room::{Presence, RoomEvent, Rooms},
+    routing::{RoutingEntry, RoutingTable},
+    rpc::{self, Balancer, RpcError, RpcErrorKind, Services, RPC_PROTOCOL},
+    schedule::Scheduler,
+    session::{Backlog, Delivery, DeliveryGuarantee, Outbox, StreamReport},
+    stats::Stats,
+    sync::{Shares, SYNC_PROTOCOL},
+    tracer::CommandTracer,
+    transport,
+    update::{SignedManifest, UpdateChannel},
+    version::ProtocolVersion,
+    watchdog::{Heartbeat, WatchdogConfig},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use super::{
+    lan::LanAnnouncer,
+    log::EventLog,
+    socks::{proxy_address, Socks5Transport},
 };
 
 #[derive(NetworkBehaviour)]
 struct Behaviour {
     pub stream: libp2p_stream::Behaviour,
+    pub relay_path: RelayPath,
     pub ping: libp2p::ping::Behaviour,
-    pub mdns: libp2p::mdns::tokio::Behaviour,
-    pub upnp: libp2p::upnp::tokio::Behaviour,
-    pub identify: libp2p::identify::Behaviour,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub mdns: Toggle<Mdns>,
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    pub upnp: Toggle<libp2p::upnp::tokio::Behaviour>,
+    pub identify: Identify,
+    pub autonat: libp2p::autonat::Behaviour,
+    pub dcutr: libp2p::dcutr::Behaviour,
     pub rendezvous: libp2p::rendezvous::client::Behaviour,
     pub relay: libp2p::relay::client::Behaviour,
+    pub kad: libp2p::kad::Behaviour<libp2p::kad::store::MemoryStore>,
+    pub gossipsub: gossipsub::Behaviour,
 }
 
 enum LoopEvent {
     Command(CommandWrapper),
     Swarm(SwarmEvent<BehaviourEvent>),
     Stream(PeerId, Stream),
+    HistoryRequest(PeerId, Stream),
+    Departure(PeerId, Stream),
+    Departed(PeerId, Departure),
+    GroupKeyGrant(PeerId, Stream),
+    GroupKeyReceived(PeerId, GroupKey),
+    StreamDone(StreamReport),
+    Violation(Violation),
+    AdminStream(PeerId, Stream),
+    AdminCall(AdminCall),
+    ShardStream(PeerId, Stream),
+    SyncStream(PeerId, Stream),
+    FeedStream(PeerId, Stream),
+    CausalStream(PeerId, Stream),
+    ProbeStream(PeerId, Stream),
+    RpcStream(PeerId, Stream),
+    Retransmitted(String, CausalMessage),
+    LanIdentity(LanIdentity),
+    Tick,
+}
+
+impl LoopEvent {
+    fn label(&self) -> &'static str {
+        match self {
+            LoopEvent::Command(_) => "Command",
+            LoopEvent::Swarm(_) => "Swarm",
+            LoopEvent::Stream(..) => "Stream",
+            LoopEvent::HistoryRequest(..) => "HistoryRequest",
+            LoopEvent::Departure(..) => "Departure",
+            LoopEvent::Departed(..) => "Departed",
+            LoopEvent::GroupKeyGrant(..) => "GroupKeyGrant",
+            LoopEvent::GroupKeyReceived(..) => "GroupKeyReceived",
+            LoopEvent::StreamDone(_) => "StreamDone",
+            LoopEvent::Violation(_) => "Violation",
+            LoopEvent::AdminStream(..) => "AdminStream",
+            LoopEvent::AdminCall(_) => "AdminCall",
+            LoopEvent::ShardStream(..) => "ShardStream",
+            LoopEvent::SyncStream(..) => "SyncStream",
+            LoopEvent::FeedStream(..) => "FeedStream",
+            LoopEvent::CausalStream(..) => "CausalStream",
+            LoopEvent::ProbeStream(..) => "ProbeStream",
+            LoopEvent::RpcStream(..) => "RpcStream",
+            LoopEvent::Retransmitted(..) => "Retransmitted",
+            LoopEvent::LanIdentity(_) => "LanIdentity",
+            LoopEvent::Tick => "Tick",
+        }
+    }
 }
 
+pub type ClientParts = (Client, Sender<CommandWrapper>, EventBus);
+
 pub struct Client {
     commands: Receiver<CommandWrapper>,
     events: Sender<Event>,
-    key: Keypair,
-    name: String,
     group: String,
     port: usize,
-    swarm: Arc<Mutex<Swarm<Behaviour>>>,
+    port_fallback: bool,
+    listeners: Listeners,
+    listen: bool,
+    advertise: Advertise,
+    custom_listen: Vec<Multiaddr>,
+    peers: PeerStore,
+    routing: RoutingTable,
+    blobs: BlobStore,
+    replicator: Replicator,
+    shares: Shares,
+    services: Services,
+    advertised: HashSet<String>,
+    service_streams: SelectAll<IncomingStreams>,
+    balancer: Balancer,
+    feeds: Feeds,
+    stats: Stats,
+    breaker: CircuitBreaker,
+    heartbeat: Heartbeat,
+    tracer: CommandTracer,
+    idempotency: Idempotency,
+    watchdog: Option<WatchdogConfig>,
+    next_repair: DateTime<Utc>,
+    acl: AccessControl,
+    violations: Receiver<Violation>,
+    causality: Causality,
+    retransmitted: Receiver<(String, CausalMessage)>,
+    strikes: HashMap<PeerId, u32>,
+    nat: NatState,
+    rendezvous: HashSet<PeerId>,
+    ipfs: Option<IpfsDiscovery>,
+    paused: bool,
+    budget: Option<BudgetMeter>,
+    doctor: Option<Doctor>,
+    dialer: Dialer,
+    relays: RelaySelector,
+    scheduler: Scheduler,
+    providers: LookupCache<RecordKey, HashSet<PeerId>>,
+    registrations: LookupCache<PeerId, Vec<Registration>>,
+    journal: Journal,
+    coalescer: Coalescer,
+    bootstrap: Bootstrap,
+    outbox: Arc<Mutex<Outbox>>,
+    delivery: Delivery,
+    #[cfg(feature = "ratchet")]
+    ratchets: Ratchets,
+    reports: Receiver<StreamReport>,
+    dispatcher: Dispatcher,
+    rooms: Rooms,
+    topics: HashMap<TopicHash, String>,
+    presence: Presence,
+    history: History,
+    control: Control,
+    version: ProtocolVersion,
+    max_message: usize,
+    limits: HashMap<PeerId, usize>,
+    dead: DeadLetters,
+    key: Keypair,
+    departed: HashSet<PeerId>,
+    closing: HashMap<PeerId, DisconnectReason>,
+    prune: PrunePolicy,
+    next_prune: DateTime<Utc>,
+    auto_relay: Option<usize>,
+    next_relay_check: DateTime<Utc>,
+    keyring: Keyring,
+    updates: UpdateChannel,
+    liveness: LivenessGossip,
+    coordinator: Coordinator,
+    lan: HashMap<PeerId, LanIdentity>,
+    lan_found: Receiver<LanIdentity>,
+    #[cfg(not(target_arch = "wasm32"))]
+    lan_announcer: Option<LanAnnouncer>,
+    group_admin: bool,
+    group_admins: HashSet<PeerId>,
+    key_rotation: TimeDelta,
+    granted: HashMap<PeerId, u64>,
+    swarm: Swarm<Behaviour>,
 }
 
 const MODIUS_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/1.0.0");
+const KAD_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/kad/1.0.0");
+const TICK: Duration = Duration::from_millis(250);
+const PRUNE_INTERVAL: TimeDelta = TimeDelta::minutes(10);
+const RELAY_CHECK_INTERVAL: TimeDelta = TimeDelta::seconds(30);
+const REPAIR_INTERVAL: TimeDelta = TimeDelta::minutes(1);
+const HOP_PROTOCOL: StreamProtocol = StreamProtocol::new("/libp2p/circuit/relay/0.2.0/hop");
 
 impl Client {
-    pub fn create(
-        key: Keypair,
-        name: String,
-        group: String,
-        port: usize,
-    ) -> Result<(Self, Sender<CommandWrapper>, Receiver<Event>), Box<dyn Error>> {
+    pub fn create(node: &Node) -> Result<ClientParts, Box<dyn Error + Send + Sync>> {
         let (tx_cmd, rx_cmd) = async_channel::unbounded::<CommandWrapper>();
         let (tx_evt, rx_evt) = async_channel::unbounded::<Event>();
-        let swarm = SwarmBuilder::with_existing_identity(key.clone())
-            .with_tokio()
-            .with_tcp(
-                tcp::Config::default(),
-                noise::Config::new,
-                yamux::Config::default,
-            )?
-            .with_relay_client(noise::Config::new, yamux::Config::default)?
+        let bus = EventBus::new(node.event_buffer);
+        bus.pump(rx_evt);
+        #[cfg(not(target_arch = "wasm32"))]
+        let tx_evt = match node.event_log_path.clone() {
+            Some(path) => {
+                let (tx_log, rx_log) = async_channel::unbounded::<Event>();
+                EventLog::open(path, node.event_log_max_bytes, node.event_log_max_age)?
+                    .spawn(rx_log, tx_evt);
+                tx_log
+            }
+            None => tx_evt,
+        };
+        let tx_evt = match node.audit_log.clone() {
+            Some(config) => {
+                let (tx_audit, rx_audit) = async_channel::unbounded::<Event>();
+                AuditLog::new(node.storage.clone(), config).spawn(rx_audit, tx_evt);
+                tx_audit
+            }
+            None => tx_evt,
+        };
+        let tx_evt = match node.acknowledged_events.is_empty() {
+            true => tx_evt,
+            false => {
+                let (tx_ack, rx_ack) = async_channel::unbounded::<Event>();
+                AckQueue::new(node.storage.clone(), &node.acknowledged_events)
+                    .spawn(rx_ack, tx_evt);
+                tx_ack
+            }
+        };
+        #[cfg(all(feature = "webhooks", not(target_arch = "wasm32")))]
+        let tx_evt = match node.webhooks.is_empty() {
+            true => tx_evt,
+            false => {
+                let (tx_hook, rx_hook) = async_channel::unbounded::<Event>();
+                Webhooks::new(node.webhooks.clone())?.spawn(rx_hook, tx_evt);
+                tx_hook
+            }
+        };
+        let tx_evt = match node.address_privacy {
+            true => {
+                let (tx_private, rx_private) = async_channel::unbounded::<Event>();
+                Redactor::load(&node.storage)?.spawn(rx_private, tx_evt);
+                tx_private
+            }
+            false => tx_evt,
+        };
+        #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+        let builder = SwarmBuilder::with_existing_identity(node.key.clone()).with_tokio();
+        #[cfg(all(
+            feature = "async-std",
+            not(feature = "tokio"),
+            not(target_arch = "wasm32")
+        ))]
+        let builder = SwarmBuilder::with_existing_identity(node.key.clone()).with_async_std();
+        let muxer = node.muxer.clone();
+        let prologue = node.noise_prologue.clone();
+        let security = node.security;
+        #[cfg(target_arch = "wasm32")]
+        if security.uses_tls() {
+            return Err("TLS transport security is not available on wasm".into());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = {
+            let proxy = node.socks5_proxy.as_ref().map(proxy_address).transpose()?;
+            let direct = proxy.is_none();
+            let resolver = node
+                .dns_resolver
+                .clone()
+                .or_else(|| node.ipfs_dht.then(transport::DnsResolver::default));
+            builder
+                .with_other_transport(|key| {
+                    let tcp = Tcp::new(tcp::Config::default().nodelay(true));
+                    let (proxied, resolved, direct) = match (proxy, resolver) {
+                        (Some(proxy), _) => (
+                            OptionalTransport::some(Socks5Transport::new(tcp, proxy)),
+                            OptionalTransport::none(),
+                            OptionalTransport::none(),
+                        ),
+                        (None, Some(resolver)) => (
+                            OptionalTransport::none(),
+                            OptionalTransport::some(dns(tcp, &resolver)?),
+                            OptionalTransport::none(),
+                        ),
+                        (None, None) => (
+                            OptionalTransport::none(),
+                            OptionalTransport::none(),
+                            OptionalTransport::some(tcp),
+                        ),
+                    };
+                    Ok::<_, Box<dyn Error + Send + Sync>>(
+                        proxied
+                            .or_transport(resolved)
+                            .or_transport(direct)
+                            .upgrade(libp2p::core::upgrade::Version::V1)
+                            .authenticate(transport::SecurityUpgrade::new(
+                                key, security, &prologue,
+                            )?)
+                            .multiplex(muxer.yamux()),
+                    )
+                })?
+                .with_other_transport(|key| match direct {
+                    true => OptionalTransport::some(Quic::new(quic::Config::new(key))),
+                    false => OptionalTransport::none(),
+                })?
+                .with_other_transport(|key| transport::custom(&node.custom_transports, key))?
+        };
+        #[cfg(all(unix, feature = "tokio"))]
+        let builder = builder.with_other_transport(|key| {
+            Ok::<_, Box<dyn Error + Send + Sync>>(
+                UnixTransport::new()
+                    .upgrade(libp2p::core::upgrade::Version::V1)
+                    .authenticate(transport::SecurityUpgrade::new(key, security, &prologue)?)
+                    .multiplex(muxer.yamux()),
+            )
+        })?;
+        #[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+        let builder = builder.with_other_transport(|key| match node.webrtc {
+            true => webrtc::transport(key, &node.storage).map(OptionalTransport::some),
+            false => Ok(OptionalTransport::none()),
+        })?;
+        #[cfg(target_arch = "wasm32")]
+        let builder = SwarmBuilder::with_existing_identity(node.key.clone())
+            .with_wasm_bindgen()
+            .with_other_transport(|key| {
+                Ok::<_, Box<dyn Error + Send + Sync>>(
+                    libp2p::websocket_websys::Transport::default()
+                        .upgrade(libp2p::core::upgrade::Version::V1)
+                        .authenticate(transport::noise(key, &prologue)?)
+                        .multiplex(muxer.yamux()),
+                )
+            })?
+            .with_other_transport(|key| {
+                libp2p_webrtc_websys::Transport::new(libp2p_webrtc_websys::Config::new(key))
+            })?
+            .with_other_transport(|key| transport::custom(&node.custom_transports, key))?;
+
+        let version = ProtocolVersion::local(&node.app_version, node.max_message_size);
+        let listen = node.listen && !node.proxy_only && cfg!(not(target_arch = "wasm32"));
+        let upnp = cfg!(feature = "tokio") && listen;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = builder.with_relay_client(
+            |key: &Keypair| {
+                transport::SecurityUpgrade::new(key, node.security, &node.noise_prologue)
+            },
+            || node.muxer.yamux(),
+        )?;
+        #[cfg(target_arch = "wasm32")]
+        let builder = builder.with_relay_client(
+            |key: &Keypair| transport::noise(key, &node.noise_prologue),
+            || node.muxer.yamux(),
+        )?;
+        #[allow(deprecated)]
+        let (builder, bandwidth) = builder.with_bandwidth_logging();
+        let swarm = builder
             .with_behaviour(|key, relay| Behaviour {
                 stream: libp2p_stream::Behaviour::new(),
+                relay_path: RelayPath::new(),
                 ping: libp2p::ping::Behaviour::default(),
-                mdns: libp2p::mdns::tokio::Behaviour::new(
-                    libp2p::mdns::Config::default(),
+                #[cfg(not(target_arch = "wasm32"))]
+                mdns: Toggle::from(listen.then(|| {
+                    Mdns::new(libp2p::mdns::Config::default(), key.public().to_peer_id())
+                        .expect("To be able to configure MDNS")
+                })),
+                #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+                upnp: Toggle::from(upnp.then(libp2p::upnp::tokio::Behaviour::default)),
+                identify: Identify::new(
+                    libp2p::identify::Config::new(version.encode(), key.public())
+                        .with_agent_version(agent_version(&node.name, &node.device_id)),
+                    node.advertise,
+                ),
+                autonat: libp2p::autonat::Behaviour::new(
                     key.public().to_peer_id(),
-                )
-                .expect("To be able to configure MDNS"),
-                upnp: libp2p::upnp::tokio::Behaviour::default(),
-                identify: libp2p::identify::Behaviour::new(libp2p::identify::Config::new(
-                    String::from("/modius/1.0.0"),
-                    key.public(),
-                )),
+                    libp2p::autonat::Config::default(),
+                ),
+                dcutr: libp2p::dcutr::Behaviour::new(key.public().to_peer_id()),
                 rendezvous: libp2p::rendezvous::client::Behaviour::new(key.clone()),
                 relay,
+                kad: libp2p::kad::Behaviour::with_config(
+                    key.public().to_peer_id(),
+                    libp2p::kad::store::MemoryStore::new(key.public().to_peer_id()),
+                    libp2p::kad::Config::new(match node.ipfs_dht {
+                        true => IPFS_KAD_PROTOCOL,
+                        false => KAD_PROTOCOL,
+                    }),
+                ),
+                gossipsub: gossipsub::Behaviour::new(
+                    gossipsub::MessageAuthenticity::Signed(key.clone()),
+                    gossipsub::Config::default(),
+                )
+                .expect("To be able to configure gossipsub"),
             })?
             .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
             .build();
+        let control = swarm.behaviour().stream.new_control();
+        let relay_path = swarm.behaviour().relay_path.new_control();
+        let dead = DeadLetters::new();
+        let (acl, violations) = AccessControl::new(node.acl.clone(), node.peer_store());
+        let (causality, retransmitted) =
+            Causality::new(node.key.public().to_peer_id(), node.causal_rooms);
+        let (delivery, reports) = Delivery::new(
+            tx_evt.clone(),
+            node.channels.clone(),
+            node.schemas.clone(),
+            dead.clone(),
+            acl.clone(),
+            node.max_inbound_streams,
+        );
+        #[cfg(feature = "ratchet")]
+        let ratchets = Ratchets::new(*swarm.local_peer_id());
+        let stats = Stats::new();
+        let coordinator = Coordinator::new(&node.group, *swarm.local_peer_id());
+        let (tx_lan, lan_found) = async_channel::unbounded::<LanIdentity>();
+        #[cfg(not(target_arch = "wasm32"))]
+        let lan_announcer = listen.then(|| {
+            LanAnnouncer::new(LanIdentity {
+                peer: *swarm.local_peer_id(),
+                name: node.name.clone(),
+                group: node.group.clone(),
+                device: Some(node.device_id),
+            })
+        });
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(announcer) = lan_announcer.as_ref() {
+            let _ = announcer.spawn(tx_lan);
+        }
+        #[cfg(target_arch = "wasm32")]
+        drop(tx_lan);
+        let breaker = CircuitBreaker::new(node.circuit_breaker.clone(), tx_evt.clone());
+        let (outbox, delivery) = (
+            Outbox::new(control.clone(), MODIUS_PROTOCOL, dead.clone())
+                .with_backlog(Backlog::for_node(node.storage.clone(), &node.key))
+                .with_stats(stats.clone())
+                .with_breaker(breaker.clone())
+                .with_scheduling(node.send_scheduling)
+                .with_relay_path(relay_path),
+            delivery
+                .with_stats(stats.clone())
+                .with_max_message(node.max_message_size),
+        );
+        #[cfg(feature = "ratchet")]
+        let (outbox, delivery) = (
+            outbox.with_ratchets(ratchets.clone()),
+            delivery.with_ratchets(ratchets.clone()),
+        );
+        let outbox = Arc::new(Mutex::new(outbox));
+        let custom_listen = node
+            .custom_transports
+            .iter()
+            .flat_map(|transport| transport.listen_addresses());
+        #[cfg(all(unix, feature = "tokio"))]
+        let custom_listen = custom_listen.chain(node.unix_sockets.iter().map(unix::address));
+        #[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+        let custom_listen = custom_listen.chain(
+            node.webrtc
+                .then(|| webrtc::address(u16::try_from(node.port).unwrap_or_default())),
+        );
         Ok((
             Client {
                 commands: rx_cmd,
-                events: tx_evt,
-                key: key.clone(),
-                name: name.clone(),
-                group: group.clone(),
-                port,
-                swarm: Arc::new(Mutex::new(swarm)),
+                events: tx_evt.clone(),
+                group: node.group.clone(),
+                port: node.port,
+                port_fallback: node.port_fallback,
+                listeners: Listeners::new(),
+                listen,
+                advertise: node.advertise,
+                custom_listen: custom_listen.collect(),
+                peers: node.peer_store(),
+                routing: node.routing_table(),
+                blobs: node.blob_store(),
+                replicator: Replicator::new(
+                    control.clone(),
+                    ShardStore::new(node.storage.clone()),
+                    node.blob_store(),
+                    node.replication.clone(),
+                ),
+                next_repair: Utc::now(),
+                shares: node.shares.clone(),
+                services: node.services.clone(),
+                advertised: HashSet::new(),
+                service_streams: SelectAll::new(),
+                balancer: Balancer::new(),
+                feeds: node.feeds(),
+                providers: LookupCache::new(node.lookup_cache_ttl, stats.clone()),
+                registrations: LookupCache::new(node.lookup_cache_ttl, stats.clone()),
+                stats,
+                breaker,
+                heartbeat: Heartbeat::new(),
+                tracer: CommandTracer::new(tx_evt.clone(), node.trace_commands),
+                idempotency: Idempotency::new(),
+                watchdog: node.watchdog.clone(),
+                acl,
+                violations,
+                causality,
+                retransmitted,
+                strikes: HashMap::new(),
+                nat: NatState::new(upnp),
+                rendezvous: HashSet::new(),
+                ipfs: node.ipfs_dht.then(|| IpfsDiscovery::new(&node.group)),
+                paused: false,
+                budget: node
+                    .bandwidth_budget
+                    .clone()
+                    .map(|budget| BudgetMeter::new(budget, bandwidth)),
+                doctor: None,
+                dialer: Dialer::new(),
+                relays: RelaySelector::new(),
+                scheduler: Scheduler::new(),
+                journal: Journal::new(node.storage.clone(), node.journal_commands),
+                coalescer: Coalescer::new(node.coalesce_discovery),
+                bootstrap: Bootstrap::default(),
+                outbox: outbox.clone(),
+                #[cfg(feature = "ratchet")]
+                ratchets,
+                delivery,
+                reports,
+                dispatcher: Dispatcher::new(node.peer_store(), outbox, node.command_concurrency),
+                rooms: node.rooms.clone(),
+                topics: HashMap::new(),
+                presence: Presence::new(),
+                history: History::new(),
+                control,
+                version,
+                max_message: node.max_message_size,
+                limits: HashMap::new(),
+                dead,
+                key: node.key.clone(),
+                departed: HashSet::new(),
+                closing: HashMap::new(),
+                prune: node.prune.clone(),
+                next_prune: Utc::now(),
+                auto_relay: node.auto_relay.then_some(node.min_reservations.max(1)),
+                next_relay_check: Utc::now(),
+                keyring: Keyring::new(),
+                updates: UpdateChannel::new(&node.group, node.update_publishers.clone()),
+                liveness: LivenessGossip::new(&node.group),
+                coordinator,
+                lan: HashMap::new(),
+                lan_found,
+                #[cfg(not(target_arch = "wasm32"))]
+                lan_announcer,
+                group_admin: node.group_admin,
+                group_admins: node.group_admins.iter().copied().collect(),
+                key_rotation: TimeDelta::from_std(node.group_key_rotation)
+                    .unwrap_or(TimeDelta::MAX),
+                granted: HashMap::new(),
+                swarm,
             },
             tx_cmd,
-            rx_evt,
+            bus,
         ))
     }
 
-    async fn handle_command(&mut self, command: CommandWrapper) -> Result<(), Box<dyn Error>> {
-        if let Ok(mut swarm) = self.swarm.clone().lock() {
-            let result: Result<(), Box<dyn Error>> = match command.clone().kind() {
-                CommandKind::AddRelay(peer) => {
-                    command.respond(swarm.dial(peer.address)).await?;
-                    Ok(())
+    async fn run_command(
+        &mut self,
+        mut command: CommandWrapper,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.idempotency.admit(&mut command) {
+            return Ok(());
+        }
+
+        self.tracer.begin(&mut command);
+        let result = self.handle_command(command).await;
+        self.tracer.end();
+        result
+    }
+
+    async fn handle_command(
+        &mut self,
+        command: CommandWrapper,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(denied) = self
+            .budget
+            .as_ref()
+            .and_then(|budget| budget.denies(&command.command))
+        {
+            command.respond::<(), _>(Err(denied)).await?;
+            return Ok(());
+        }
+
+        match command.kind() {
+            CommandKind::AddRelay(peer) | CommandKind::AddRendezvous(peer)
+                if self.rejects_self(&peer).await? =>
+            {
+                command
+                    .respond::<(), _>(Err("Refusing to add this node as its own peer"))
+                    .await?
+            }
+            CommandKind::AddRelay(peer) => {
+                self.peers.insert(peer.clone())?;
+                let reserved = self.reserve(&peer);
+                if let Ok(listener) = reserved.as_ref() {
+                    if self.relays.active().is_none() {
+                        self.relays.activate(peer.id, *listener);
+                    }
+                }
+                command.respond(reserved.map(|_| ())).await?;
+            }
+            CommandKind::AddRendezvous(peer) => {
+                self.peers.insert(peer.clone())?;
+                match self.dial_peer(&peer) {
+                    Ok(_) | Err(DialError::DialPeerConditionFalse(_)) => {
+                        let namespace = Namespace::new(self.group.clone())?;
+                        self.rendezvous.insert(peer.id);
+                        self.journal.record(command.id, &command.command)?;
+                        command
+                            .respond(
+                                self.swarm
+                                    .behaviour_mut()
+                                    .rendezvous
+                                    .register(namespace, peer.id, None),
+                            )
+                            .await?
+                    }
+                    Err(e) => command.respond::<(), DialError>(Err(e)).await?,
+                }
+            }
+            CommandKind::NatReport => command.reply(self.nat.report()).await?,
+            CommandKind::Doctor { target } => match self.doctor {
+                Some(_) => {
+                    command
+                        .respond::<(), _>(Err("A diagnosis is already running"))
+                        .await?
+                }
+                None => self.diagnose(command, target)?,
+            },
+            CommandKind::BestPeerFor { .. } | CommandKind::ListPeers(_) => {
+                let connected = self.swarm.connected_peers().copied().collect();
+                self.dispatcher.dispatch(command, connected, None);
+            }
+            CommandKind::Send { peer, .. } | CommandKind::ChannelSend { peer, .. }
+                if self.departed.contains(&peer) =>
+            {
+                command
+                    .respond::<(), _>(Err("Peer has left the group"))
+                    .await?
+            }
+            CommandKind::Send { peer, .. } | CommandKind::ChannelSend { peer, .. }
+                if self.breaker.is_open(&peer) =>
+            {
+                command
+                    .respond::<(), _>(Err("Circuit open for peer"))
+                    .await?
+            }
+            CommandKind::Send { peer, payload, .. }
+            | CommandKind::ChannelSend { peer, payload, .. }
+                if payload.len() > self.message_limit(&peer) =>
+            {
+                command
+                    .respond::<(), _>(Err(format!(
+                        "Payload of {} bytes exceeds the {} byte limit for this peer",
+                        payload.len(),
+                        self.message_limit(&peer)
+                    )))
+                    .await?
+            }
+            CommandKind::Send { .. } | CommandKind::ChannelSend { .. } => {
+                let journaled = self.journal.record(command.id, &command.command)?;
+                let connected = self.swarm.connected_peers().copied().collect();
+                self.dispatcher.dispatch(command, connected, journaled);
+            }
+            CommandKind::JoinRoom(room) => {
+                let topic = Rooms::topic(&self.group, &room);
+                let joined = self.swarm.behaviour_mut().gossipsub.subscribe(&topic);
+                if joined.is_ok() {
+                    self.topics.insert(topic.hash(), room);
+                }
+                command.respond(joined).await?
+            }
+            CommandKind::LeaveRoom(room) => {
+                let topic = Rooms::topic(&self.group, &room);
+                self.topics.remove(&topic.hash());
+                self.rooms.close(&room);
+                self.history.forget(&room);
+                self.causality.forget(&room);
+                command
+                    .respond(self.swarm.behaviour_mut().gossipsub.unsubscribe(&topic))
+                    .await?
+            }
+            CommandKind::RoomSend { room, payload } => {
+                let topic = Rooms::topic(&self.group, &room);
+                let local = *self.swarm.local_peer_id();
+                let header = self
+                    .causality
+                    .is_enabled()
+                    .then(|| self.causality.stamp(&room));
+                let stamped = match header.as_ref().and_then(|header| header.wrap(&payload)) {
+                    Some(stamped) => stamped,
+                    None => payload.clone(),
+                };
+                let sealed = match self.keyring.seal(&stamped) {
+                    Some(sealed) => sealed,
+                    None if self.group_admin || !self.group_admins.is_empty() => {
+                        command
+                            .respond::<(), _>(Err("No group key available"))
+                            .await?;
+                        return Ok(());
+                    }
+                    None => stamped,
+                };
+                if let Some(header) = header {
+                    self.causality.retain(
+                        &room,
+                        CausalMessage {
+                            header,
+                            data: sealed.clone(),
+                        },
+                    );
                 }
-                CommandKind::AddRendezvous(peer) => {
-                    match swarm.dial(peer.address) {
-                        Ok(_) => {
+                let published = self
+                    .swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .publish(topic, sealed)
+                    .map(|id| {
+                        self.history
+                            .record(&room, id.to_string(), Some(local), payload)
+                    });
+                command.respond(published).await?
+            }
+            CommandKind::RoomMembers(room) => {
+                let topic = Rooms::topic(&self.group, &room).hash();
+                command.reply(self.presence.members(&topic)).await?
+            }
+            CommandKind::RoomHistory { room, count } => {
+                let topic = Rooms::topic(&self.group, &room).hash();
+                let members = self.presence.members(&topic);
+                self.history
+                    .backfill(command, self.control.clone(), members, room, count);
+            }
+            CommandKind::Broadcast {
+                payload,
+                fanout,
+                to,
+            } => {
+                let members: Vec<PeerId> = match to {
+                    Audience::Group => self
+                        .peers
+                        .list()?
+                        .into_iter()
+                        .filter(|peer| peer.group.as_ref() == Some(&self.group))
+                        .map(|peer| peer.id)
+                        .collect(),
+                    Audience::Set(name) => match self.peers.set(&name)? {
+                        Some(set) => set.members.into_iter().collect(),
+                        None => {
                             command
-                                .respond(swarm.behaviour_mut().rendezvous.register(
-                                    Namespace::from_static("modius"),
-                                    peer.id,
-                                    None,
-                                ))
-                                .await?
+                                .respond::<(), _>(Err(format!("Unknown peer set {name}")))
+                                .await?;
+                            return Ok(());
+                        }
+                    },
+                };
+                let members = members
+                    .into_iter()
+                    .filter(|peer| self.swarm.is_connected(peer))
+                    .collect();
+                let pending = fanout
+                    .select(members)
+                    .into_iter()
+                    .map(|peer| {
+                        let (receipt, acked) = self.outbox().push_tracked(
+                            peer,
+                            payload.clone(),
+                            DeliveryGuarantee::Ordered,
+                        );
+                        (peer, receipt, acked)
+                    })
+                    .collect();
+                broadcast::collect(command, pending);
+            }
+            CommandKind::Schedule {
+                interval,
+                command: scheduled,
+            } => {
+                command
+                    .reply(self.scheduler.schedule(interval, *scheduled))
+                    .await?
+            }
+            CommandKind::Unschedule(id) => command.reply(self.scheduler.cancel(id)).await?,
+            CommandKind::Barrier { name, count } => {
+                let outcome = self.coordinator.barrier(command, name, count);
+                self.coordinate(outcome).await?
+            }
+            CommandKind::Propose {
+                key,
+                value,
+                quorum,
+                timeout,
+            } => {
+                let members = self.group_size()?;
+                let outcome = self
+                    .coordinator
+                    .propose(command, key, value, quorum, members, timeout);
+                self.coordinate(outcome).await?
+            }
+            CommandKind::AwaitDecision(key) => {
+                let outcome = self.coordinator.accept(command, key);
+                self.coordinate(outcome).await?
+            }
+            CommandKind::FlushCaches => {
+                command
+                    .reply(self.providers.flush() + self.registrations.flush())
+                    .await?
+            }
+            CommandKind::TagPeer { peer, key, value } => {
+                command.respond(self.peers.tag(&peer, key, value)).await?
+            }
+            CommandKind::PinPeer { peer, pinned } => {
+                command.respond(self.peers.pin(&peer, pinned)).await?
+            }
+            CommandKind::DefinePeerSet { name, peers } => {
+                command.respond(self.peers.define_set(&name, peers)).await?
+            }
+            CommandKind::UpdatePeerSet { name, add, remove } => {
+                command
+                    .respond(self.peers.update_set(&name, add, remove))
+                    .await?
+            }
+            CommandKind::DeletePeerSet(name) => {
+                command.respond(self.peers.delete_set(&name)).await?
+            }
+            CommandKind::ListPeerSets => command.respond(self.peers.sets()).await?,
+            CommandKind::Disconnect { set } => match self.peers.set(&set)? {
+                Some(set) => {
+                    let mut disconnected = Vec::new();
+                    for peer in set.members {
+                        if self.swarm.is_connected(&peer) {
+                            self.disconnect(peer, DisconnectReason::Requested);
+                            disconnected.push(peer);
                         }
-                        Err(e) => command.respond::<(), DialError>(Err(e)).await?,
                     }
-                    Ok(())
+                    command.reply(disconnected).await?
                 }
-            };
+                None => {
+                    command
+                        .respond::<(), _>(Err(format!("Unknown peer set {set}")))
+                        .await?
+                }
+            },
+            CommandKind::PrunePeers => {
+                let pruned = self.prune_peers().await;
+                command.respond(pruned).await?
+            }
+            CommandKind::DeadLetters { drain } => command.reply(self.dead.list(drain)).await?,
+            CommandKind::Admin { peer, request } => match AdminCommand::sign(&self.key, request) {
+                Some(signed) => admin::call(command, self.control.clone(), peer, signed),
+                None => {
+                    command
+                        .respond::<(), _>(Err("Unable to sign admin command"))
+                        .await?
+                }
+            },
+            CommandKind::Call {
+                peer,
+                method,
+                params,
+                callbacks,
+                options,
+            } => {
+                let callbacks = callbacks
+                    .and_then(|call| self.services.detach(call))
+                    .unwrap_or_default();
+                rpc::call(
+                    command,
+                    self.control.clone(),
+                    peer,
+                    method,
+                    params,
+                    callbacks,
+                    options,
+                )
+            }
+            CommandKind::CallAny {
+                service,
+                method,
+                params,
+                balance,
+                options,
+            } => {
+                let providers = self.service_providers(&service)?;
+                let peers = self.balancer.order(&service, balance, providers);
+                match peers.is_empty() {
+                    false => rpc::call_any(
+                        command,
+                        self.control.clone(),
+                        peers,
+                        method,
+                        params,
+                        options,
+                    ),
+                    true => {
+                        let error = RpcError::new(
+                            RpcErrorKind::Unavailable,
+                            &method,
+                            format!("No connected providers for service {service}"),
+                        );
+                        command.respond::<(), _>(Err(error)).await?
+                    }
+                }
+            }
+            CommandKind::CallStreaming {
+                peer,
+                method,
+                params,
+                call,
+            } => self.services.call_streaming(
+                command,
+                self.control.clone(),
+                peer,
+                method,
+                params,
+                call,
+            ),
+            CommandKind::StorageStats => command.respond(self.blobs.stats()).await?,
+            CommandKind::Stats => command.reply(self.stats.snapshot()).await?,
+            CommandKind::ExportPeers { format } => {
+                let exported = self
+                    .peers
+                    .list()
+                    .and_then(|peers| addressbook::export(&peers, format));
+                command.respond(exported).await?
+            }
+            CommandKind::ImportPeers { format, data } => {
+                let peers = match addressbook::import(&data, format) {
+                    Ok(peers) => peers,
+                    Err(e) => {
+                        command.respond::<(), _>(Err(e)).await?;
+                        return Ok(());
+                    }
+                };
+                let mut imported = 0usize;
+                for peer in peers {
+                    if !self.rejects_self(&peer).await? {
+                        self.peers.insert(peer)?;
+                        imported += 1;
+                    }
+                }
+                command.reply(imported).await?
+            }
+            CommandKind::Pause => {
+                let paused = self.pause().await;
+                command.reply(paused).await?
+            }
+            CommandKind::Resume => {
+                let resumed = self.resume().await;
+                command.reply(resumed).await?
+            }
+            CommandKind::ProbeThroughput { peer, duration } => probe::measure(
+                command,
+                self.control.clone(),
+                self.peers.clone(),
+                peer,
+                duration,
+            ),
+            CommandKind::BandwidthUsage => {
+                command
+                    .reply(self.budget.as_ref().map(BudgetMeter::usage))
+                    .await?
+            }
+            CommandKind::GetListeners => command.reply(self.listeners.list()).await?,
+            CommandKind::AddListener(address) => match self.listen(address) {
+                Ok(listener) => {
+                    command
+                        .respond(self.listeners.id(&listener).ok_or("Unknown listener"))
+                        .await?
+                }
+                Err(e) => command.respond::<(), _>(Err(e)).await?,
+            },
+            CommandKind::RemoveListener(id) => match self.listeners.get(id) {
+                Some(listener) => command.reply(self.swarm.remove_listener(listener)).await?,
+                None => command.respond::<(), _>(Err("Unknown listener")).await?,
+            },
+            CommandKind::Replicate(blob) => {
+                let members = self.connected_members()?;
+                self.replicator.replicate(command, blob, members);
+            }
+            CommandKind::AppendFeed { name, data } => {
+                let entry = self.feeds.append(&self.key, &name, data);
+                if let Ok(entry) = entry.as_ref() {
+                    let topic = self.feeds.topic().clone();
+                    let _ = self
+                        .swarm
+                        .behaviour_mut()
+                        .gossipsub
+                        .publish(topic, serde_json::to_vec(entry)?);
+                }
+                command.respond(entry).await?
+            }
+            CommandKind::CatchUpFeed(feed) => {
+                let peers = self.swarm.connected_peers().copied().collect();
+                self.feeds.catch_up(
+                    self.control.clone(),
+                    peers,
+                    feed,
+                    self.events.clone(),
+                    Some(command),
+                );
+            }
+            CommandKind::Sync { peer, share } => {
+                self.shares.pull(command, self.control.clone(), peer, share)
+            }
+            CommandKind::RestoreBlob(blob) => {
+                let connected = self.swarm.connected_peers().copied().collect();
+                self.replicator.restore(command, blob, connected);
+            }
+            CommandKind::PublishUpdate(manifest) => {
+                let Some(signed) = SignedManifest::sign(&self.key, manifest) else {
+                    command
+                        .respond::<(), _>(Err("Unable to sign update manifest"))
+                        .await?;
+                    return Ok(());
+                };
+                let topic = self.updates.topic().clone();
+                let published = self
+                    .swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .publish(topic, serde_json::to_vec(&signed)?)
+                    .map(|_| ());
+                command.respond(published).await?
+            }
+            CommandKind::LeaveGroup => {
+                let finished = self.announce_departure()?;
+                Runtime::spawn(async move {
+                    let _ = finished.recv().await;
+                    let _ = command.reply(()).await;
+                });
+            }
+            CommandKind::ChangeGroup(group) => match valid_group(&group) {
+                true => {
+                    let changed = self.change_group(group).await?;
+                    command.reply(changed).await?
+                }
+                false => {
+                    command
+                        .respond::<(), _>(Err(format!("Invalid group name {group}")))
+                        .await?
+                }
+            },
+            CommandKind::AwaitReady => {
+                if let Some(command) = self.bootstrap.wait(command) {
+                    self.resolve_ready(command).await?;
+                }
+            }
         }
 
         Ok(())
     }
 
-    async fn handle_event(
-        &mut self,
-        event: SwarmEvent<BehaviourEvent>,
-    ) -> Result<(), Box<dyn Error>> {
-        Ok(())
+    async fn rejects_self(&mut self, peer: &Peer) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        if peer.id != *self.swarm.local_peer_id() {
+            return Ok(false);
+        }
+
+        self.peers.remove(&peer.id)?;
+        self.emit(Event::SelfDialRejected {
+            address: peer.address.clone(),
+        })
+        .await;
+        Ok(true)
     }
 
-    async fn handle_stream(&mut self, peer: PeerId, stream: Stream) -> Result<(), Box<dyn Error>> {
+    fn dial(&mut self, opts: impl Into<DialOpts>) -> Result<(), DialError> {
+        let opts = opts.into();
+        let (connection, peer) = (opts.connection_id(), opts.get_peer_id());
+        if self.paused {
+            return Err(DialError::Denied {
+                cause: ConnectionDenied::new("Node is paused"),
+            });
+        }
+        if peer.is_some_and(|peer| !self.breaker.allow(&peer)) {
+            return Err(DialError::Denied {
+                cause: ConnectionDenied::new("Circuit open for peer"),
+            });
+        }
+        self.swarm.dial(opts)?;
+        self.tracer.dialing(connection, peer);
         Ok(())
     }
 
-    async fn event_loop(&mut self) -> Result<(), Box<dyn Error>> {
-        let mut inbox = self
-            .swarm
-            .clone()
-            .lock()
-            .expect("To be able to lock swarm")
-            .behaviour()
-            .stream
-            .new_control()
-            .accept(MODIUS_PROTOCOL)?;
-        loop {
-            let event = match self.swarm.clone().lock() {
-                Ok(mut swarm) => {
-                    tokio::select! {
-                        command = self.commands.recv() => command.and_then(|s| Ok(LoopEvent::Command(s))).or(Err(())),
-                        event = swarm.next() => event.and_then(|s| Some(Ok(LoopEvent::Swarm(s)))).or(Some(Err(()))).unwrap(),
-                        recv = inbox.next() => recv.and_then(|(peer, stream)| Some(Ok(LoopEvent::Stream(peer, stream)))).or(Some(Err(()))).unwrap()
-                    }
+    fn dial_providers(&mut self, key: &RecordKey, providers: HashSet<PeerId>) {
+        let local = *self.swarm.local_peer_id();
+        let Some(ipfs) = self.ipfs.as_mut().filter(|ipfs| ipfs.is_group_key(key)) else {
+            return;
+        };
+
+        let candidates = providers
+            .into_iter()
+            .filter(|peer| *peer != local && !self.swarm.is_connected(peer));
+        for peer in ipfs.found(candidates) {
+            let dialed = self.dial(
+                DialOpts::peer_id(peer)
+                    .condition(PeerCondition::Disconnected)
+                    .build(),
+            );
+            if dialed.is_err() {
+                if let Some(ipfs) = self.ipfs.as_mut() {
+                    ipfs.on_dial_failed(&peer);
                 }
-                _ => Err(()),
-            };
+            }
+        }
+    }
+
+    fn dial_peer(&mut self, peer: &Peer) -> Result<(), DialError> {
+        let addresses = self.dialer.plan(peer.id, peer.dial_addresses());
+        self.dial(
+            DialOpts::peer_id(peer.id)
+                .addresses(addresses)
+                .condition(PeerCondition::Disconnected)
+                .build(),
+        )
+    }
+
+    fn dial_fallback(&mut self, peer: PeerId, addresses: Vec<Multiaddr>) {
+        if self.dialer.has_direct(&peer) {
+            return;
+        }
+
+        let _ = self.dial(
+            DialOpts::peer_id(peer)
+                .addresses(addresses)
+                .condition(PeerCondition::Always)
+                .build(),
+        );
+    }
+
+    fn reserve(&mut self, relay: &Peer) -> Result<ListenerId, Box<dyn Error + Send + Sync>> {
+        let mut circuit = relay.address.clone();
+        if !matches!(circuit.iter().last(), Some(Protocol::P2p(_))) {
+            circuit.push(Protocol::P2p(relay.id));
+        }
+        circuit.push(Protocol::P2pCircuit);
+
+        self.dial(relay.address.clone())?;
+        self.listen(circuit)
+    }
+
+    fn listen(&mut self, address: Multiaddr) -> Result<ListenerId, Box<dyn Error + Send + Sync>> {
+        let listener = self.swarm.listen_on(address.clone())?;
+        self.listeners.opened(listener, address);
+        self.tracer.listening(listener);
+        Ok(listener)
+    }
+
+    fn select_relay(&mut self) -> Result<Option<PeerId>, Box<dyn Error + Send + Sync>> {
+        let relays: Vec<Peer> = self
+            .peers
+            .list()?
+            .into_iter()
+            .filter(|peer| {
+                matches!(peer.kind, PeerType::Relay)
+                    || (self.auto_relay.is_some()
+                        && peer.services.iter().any(|s| s == HOP_PROTOCOL.as_ref()))
+            })
+            .collect();
+        for relay in self.relays.pick(relays) {
+            if let Ok(listener) = self.reserve(&relay) {
+                self.relays.activate(relay.id, listener);
+                return Ok(Some(relay.id));
+            }
+        }
+
+        self.relays.exhausted();
+        Ok(None)
+    }
+
+    async fn fail_over(
+        &mut self,
+        from: Option<PeerId>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(from) = from else {
+            return Ok(());
+        };
+
+        if let Some(to) = self.select_relay()? {
+            self.emit(Event::RelayFailover { from, to }).await;
+        }
+        Ok(())
+    }
+
+    fn diagnose(
+        &mut self,
+        command: CommandWrapper,
+        target: Option<PeerId>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut doctor = Doctor::new(command, *self.swarm.local_peer_id(), target);
+        let known = self.peers.list()?;
+
+        let listening: Vec<String> = self.swarm.listeners().map(|a| a.to_string()).collect();
+        if !self.listen {
+            doctor.resolve(
+                Check::Bind,
+                Outcome::Skipped,
+                "Listening is disabled on this node",
+                None,
+            );
+        } else if listening.is_empty() {
+            doctor.resolve(
+                Check::Bind,
+                Outcome::Failed,
+                format!("Could not bind to port {}", self.port),
+                Some("Make sure no other process uses the port and that the firewall allows it"),
+            );
+        } else {
+            doctor.resolve(
+                Check::Bind,
+                Outcome::Passed,
+                format!("Listening on {}", listening.join(", ")),
+                None,
+            );
+        }
+
+        let bootstrap: Vec<&Peer> = known
+            .iter()
+            .filter(|peer| matches!(peer.kind, PeerType::Bootstrap))
+            .collect();
+        if bootstrap.is_empty() {
+            doctor.resolve(
+                Check::Bootstrap,
+                Outcome::Skipped,
+                "No bootstrap peers are configured",
+                Some("Add one with NodeBuilder::try_bootstrap"),
+            );
+        } else if let Some(peer) = bootstrap.iter().find(|p| self.swarm.is_connected(&p.id)) {
+            doctor.resolve(
+                Check::Bootstrap,
+                Outcome::Passed,
+                format!("Connected to bootstrap peer {}", peer.id),
+                None,
+            );
+        } else {
+            for peer in bootstrap.iter() {
+                let _ = self.dial_peer(peer);
+            }
+            doctor.await_bootstrap(bootstrap.iter().map(|peer| peer.id).collect());
+        }
+
+        if self.rendezvous.is_empty() {
+            doctor.resolve(
+                Check::Rendezvous,
+                Outcome::Skipped,
+                "No rendezvous server is registered",
+                Some("Register with CommandKind::AddRendezvous"),
+            );
+        } else {
+            let namespace = Namespace::new(self.group.clone())?;
+            for server in self.rendezvous.iter() {
+                self.swarm.behaviour_mut().rendezvous.discover(
+                    Some(namespace.clone()),
+                    None,
+                    None,
+                    *server,
+                );
+            }
+            doctor.await_rendezvous(self.rendezvous.clone());
+        }
+
+        let relays: Vec<&Peer> = known
+            .iter()
+            .filter(|peer| matches!(peer.kind, PeerType::Relay))
+            .collect();
+        if self.nat.has_reservation() {
+            doctor.resolve(
+                Check::Relay,
+                Outcome::Passed,
+                "A relay reservation is active",
+                None,
+            );
+        } else if relays.is_empty() {
+            doctor.resolve(
+                Check::Relay,
+                Outcome::Skipped,
+                "No relay peers are configured",
+                Some("Add one with CommandKind::AddRelay"),
+            );
+        } else {
+            for relay in relays {
+                let _ = self.reserve(relay);
+            }
+        }
 
-            if let Ok(evt) = event {
-                if let Err(e) = match evt {
-                    LoopEvent::Command(command) => self.handle_command(command).await,
-                    LoopEvent::Swarm(event) => self.handle_event(event).await,
-                    LoopEvent::Stream(peer, stream) => self.handle_stream(peer, stream).await,
-                } {
-                    return Err(e);
+        match target {
+            Some(peer) => {
+                if let Err(e) = self.dial(peer) {
+                    doctor.resolve(
+                        Check::HolePunch,
+                        Outcome::Failed,
+                        format!("Could not dial {peer}: {e}"),
+                        Some("Add the target to the peer store or share a relay with it"),
+                    );
                 }
             }
+            None => doctor.resolve(
+                Check::HolePunch,
+                Outcome::Skipped,
+                "No target peer was given",
+                None,
+            ),
         }
+
+        self.doctor = Some(doctor);
+        Ok(())
     }
 
-    pub async fn main(&mut self) -> Result<(), Box<dyn Error>> {
-        let listener = self.swarm.lock().expect("Failed to lock").listen_on(
-            String::from("/ip4/0.0.0.0/tcp/".to_owned() + &self.port.to_string()).parse()?,
-        )?;
-        let loop_result = self.event_loop().await;
-        self.swarm
-            .lock()
-            .expect("Failed to lock")
-            .remove_listener(listener);
-        self.commands.close();
-        self.events.close();
-        loop_result
+    async fn resolve_ready(
+        &mut self,
+        command: CommandWrapper,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.bootstrap.is_ready() {
+            true => command.reply(()).await?,
+            false => command.respond::<(), _>(Err("Bootstrap failed")).await?,
+        }
+
+        Ok(())
+    }
+
+    async fn advance(
+        &mut self,
+        stages: Vec<BootstrapStage>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for stage in stages {
+            self.emit(Event::BootstrapProgress { stage }).await;
+        }
+
+        for command in self.bootstrap.take_waiters() {
+            self.resolve_ready(command).await?;
+        }
+
+        Ok(())
+    }
+
+    fn join(&mut self) -> Result<Vec<BootstrapStage>, Box<dyn Error + Send + Sync>> {
+        let namespace = Namespace::new(self.group.clone())?;
+        let connected: Vec<PeerId> = self.swarm.connected_peers().cloned().collect();
+        let mut registering = HashSet::new();
+        for peer in connected {
+            if !self.bootstrap.is_bootstrap(&peer) {
+                continue;
+            }
+
+            let registered =
+                self.swarm
+                    .behaviour_mut()
+                    .rendezvous
+                    .register(namespace.clone(), peer, None);
+            if registered.is_ok() {
+                registering.insert(peer);
+            }
+        }
+
+        let dht = self.swarm.behaviour_mut().kad.bootstrap().is_ok();
+        Ok(self.bootstrap.joining(registering, dht))
+    }
+
+    async fn check_doctor(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(doctor) = self.doctor.as_mut() {
+            doctor.expire();
+        }
+
+        if let Some(doctor) = self.doctor.take_if(|doctor| doctor.complete()) {
+            let (command, report) = doctor.finish();
+            command.reply(report).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_event(
+        &mut self,
+        event: SwarmEvent<BehaviourEvent>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.tracer.observe(&event);
+        match event {
+            #[cfg(not(target_arch = "wasm32"))]
+            SwarmEvent::Behaviour(BehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
+                for (id, address) in list {
+                    if self.dialer.relayed_only(&id) {
+                        self.dial_fallback(id, vec![address.clone()]);
+                    }
+                    if !self.peers.contains(&id)? {
+                        let mut peer = Peer::new(PeerType::Discovered, id, address);
+                        if let Some(identity) = self.lan.get(&id) {
+                            peer.name = Some(identity.name.clone());
+                            peer.group = Some(identity.group.clone());
+                            peer.device = identity.device;
+                        }
+                        self.peers.insert(peer.clone())?;
+                        self.discovered(peer).await;
+                    } else {
+                        self.peers.add_addresses(&id, &[address])?;
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Ping(libp2p::ping::Event {
+                peer,
+                result: Ok(rtt),
+                ..
+            })) => self.peers.record_rtt(&peer, rtt)?,
+            SwarmEvent::Behaviour(BehaviourEvent::Identify(
+                libp2p::identify::Event::Received { peer_id, info, .. },
+            )) if !self.compatible(&info.protocol_version) => {
+                self.disconnect(peer_id, DisconnectReason::Incompatible);
+                self.emit(Event::IncompatiblePeer {
+                    peer: peer_id,
+                    their_version: info.protocol_version,
+                })
+                .await;
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Identify(
+                libp2p::identify::Event::Received { peer_id, info, .. },
+            )) => {
+                for address in info.listen_addrs.iter() {
+                    self.swarm
+                        .behaviour_mut()
+                        .kad
+                        .add_address(&peer_id, address.clone());
+                }
+                if let Some(max) = ProtocolVersion::parse(&info.protocol_version)
+                    .and_then(|version| version.max_message)
+                {
+                    self.limits.insert(peer_id, max);
+                }
+                let (name, device) = parse_agent_version(&info.agent_version);
+                self.peers.update(&peer_id, |peer| {
+                    peer.services = info.protocols.iter().map(|p| p.to_string()).collect();
+                    peer.name = peer.name.take().or(Some(name));
+                    peer.device = device.or(peer.device);
+                    for address in info.listen_addrs.iter() {
+                        peer.add_address(address.clone());
+                    }
+                })?;
+            }
+            #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+            SwarmEvent::Behaviour(BehaviourEvent::Upnp(event)) => self.nat.on_upnp(event),
+            SwarmEvent::Behaviour(BehaviourEvent::Autonat(
+                libp2p::autonat::Event::StatusChanged { new, .. },
+            )) => self.nat.on_autonat(new),
+            SwarmEvent::Behaviour(BehaviourEvent::Autonat(
+                libp2p::autonat::Event::OutboundProbe(
+                    libp2p::autonat::OutboundProbeEvent::Response { address, .. },
+                ),
+            )) => self.nat.on_address_verified(address),
+            SwarmEvent::ExternalAddrConfirmed { address }
+                if !self.advertise.advertises_externally(&address) =>
+            {
+                self.swarm.remove_external_address(&address);
+            }
+            SwarmEvent::ExternalAddrConfirmed { address } if !self.nat.is_verified(&address) => {
+                self.swarm.remove_external_address(&address);
+                self.swarm.behaviour_mut().autonat.probe_address(address);
+            }
+            SwarmEvent::ExternalAddrExpired { address } => self.nat.on_address_expired(&address),
+            SwarmEvent::Behaviour(BehaviourEvent::Relay(
+                libp2p::relay::client::Event::ReservationReqAccepted { relay_peer_id, .. },
+            )) => {
+                self.nat.on_reservation(relay_peer_id);
+                self.relays.on_accepted(&relay_peer_id);
+                if let Some(doctor) = self.doctor.as_mut() {
+                    doctor.on_reservation(relay_peer_id);
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(event)) => match event {
+                gossipsub::Event::Message {
+                    propagation_source,
+                    message_id,
+                    message,
+                } => {
+                    if message.topic == self.feeds.topic().hash() {
+                        if let Ok(entry) = serde_json::from_slice::<FeedEntry>(&message.data) {
+                            self.receive_feed_entry(propagation_source, entry).await?;
+                        }
+                    } else if message.topic == self.updates.topic().hash() {
+                        if let Some((publisher, manifest)) = self.updates.accept(&message.data) {
+                            self.emit(Event::UpdateAvailable {
+                                publisher,
+                                manifest,
+                            })
+                            .await;
+                        }
+                    } else if message.topic == self.coordinator.topic().hash() {
+                        if let Some(source) = message.source {
+                            let outcome = self.coordinator.receive(source, &message.data);
+                            self.coordinate(outcome).await?;
+                        }
+                    } else if message.topic == self.liveness.topic().hash() {
+                        let local = *self.swarm.local_peer_id();
+                        for sighting in self.liveness.decode(&message.data) {
+                            if sighting.peer != local {
+                                self.peers.report_sighting(&sighting.peer, sighting.at)?;
+                            }
+                        }
+                    } else if let Some(room) = self.topics.get(&message.topic).cloned() {
+                        self.receive_room_message(
+                            room,
+                            message.source,
+                            propagation_source,
+                            message_id.to_string(),
+                            Bytes::from(message.data),
+                            None,
+                        )
+                        .await;
+                    }
+                }
+                gossipsub::Event::Subscribed { peer_id, topic } => {
+                    if let Some(room) = self.topics.get(&topic) {
+                        self.rooms.deliver(room, RoomEvent::Joined(peer_id));
+                    }
+                    if topic == self.feeds.topic().hash() {
+                        self.feeds.catch_up(
+                            self.control.clone(),
+                            vec![peer_id],
+                            None,
+                            self.events.clone(),
+                            None,
+                        );
+                    }
+                    self.presence.join(topic, peer_id);
+                }
+                gossipsub::Event::Unsubscribed { peer_id, topic } => {
+                    let left = self.presence.leave(&topic, &peer_id);
+                    if let Some(room) = self.topics.get(&topic).filter(|_| left) {
+                        self.rooms.deliver(room, RoomEvent::Left(peer_id));
+                    }
+                }
+                _ => {}
+            },
+            SwarmEvent::Behaviour(BehaviourEvent::Dcutr(event)) => {
+                self.nat.on_hole_punch(event.result.is_ok());
+                if let Some(doctor) = self.doctor.as_mut() {
+                    doctor.on_hole_punch(event.remote_peer_id, event.result.is_ok());
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Rendezvous(
+                libp2p::rendezvous::client::Event::Discovered {
+                    rendezvous_node,
+                    registrations,
+                    ..
+                },
+            )) => {
+                self.registrations
+                    .insert(rendezvous_node, registrations.clone());
+                self.receive_registrations(rendezvous_node, registrations)
+                    .await?;
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Rendezvous(
+                libp2p::rendezvous::client::Event::Registered {
+                    rendezvous_node,
+                    namespace,
+                    ..
+                },
+            )) => {
+                match self.registrations.get(&rendezvous_node) {
+                    Some(registrations) => {
+                        self.receive_registrations(rendezvous_node, registrations)
+                            .await?
+                    }
+                    None => self.swarm.behaviour_mut().rendezvous.discover(
+                        Some(namespace),
+                        None,
+                        None,
+                        rendezvous_node,
+                    ),
+                }
+                self.journal.settle_registration(&rendezvous_node)?;
+                let stages = self.bootstrap.on_registered(&rendezvous_node);
+                self.advance(stages).await?;
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Rendezvous(
+                libp2p::rendezvous::client::Event::RegisterFailed {
+                    rendezvous_node, ..
+                },
+            )) => {
+                let stages = self.bootstrap.on_register_failed(&rendezvous_node);
+                self.advance(stages).await?;
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Kad(libp2p::kad::Event::RoutingUpdated {
+                peer,
+                addresses,
+                old_peer,
+                ..
+            })) => {
+                let _ = self.routing.record(&RoutingEntry {
+                    peer,
+                    addresses: addresses.into_vec(),
+                });
+                if let Some(old_peer) = old_peer {
+                    let _ = self.routing.evict(&old_peer);
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Kad(
+                libp2p::kad::Event::OutboundQueryProgressed {
+                    result: libp2p::kad::QueryResult::Bootstrap(result),
+                    ..
+                },
+            )) => {
+                let stages = match result {
+                    Ok(progress) if progress.num_remaining > 0 => Vec::new(),
+                    Ok(_) => self.bootstrap.on_dht(true),
+                    Err(_) => self.bootstrap.on_dht(false),
+                };
+                self.advance(stages).await?;
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Kad(
+                libp2p::kad::Event::OutboundQueryProgressed {
+                    result:
+                        libp2p::kad::QueryResult::GetProviders(Ok(
+                            libp2p::kad::GetProvidersOk::FoundProviders { key, providers },
+                        )),
+                    ..
+                },
+            )) => {
+                self.providers.update(key.clone(), HashSet::new(), |known| {
+                    known.extend(providers.iter())
+                });
+                self.dial_providers(&key, providers);
+            }
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                connection_id,
+                endpoint,
+                ..
+            } => {
+                let relayed = endpoint.is_relayed();
+                self.stats.record_connect();
+                self.departed.remove(&peer_id);
+                self.breaker.success(&peer_id);
+                self.peers.seen(&peer_id)?;
+                match &endpoint {
+                    ConnectedPoint::Dialer { address, .. } => {
+                        self.peers.address_succeeded(&peer_id, address)?
+                    }
+                    ConnectedPoint::Listener {
+                        local_addr,
+                        send_back_addr,
+                    } => {
+                        self.emit(Event::InboundConnection {
+                            peer: peer_id,
+                            remote_addr: send_back_addr.clone(),
+                            transport: transport_of(local_addr),
+                            relayed,
+                        })
+                        .await
+                    }
+                }
+                self.dialer.on_established(
+                    peer_id,
+                    connection_id,
+                    endpoint.get_remote_address(),
+                    relayed,
+                );
+                if let Some(doctor) = self.doctor.as_mut() {
+                    doctor.on_connected(peer_id, relayed);
+                }
+                self.distribute_group_key(vec![peer_id])?;
+                #[cfg(feature = "ratchet")]
+                self.ratchets.refresh(self.control.clone(), peer_id);
+                if self
+                    .ipfs
+                    .as_mut()
+                    .is_some_and(|ipfs| ipfs.on_connected(&peer_id))
+                    && !self.peers.contains(&peer_id)?
+                {
+                    let mut peer = Peer::new(
+                        PeerType::Discovered,
+                        peer_id,
+                        endpoint.get_remote_address().clone(),
+                    );
+                    peer.group = Some(self.group.clone());
+                    self.peers.insert(peer.clone())?;
+                    self.discovered(peer).await;
+                }
+                if let Some(stage) = self.bootstrap.on_connected(&peer_id) {
+                    let mut stages = vec![stage];
+                    stages.extend(self.join()?);
+                    self.advance(stages).await?;
+                }
+            }
+            SwarmEvent::OutgoingConnectionError {
+                peer_id: Some(peer_id),
+                error,
+                ..
+            } => {
+                if let DialError::Transport(attempts) = &error {
+                    for (address, _) in attempts {
+                        self.peers.address_failed(&peer_id, address)?;
+                    }
+                }
+                self.bootstrap.on_dial_failed(&peer_id);
+                self.breaker.failure(&peer_id);
+                if let Some(ipfs) = self.ipfs.as_mut() {
+                    ipfs.on_dial_failed(&peer_id);
+                }
+                if let Some(addresses) = self.dialer.fallback(&peer_id) {
+                    self.dial_fallback(peer_id, addresses);
+                }
+            }
+            SwarmEvent::ConnectionClosed {
+                peer_id,
+                connection_id,
+                num_established,
+                cause,
+                ..
+            } => {
+                if let Some(address) = self.dialer.on_closed(&peer_id, &connection_id) {
+                    self.emit(Event::ConnectionMigrated {
+                        peer: peer_id,
+                        address,
+                    })
+                    .await;
+                }
+                self.stats.record_disconnect();
+                self.peers.seen(&peer_id)?;
+                if num_established == 0 {
+                    self.granted.remove(&peer_id);
+                    let reason = self
+                        .closing
+                        .remove(&peer_id)
+                        .unwrap_or_else(|| DisconnectReason::from_cause(cause.as_ref()));
+                    self.emit(Event::PeerDisconnected {
+                        peer: peer_id,
+                        reason,
+                    })
+                    .await;
+                    for topic in self.presence.disconnect(&peer_id) {
+                        if let Some(room) = self.topics.get(&topic) {
+                            self.rooms.deliver(room, RoomEvent::Left(peer_id));
+                        }
+                    }
+                    self.nat.on_relay_lost(&peer_id);
+                    let lost = self.relays.on_lost_peer(&peer_id);
+                    self.fail_over(lost).await?;
+                }
+            }
+            SwarmEvent::NewListenAddr {
+                listener_id,
+                address,
+            } => {
+                if let Some(listener) = self.listeners.on_new_address(&listener_id, &address) {
+                    self.emit(Event::ListeningOn { listener, address }).await
+                }
+            }
+            SwarmEvent::ExpiredListenAddr {
+                listener_id,
+                address,
+            } => {
+                if let Some(listener) = self.listeners.on_expired(&listener_id, &address) {
+                    self.emit(Event::ListenerExpired { listener, address })
+                        .await
+                }
+            }
+            SwarmEvent::ListenerError { listener_id, error } => {
+                if let Some(listener) = self.listeners.id(&listener_id) {
+                    self.emit(Event::ListenerFailed {
+                        listener,
+                        error: error.to_string(),
+                    })
+                    .await
+                }
+            }
+            SwarmEvent::ListenerClosed {
+                listener_id,
+                reason,
+                ..
+            } => {
+                if let Some(listener) = self.listeners.on_closed(&listener_id) {
+                    self.emit(Event::ListenerClosed {
+                        listener,
+                        error: reason.err().map(|e| e.to_string()),
+                    })
+                    .await
+                }
+                let lost = self.relays.on_lost_listener(&listener_id);
+                self.fail_over(lost).await?;
+            }
+            _ => {}
+        }
+
+        self.check_doctor().await
+    }
+
+    fn announce_departure(&mut self) -> Result<Receiver<()>, Box<dyn Error + Send + Sync>> {
+        let notice = Departure::sign(&self.key, &self.group).ok_or("Unable to sign departure")?;
+        let connected: Vec<PeerId> = self.swarm.connected_peers().copied().collect();
+        Ok(leave::announce(self.control.clone(), connected, notice))
+    }
+
+    async fn change_group(&mut self, group: String) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        if group == self.group {
+            return Ok(false);
+        }
+
+        let _ = self.announce_departure();
+        let previous = Namespace::new(self.group.clone())?;
+        let namespace = Namespace::new(group.clone())?;
+        let servers: Vec<PeerId> = self
+            .rendezvous
+            .iter()
+            .copied()
+            .chain(
+                self.swarm
+                    .connected_peers()
+                    .copied()
+                    .filter(|peer| self.bootstrap.is_bootstrap(peer)),
+            )
+            .collect();
+        for server in servers.iter() {
+            self.swarm
+                .behaviour_mut()
+                .rendezvous
+                .unregister(previous.clone(), *server);
+        }
+
+        let mut topics = vec![
+            self.feeds.topic().clone(),
+            self.liveness.topic().clone(),
+            self.coordinator.topic().clone(),
+            self.updates.topic().clone(),
+        ];
+        for room in self.rooms.names() {
+            topics.push(Rooms::topic(&self.group, &room));
+        }
+        for topic in topics {
+            let _ = self.swarm.behaviour_mut().gossipsub.unsubscribe(&topic);
+            self.topics.remove(&topic.hash());
+        }
+        if let Some(ipfs) = self.ipfs.as_mut() {
+            let kad = &mut self.swarm.behaviour_mut().kad;
+            kad.stop_providing(&ipfs.key());
+            *ipfs = IpfsDiscovery::new(&group);
+            if self.listen {
+                let _ = kad.start_providing(ipfs.key());
+            }
+        }
+
+        let from = std::mem::replace(&mut self.group, group);
+        self.feeds.set_group(&self.group);
+        self.updates.set_group(&self.group);
+        self.liveness = LivenessGossip::new(&self.group);
+        self.coordinator = Coordinator::new(&self.group, *self.swarm.local_peer_id());
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(announcer) = self.lan_announcer.as_ref() {
+            announcer.set_group(&self.group);
+        }
+        self.departed.clear();
+        self.granted.clear();
+        self.keyring = Keyring::new();
+        if self.group_admin {
+            self.keyring.rotate();
+        }
+        self.providers.flush();
+        self.registrations.flush();
+
+        for server in servers {
+            let rendezvous = &mut self.swarm.behaviour_mut().rendezvous;
+            let _ = rendezvous.register(namespace.clone(), server, None);
+            rendezvous.discover(Some(namespace.clone()), None, None, server);
+        }
+        let mut topics = vec![
+            self.feeds.topic().clone(),
+            self.liveness.topic().clone(),
+            self.coordinator.topic().clone(),
+        ];
+        if self.updates.is_enabled() {
+            topics.push(self.updates.topic().clone());
+        }
+        for topic in topics {
+            self.swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
+        }
+        if !self.paused {
+            self.restore_rooms().await;
+        }
+
+        self.emit(Event::GroupChanged {
+            from,
+            to: self.group.clone(),
+        })
+        .await;
+        Ok(true)
+    }
+
+    async fn depart(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let finished = self.announce_departure()?;
+        let mut deadline = Box::pin(sleep(DEPARTURE_TIMEOUT).fuse());
+        loop {
+            select_biased! {
+                _ = finished.recv().fuse() => return Ok(()),
+                _ = deadline => return Ok(()),
+                event = self.swarm.select_next_some() => self.handle_event(event).await?,
+            }
+        }
+    }
+
+    async fn handle_departure(
+        &mut self,
+        peer: PeerId,
+        notice: Departure,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !notice.verify(&peer, &self.group) {
+            return Ok(());
+        }
+
+        self.departed.insert(peer);
+        self.outbox().forget(&peer);
+        for topic in self.presence.disconnect(&peer) {
+            if let Some(room) = self.topics.get(&topic) {
+                self.rooms.deliver(room, RoomEvent::Left(peer));
+            }
+        }
+        self.disconnect(peer, DisconnectReason::Departed);
+        self.emit(Event::PeerDeparted(peer)).await;
+        Ok(())
+    }
+
+    async fn install_group_key(
+        &mut self,
+        peer: PeerId,
+        key: GroupKey,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let epoch = key.epoch;
+        if self.group_admins.contains(&peer) && self.keyring.install(key) {
+            self.emit(Event::GroupKeyRotated { epoch }).await;
+        }
+        Ok(())
+    }
+
+    fn rotate_group_key(&mut self) -> Option<u64> {
+        if !self.group_admin {
+            return None;
+        }
+
+        let due = self
+            .keyring
+            .current()
+            .is_none_or(|key| Utc::now() - key.created >= self.key_rotation);
+        due.then(|| self.keyring.rotate().epoch)
+    }
+
+    fn distribute_group_key(
+        &mut self,
+        candidates: Vec<PeerId>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if !self.group_admin {
+            return Ok(());
+        }
+        let Some(key) = self.keyring.current().cloned() else {
+            return Ok(());
+        };
+
+        for peer in candidates {
+            if self.granted.get(&peer) == Some(&key.epoch) || !self.swarm.is_connected(&peer) {
+                continue;
+            }
+            let member = self
+                .peers
+                .get(&peer)?
+                .is_some_and(|known| known.group.as_ref() == Some(&self.group));
+            if member {
+                self.granted.insert(peer, key.epoch);
+                groupkey::grant(self.control.clone(), peer, key.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn disconnect(&mut self, peer: PeerId, reason: DisconnectReason) {
+        if self.swarm.disconnect_peer_id(peer).is_ok() {
+            self.closing.insert(peer, reason);
+        }
+    }
+
+    fn outbox(&self) -> MutexGuard<'_, Outbox> {
+        self.outbox.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn message_limit(&self, peer: &PeerId) -> usize {
+        self.limits
+            .get(peer)
+            .map_or(self.max_message, |limit| self.max_message.min(*limit))
+    }
+
+    fn compatible(&self, protocol_version: &str) -> bool {
+        ProtocolVersion::parse(protocol_version)
+            .is_none_or(|theirs| self.version.compatible(&theirs))
+    }
+
+    async fn restore_rooms(&mut self) {
+        let mut restored = Vec::new();
+        for room in self.rooms.names() {
+            let topic = Rooms::topic(&self.group, &room);
+            if self
+                .swarm
+                .behaviour_mut()
+                .gossipsub
+                .subscribe(&topic)
+                .is_ok()
+            {
+                self.topics.insert(topic.hash(), room.clone());
+                restored.push(room);
+            }
+        }
+
+        if !restored.is_empty() {
+            self.emit(Event::SubscriptionsRestored(restored)).await;
+        }
+    }
+
+    async fn replay_journal(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for (id, command) in self.journal.pending()? {
+            let (command, _) = command.wrap_with_id(id);
+            self.run_command(command).await?;
+        }
+        Ok(())
+    }
+
+    async fn receive_registrations(
+        &mut self,
+        rendezvous_node: PeerId,
+        registrations: Vec<Registration>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let local = *self.swarm.local_peer_id();
+        let mut members = Vec::new();
+        for registration in registrations.iter() {
+            let id = registration.record.peer_id();
+            let Some(address) = registration.record.addresses().first() else {
+                continue;
+            };
+            if id == local || registration.namespace.to_string() != self.group {
+                continue;
+            }
+
+            let known = self.peers.get(&id)?;
+            let is_new = known.is_none();
+            let mut peer =
+                known.unwrap_or_else(|| Peer::new(PeerType::Discovered, id, address.clone()));
+            peer.group = Some(self.group.clone());
+            self.peers.insert(peer.clone())?;
+            members.push(id);
+            if is_new {
+                self.discovered(peer).await;
+            }
+        }
+        self.distribute_group_key(members)?;
+
+        if let Some(doctor) = self.doctor.as_mut() {
+            let registered: Vec<PeerId> = registrations
+                .iter()
+                .map(|registration| registration.record.peer_id())
+                .collect();
+            doctor.on_discovered(rendezvous_node, &registered);
+        }
+        Ok(())
+    }
+
+    async fn emit(&mut self, event: Event) {
+        let _ = self.events.send(event).await;
+    }
+
+    async fn discovered(&mut self, peer: Peer) {
+        if let Some(event) = self.coalescer.push(peer) {
+            self.emit(event).await;
+        }
+    }
+
+    async fn handle_stream(
+        &mut self,
+        peer: PeerId,
+        stream: Stream,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.admits(peer, &MODIUS_PROTOCOL) {
+            self.delivery.accept(peer, stream);
+        }
+        Ok(())
+    }
+
+    fn admits(&self, peer: PeerId, protocol: &StreamProtocol) -> bool {
+        self.acl
+            .check(peer, Permission::Protocol(protocol.to_string()))
+    }
+
+    fn group_size(&self) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let local = *self.swarm.local_peer_id();
+        Ok(self
+            .peers
+            .list()?
+            .into_iter()
+            .filter(|peer| peer.group.as_ref() == Some(&self.group) && peer.id != local)
+            .count()
+            + 1)
+    }
+
+    fn connected_members(&self) -> Result<Vec<PeerId>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .peers
+            .list()?
+            .into_iter()
+            .filter(|peer| peer.group.as_ref() == Some(&self.group))
+            .filter(|peer| self.swarm.is_connected(&peer.id))
+            .map(|peer| peer.id)
+            .collect())
+    }
+
+    async fn receive_room_message(
+        &mut self,
+        room: String,
+        source: Option<PeerId>,
+        propagation_source: PeerId,
+        id: String,
+        data: Bytes,
+        expected: Option<CausalHeader>,
+    ) {
+        let peer = source.unwrap_or(propagation_source);
+        let opened = self
+            .keyring
+            .open(data.clone())
+            .and_then(CausalHeader::unwrap);
+        let (header, payload) = match opened {
+            Ok((header, _)) if expected.is_some() && header != expected => {
+                self.dead.bury_room(
+                    peer,
+                    &room,
+                    data,
+                    DeadReason::Undecodable("Mismatched causal header".to_string()),
+                );
+                return;
+            }
+            Ok(opened) => opened,
+            Err(e) => {
+                self.dead
+                    .bury_room(peer, &room, data, DeadReason::Undecodable(e.to_string()));
+                return;
+            }
+        };
+
+        if let Some(header) = header {
+            let observation = self.causality.observe(&room, &header);
+            for (from, range) in observation.gaps {
+                let mut peers = vec![from];
+                if propagation_source != from {
+                    peers.push(propagation_source);
+                }
+                self.causality.repair(
+                    self.control.clone(),
+                    peers,
+                    room.clone(),
+                    from,
+                    range.clone(),
+                );
+                self.emit(Event::MissedMessages {
+                    from,
+                    room: room.clone(),
+                    range,
+                })
+                .await;
+            }
+            if !observation.fresh {
+                return;
+            }
+            self.causality.retain(&room, CausalMessage { header, data });
+        }
+
+        self.history.record(&room, id, source, payload.clone());
+        self.rooms.deliver(
+            &room,
+            RoomEvent::Message {
+                peer: source,
+                payload,
+            },
+        );
+    }
+
+    async fn receive_feed_entry(
+        &mut self,
+        source: PeerId,
+        entry: FeedEntry,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.feeds.accept(&entry)? {
+            Appended::Accepted => self.emit(Event::FeedAppended(entry)).await,
+            Appended::Gap { .. } => {
+                let mut peers = vec![source];
+                if entry.author != source && self.swarm.is_connected(&entry.author) {
+                    peers.push(entry.author);
+                }
+                self.feeds.catch_up(
+                    self.control.clone(),
+                    peers,
+                    Some(entry.feed),
+                    self.events.clone(),
+                    None,
+                );
+            }
+            Appended::Duplicate | Appended::Rejected => {}
+        }
+        Ok(())
+    }
+
+    fn repair_shards(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let grace =
+            TimeDelta::from_std(self.replicator.policy().repair_after).unwrap_or(TimeDelta::MAX);
+        let cutoff = Utc::now().checked_sub_signed(grace);
+        let members: HashSet<PeerId> = self.connected_members()?.into_iter().collect();
+
+        for placement in self.replicator.placements()? {
+            let mut offline = Vec::new();
+            for (index, holder) in placement.holders.iter().enumerate() {
+                if self.swarm.is_connected(holder) {
+                    continue;
+                }
+                let last_seen = self.peers.get(holder)?.and_then(|peer| peer.last_seen);
+                if cutoff.is_some_and(|cutoff| last_seen.is_none_or(|seen| seen < cutoff)) {
+                    offline.push(index);
+                }
+            }
+            if !offline.is_empty() {
+                self.replicator
+                    .repair(placement, offline, members.clone(), self.events.clone());
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_admin(&mut self, call: AdminCall) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let permission = Permission::Command(call.request.name().to_string());
+        let reply = if self.acl.check(call.peer, permission) {
+            self.administer(call.peer, call.request)
+                .await
+                .map_err(|e| e.to_string())
+        } else {
+            Err(String::from("Access denied"))
+        };
+        let _ = call.reply.send(reply).await;
+        Ok(())
+    }
+
+    async fn administer(
+        &mut self,
+        peer: PeerId,
+        request: AdminRequest,
+    ) -> Result<Value, Box<dyn Error + Send + Sync>> {
+        match request {
+            AdminRequest::Metrics => Ok(serde_json::to_value(AdminMetrics {
+                connected: self.swarm.connected_peers().count(),
+                known: self.peers.list()?.len(),
+                reservations: self.relays.count(),
+                rooms: self.rooms.names(),
+                dead_letters: self.dead.list(false).len(),
+                group_key_epoch: self.keyring.current().map(|key| key.epoch),
+            })?),
+            AdminRequest::Peers(filter) => {
+                let listed = filter.apply(self.peers.list()?, |id| self.swarm.is_connected(id));
+                Ok(serde_json::to_value(listed)?)
+            }
+            AdminRequest::Reconfigure(changes) => {
+                if let Some(prune) = changes.prune.clone() {
+                    self.prune = prune;
+                }
+                if let Some(min) = changes.min_reservations {
+                    self.auto_relay = (min > 0).then_some(min);
+                }
+                if let Some(rotation) = changes.group_key_rotation {
+                    self.key_rotation = TimeDelta::from_std(rotation).unwrap_or(TimeDelta::MAX);
+                }
+                self.emit(Event::Reconfigured { by: peer, changes }).await;
+                Ok(Value::Null)
+            }
+        }
+    }
+
+    async fn handle_violation(
+        &mut self,
+        violation: Violation,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let strikes = self.strikes.entry(violation.peer).or_default();
+        *strikes += 1;
+        let strikes = *strikes;
+
+        if self
+            .acl
+            .max_violations()
+            .is_some_and(|limit| strikes >= limit)
+        {
+            self.disconnect(violation.peer, DisconnectReason::AccessDenied);
+        }
+        self.emit(Event::AclViolation {
+            peer: violation.peer,
+            permission: violation.permission,
+            strikes,
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn handle_report(
+        &mut self,
+        report: StreamReport,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if report.frames > 0 {
+            self.peers.seen(&report.peer)?;
+        }
+        let violations = report.replays + report.oversized;
+        if violations > 0 {
+            let strikes = self.strikes.entry(report.peer).or_default();
+            *strikes += violations as u32;
+            if self
+                .acl
+                .max_violations()
+                .is_some_and(|limit| *strikes >= limit)
+            {
+                self.disconnect(report.peer, DisconnectReason::AccessDenied);
+            }
+        }
+        Ok(())
+    }
+
+    async fn prune_peers(&mut self) -> Result<Vec<PeerId>, Box<dyn Error + Send + Sync>> {
+        self.next_prune = Utc::now() + PRUNE_INTERVAL;
+        let pruned = self
+            .peers
+            .prune(&self.prune, |id| self.swarm.is_connected(id))?;
+        if !pruned.is_empty() {
+            self.emit(Event::PeersPruned(pruned.clone())).await;
+        }
+        Ok(pruned)
+    }
+
+    fn maintain_relays(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(minimum) = self.auto_relay else {
+            return Ok(());
+        };
+        if Utc::now() < self.next_relay_check {
+            return Ok(());
+        }
+
+        self.next_relay_check = Utc::now() + RELAY_CHECK_INTERVAL;
+        while self.relays.count() < minimum {
+            if self.select_relay()?.is_none() {
+                let _ = self
+                    .swarm
+                    .behaviour_mut()
+                    .kad
+                    .get_closest_peers(PeerId::random());
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn pause(&mut self) -> bool {
+        if self.paused {
+            return false;
+        }
+
+        self.paused = true;
+        for room in self.rooms.names() {
+            let topic = Rooms::topic(&self.group, &room);
+            let _ = self.swarm.behaviour_mut().gossipsub.unsubscribe(&topic);
+            self.topics.remove(&topic.hash());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.swarm.behaviour_mut().mdns = Toggle::from(None);
+            if let Some(announcer) = self.lan_announcer.as_ref() {
+                announcer.pause(true);
+            }
+        }
+        self.emit(Event::Paused).await;
+        true
+    }
+
+    async fn resume(&mut self) -> bool {
+        if !self.paused {
+            return false;
+        }
+
+        self.paused = false;
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.listen {
+            let local = *self.swarm.local_peer_id();
+            self.swarm.behaviour_mut().mdns =
+                Toggle::from(Mdns::new(libp2p::mdns::Config::default(), local).ok());
+            if let Some(announcer) = self.lan_announcer.as_ref() {
+                announcer.pause(false);
+            }
+        }
+        self.restore_rooms().await;
+        self.emit(Event::Resumed).await;
+        true
+    }
+
+    fn handle_lan_identity(
+        &mut self,
+        identity: LanIdentity,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.peers.update(&identity.peer, |peer| {
+            peer.name = Some(identity.name.clone());
+            peer.group = Some(identity.group.clone());
+            peer.device = identity.device.or(peer.device);
+        })?;
+        self.lan.insert(identity.peer, identity);
+        Ok(())
+    }
+
+    async fn coordinate(
+        &mut self,
+        outcome: coordination::Outcome,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let topic = self.coordinator.topic().clone();
+        for signal in outcome.publish {
+            if let Some(data) = Coordinator::encode(&signal) {
+                let _ = self
+                    .swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .publish(topic.clone(), data);
+            }
+        }
+        for (command, result) in outcome.resolved {
+            command.respond(result).await?;
+        }
+        for (key, value) in outcome.decided {
+            self.emit(Event::ConsensusReached { key, value }).await;
+        }
+        Ok(())
+    }
+
+    fn gossip_liveness(&mut self) {
+        let now = Utc::now();
+        let sightings = self
+            .swarm
+            .connected_peers()
+            .map(|peer| Sighting {
+                peer: *peer,
+                at: now,
+            })
+            .collect();
+        if let Some(data) = self.liveness.encode(sightings) {
+            let topic = self.liveness.topic().clone();
+            let _ = self.swarm.behaviour_mut().gossipsub.publish(topic, data);
+        }
+    }
+
+    async fn maintain_network(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.maintain_relays()?;
+        if Utc::now() >= self.next_repair && self.budget_level() == BudgetLevel::Normal {
+            self.next_repair = Utc::now() + REPAIR_INTERVAL;
+            self.repair_shards()?;
+        }
+        if self.prune.is_enabled() && Utc::now() >= self.next_prune {
+            self.prune_peers().await?;
+        }
+        for (peer, addresses) in self.dialer.due() {
+            self.dial_fallback(peer, addresses);
+        }
+        for circuit in self.dialer.drained() {
+            self.swarm.close_connection(circuit);
+        }
+        if let Some(key) = self.ipfs.as_mut().and_then(IpfsDiscovery::lookup_due) {
+            match self.providers.get(&key) {
+                Some(providers) => self.dial_providers(&key, providers),
+                None => {
+                    self.swarm.behaviour_mut().kad.get_providers(key);
+                }
+            }
+        }
+        for id in self.bootstrap.due() {
+            if let Some(peer) = self.peers.get(&id)? {
+                if let Err(DialError::Denied { .. }) = self.dial_peer(&peer) {
+                    self.bootstrap.on_dial_failed(&id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn budget_level(&self) -> BudgetLevel {
+        self.budget
+            .as_ref()
+            .map_or(BudgetLevel::Normal, BudgetMeter::level)
+    }
+
+    async fn check_budget(&mut self) {
+        let Some(budget) = self.budget.as_mut() else {
+            return;
+        };
+
+        if budget.update() == Some(BudgetLevel::Exceeded) {
+            let usage = budget.usage();
+            self.emit(Event::BandwidthBudgetExceeded {
+                used: usage.used,
+                limit: usage.limit,
+            })
+            .await;
+        }
+    }
+
+    fn service_providers(
+        &self,
+        service: &str,
+    ) -> Result<Vec<rpc::Provider>, Box<dyn Error + Send + Sync>> {
+        let Some(protocol) = rpc::service_protocol(service) else {
+            return Ok(Vec::new());
+        };
+        Ok(self
+            .peers
+            .list()?
+            .into_iter()
+            .filter(|peer| self.swarm.is_connected(&peer.id))
+            .filter(|peer| peer.services.iter().any(|s| s == protocol.as_ref()))
+            .map(|peer| (peer.id, peer.stats.rtt_ms))
+            .collect())
+    }
+
+    fn advertise_services(&mut self) {
+        for service in self.services.service_names() {
+            if self.advertised.contains(&service) {
+                continue;
+            }
+            let Some(protocol) = rpc::service_protocol(&service) else {
+                continue;
+            };
+            if let Ok(incoming) = self.control.accept(protocol) {
+                self.service_streams.push(incoming);
+                self.advertised.insert(service);
+            }
+        }
+    }
+
+    async fn handle_tick(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.check_budget().await;
+        self.advertise_services();
+        if !self.paused {
+            self.maintain_network().await?;
+        }
+        if let Some(epoch) = self.rotate_group_key() {
+            let connected: Vec<PeerId> = self.swarm.connected_peers().copied().collect();
+            self.distribute_group_key(connected)?;
+            self.emit(Event::GroupKeyRotated { epoch }).await;
+        }
+        if let Some(event) = self.coalescer.flush() {
+            self.emit(event).await;
+        }
+        if !self.paused && self.liveness.due() {
+            self.gossip_liveness();
+        }
+        let outcome = self.coordinator.tick();
+        self.coordinate(outcome).await?;
+        for scheduled in self.scheduler.due() {
+            let (command, _) = scheduled.wrap();
+            self.run_command(command).await?;
+        }
+
+        self.check_doctor().await
+    }
+
+    async fn event_loop(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut inbox = self
+            .swarm
+            .behaviour()
+            .stream
+            .new_control()
+            .accept(MODIUS_PROTOCOL)?
+            .fuse();
+        let mut history = self.control.accept(HISTORY_PROTOCOL)?.fuse();
+        let mut leaving = self.control.accept(LEAVE_PROTOCOL)?.fuse();
+        let (tx_departed, rx_departed) = async_channel::unbounded::<(PeerId, Departure)>();
+        let mut rx_departed = pin!(rx_departed);
+        let mut keys = self.control.accept(GROUP_KEY_PROTOCOL)?.fuse();
+        let (tx_grants, rx_grants) = async_channel::unbounded::<(PeerId, GroupKey)>();
+        let mut rx_grants = pin!(rx_grants);
+        let mut admins = self.control.accept(ADMIN_PROTOCOL)?.fuse();
+        let (tx_calls, rx_calls) = async_channel::unbounded::<AdminCall>();
+        let mut rx_calls = pin!(rx_calls);
+        let mut shards = self.control.accept(SHARD_PROTOCOL)?.fuse();
+        let mut syncs = self.control.accept(SYNC_PROTOCOL)?.fuse();
+        let mut feeds = self.control.accept(FEED_PROTOCOL)?.fuse();
+        let mut causal = self.control.accept(CAUSAL_PROTOCOL)?.fuse();
+        let mut probes = self.control.accept(PROBE_PROTOCOL)?.fuse();
+        let mut rpcs = self.control.accept(RPC_PROTOCOL)?.fuse();
+        #[cfg(feature = "ratchet")]
+        self.ratchets.serve(self.control.accept(RATCHET_PROTOCOL)?);
+        let mut reports = pin!(self.reports.clone());
+        let mut violations = pin!(self.violations.clone());
+        let mut retransmitted = pin!(self.retransmitted.clone());
+        let mut lan_found = pin!(self.lan_found.clone());
+        let mut tick = Box::pin(sleep(TICK).fuse());
+        let mut tick_due = Utc::now() + TICK;
+        self.heartbeat.tick();
+        loop {
+            let event = select! {
+                command = self.commands.recv().fuse() => match command {
+                    Ok(command) => LoopEvent::Command(command),
+                    Err(_) => return self.depart().await,
+                },
+                event = self.swarm.select_next_some() => LoopEvent::Swarm(event),
+                (peer, stream) = inbox.select_next_some() => LoopEvent::Stream(peer, stream),
+                (peer, stream) = history.select_next_some() => LoopEvent::HistoryRequest(peer, stream),
+                (peer, stream) = leaving.select_next_some() => LoopEvent::Departure(peer, stream),
+                (peer, notice) = rx_departed.select_next_some() => LoopEvent::Departed(peer, notice),
+                (peer, stream) = keys.select_next_some() => LoopEvent::GroupKeyGrant(peer, stream),
+                (peer, key) = rx_grants.select_next_some() => LoopEvent::GroupKeyReceived(peer, key),
+                report = reports.select_next_some() => LoopEvent::StreamDone(report),
+                violation = violations.select_next_some() => LoopEvent::Violation(violation),
+                (peer, stream) = admins.select_next_some() => LoopEvent::AdminStream(peer, stream),
+                call = rx_calls.select_next_some() => LoopEvent::AdminCall(call),
+                (peer, stream) = shards.select_next_some() => LoopEvent::ShardStream(peer, stream),
+                (peer, stream) = syncs.select_next_some() => LoopEvent::SyncStream(peer, stream),
+                (peer, stream) = feeds.select_next_some() => LoopEvent::FeedStream(peer, stream),
+                (peer, stream) = causal.select_next_some() => LoopEvent::CausalStream(peer, stream),
+                (peer, stream) = probes.select_next_some() => LoopEvent::ProbeStream(peer, stream),
+                (peer, stream) = rpcs.select_next_some() => LoopEvent::RpcStream(peer, stream),
+                (peer, stream) = self.service_streams.select_next_some() => LoopEvent::RpcStream(peer, stream),
+                (room, message) = retransmitted.select_next_some() => LoopEvent::Retransmitted(room, message),
+                identity = lan_found.select_next_some() => LoopEvent::LanIdentity(identity),
+                _ = tick => LoopEvent::Tick,
+            };
+
+            self.heartbeat.processing(event.label());
+            match event {
+                LoopEvent::Command(command) => {
+                    let started = Utc::now();
+                    let result = self.run_command(command).await;
+                    self.stats.record_latency(Utc::now() - started);
+                    result
+                }
+                LoopEvent::Swarm(event) => self.handle_event(event).await,
+                LoopEvent::Stream(peer, stream) => self.handle_stream(peer, stream).await,
+                LoopEvent::HistoryRequest(peer, stream) => {
+                    if self.admits(peer, &HISTORY_PROTOCOL) {
+                        self.history.serve(stream);
+                    }
+                    Ok(())
+                }
+                LoopEvent::Departure(peer, stream) => {
+                    if self.admits(peer, &LEAVE_PROTOCOL) {
+                        leave::receive(peer, stream, tx_departed.clone());
+                    }
+                    Ok(())
+                }
+                LoopEvent::Departed(peer, notice) => self.handle_departure(peer, notice).await,
+                LoopEvent::GroupKeyGrant(peer, stream) => {
+                    if self.admits(peer, &GROUP_KEY_PROTOCOL) {
+                        groupkey::receive(peer, stream, tx_grants.clone());
+                    }
+                    Ok(())
+                }
+                LoopEvent::GroupKeyReceived(peer, key) => self.install_group_key(peer, key).await,
+                LoopEvent::StreamDone(report) => self.handle_report(report).await,
+                LoopEvent::Violation(violation) => self.handle_violation(violation).await,
+                LoopEvent::AdminStream(peer, stream) => {
+                    if self.admits(peer, &ADMIN_PROTOCOL) {
+                        admin::receive(peer, stream, tx_calls.clone());
+                    }
+                    Ok(())
+                }
+                LoopEvent::AdminCall(call) => self.handle_admin(call).await,
+                LoopEvent::ShardStream(peer, stream) => {
+                    if self.admits(peer, &SHARD_PROTOCOL) {
+                        self.replicator.serve(stream);
+                    }
+                    Ok(())
+                }
+                LoopEvent::SyncStream(peer, stream) => {
+                    if self.admits(peer, &SYNC_PROTOCOL) {
+                        self.shares.serve(stream);
+                    }
+                    Ok(())
+                }
+                LoopEvent::FeedStream(peer, stream) => {
+                    if self.admits(peer, &FEED_PROTOCOL) {
+                        self.feeds.serve(stream);
+                    }
+                    Ok(())
+                }
+                LoopEvent::CausalStream(peer, stream) => {
+                    if self.admits(peer, &CAUSAL_PROTOCOL) {
+                        self.causality.serve(stream);
+                    }
+                    Ok(())
+                }
+                LoopEvent::ProbeStream(peer, stream) => {
+                    if self.admits(peer, &PROBE_PROTOCOL) {
+                        probe::serve(stream);
+                    }
+                    Ok(())
+                }
+                LoopEvent::RpcStream(peer, stream) => {
+                    if self.admits(peer, &RPC_PROTOCOL) {
+                        self.services.accept(peer, stream);
+                    }
+                    Ok(())
+                }
+                LoopEvent::Retransmitted(room, message) => {
+                    if self.topics.values().any(|joined| *joined == room) {
+                        let sender = message.header.sender;
+                        let id = format!("{sender}:{}", message.header.seq);
+                        self.receive_room_message(
+                            room,
+                            Some(sender),
+                            sender,
+                            id,
+                            message.data,
+                            Some(message.header),
+                        )
+                        .await;
+                    }
+                    Ok(())
+                }
+                LoopEvent::LanIdentity(identity) => self.handle_lan_identity(identity),
+                LoopEvent::Tick => {
+                    self.heartbeat.tick();
+                    self.stats.record_lag(Utc::now() - tick_due);
+                    tick = Box::pin(sleep(TICK).fuse());
+                    tick_due = Utc::now() + TICK;
+                    self.handle_tick().await
+                }
+            }?;
+        }
+    }
+
+    fn bind(&mut self) -> Result<ListenerId, Box<dyn Error + Send + Sync>> {
+        match self.listen(format!("/ip4/0.0.0.0/tcp/{}", self.port).parse()?) {
+            Ok(listener) => Ok(listener),
+            Err(_) if self.port_fallback && self.port != 0 => {
+                self.listen("/ip4/0.0.0.0/tcp/0".parse()?)
+            }
+            Err(e) => Err(format!("Unable to listen on port {}: {e:?}", self.port).into()),
+        }
+    }
+
+    pub async fn main(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut custom = Vec::new();
+        if self.listen {
+            for address in self.custom_listen.clone() {
+                custom.push(self.listen(address)?);
+            }
+        }
+        let listener = match self.listen {
+            true => Some(self.bind()?),
+            false => {
+                self.swarm
+                    .behaviour_mut()
+                    .kad
+                    .set_mode(Some(libp2p::kad::Mode::Client));
+                self.emit(Event::OutboundOnly).await;
+                None
+            }
+        };
+        let mut peers = Vec::new();
+        for peer in self.peers.list()? {
+            if !self.rejects_self(&peer).await? {
+                peers.push(peer);
+            }
+        }
+        let bootstrap: Vec<&Peer> = peers
+            .iter()
+            .filter(|peer| matches!(peer.kind, PeerType::Bootstrap))
+            .collect();
+        for peer in bootstrap.iter() {
+            self.swarm
+                .behaviour_mut()
+                .kad
+                .add_address(&peer.id, peer.address.clone());
+        }
+        for entry in self.routing.entries()? {
+            let kad = &mut self.swarm.behaviour_mut().kad;
+            for address in entry.addresses {
+                kad.add_address(&entry.peer, address);
+            }
+        }
+        if let Some(key) = self.ipfs.as_ref().map(IpfsDiscovery::key) {
+            let kad = &mut self.swarm.behaviour_mut().kad;
+            for (peer, address) in ipfs::bootstrap_peers() {
+                kad.add_address(&peer, address);
+            }
+            let _ = kad.bootstrap();
+            if self.listen {
+                let _ = kad.start_providing(key);
+            }
+        }
+        self.bootstrap = Bootstrap::new(bootstrap.iter().map(|peer| peer.id).collect());
+        let stages = self.bootstrap.start();
+        self.advance(stages).await?;
+        self.select_relay()?;
+
+        for peer in peers.iter() {
+            let _ = self.dial_peer(peer);
+        }
+        if self.group_admin {
+            self.keyring.rotate();
+        }
+        self.restore_rooms().await;
+        self.outbox().recover()?;
+        self.replay_journal().await?;
+        let topic = self.feeds.topic().clone();
+        self.swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
+        let topic = self.liveness.topic().clone();
+        self.swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
+        let topic = self.coordinator.topic().clone();
+        self.swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
+        if self.updates.is_enabled() {
+            let topic = self.updates.topic().clone();
+            self.swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
+        }
+        let loop_result = self.event_loop().await;
+        for listener in listener.into_iter().chain(custom) {
+            self.swarm.remove_listener(listener);
+        }
+        self.outbox().close();
+        self.commands.close();
+        self.events.close();
+        loop_result
+    }
+
+    pub async fn run(mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(config) = self.watchdog.clone() else {
+            return self.main().await;
+        };
+
+        let mut aborted = pin!(self.heartbeat.watch(config, self.events.clone()));
+        let result = select_biased! {
+            result = self.main().fuse() => result,
+            () = aborted.select_next_some() => Err("Event loop stalled".into()),
+        };
+        self.commands.close();
+        result
     }
 }
@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::util::Peer;
+
+use super::event::Event;
+
+pub struct Coalescer {
+    window: Option<TimeDelta>,
+    pending: Vec<Peer>,
+    since: Option<DateTime<Utc>>,
+}
+
+impl Coalescer {
+    pub fn new(window: Option<Duration>) -> Self {
+        Coalescer {
+            window: window.map(|window| TimeDelta::from_std(window).unwrap_or(TimeDelta::MAX)),
+            pending: Vec::new(),
+            since: None,
+        }
+    }
+
+    pub fn push(&mut self, peer: Peer) -> Option<Event> {
+        if self.window.is_none() {
+            return Some(Event::PeerDiscovered(peer));
+        }
+
+        self.since.get_or_insert_with(Utc::now);
+        self.pending.push(peer);
+        None
+    }
+
+    pub fn flush(&mut self) -> Option<Event> {
+        let window = self.window?;
+        let since = self.since?;
+        if Utc::now() - since < window {
+            return None;
+        }
+
+        self.since = None;
+        Some(Event::PeersDiscovered(self.pending.drain(..).collect()))
+    }
+}
@@ -0,0 +1,136 @@
+use std::{io, marker::PhantomData};
+
+use bytes::{Bytes, BytesMut};
+use libp2p::futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::wire::MAX_FRAME;
+
+pub trait Codec: Clone + Send + Sync + 'static {
+    type Item: Send + 'static;
+
+    fn encode(&self, item: &Self::Item) -> io::Result<Bytes>;
+    fn decode(&self, data: Bytes) -> io::Result<Self::Item>;
+}
+
+#[derive(Debug)]
+pub struct JsonCodec<T>(PhantomData<fn() -> T>);
+
+impl<T> JsonCodec<T> {
+    pub fn new() -> Self {
+        JsonCodec(PhantomData)
+    }
+}
+
+impl<T> Default for JsonCodec<T> {
+    fn default() -> Self {
+        JsonCodec::new()
+    }
+}
+
+impl<T> Clone for JsonCodec<T> {
+    fn clone(&self) -> Self {
+        JsonCodec::new()
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Send + 'static> Codec for JsonCodec<T> {
+    type Item = T;
+
+    fn encode(&self, item: &T) -> io::Result<Bytes> {
+        Ok(serde_json::to_vec(item)?.into())
+    }
+
+    fn decode(&self, data: Bytes) -> io::Result<T> {
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+#[derive(Debug)]
+pub struct CborCodec<T>(PhantomData<fn() -> T>);
+
+impl<T> CborCodec<T> {
+    pub fn new() -> Self {
+        CborCodec(PhantomData)
+    }
+}
+
+impl<T> Default for CborCodec<T> {
+    fn default() -> Self {
+        CborCodec::new()
+    }
+}
+
+impl<T> Clone for CborCodec<T> {
+    fn clone(&self) -> Self {
+        CborCodec::new()
+    }
+}
+
+impl<T: Serialize + DeserializeOwned + Send + 'static> Codec for CborCodec<T> {
+    type Item = T;
+
+    fn encode(&self, item: &T) -> io::Result<Bytes> {
+        let mut out = Vec::new();
+        ciborium::into_writer(item, &mut out)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+        Ok(out.into())
+    }
+
+    fn decode(&self, data: Bytes) -> io::Result<T> {
+        ciborium::from_reader(&data[..])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RawCodec;
+
+impl Codec for RawCodec {
+    type Item = Bytes;
+
+    fn encode(&self, item: &Bytes) -> io::Result<Bytes> {
+        Ok(item.clone())
+    }
+
+    fn decode(&self, data: Bytes) -> io::Result<Bytes> {
+        Ok(data)
+    }
+}
+
+pub async fn write_item<W: AsyncWrite + Unpin, C: Codec>(
+    io: &mut W,
+    codec: &C,
+    item: &C::Item,
+) -> io::Result<()> {
+    let data = codec.encode(item)?;
+    if data.len() > MAX_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Item exceeds the maximum size",
+        ));
+    }
+
+    io.write_all(&(data.len() as u32).to_be_bytes()).await?;
+    io.write_all(&data).await?;
+    io.flush().await
+}
+
+pub async fn read_item<R: AsyncRead + Unpin, C: Codec>(
+    io: &mut R,
+    codec: &C,
+) -> io::Result<C::Item> {
+    let mut length = [0u8; 4];
+    io.read_exact(&mut length).await?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_FRAME {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Item exceeds the maximum size",
+        ));
+    }
+
+    let mut data = BytesMut::zeroed(length);
+    io.read_exact(&mut data).await?;
+    codec.decode(data.freeze())
+}
@@ -1,15 +1,45 @@
-use std::error::Error;
+use std::{error::Error, fmt, time::Duration};
 
 use async_channel::{Receiver, Sender};
+use bytes::Bytes;
+use libp2p::{Multiaddr, PeerId};
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
+use uuid::Uuid;
 
-use crate::util::Peer;
+use crate::{addressbook::AddressBookFormat, peers::PeerFilter, util::Peer};
+
+use super::{admin::AdminRequest, broadcast::{Audience, FanOut}, event::Event, multipath::Redundancy, rpc::{Balance, CallOptions}, schema::MessageTag, session::DeliveryGuarantee, tracer::TraceStep, update::UpdateManifest};
+#[cfg(feature = "opentelemetry")]
+use super::trace::TraceContext;
+
+pub type CommandResponse = Result<Value, Box<dyn Error + Send + Sync>>;
+
+#[derive(Debug)]
+pub struct CommandError {
+    pub command: Uuid,
+    pub error: Box<dyn Error + Send + Sync>
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl Error for CommandError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct CommandWrapper {
+    pub id: Uuid,
     pub command: CommandKind,
-    pub response: Sender<Result<Value, Box<dyn Error>>>
+    pub response: Sender<CommandResponse>,
+    pub tracer: Option<Sender<Event>>,
+    pub idempotency_key: Option<String>
 }
 
 impl CommandWrapper {
@@ -17,42 +47,212 @@ impl CommandWrapper {
         self.command.clone()
     }
 
-    pub async fn respond<T: Serialize + DeserializeOwned, E: Error + 'static>(&self, result: Result<T, E>) -> Result<(), serde_json::Error> {
+    fn traced(&self, error: Option<String>) {
+        if let Some(tracer) = &self.tracer {
+            let _ = tracer.try_send(Event::CommandTrace { command: self.id, step: TraceStep::Responded { error } });
+        }
+    }
+
+    pub async fn respond<T: Serialize + DeserializeOwned, E: Into<Box<dyn Error + Send + Sync>>>(&self, result: Result<T, E>) -> Result<(), serde_json::Error> {
         if let Ok(val) = result {
-            let _ = self.response.send(Ok(serde_json::to_value(val)?)).await;
+            let value = serde_json::to_value(val)?;
+            self.traced(None);
+            let _ = self.response.send(Ok(value)).await;
         } else if let Err(e) = result {
-            let _ = self.response.send(Err(Box::new(e))).await;
+            let error = e.into();
+            self.traced(Some(error.to_string()));
+            let _ = self.response.send(Err(error)).await;
         }
 
         Ok(())
     }
+
+    pub async fn reply<T: Serialize + DeserializeOwned>(&self, value: T) -> Result<(), serde_json::Error> {
+        let value = serde_json::to_value(value)?;
+        self.traced(None);
+        let _ = self.response.send(Ok(value)).await;
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum CommandKind {
     AddRendezvous(Peer),
-    AddRelay(Peer)
+    AddRelay(Peer),
+    NatReport,
+    Doctor { target: Option<PeerId> },
+    BestPeerFor { service_or_cid: String },
+    Send {
+        peer: PeerId,
+        payload: Bytes,
+        guarantee: DeliveryGuarantee,
+        tag: Option<MessageTag>,
+        redundancy: Redundancy,
+        #[cfg(feature = "opentelemetry")]
+        trace: Option<TraceContext>
+    },
+    Broadcast { payload: Bytes, fanout: FanOut, to: Audience },
+    ChannelSend { peer: PeerId, label: String, payload: Bytes },
+    JoinRoom(String),
+    LeaveRoom(String),
+    RoomSend { room: String, payload: Bytes },
+    RoomMembers(String),
+    RoomHistory { room: String, count: usize },
+    AwaitReady,
+    Schedule { interval: Duration, command: Box<CommandKind> },
+    Unschedule(u64),
+    TagPeer { peer: PeerId, key: String, value: String },
+    PinPeer { peer: PeerId, pinned: bool },
+    DefinePeerSet { name: String, peers: Vec<PeerId> },
+    UpdatePeerSet { name: String, add: Vec<PeerId>, remove: Vec<PeerId> },
+    DeletePeerSet(String),
+    ListPeerSets,
+    Disconnect { set: String },
+    PrunePeers,
+    ListPeers(PeerFilter),
+    DeadLetters { drain: bool },
+    LeaveGroup,
+    ChangeGroup(String),
+    Admin { peer: PeerId, request: AdminRequest },
+    Call { peer: PeerId, method: String, params: Value, callbacks: Option<u64>, options: CallOptions },
+    CallAny { service: String, method: String, params: Value, balance: Balance, options: CallOptions },
+    CallStreaming { peer: PeerId, method: String, params: Value, call: u64 },
+    PublishUpdate(UpdateManifest),
+    StorageStats,
+    Replicate(String),
+    RestoreBlob(String),
+    Sync { peer: PeerId, share: String },
+    AppendFeed { name: String, data: Value },
+    CatchUpFeed(Option<String>),
+    Stats,
+    GetListeners,
+    AddListener(Multiaddr),
+    RemoveListener(u64),
+    ExportPeers { format: AddressBookFormat },
+    ImportPeers { format: AddressBookFormat, data: String },
+    Pause,
+    Resume,
+    BandwidthUsage,
+    ProbeThroughput { peer: PeerId, duration: Duration },
+    FlushCaches,
+    Barrier { name: String, count: usize },
+    Propose { key: String, value: Value, quorum: usize, timeout: Duration },
+    AwaitDecision(String)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CommandClass {
+    Concurrent,
+    Serial
 }
 
 impl CommandKind {
-    pub fn wrap(&self) -> (CommandWrapper, Receiver<Result<Value, Box<dyn Error>>>) {
-        let (tx, rx) = async_channel::bounded::<Result<Value, Box<dyn Error>>>(1);
-        return (
+    pub fn class(&self) -> CommandClass {
+        match self {
+            CommandKind::BestPeerFor { .. } | CommandKind::ListPeers(_) | CommandKind::Send { .. } | CommandKind::ChannelSend { .. } => CommandClass::Concurrent,
+            _ => CommandClass::Serial
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CommandKind::AddRendezvous(..) => "AddRendezvous",
+            CommandKind::AddRelay(..) => "AddRelay",
+            CommandKind::NatReport => "NatReport",
+            CommandKind::Doctor { .. } => "Doctor",
+            CommandKind::BestPeerFor { .. } => "BestPeerFor",
+            CommandKind::Send { .. } => "Send",
+            CommandKind::Broadcast { .. } => "Broadcast",
+            CommandKind::ChannelSend { .. } => "ChannelSend",
+            CommandKind::JoinRoom(..) => "JoinRoom",
+            CommandKind::LeaveRoom(..) => "LeaveRoom",
+            CommandKind::RoomSend { .. } => "RoomSend",
+            CommandKind::RoomMembers(..) => "RoomMembers",
+            CommandKind::RoomHistory { .. } => "RoomHistory",
+            CommandKind::AwaitReady => "AwaitReady",
+            CommandKind::Schedule { .. } => "Schedule",
+            CommandKind::Unschedule(..) => "Unschedule",
+            CommandKind::TagPeer { .. } => "TagPeer",
+            CommandKind::PinPeer { .. } => "PinPeer",
+            CommandKind::DefinePeerSet { .. } => "DefinePeerSet",
+            CommandKind::UpdatePeerSet { .. } => "UpdatePeerSet",
+            CommandKind::DeletePeerSet(..) => "DeletePeerSet",
+            CommandKind::ListPeerSets => "ListPeerSets",
+            CommandKind::Disconnect { .. } => "Disconnect",
+            CommandKind::PrunePeers => "PrunePeers",
+            CommandKind::ListPeers(..) => "ListPeers",
+            CommandKind::DeadLetters { .. } => "DeadLetters",
+            CommandKind::LeaveGroup => "LeaveGroup",
+            CommandKind::ChangeGroup(..) => "ChangeGroup",
+            CommandKind::Admin { .. } => "Admin",
+            CommandKind::Call { .. } => "Call",
+            CommandKind::CallAny { .. } => "CallAny",
+            CommandKind::CallStreaming { .. } => "CallStreaming",
+            CommandKind::PublishUpdate(..) => "PublishUpdate",
+            CommandKind::StorageStats => "StorageStats",
+            CommandKind::Replicate(..) => "Replicate",
+            CommandKind::RestoreBlob(..) => "RestoreBlob",
+            CommandKind::Sync { .. } => "Sync",
+            CommandKind::AppendFeed { .. } => "AppendFeed",
+            CommandKind::CatchUpFeed(..) => "CatchUpFeed",
+            CommandKind::Stats => "Stats",
+            CommandKind::GetListeners => "GetListeners",
+            CommandKind::AddListener(..) => "AddListener",
+            CommandKind::RemoveListener(..) => "RemoveListener",
+            CommandKind::ExportPeers { .. } => "ExportPeers",
+            CommandKind::ImportPeers { .. } => "ImportPeers",
+            CommandKind::Pause => "Pause",
+            CommandKind::Resume => "Resume",
+            CommandKind::BandwidthUsage => "BandwidthUsage",
+            CommandKind::ProbeThroughput { .. } => "ProbeThroughput",
+            CommandKind::FlushCaches => "FlushCaches",
+            CommandKind::Barrier { .. } => "Barrier",
+            CommandKind::Propose { .. } => "Propose",
+            CommandKind::AwaitDecision(..) => "AwaitDecision",
+        }
+    }
+
+    pub fn wrap(&self) -> (CommandWrapper, Receiver<CommandResponse>) {
+        self.wrap_with_id(Uuid::new_v4())
+    }
+
+    pub fn wrap_with_id(&self, id: Uuid) -> (CommandWrapper, Receiver<CommandResponse>) {
+        let (tx, rx) = async_channel::bounded::<CommandResponse>(1);
+        (
             CommandWrapper {
+                id,
                 command: self.clone(),
-                response: tx
+                response: tx,
+                tracer: None,
+                idempotency_key: None
             },
             rx
-        );
+        )
+    }
+
+    pub async fn send<T: Serialize + DeserializeOwned>(&self, tx: Sender<CommandWrapper>) -> Result<T, Box<dyn Error + Send + Sync>> {
+        self.send_traced(Uuid::new_v4(), tx).await
+    }
+
+    pub async fn send_traced<T: Serialize + DeserializeOwned>(&self, id: Uuid, tx: Sender<CommandWrapper>) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let (wrapped, rx) = self.wrap_with_id(id);
+        tx.send(wrapped).await?;
+
+        match rx.recv().await? {
+            Ok(value) => Ok(serde_json::from_value::<T>(value)?),
+            Err(error) => Err(Box::new(CommandError { command: id, error }))
+        }
     }
 
-    pub async fn send<T: Serialize + DeserializeOwned>(&self, tx: Sender<CommandWrapper>) -> Result<T, Box<dyn Error>> {
-        let (wrapped, rx) = self.wrap();
+    pub async fn send_idempotent<T: Serialize + DeserializeOwned>(&self, key: &str, tx: Sender<CommandWrapper>) -> Result<T, Box<dyn Error + Send + Sync>> {
+        let (mut wrapped, rx) = self.wrap();
+        let id = wrapped.id;
+        wrapped.idempotency_key = Some(key.to_string());
         tx.send(wrapped).await?;
 
         match rx.recv().await? {
             Ok(value) => Ok(serde_json::from_value::<T>(value)?),
-            Err(error) => Err(error)
+            Err(error) => Err(Box::new(CommandError { command: id, error }))
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,461 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use libp2p::{gossipsub::IdentTopic, PeerId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::command::CommandWrapper;
+
+const ANNOUNCE_INTERVAL: TimeDelta = TimeDelta::seconds(5);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Signal {
+    Arrive {
+        barrier: String,
+        #[serde(default)]
+        round: u64,
+    },
+    Propose {
+        key: String,
+        proposal: Uuid,
+        value: Value,
+        deadline: DateTime<Utc>,
+    },
+    Vote {
+        key: String,
+        proposal: Uuid,
+    },
+    Commit {
+        key: String,
+        proposal: Uuid,
+        value: Value,
+    },
+    Learn {
+        key: String,
+    },
+}
+
+#[derive(Default)]
+pub struct Outcome {
+    pub publish: Vec<Signal>,
+    pub resolved: Vec<(CommandWrapper, Result<Value, String>)>,
+    pub decided: Vec<(String, Value)>,
+}
+
+impl Outcome {
+    fn resolve<T: Serialize>(&mut self, command: CommandWrapper, value: T) {
+        let result = serde_json::to_value(value).map_err(|e| e.to_string());
+        self.resolved.push((command, result));
+    }
+
+    fn merge(&mut self, other: Outcome) {
+        self.publish.extend(other.publish);
+        self.resolved.extend(other.resolved);
+        self.decided.extend(other.decided);
+    }
+}
+
+#[derive(Default)]
+struct Barrier {
+    arrived: HashSet<PeerId>,
+    waiters: Vec<(usize, CommandWrapper)>,
+}
+
+struct Proposal {
+    key: String,
+    value: Value,
+    quorum: usize,
+    accepted: HashSet<PeerId>,
+    deadline: DateTime<Utc>,
+    command: CommandWrapper,
+}
+
+pub struct Coordinator {
+    topic: IdentTopic,
+    local: PeerId,
+    barriers: HashMap<(String, u64), Barrier>,
+    rounds: HashMap<String, u64>,
+    votes: HashMap<String, Uuid>,
+    proposals: HashMap<Uuid, Proposal>,
+    decided: HashMap<String, (Uuid, Value)>,
+    learners: HashMap<String, Vec<CommandWrapper>>,
+    next_announce: DateTime<Utc>,
+}
+
+impl Coordinator {
+    pub fn new(group: &str, local: PeerId) -> Self {
+        Coordinator {
+            topic: IdentTopic::new(format!("/modius/{group}/coordination")),
+            local,
+            barriers: HashMap::new(),
+            rounds: HashMap::new(),
+            votes: HashMap::new(),
+            proposals: HashMap::new(),
+            decided: HashMap::new(),
+            learners: HashMap::new(),
+            next_announce: Utc::now() + ANNOUNCE_INTERVAL,
+        }
+    }
+
+    pub fn topic(&self) -> &IdentTopic {
+        &self.topic
+    }
+
+    pub fn encode(signal: &Signal) -> Option<Vec<u8>> {
+        serde_json::to_vec(signal).ok()
+    }
+
+    pub fn decision(&self, key: &str) -> Option<Value> {
+        self.decided.get(key).map(|(_, value)| value.clone())
+    }
+
+    fn arrived(&mut self, barrier: &str, round: u64, peer: PeerId) -> Outcome {
+        let mut outcome = Outcome::default();
+        let entered = self.rounds.get(barrier).copied().unwrap_or_default();
+        let key = (barrier.to_string(), round);
+        if round <= entered && !self.barriers.contains_key(&key) {
+            return outcome;
+        }
+
+        let state = self.barriers.entry(key.clone()).or_default();
+        state.arrived.insert(peer);
+        let arrived = state.arrived.len();
+        let (ready, waiting) = state
+            .waiters
+            .drain(..)
+            .partition::<Vec<_>, _>(|(count, _)| arrived >= *count);
+        state.waiters = waiting;
+        let members: Vec<PeerId> = state.arrived.iter().copied().collect();
+        if round <= entered && state.waiters.is_empty() {
+            self.barriers.remove(&key);
+        }
+        for (_, command) in ready {
+            outcome.resolve(command, &members);
+        }
+        outcome
+    }
+
+    pub fn barrier(&mut self, command: CommandWrapper, barrier: String, count: usize) -> Outcome {
+        let round = self.rounds.entry(barrier.clone()).or_default();
+        *round += 1;
+        let round = *round;
+        let state = self.barriers.entry((barrier.clone(), round)).or_default();
+        state.waiters.push((count, command));
+        let mut outcome = self.arrived(&barrier, round, self.local);
+        outcome.publish.push(Signal::Arrive { barrier, round });
+        outcome
+    }
+
+    pub fn propose(
+        &mut self,
+        command: CommandWrapper,
+        key: String,
+        value: Value,
+        quorum: usize,
+        members: usize,
+        timeout: Duration,
+    ) -> Outcome {
+        let mut outcome = Outcome::default();
+        if let Some(decided) = self.decision(&key) {
+            outcome.resolve(command, decided);
+            return outcome;
+        }
+        if quorum <= members / 2 {
+            let error =
+                format!("A quorum of {quorum} is not a majority of the {members} group members");
+            outcome.resolved.push((command, Err(error)));
+            return outcome;
+        }
+
+        let proposal = Uuid::new_v4();
+        let deadline = TimeDelta::from_std(timeout)
+            .ok()
+            .and_then(|timeout| Utc::now().checked_add_signed(timeout))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        self.proposals.insert(
+            proposal,
+            Proposal {
+                key: key.clone(),
+                value: value.clone(),
+                quorum,
+                accepted: HashSet::new(),
+                deadline,
+                command,
+            },
+        );
+        outcome.publish.push(Signal::Propose {
+            key: key.clone(),
+            proposal,
+            value,
+            deadline,
+        });
+        if !self.votes.contains_key(&key) {
+            self.votes.insert(key.clone(), proposal);
+            outcome.publish.push(Signal::Vote {
+                key: key.clone(),
+                proposal,
+            });
+            outcome.merge(self.voted(self.local, &key, proposal));
+        }
+        outcome
+    }
+
+    pub fn accept(&mut self, command: CommandWrapper, key: String) -> Outcome {
+        let mut outcome = Outcome::default();
+        match self.decision(&key) {
+            Some(decided) => outcome.resolve(command, decided),
+            None => {
+                self.learners.entry(key.clone()).or_default().push(command);
+                outcome.publish.push(Signal::Learn { key });
+            }
+        }
+        outcome
+    }
+
+    fn voted(&mut self, peer: PeerId, key: &str, proposal: Uuid) -> Outcome {
+        let Some(pending) = self.proposals.get_mut(&proposal) else {
+            return Outcome::default();
+        };
+        if pending.key != key {
+            return Outcome::default();
+        }
+        pending.accepted.insert(peer);
+        if pending.accepted.len() < pending.quorum {
+            return Outcome::default();
+        }
+
+        let value = pending.value.clone();
+        let mut outcome = self.commit(key, proposal, value.clone());
+        outcome.publish.push(Signal::Commit {
+            key: key.to_string(),
+            proposal,
+            value,
+        });
+        outcome
+    }
+
+    fn commit(&mut self, key: &str, proposal: Uuid, value: Value) -> Outcome {
+        let mut outcome = Outcome::default();
+        if self.decided.contains_key(key) {
+            return outcome;
+        }
+
+        self.decided
+            .insert(key.to_string(), (proposal, value.clone()));
+        self.votes.remove(key);
+        let settled: Vec<Uuid> = self
+            .proposals
+            .iter()
+            .filter(|(_, pending)| pending.key == key)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in settled {
+            if let Some(pending) = self.proposals.remove(&id) {
+                outcome.resolve(pending.command, &value);
+            }
+        }
+        for command in self.learners.remove(key).unwrap_or_default() {
+            outcome.resolve(command, &value);
+        }
+        outcome.decided.push((key.to_string(), value));
+        outcome
+    }
+
+    pub fn receive(&mut self, source: PeerId, data: &[u8]) -> Outcome {
+        let Ok(signal) = serde_json::from_slice::<Signal>(data) else {
+            return Outcome::default();
+        };
+
+        match signal {
+            Signal::Arrive { barrier, round } => self.arrived(&barrier, round, source),
+            Signal::Propose {
+                key,
+                proposal,
+                deadline,
+                ..
+            } => {
+                let mut outcome = Outcome::default();
+                if let Some(commit) = self.committed(&key) {
+                    outcome.publish.push(commit);
+                    return outcome;
+                }
+                if deadline <= Utc::now() {
+                    return outcome;
+                }
+                let ballot = *self.votes.entry(key.clone()).or_insert(proposal);
+                if ballot == proposal {
+                    outcome.publish.push(Signal::Vote { key, proposal });
+                }
+                outcome
+            }
+            Signal::Vote { key, proposal } => self.voted(source, &key, proposal),
+            Signal::Commit {
+                key,
+                proposal,
+                value,
+            } => self.commit(&key, proposal, value),
+            Signal::Learn { key } => Outcome {
+                publish: self.committed(&key).into_iter().collect(),
+                ..Outcome::default()
+            },
+        }
+    }
+
+    fn committed(&self, key: &str) -> Option<Signal> {
+        self.decided
+            .get(key)
+            .map(|(proposal, value)| Signal::Commit {
+                key: key.to_string(),
+                proposal: *proposal,
+                value: value.clone(),
+            })
+    }
+
+    pub fn tick(&mut self) -> Outcome {
+        let mut outcome = Outcome::default();
+        let now = Utc::now();
+        let expired: Vec<Uuid> = self
+            .proposals
+            .iter()
+            .filter(|(_, pending)| pending.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            if let Some(pending) = self.proposals.remove(&id) {
+                let error = format!("No quorum for {} before the deadline", pending.key);
+                outcome.resolved.push((pending.command, Err(error)));
+            }
+        }
+
+        if now < self.next_announce {
+            return outcome;
+        }
+        self.next_announce = now + ANNOUNCE_INTERVAL;
+        for ((barrier, round), state) in self.barriers.iter() {
+            if !state.waiters.is_empty() {
+                outcome.publish.push(Signal::Arrive {
+                    barrier: barrier.clone(),
+                    round: *round,
+                });
+            }
+        }
+        for (proposal, pending) in self.proposals.iter() {
+            outcome.publish.push(Signal::Propose {
+                key: pending.key.clone(),
+                proposal: *proposal,
+                value: pending.value.clone(),
+                deadline: pending.deadline,
+            });
+        }
+        for (key, proposal) in self.votes.iter() {
+            outcome.publish.push(Signal::Vote {
+                key: key.clone(),
+                proposal: *proposal,
+            });
+        }
+        for key in self.learners.keys() {
+            outcome.publish.push(Signal::Learn { key: key.clone() });
+        }
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::identity::Keypair;
+    use serde_json::json;
+
+    use crate::net::command::CommandKind;
+
+    use super::*;
+
+    fn coordinator() -> Coordinator {
+        Coordinator::new("group", Keypair::generate_ed25519().public().to_peer_id())
+    }
+
+    fn command() -> CommandWrapper {
+        CommandKind::AwaitDecision(String::new()).wrap().0
+    }
+
+    fn deliver(from: &Coordinator, outcome: &Outcome, to: &mut Coordinator) -> Outcome {
+        let mut delivered = Outcome::default();
+        for signal in outcome.publish.iter() {
+            let data = Coordinator::encode(signal).unwrap();
+            delivered.merge(to.receive(from.local, &data));
+        }
+        delivered
+    }
+
+    #[test]
+    fn barriers_start_fresh_each_round() {
+        let (mut a, mut b) = (coordinator(), coordinator());
+        let arrived = a.barrier(command(), "phase".to_string(), 2);
+        assert!(arrived.resolved.is_empty());
+        deliver(&a, &arrived, &mut b);
+
+        let arrived = b.barrier(command(), "phase".to_string(), 2);
+        assert_eq!(arrived.resolved.len(), 1);
+        assert_eq!(deliver(&b, &arrived, &mut a).resolved.len(), 1);
+
+        assert!(a
+            .barrier(command(), "phase".to_string(), 2)
+            .resolved
+            .is_empty());
+    }
+
+    #[test]
+    fn minority_quorums_are_refused() {
+        let mut a = coordinator();
+        let outcome = a.propose(command(), "key".into(), json!(1), 2, 4, Duration::MAX);
+        assert!(matches!(outcome.resolved.as_slice(), [(_, Err(_))]));
+        assert!(outcome.publish.is_empty());
+    }
+
+    #[test]
+    fn ballots_are_held_until_commit() {
+        let (mut a, mut b, mut c) = (coordinator(), coordinator(), coordinator());
+        let first = a.propose(
+            command(),
+            "key".into(),
+            json!(1),
+            2,
+            3,
+            Duration::from_secs(60),
+        );
+        let second = b.propose(
+            command(),
+            "key".into(),
+            json!(2),
+            2,
+            3,
+            Duration::from_secs(60),
+        );
+
+        let voted = deliver(&a, &first, &mut c);
+        assert_eq!(voted.publish.len(), 1);
+        c.next_announce = Utc::now();
+        c.tick();
+        assert!(deliver(&b, &second, &mut c).publish.is_empty());
+
+        let committed = deliver(&c, &voted, &mut a);
+        assert_eq!(committed.decided, vec![("key".to_string(), json!(1))]);
+    }
+
+    #[test]
+    fn learners_catch_up_on_missed_commits() {
+        let (mut a, mut b) = (coordinator(), coordinator());
+        let decided = a.propose(command(), "key".into(), json!(1), 1, 1, Duration::MAX);
+        assert_eq!(decided.decided.len(), 1);
+
+        let learning = b.accept(command(), "key".into());
+        let replied = deliver(&b, &learning, &mut a);
+        let learned = deliver(&a, &replied, &mut b);
+        assert_eq!(learned.decided, vec![("key".to_string(), json!(1))]);
+        assert!(matches!(learned.resolved.as_slice(), [(_, Ok(value))] if *value == json!(1)));
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use super::{schema::MessageTag, wire::Envelope};
+
+const DEAD_LETTER_CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum DeadReason {
+    Undeliverable { attempts: u32 },
+    UnknownType,
+    Undecodable(String),
+    Forbidden,
+    CircuitOpen,
+    HandshakeFailed,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub peer: PeerId,
+    pub reason: DeadReason,
+    pub seq: u64,
+    pub channel: Option<String>,
+    pub tag: Option<MessageTag>,
+    pub payload: Bytes,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DeadLetters {
+    letters: Arc<Mutex<VecDeque<DeadLetter>>>,
+}
+
+impl DeadLetters {
+    pub fn new() -> Self {
+        DeadLetters::default()
+    }
+
+    pub fn bury(&self, peer: PeerId, envelope: Envelope, reason: DeadReason) {
+        self.push(DeadLetter {
+            peer,
+            reason,
+            seq: envelope.seq,
+            channel: envelope.channel,
+            tag: envelope.tag,
+            payload: envelope.payload,
+            at: Utc::now(),
+        });
+    }
+
+    pub fn bury_room(&self, peer: PeerId, room: &str, payload: Bytes, reason: DeadReason) {
+        self.push(DeadLetter {
+            peer,
+            reason,
+            seq: 0,
+            channel: Some(room.to_string()),
+            tag: None,
+            payload,
+            at: Utc::now(),
+        });
+    }
+
+    fn push(&self, letter: DeadLetter) {
+        let mut letters = self.letters.lock().unwrap_or_else(|e| e.into_inner());
+        letters.push_back(letter);
+        if letters.len() > DEAD_LETTER_CAPACITY {
+            letters.pop_front();
+        }
+    }
+
+    pub fn list(&self, drain: bool) -> Vec<DeadLetter> {
+        let mut letters = self.letters.lock().unwrap_or_else(|e| e.into_inner());
+        match drain {
+            true => letters.drain(..).collect(),
+            false => letters.iter().cloned().collect(),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use libp2p::{multiaddr::Protocol, swarm::ConnectionId, Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+const STAGGER: TimeDelta = TimeDelta::milliseconds(250);
+const DRAIN_GRACE: TimeDelta = TimeDelta::seconds(10);
+
+pub fn is_relayed(address: &Multiaddr) -> bool {
+    address.iter().any(|p| matches!(p, Protocol::P2pCircuit))
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TransportKind {
+    Tcp,
+    Quic,
+    WebSocket,
+    WebTransport,
+    WebRtc,
+    Relay,
+    Memory,
+    Unknown,
+}
+
+pub fn transport_of(address: &Multiaddr) -> TransportKind {
+    let mut kind = TransportKind::Unknown;
+    for protocol in address.iter() {
+        kind = match protocol {
+            Protocol::P2pCircuit => return TransportKind::Relay,
+            Protocol::Ws(_) | Protocol::Wss(_) => TransportKind::WebSocket,
+            Protocol::WebTransport => TransportKind::WebTransport,
+            Protocol::WebRTC | Protocol::WebRTCDirect => TransportKind::WebRtc,
+            Protocol::QuicV1 | Protocol::Quic => TransportKind::Quic,
+            Protocol::Memory(_) => TransportKind::Memory,
+            Protocol::Tcp(_) if kind == TransportKind::Unknown => TransportKind::Tcp,
+            _ => kind,
+        };
+    }
+    kind
+}
+
+struct PendingDial {
+    relayed: Vec<Multiaddr>,
+    due: DateTime<Utc>,
+}
+
+struct Migration {
+    address: Multiaddr,
+    circuits: HashSet<ConnectionId>,
+    deadline: DateTime<Utc>,
+    closing: bool,
+}
+
+#[derive(Default)]
+pub struct Dialer {
+    pending: HashMap<PeerId, PendingDial>,
+    connections: HashMap<PeerId, HashMap<ConnectionId, bool>>,
+    migrations: HashMap<PeerId, Migration>,
+}
+
+impl Dialer {
+    pub fn new() -> Self {
+        Dialer::default()
+    }
+
+    pub fn plan(&mut self, peer: PeerId, addresses: Vec<Multiaddr>) -> Vec<Multiaddr> {
+        let (relayed, direct): (Vec<Multiaddr>, Vec<Multiaddr>) =
+            addresses.into_iter().partition(is_relayed);
+
+        if direct.is_empty() || relayed.is_empty() {
+            return if direct.is_empty() { relayed } else { direct };
+        }
+
+        self.pending.insert(
+            peer,
+            PendingDial {
+                relayed,
+                due: Utc::now() + STAGGER,
+            },
+        );
+        direct
+    }
+
+    pub fn due(&mut self) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let now = Utc::now();
+        let due: Vec<PeerId> = self
+            .pending
+            .iter()
+            .filter(|(_, dial)| dial.due <= now)
+            .map(|(peer, _)| *peer)
+            .collect();
+
+        due.into_iter()
+            .filter_map(|peer| self.fallback(&peer).map(|addresses| (peer, addresses)))
+            .collect()
+    }
+
+    pub fn fallback(&mut self, peer: &PeerId) -> Option<Vec<Multiaddr>> {
+        self.pending.remove(peer).map(|dial| dial.relayed)
+    }
+
+    pub fn on_established(
+        &mut self,
+        peer: PeerId,
+        connection: ConnectionId,
+        address: &Multiaddr,
+        relayed: bool,
+    ) {
+        let connections = self.connections.entry(peer).or_default();
+        connections.insert(connection, relayed);
+        if relayed {
+            return;
+        }
+
+        self.pending.remove(&peer);
+        let circuits: HashSet<ConnectionId> = connections
+            .iter()
+            .filter(|(_, relayed)| **relayed)
+            .map(|(id, _)| *id)
+            .collect();
+        if circuits.is_empty() {
+            return;
+        }
+        let migration = self.migrations.entry(peer).or_insert_with(|| Migration {
+            address: address.clone(),
+            circuits: HashSet::new(),
+            deadline: Utc::now() + DRAIN_GRACE,
+            closing: false,
+        });
+        migration.circuits.extend(circuits);
+    }
+
+    pub fn drained(&mut self) -> Vec<ConnectionId> {
+        let now = Utc::now();
+        self.migrations
+            .values_mut()
+            .filter(|migration| !migration.closing && migration.deadline <= now)
+            .flat_map(|migration| {
+                migration.closing = true;
+                migration.circuits.iter().copied()
+            })
+            .collect()
+    }
+
+    pub fn on_closed(&mut self, peer: &PeerId, connection: &ConnectionId) -> Option<Multiaddr> {
+        if let Some(connections) = self.connections.get_mut(peer) {
+            connections.remove(connection);
+            if connections.is_empty() {
+                self.connections.remove(peer);
+            }
+        }
+
+        if !self.has_direct(peer) {
+            self.migrations.remove(peer);
+            return None;
+        }
+        let migration = self.migrations.get_mut(peer)?;
+        if !migration.circuits.remove(connection) || !migration.circuits.is_empty() {
+            return None;
+        }
+        self.migrations
+            .remove(peer)
+            .map(|migration| migration.address)
+    }
+
+    pub fn relayed_only(&self, peer: &PeerId) -> bool {
+        self.connections
+            .get(peer)
+            .is_some_and(|connections| connections.values().all(|relayed| *relayed))
+    }
+
+    pub fn has_direct(&self, peer: &PeerId) -> bool {
+        self.connections
+            .get(peer)
+            .is_some_and(|connections| connections.values().any(|relayed| !relayed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(value: &str) -> Multiaddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn migration_completes_once_circuits_close() {
+        let mut dialer = Dialer::new();
+        let peer = PeerId::random();
+        let circuit = ConnectionId::new_unchecked(1);
+        let direct = ConnectionId::new_unchecked(2);
+        let relay = address("/ip4/10.0.0.1/tcp/4001/p2p-circuit");
+        let local = address("/ip4/192.168.1.5/tcp/4001");
+
+        dialer.on_established(peer, circuit, &relay, true);
+        dialer.on_established(peer, direct, &local, false);
+        assert!(dialer.drained().is_empty());
+        assert!(dialer.has_direct(&peer));
+
+        assert_eq!(dialer.on_closed(&peer, &circuit), Some(local));
+        assert!(!dialer.relayed_only(&peer));
+    }
+
+    #[test]
+    fn migration_is_abandoned_when_the_direct_connection_drops() {
+        let mut dialer = Dialer::new();
+        let peer = PeerId::random();
+        let circuit = ConnectionId::new_unchecked(1);
+        let direct = ConnectionId::new_unchecked(2);
+
+        dialer.on_established(
+            peer,
+            circuit,
+            &address("/ip4/10.0.0.1/tcp/4001/p2p-circuit"),
+            true,
+        );
+        dialer.on_established(peer, direct, &address("/ip4/192.168.1.5/tcp/4001"), false);
+        assert_eq!(dialer.on_closed(&peer, &direct), None);
+        assert!(dialer.relayed_only(&peer));
+        assert_eq!(dialer.on_closed(&peer, &circuit), None);
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+use async_channel::Sender;
+use async_lock::Semaphore;
+use libp2p::PeerId;
+
+use crate::{
+    peers::PeerStore,
+    runtime::{Executor, Runtime},
+};
+
+use super::{
+    command::{CommandClass, CommandKind, CommandWrapper},
+    session::Outbox,
+};
+
+pub struct Dispatcher {
+    peers: PeerStore,
+    outbox: Arc<Mutex<Outbox>>,
+    limit: Arc<Semaphore>,
+}
+
+impl Dispatcher {
+    pub fn new(peers: PeerStore, outbox: Arc<Mutex<Outbox>>, concurrency: usize) -> Self {
+        Dispatcher {
+            peers,
+            outbox,
+            limit: Arc::new(Semaphore::new(concurrency.max(1))),
+        }
+    }
+
+    pub fn dispatch(
+        &self,
+        command: CommandWrapper,
+        connected: HashSet<PeerId>,
+        journaled: Option<Sender<()>>,
+    ) {
+        let peers = self.peers.clone();
+        let outbox = self.outbox.clone();
+        let limit = self.limit.clone();
+        Runtime::spawn(async move {
+            let _permit = limit.acquire().await;
+            let _ = run(peers, outbox, command, connected, journaled).await;
+        });
+    }
+}
+
+async fn run(
+    peers: PeerStore,
+    outbox: Arc<Mutex<Outbox>>,
+    command: CommandWrapper,
+    connected: HashSet<PeerId>,
+    journaled: Option<Sender<()>>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if command.command.class() == CommandClass::Serial {
+        command
+            .respond::<(), _>(Err("Command must run on the event loop"))
+            .await?;
+        return Ok(());
+    }
+
+    match command.kind() {
+        CommandKind::BestPeerFor { service_or_cid } => {
+            command.respond(peers.best_for(&service_or_cid)).await?
+        }
+        CommandKind::ListPeers(filter) => {
+            let listed = peers
+                .list()
+                .map(|list| filter.apply(list, |id| connected.contains(id)));
+            command.respond(listed).await?
+        }
+        CommandKind::Send {
+            peer,
+            payload,
+            guarantee,
+            tag,
+            redundancy,
+            #[cfg(feature = "opentelemetry")]
+            trace,
+        } => {
+            let receipt = outbox.lock().unwrap_or_else(|e| e.into_inner()).push(
+                peer,
+                payload,
+                guarantee,
+                tag,
+                redundancy,
+                None,
+                #[cfg(feature = "opentelemetry")]
+                trace,
+            );
+            settle(journaled);
+            command.reply(receipt).await?
+        }
+        CommandKind::ChannelSend {
+            peer,
+            label,
+            payload,
+        } => {
+            let receipt = outbox
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push_channel(peer, label, payload, None);
+            settle(journaled);
+            command.reply(receipt).await?
+        }
+        _ => {
+            command
+                .respond::<(), _>(Err("Command has no concurrent handler"))
+                .await?
+        }
+    }
+
+    Ok(())
+}
+
+fn settle(journaled: Option<Sender<()>>) {
+    if let Some(journaled) = journaled {
+        let _ = journaled.try_send(());
+    }
+}
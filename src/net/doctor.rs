@@ -0,0 +1,216 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+
+use super::command::CommandWrapper;
+
+const DOCTOR_TIMEOUT: TimeDelta = TimeDelta::seconds(20);
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Check {
+    Bind,
+    Bootstrap,
+    Rendezvous,
+    Relay,
+    HolePunch,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DoctorStep {
+    pub check: Check,
+    pub outcome: Outcome,
+    pub detail: String,
+    pub fix: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DoctorReport {
+    pub steps: Vec<DoctorStep>,
+    pub healthy: bool,
+}
+
+pub struct Doctor {
+    command: CommandWrapper,
+    local: PeerId,
+    target: Option<PeerId>,
+    deadline: DateTime<Utc>,
+    steps: BTreeMap<Check, DoctorStep>,
+    bootstrap: HashSet<PeerId>,
+    rendezvous: HashSet<PeerId>,
+}
+
+impl Doctor {
+    pub fn new(command: CommandWrapper, local: PeerId, target: Option<PeerId>) -> Self {
+        Doctor {
+            command,
+            local,
+            target,
+            deadline: Utc::now() + DOCTOR_TIMEOUT,
+            steps: BTreeMap::new(),
+            bootstrap: HashSet::new(),
+            rendezvous: HashSet::new(),
+        }
+    }
+
+    pub fn resolve<D: AsRef<str>>(
+        &mut self,
+        check: Check,
+        outcome: Outcome,
+        detail: D,
+        fix: Option<&str>,
+    ) {
+        self.steps.entry(check).or_insert(DoctorStep {
+            check,
+            outcome,
+            detail: detail.as_ref().to_string(),
+            fix: fix.map(String::from),
+        });
+    }
+
+    fn pending(&self, check: Check) -> bool {
+        !self.steps.contains_key(&check)
+    }
+
+    pub fn await_bootstrap(&mut self, peers: HashSet<PeerId>) {
+        self.bootstrap = peers;
+    }
+
+    pub fn await_rendezvous(&mut self, servers: HashSet<PeerId>) {
+        self.rendezvous = servers;
+    }
+
+    pub fn on_connected(&mut self, peer: PeerId, relayed: bool) {
+        if self.pending(Check::Bootstrap) && self.bootstrap.contains(&peer) {
+            self.resolve(
+                Check::Bootstrap,
+                Outcome::Passed,
+                format!("Connected to bootstrap peer {peer}"),
+                None,
+            );
+        }
+
+        if self.pending(Check::HolePunch) && self.target == Some(peer) && !relayed {
+            self.resolve(
+                Check::HolePunch,
+                Outcome::Passed,
+                format!("Direct connection to {peer} established"),
+                None,
+            );
+        }
+    }
+
+    pub fn on_reservation(&mut self, relay: PeerId) {
+        self.resolve(
+            Check::Relay,
+            Outcome::Passed,
+            format!("Relay {relay} accepted a circuit reservation"),
+            None,
+        );
+    }
+
+    pub fn on_discovered(&mut self, server: PeerId, registered: &[PeerId]) {
+        if !self.pending(Check::Rendezvous) || !self.rendezvous.contains(&server) {
+            return;
+        }
+
+        if registered.contains(&self.local) {
+            self.resolve(
+                Check::Rendezvous,
+                Outcome::Passed,
+                format!("Rendezvous server {server} lists this node"),
+                None,
+            );
+        } else {
+            self.rendezvous.remove(&server);
+            if self.rendezvous.is_empty() {
+                self.resolve(
+                    Check::Rendezvous,
+                    Outcome::Failed,
+                    "No rendezvous server lists this node",
+                    Some("Re-run AddRendezvous and check that the group name is the same on every node"),
+                );
+            }
+        }
+    }
+
+    pub fn on_hole_punch(&mut self, peer: PeerId, success: bool) {
+        if !self.pending(Check::HolePunch) || self.target != Some(peer) {
+            return;
+        }
+
+        match success {
+            true => self.resolve(
+                Check::HolePunch,
+                Outcome::Passed,
+                format!("Hole punch to {peer} succeeded"),
+                None,
+            ),
+            false => self.resolve(
+                Check::HolePunch,
+                Outcome::Failed,
+                format!("Hole punch to {peer} failed; traffic stays on the relay"),
+                Some("One side is behind a symmetric NAT; keep a relay reservation or forward a port"),
+            ),
+        }
+    }
+
+    pub fn expire(&mut self) {
+        if Utc::now() < self.deadline {
+            return;
+        }
+
+        self.resolve(
+            Check::Bootstrap,
+            Outcome::Failed,
+            "Timed out dialing bootstrap peers",
+            Some("Check the bootstrap addresses and that outbound TCP is allowed"),
+        );
+        self.resolve(
+            Check::Rendezvous,
+            Outcome::Failed,
+            "Timed out waiting for the rendezvous server",
+            Some("Check that the rendezvous server is online and reachable"),
+        );
+        self.resolve(
+            Check::Relay,
+            Outcome::Failed,
+            "Timed out waiting for a relay reservation",
+            Some(
+                "Check that the relay runs the circuit relay v2 protocol and accepts reservations",
+            ),
+        );
+        self.resolve(
+            Check::HolePunch,
+            Outcome::Failed,
+            "Timed out connecting to the target",
+            Some("Make sure the target shares a relay with this node"),
+        );
+    }
+
+    pub fn complete(&self) -> bool {
+        [
+            Check::Bind,
+            Check::Bootstrap,
+            Check::Rendezvous,
+            Check::Relay,
+            Check::HolePunch,
+        ]
+        .iter()
+        .all(|check| !self.pending(*check))
+    }
+
+    pub fn finish(self) -> (CommandWrapper, DoctorReport) {
+        let steps: Vec<DoctorStep> = self.steps.into_values().collect();
+        let healthy = steps.iter().all(|step| step.outcome != Outcome::Failed);
+        (self.command, DoctorReport { steps, healthy })
+    }
+}
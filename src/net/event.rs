@@ -1,2 +1,143 @@
-#[derive(Clone, Debug)]
-pub enum Event {}
\ No newline at end of file
+use std::{io::ErrorKind, ops::RangeInclusive, time::Duration};
+
+use bytes::Bytes;
+use libp2p::{swarm::ConnectionError, Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::util::Peer;
+
+use super::{acl::Permission, feed::FeedEntry, admin::Reconfiguration, bootstrap::BootstrapStage, dial::TransportKind, schema::MessageTag, tracer::TraceStep, update::UpdateManifest};
+#[cfg(feature = "opentelemetry")]
+use super::trace::TraceContext;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DisconnectReason {
+    Closed,
+    RemoteClosed,
+    KeepAliveTimeout,
+    Io(String),
+    Incompatible,
+    Departed,
+    AccessDenied,
+    Requested
+}
+
+impl DisconnectReason {
+    pub fn from_cause(cause: Option<&ConnectionError>) -> Self {
+        match cause {
+            None => DisconnectReason::Closed,
+            Some(ConnectionError::KeepAliveTimeout) => DisconnectReason::KeepAliveTimeout,
+            Some(ConnectionError::IO(e)) => match e.kind() {
+                ErrorKind::UnexpectedEof
+                | ErrorKind::ConnectionReset
+                | ErrorKind::BrokenPipe => DisconnectReason::RemoteClosed,
+                _ => DisconnectReason::Io(e.to_string())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Event {
+    ConnectionMigrated { peer: PeerId, address: Multiaddr },
+    BootstrapProgress { stage: BootstrapStage },
+    OutboundOnly,
+    PeerDiscovered(Peer),
+    PeersDiscovered(Vec<Peer>),
+    RelayFailover { from: PeerId, to: PeerId },
+    SubscriptionsRestored(Vec<String>),
+    IncompatiblePeer { peer: PeerId, their_version: String },
+    PeerDeparted(PeerId),
+    PeerDisconnected { peer: PeerId, reason: DisconnectReason },
+    PeersPruned(Vec<PeerId>),
+    GroupKeyRotated { epoch: u64 },
+    GroupChanged { from: String, to: String },
+    ConsensusReached { key: String, value: Value },
+    AclViolation { peer: PeerId, permission: Permission, strikes: u32 },
+    ReplayDetected { peer: PeerId },
+    Reconfigured { by: PeerId, changes: Reconfiguration },
+    UpdateAvailable { publisher: PeerId, manifest: UpdateManifest },
+    ShardsRepaired { blob: String, shards: Vec<usize> },
+    FeedAppended(FeedEntry),
+    MissedMessages { from: PeerId, room: String, range: RangeInclusive<u64> },
+    EventLoopStalled { stalled_for: Duration, last_item: String },
+    CommandTrace { command: Uuid, step: TraceStep },
+    SelfDialRejected { address: Multiaddr },
+    ListeningOn { listener: u64, address: Multiaddr },
+    ListenerExpired { listener: u64, address: Multiaddr },
+    ListenerFailed { listener: u64, error: String },
+    ListenerClosed { listener: u64, error: Option<String> },
+    CircuitOpened { peer: PeerId },
+    CircuitClosed { peer: PeerId },
+    Paused,
+    Resumed,
+    BandwidthBudgetExceeded { used: u64, limit: u64 },
+    Critical { id: Uuid, event: Box<Event> },
+    InboundConnection {
+        peer: PeerId,
+        remote_addr: Multiaddr,
+        transport: TransportKind,
+        relayed: bool
+    },
+    Message {
+        peer: PeerId,
+        seq: u64,
+        id: Option<Uuid>,
+        #[cfg(feature = "opentelemetry")]
+        trace: Option<TraceContext>,
+        payload: Bytes
+    },
+    TypedMessage {
+        peer: PeerId,
+        seq: u64,
+        id: Option<Uuid>,
+        tag: MessageTag,
+        value: Value
+    }
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::ConnectionMigrated { .. } => "ConnectionMigrated",
+            Event::BootstrapProgress { .. } => "BootstrapProgress",
+            Event::OutboundOnly => "OutboundOnly",
+            Event::PeerDiscovered(..) => "PeerDiscovered",
+            Event::PeersDiscovered(..) => "PeersDiscovered",
+            Event::RelayFailover { .. } => "RelayFailover",
+            Event::SubscriptionsRestored(..) => "SubscriptionsRestored",
+            Event::IncompatiblePeer { .. } => "IncompatiblePeer",
+            Event::PeerDeparted(..) => "PeerDeparted",
+            Event::PeerDisconnected { .. } => "PeerDisconnected",
+            Event::PeersPruned(..) => "PeersPruned",
+            Event::GroupKeyRotated { .. } => "GroupKeyRotated",
+            Event::GroupChanged { .. } => "GroupChanged",
+            Event::ConsensusReached { .. } => "ConsensusReached",
+            Event::AclViolation { .. } => "AclViolation",
+            Event::ReplayDetected { .. } => "ReplayDetected",
+            Event::Reconfigured { .. } => "Reconfigured",
+            Event::UpdateAvailable { .. } => "UpdateAvailable",
+            Event::ShardsRepaired { .. } => "ShardsRepaired",
+            Event::FeedAppended(..) => "FeedAppended",
+            Event::MissedMessages { .. } => "MissedMessages",
+            Event::EventLoopStalled { .. } => "EventLoopStalled",
+            Event::CommandTrace { .. } => "CommandTrace",
+            Event::SelfDialRejected { .. } => "SelfDialRejected",
+            Event::ListeningOn { .. } => "ListeningOn",
+            Event::ListenerExpired { .. } => "ListenerExpired",
+            Event::ListenerFailed { .. } => "ListenerFailed",
+            Event::ListenerClosed { .. } => "ListenerClosed",
+            Event::CircuitOpened { .. } => "CircuitOpened",
+            Event::CircuitClosed { .. } => "CircuitClosed",
+            Event::Paused => "Paused",
+            Event::Resumed => "Resumed",
+            Event::BandwidthBudgetExceeded { .. } => "BandwidthBudgetExceeded",
+            Event::Critical { .. } => "Critical",
+            Event::InboundConnection { .. } => "InboundConnection",
+            Event::Message { .. } => "Message",
+            Event::TypedMessage { .. } => "TypedMessage",
+        }
+    }
+}
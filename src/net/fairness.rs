@@ -0,0 +1,71 @@
+use std::{io, sync::Arc, time::Duration};
+
+use async_lock::Semaphore;
+use libp2p::futures::{select_biased, AsyncWrite, AsyncWriteExt, FutureExt};
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::sleep;
+
+const QUANTUM: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SendScheduling {
+    #[default]
+    Concurrent,
+    RoundRobin {
+        slice: usize,
+    },
+}
+
+#[derive(Clone)]
+pub struct FairScheduler {
+    slice: Option<usize>,
+    turns: Arc<Semaphore>,
+}
+
+impl Default for FairScheduler {
+    fn default() -> Self {
+        FairScheduler::new(SendScheduling::default())
+    }
+}
+
+impl FairScheduler {
+    pub fn new(scheduling: SendScheduling) -> Self {
+        FairScheduler {
+            slice: match scheduling {
+                SendScheduling::Concurrent => None,
+                SendScheduling::RoundRobin { slice } => Some(slice.max(1)),
+            },
+            turns: Arc::new(Semaphore::new(1)),
+        }
+    }
+
+    pub async fn write_all<W: AsyncWrite + Unpin>(
+        &self,
+        io: &mut W,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let Some(slice) = self.slice else {
+            return io.write_all(data).await;
+        };
+
+        let mut written = 0;
+        while written < data.len() {
+            let turn = self.turns.acquire().await;
+            let end = (written + slice).min(data.len());
+            let result = select_biased! {
+                result = io.write(&data[written..end]).fuse() => Some(result),
+                _ = sleep(QUANTUM).fuse() => None,
+            };
+            drop(turn);
+
+            match result {
+                Some(Ok(0)) => return Err(io::ErrorKind::WriteZero.into()),
+                Some(Ok(count)) => written += count,
+                Some(Err(e)) => return Err(e),
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,398 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    io,
+    sync::{Arc, Mutex},
+};
+
+use async_channel::Sender;
+use chrono::{DateTime, Utc};
+use libp2p::{
+    futures::AsyncWriteExt,
+    gossipsub::IdentTopic,
+    identity::{Keypair, PublicKey},
+    PeerId, Stream, StreamProtocol,
+};
+use libp2p_stream::Control;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    blobs::digest,
+    runtime::{Executor, Runtime},
+    storage::Storage,
+};
+
+use super::{
+    command::CommandWrapper,
+    event::Event,
+    wire::{read_frame, write_frame, Frame},
+};
+
+pub const FEED_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/feed/1.0.0");
+const ENTRIES: &str = "feed_entries";
+const HEADS: &str = "feed_heads";
+const PAGE_SIZE: u64 = 128;
+
+pub fn feed_id(author: &PeerId, name: &str) -> String {
+    format!("{author}/{name}")
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeedEntry {
+    pub feed: String,
+    pub author: PeerId,
+    pub seq: u64,
+    pub previous: Option<String>,
+    pub data: Value,
+    pub timestamp: DateTime<Utc>,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+fn message(
+    feed: &str,
+    seq: u64,
+    previous: &Option<String>,
+    data: &Value,
+    timestamp: &DateTime<Utc>,
+) -> Option<Vec<u8>> {
+    let mut message = format!(
+        "{feed}:{seq}:{}:{}:",
+        previous.as_deref().unwrap_or_default(),
+        timestamp.timestamp_micros()
+    )
+    .into_bytes();
+    message.extend_from_slice(&serde_json::to_vec(data).ok()?);
+    Some(message)
+}
+
+impl FeedEntry {
+    fn sign(
+        key: &Keypair,
+        feed: String,
+        seq: u64,
+        previous: Option<String>,
+        data: Value,
+    ) -> Option<Self> {
+        let timestamp = Utc::now();
+        let signature = key
+            .sign(&message(&feed, seq, &previous, &data, &timestamp)?)
+            .ok()?;
+        Some(FeedEntry {
+            feed,
+            author: key.public().to_peer_id(),
+            seq,
+            previous,
+            data,
+            timestamp,
+            public_key: key.public().encode_protobuf(),
+            signature,
+        })
+    }
+
+    pub fn hash(&self) -> String {
+        let mut content = self.signature.clone();
+        content.extend_from_slice(self.feed.as_bytes());
+        content.extend_from_slice(&self.seq.to_be_bytes());
+        digest(&content)
+    }
+
+    pub fn verify(&self) -> bool {
+        let Ok(key) = PublicKey::try_decode_protobuf(&self.public_key) else {
+            return false;
+        };
+        let Some(message) = message(
+            &self.feed,
+            self.seq,
+            &self.previous,
+            &self.data,
+            &self.timestamp,
+        ) else {
+            return false;
+        };
+        key.to_peer_id() == self.author
+            && self.feed.starts_with(&format!("{}/", self.author))
+            && key.verify(&message, &self.signature)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FeedHead {
+    pub feed: String,
+    pub author: PeerId,
+    pub length: u64,
+    pub hash: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Appended {
+    Accepted,
+    Duplicate,
+    Gap { expected: u64 },
+    Rejected,
+}
+
+#[derive(Clone, Debug)]
+pub struct Feeds {
+    storage: Arc<dyn Storage>,
+    topic: IdentTopic,
+    catching_up: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Feeds {
+    pub fn new(storage: Arc<dyn Storage>, group: &str) -> Self {
+        Feeds {
+            storage,
+            topic: IdentTopic::new(format!("/modius/{group}/feeds")),
+            catching_up: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    pub fn topic(&self) -> &IdentTopic {
+        &self.topic
+    }
+
+    pub fn set_group(&mut self, group: &str) {
+        self.topic = IdentTopic::new(format!("/modius/{group}/feeds"));
+    }
+
+    pub fn head(&self, feed: &str) -> Result<Option<FeedHead>, Box<dyn Error + Send + Sync>> {
+        self.storage.get_value::<FeedHead>(HEADS, feed.as_bytes())
+    }
+
+    pub fn heads(&self) -> Result<Vec<FeedHead>, Box<dyn Error + Send + Sync>> {
+        self.storage.values::<FeedHead>(HEADS)
+    }
+
+    fn key(feed: &str, seq: u64) -> Vec<u8> {
+        format!("{feed}/{seq:020}").into_bytes()
+    }
+
+    pub fn entry(
+        &self,
+        feed: &str,
+        seq: u64,
+    ) -> Result<Option<FeedEntry>, Box<dyn Error + Send + Sync>> {
+        self.storage
+            .get_value::<FeedEntry>(ENTRIES, &Feeds::key(feed, seq))
+    }
+
+    pub fn read(
+        &self,
+        feed: &str,
+        from: u64,
+        limit: u64,
+    ) -> Result<Vec<FeedEntry>, Box<dyn Error + Send + Sync>> {
+        let Some(head) = self.head(feed)? else {
+            return Ok(Vec::new());
+        };
+
+        let mut entries = Vec::new();
+        for seq in from..head.length.min(from.saturating_add(limit)) {
+            match self.entry(feed, seq)? {
+                Some(entry) => entries.push(entry),
+                None => return Err(format!("Missing entry {seq} of feed {feed}").into()),
+            }
+        }
+        Ok(entries)
+    }
+
+    fn store(&self, entry: &FeedEntry) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.storage
+            .put_value(ENTRIES, &Feeds::key(&entry.feed, entry.seq), entry)?;
+        self.storage.put_value(
+            HEADS,
+            entry.feed.as_bytes(),
+            &FeedHead {
+                feed: entry.feed.clone(),
+                author: entry.author,
+                length: entry.seq + 1,
+                hash: entry.hash(),
+            },
+        )
+    }
+
+    pub fn append(
+        &self,
+        key: &Keypair,
+        name: &str,
+        data: Value,
+    ) -> Result<FeedEntry, Box<dyn Error + Send + Sync>> {
+        let feed = feed_id(&key.public().to_peer_id(), name);
+        let head = self.head(&feed)?;
+        let entry = FeedEntry::sign(
+            key,
+            feed,
+            head.as_ref().map(|head| head.length).unwrap_or(0),
+            head.map(|head| head.hash),
+            data,
+        )
+        .ok_or("Unable to sign feed entry")?;
+        self.store(&entry)?;
+        Ok(entry)
+    }
+
+    pub fn accept(&self, entry: &FeedEntry) -> Result<Appended, Box<dyn Error + Send + Sync>> {
+        if !entry.verify() {
+            return Ok(Appended::Rejected);
+        }
+
+        let head = self.head(&entry.feed)?;
+        let expected = head.as_ref().map(|head| head.length).unwrap_or(0);
+        if entry.seq < expected {
+            return Ok(match self.entry(&entry.feed, entry.seq)? {
+                Some(existing) if existing.signature == entry.signature => Appended::Duplicate,
+                _ => Appended::Rejected,
+            });
+        }
+        if entry.seq > expected {
+            return Ok(Appended::Gap { expected });
+        }
+        if entry.previous != head.map(|head| head.hash) {
+            return Ok(Appended::Rejected);
+        }
+
+        self.store(entry)?;
+        Ok(Appended::Accepted)
+    }
+
+    pub fn serve(&self, stream: Stream) {
+        let feeds = self.clone();
+        Runtime::spawn(async move {
+            let _ = feeds.respond(stream).await;
+        });
+    }
+
+    async fn respond(&self, mut stream: Stream) -> io::Result<()> {
+        loop {
+            let reply = match read_frame(&mut stream).await? {
+                Frame::FeedHeadsRequest => Frame::FeedHeads(self.heads().unwrap_or_default()),
+                Frame::FeedRequest { feed, from } => {
+                    Frame::FeedEntries(self.read(&feed, from, PAGE_SIZE).unwrap_or_default())
+                }
+                _ => break,
+            };
+            write_frame(&mut stream, &reply).await?;
+        }
+        stream.close().await
+    }
+
+    pub fn catch_up(
+        &self,
+        control: Control,
+        peers: Vec<PeerId>,
+        feed: Option<String>,
+        events: Sender<Event>,
+        command: Option<CommandWrapper>,
+    ) {
+        let key = feed.clone().unwrap_or_default();
+        let tracked = command.is_none()
+            && self
+                .catching_up
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key.clone());
+        if command.is_none() && !tracked {
+            return;
+        }
+
+        let feeds = self.clone();
+        Runtime::spawn(async move {
+            let mut appended = 0;
+            for peer in peers {
+                if let Ok(entries) = feeds.fetch(control.clone(), peer, feed.as_deref()).await {
+                    appended += entries.len();
+                    for entry in entries {
+                        let _ = events.send(Event::FeedAppended(entry)).await;
+                    }
+                }
+            }
+            if tracked {
+                feeds
+                    .catching_up
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(&key);
+            }
+            if let Some(command) = command {
+                let _ = command.reply(appended).await;
+            }
+        });
+    }
+
+    async fn fetch(
+        &self,
+        mut control: Control,
+        peer: PeerId,
+        feed: Option<&str>,
+    ) -> io::Result<Vec<FeedEntry>> {
+        let mut stream = control
+            .open_stream(peer, FEED_PROTOCOL)
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::ConnectionRefused, e))?;
+
+        let mut wanted = Vec::new();
+        match feed {
+            Some(feed) => wanted.push(feed.to_string()),
+            None => {
+                write_frame(&mut stream, &Frame::FeedHeadsRequest).await?;
+                let Frame::FeedHeads(heads) = read_frame(&mut stream).await? else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Unexpected feed response",
+                    ));
+                };
+                for head in heads {
+                    let length = self
+                        .head(&head.feed)
+                        .ok()
+                        .flatten()
+                        .map(|local| local.length)
+                        .unwrap_or(0);
+                    if head.length > length {
+                        wanted.push(head.feed);
+                    }
+                }
+            }
+        }
+
+        let mut appended = Vec::new();
+        'feeds: for feed in wanted {
+            loop {
+                let from = self
+                    .head(&feed)
+                    .ok()
+                    .flatten()
+                    .map(|head| head.length)
+                    .unwrap_or(0);
+                write_frame(
+                    &mut stream,
+                    &Frame::FeedRequest {
+                        feed: feed.clone(),
+                        from,
+                    },
+                )
+                .await?;
+                let Frame::FeedEntries(entries) = read_frame(&mut stream).await? else {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Unexpected feed response",
+                    ));
+                };
+                if entries.is_empty() {
+                    break;
+                }
+
+                for entry in entries {
+                    if entry.feed != feed || self.accept(&entry).ok() != Some(Appended::Accepted) {
+                        continue 'feeds;
+                    }
+                    appended.push(entry);
+                }
+            }
+        }
+        let _ = stream.close().await;
+        Ok(appended)
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+
+use async_channel::Sender;
+use bytes::{BufMut, Bytes, BytesMut};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use chrono::{DateTime, Utc};
+use libp2p::{futures::AsyncWriteExt, PeerId, Stream, StreamProtocol};
+use libp2p_stream::Control;
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::{Executor, Runtime};
+
+use super::wire::{read_frame, write_frame, Frame};
+
+pub const GROUP_KEY_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/groupkey/1.0.0");
+const SEALED_MAGIC: &[u8; 4] = b"MGK1";
+const NONCE_LENGTH: usize = 12;
+const RETAINED_EPOCHS: usize = 2;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GroupKey {
+    pub epoch: u64,
+    pub key: [u8; 32],
+    pub created: DateTime<Utc>,
+}
+
+impl GroupKey {
+    pub fn generate(epoch: u64) -> Self {
+        GroupKey {
+            epoch,
+            key: rand::random(),
+            created: Utc::now(),
+        }
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.key))
+    }
+}
+
+#[derive(Default)]
+pub struct Keyring {
+    keys: BTreeMap<u64, GroupKey>,
+}
+
+impl Keyring {
+    pub fn new() -> Self {
+        Keyring::default()
+    }
+
+    pub fn current(&self) -> Option<&GroupKey> {
+        self.keys.values().next_back()
+    }
+
+    pub fn install(&mut self, key: GroupKey) -> bool {
+        if self
+            .current()
+            .is_some_and(|current| current.epoch >= key.epoch)
+        {
+            return false;
+        }
+
+        self.keys.insert(key.epoch, key);
+        while self.keys.len() > RETAINED_EPOCHS {
+            self.keys.pop_first();
+        }
+        true
+    }
+
+    pub fn rotate(&mut self) -> GroupKey {
+        let epoch = self.current().map(|key| key.epoch + 1).unwrap_or(0);
+        let key = GroupKey::generate(epoch);
+        self.install(key.clone());
+        key
+    }
+
+    pub fn seal(&self, payload: &[u8]) -> Option<Bytes> {
+        let key = self.current()?;
+        let nonce: [u8; NONCE_LENGTH] = rand::random();
+        let sealed = key
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .ok()?;
+
+        let mut out = BytesMut::with_capacity(4 + 8 + NONCE_LENGTH + sealed.len());
+        out.put_slice(SEALED_MAGIC);
+        out.put_u64(key.epoch);
+        out.put_slice(&nonce);
+        out.put_slice(&sealed);
+        Some(out.freeze())
+    }
+
+    pub fn open(&self, payload: Bytes) -> Result<Bytes, &'static str> {
+        let Some(body) = payload.strip_prefix(SEALED_MAGIC) else {
+            return Ok(payload);
+        };
+        if body.len() < 8 + NONCE_LENGTH {
+            return Err("Sealed payload is truncated");
+        }
+
+        let (epoch, body) = body.split_at(8);
+        let (nonce, sealed) = body.split_at(NONCE_LENGTH);
+        let epoch = u64::from_be_bytes(epoch.try_into().map_err(|_| "Invalid epoch")?);
+        let key = self.keys.get(&epoch).ok_or("Missing group key")?;
+        key.cipher()
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map(Bytes::from)
+            .map_err(|_| "Unable to decrypt payload")
+    }
+}
+
+pub fn grant(mut control: Control, peer: PeerId, key: GroupKey) {
+    Runtime::spawn(async move {
+        if let Ok(mut stream) = control.open_stream(peer, GROUP_KEY_PROTOCOL).await {
+            if write_frame(&mut stream, &Frame::GroupKey(key))
+                .await
+                .is_ok()
+            {
+                let _ = read_frame(&mut stream).await;
+            }
+        }
+    });
+}
+
+pub fn receive(peer: PeerId, mut stream: Stream, grants: Sender<(PeerId, GroupKey)>) {
+    Runtime::spawn(async move {
+        if let Ok(Frame::GroupKey(key)) = read_frame(&mut stream).await {
+            let _ = grants.send((peer, key)).await;
+        }
+        let _ = stream.close().await;
+    });
+}
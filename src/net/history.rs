@@ -0,0 +1,231 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use libp2p::{PeerId, Stream, StreamProtocol};
+use libp2p_stream::Control;
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::{Executor, Runtime};
+
+use super::{
+    command::CommandWrapper,
+    wire::{read_frame, write_frame, Frame},
+};
+
+pub const HISTORY_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/history/1.0.0");
+const HISTORY_CAPACITY: usize = 1024;
+const PAGE_SIZE: usize = 64;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub index: u64,
+    pub peer: Option<PeerId>,
+    pub payload: Bytes,
+    pub received: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct Log {
+    next_index: u64,
+    ids: HashSet<String>,
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl Log {
+    fn record(
+        &mut self,
+        id: String,
+        peer: Option<PeerId>,
+        payload: Bytes,
+        received: DateTime<Utc>,
+    ) {
+        if !self.ids.insert(id.clone()) {
+            return;
+        }
+
+        self.entries.push_back(HistoryEntry {
+            id,
+            index: self.next_index,
+            peer,
+            payload,
+            received,
+        });
+        self.next_index += 1;
+        if self.entries.len() > HISTORY_CAPACITY {
+            if let Some(expired) = self.entries.pop_front() {
+                self.ids.remove(&expired.id);
+            }
+        }
+    }
+
+    fn page(&self, before: Option<u64>, limit: usize) -> (Vec<HistoryEntry>, Option<u64>) {
+        let older: Vec<&HistoryEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| before.is_none_or(|before| entry.index < before))
+            .collect();
+        let start = older.len().saturating_sub(limit.min(PAGE_SIZE));
+        let next = match start {
+            0 => None,
+            _ => older.get(start).map(|entry| entry.index),
+        };
+        (
+            older[start..]
+                .iter()
+                .map(|entry| (*entry).clone())
+                .collect(),
+            next,
+        )
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct History {
+    rooms: Arc<Mutex<HashMap<String, Log>>>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        History::default()
+    }
+
+    pub fn record(&self, room: &str, id: String, peer: Option<PeerId>, payload: Bytes) {
+        let mut rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        rooms
+            .entry(room.to_string())
+            .or_default()
+            .record(id, peer, payload, Utc::now());
+    }
+
+    fn merge(&self, room: &str, entries: &[HistoryEntry]) {
+        let mut rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        let log = rooms.entry(room.to_string()).or_default();
+        for entry in entries {
+            log.record(
+                entry.id.clone(),
+                entry.peer,
+                entry.payload.clone(),
+                entry.received,
+            );
+        }
+    }
+
+    pub fn forget(&self, room: &str) {
+        let mut rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        rooms.remove(room);
+    }
+
+    fn page(
+        &self,
+        room: &str,
+        before: Option<u64>,
+        limit: usize,
+    ) -> (Vec<HistoryEntry>, Option<u64>) {
+        let rooms = self.rooms.lock().unwrap_or_else(|e| e.into_inner());
+        rooms
+            .get(room)
+            .map(|log| log.page(before, limit))
+            .unwrap_or_default()
+    }
+
+    pub fn serve(&self, stream: Stream) {
+        let history = self.clone();
+        Runtime::spawn(async move {
+            let _ = history.respond(stream).await;
+        });
+    }
+
+    async fn respond(&self, mut stream: Stream) -> io::Result<()> {
+        while let Frame::HistoryRequest {
+            room,
+            before,
+            limit,
+        } = read_frame(&mut stream).await?
+        {
+            let (entries, next) = self.page(&room, before, limit);
+            write_frame(&mut stream, &Frame::HistoryPage { entries, next }).await?;
+        }
+        Ok(())
+    }
+
+    pub fn backfill(
+        &self,
+        command: CommandWrapper,
+        control: Control,
+        members: Vec<PeerId>,
+        room: String,
+        count: usize,
+    ) {
+        let history = self.clone();
+        Runtime::spawn(async move {
+            let mut seen = HashSet::new();
+            let mut collected = Vec::new();
+            for peer in members {
+                if let Ok(entries) = fetch(control.clone(), peer, &room, count).await {
+                    collected.extend(
+                        entries
+                            .into_iter()
+                            .filter(|entry| seen.insert(entry.id.clone())),
+                    );
+                }
+            }
+            collected.sort_by_key(|entry| entry.received);
+            let skip = collected.len().saturating_sub(count);
+            let collected = collected.split_off(skip);
+
+            history.merge(&room, &collected);
+            let _ = command.reply(collected).await;
+        });
+    }
+}
+
+async fn fetch(
+    mut control: Control,
+    peer: PeerId,
+    room: &str,
+    count: usize,
+) -> io::Result<Vec<HistoryEntry>> {
+    let mut stream = control
+        .open_stream(peer, HISTORY_PROTOCOL)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::ConnectionRefused, e))?;
+
+    let mut entries = Vec::new();
+    let mut before = None;
+    while entries.len() < count {
+        write_frame(
+            &mut stream,
+            &Frame::HistoryRequest {
+                room: room.to_string(),
+                before,
+                limit: count - entries.len(),
+            },
+        )
+        .await?;
+        let Frame::HistoryPage {
+            entries: page,
+            next,
+        } = read_frame(&mut stream).await?
+        else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unexpected history response",
+            ));
+        };
+
+        let mut page = page;
+        page.append(&mut entries);
+        entries = page;
+        match next {
+            Some(next) => before = Some(next),
+            None => break,
+        }
+    }
+    Ok(entries)
+}
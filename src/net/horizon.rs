@@ -0,0 +1,161 @@
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    task::{Context, Poll},
+};
+
+use libp2p::{
+    core::{transport::PortUse, Endpoint},
+    identify,
+    multiaddr::Protocol,
+    swarm::{
+        ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler, THandlerInEvent,
+        THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Advertise {
+    #[default]
+    All,
+    SplitHorizon,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Scope {
+    Lan,
+    Wan,
+}
+
+fn lan_v4(ip: &Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || (a == 100 && (64..128).contains(&b))
+}
+
+fn lan_v6(ip: &Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || ip.to_ipv4_mapped().is_some_and(|ip| lan_v4(&ip))
+}
+
+pub fn scope(address: &Multiaddr) -> Scope {
+    for protocol in address.iter() {
+        match protocol {
+            Protocol::Ip4(ip) if lan_v4(&ip) => return Scope::Lan,
+            Protocol::Ip6(ip) if lan_v6(&ip) => return Scope::Lan,
+            Protocol::Ip4(_) | Protocol::Ip6(_) => return Scope::Wan,
+            _ => {}
+        }
+    }
+    Scope::Wan
+}
+
+impl Advertise {
+    pub fn advertises_externally(&self, address: &Multiaddr) -> bool {
+        match self {
+            Advertise::All => true,
+            Advertise::SplitHorizon => scope(address) == Scope::Wan,
+        }
+    }
+}
+
+pub struct Identify {
+    inner: identify::Behaviour,
+    advertise: Advertise,
+}
+
+impl Identify {
+    pub fn new(config: identify::Config, advertise: Advertise) -> Self {
+        Identify {
+            inner: identify::Behaviour::new(config),
+            advertise,
+        }
+    }
+}
+
+impl NetworkBehaviour for Identify {
+    type ConnectionHandler = THandler<identify::Behaviour>;
+    type ToSwarm = identify::Event;
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        addresses: &[Multiaddr],
+        effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        self.inner.handle_pending_outbound_connection(
+            connection_id,
+            maybe_peer,
+            addresses,
+            effective_role,
+        )
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.inner.handle_established_inbound_connection(
+            connection_id,
+            peer,
+            local_addr,
+            remote_addr,
+        )
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        role_override: Endpoint,
+        port_use: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.inner.handle_established_outbound_connection(
+            connection_id,
+            peer,
+            addr,
+            role_override,
+            port_use,
+        )
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        match &event {
+            FromSwarm::NewListenAddr(listen)
+                if !self.advertise.advertises_externally(listen.addr) => {}
+            FromSwarm::ExpiredListenAddr(listen)
+                if !self.advertise.advertises_externally(listen.addr) => {}
+            _ => self.inner.on_swarm_event(event),
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        self.inner
+            .on_connection_handler_event(peer_id, connection_id, event)
+    }
+
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<identify::Event, THandlerInEvent<Self>>> {
+        self.inner.poll(cx)
+    }
+}
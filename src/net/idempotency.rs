@@ -0,0 +1,95 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use async_channel::Sender;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde_json::Value;
+
+use crate::runtime::{Executor, Runtime};
+
+use super::command::{CommandResponse, CommandWrapper};
+
+const IDEMPOTENCY_WINDOW: TimeDelta = TimeDelta::minutes(10);
+
+struct Keyed {
+    at: DateTime<Utc>,
+    outcome: Option<Result<Value, String>>,
+    waiters: Vec<Sender<CommandResponse>>,
+}
+
+impl Keyed {
+    fn answer(outcome: &Result<Value, String>) -> CommandResponse {
+        outcome.clone().map_err(|e| e.into())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Idempotency {
+    keys: Arc<Mutex<HashMap<String, Keyed>>>,
+}
+
+impl Idempotency {
+    pub fn new() -> Self {
+        Idempotency::default()
+    }
+
+    pub fn admit(&self, command: &mut CommandWrapper) -> bool {
+        let Some(key) = command.idempotency_key.clone() else {
+            return true;
+        };
+
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        let cutoff = Utc::now() - IDEMPOTENCY_WINDOW;
+        keys.retain(|_, keyed| keyed.outcome.is_none() || keyed.at > cutoff);
+        if let Some(keyed) = keys.get_mut(&key) {
+            match keyed.outcome.as_ref() {
+                Some(outcome) => {
+                    let _ = command.response.try_send(Keyed::answer(outcome));
+                }
+                None => keyed.waiters.push(command.response.clone()),
+            }
+            return false;
+        }
+
+        keys.insert(
+            key.clone(),
+            Keyed {
+                at: Utc::now(),
+                outcome: None,
+                waiters: Vec::new(),
+            },
+        );
+        let (response, responded) = async_channel::bounded::<CommandResponse>(1);
+        let caller = std::mem::replace(&mut command.response, response);
+        let shared = self.keys.clone();
+        Runtime::spawn(async move {
+            let Ok(result) = responded.recv().await else {
+                shared
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .remove(&key);
+                return;
+            };
+
+            let outcome = result.as_ref().map(Value::clone).map_err(|e| e.to_string());
+            let waiters = {
+                let mut keys = shared.lock().unwrap_or_else(|e| e.into_inner());
+                match keys.get_mut(&key) {
+                    Some(keyed) => {
+                        keyed.at = Utc::now();
+                        keyed.outcome = Some(outcome.clone());
+                        std::mem::take(&mut keyed.waiters)
+                    }
+                    None => Vec::new(),
+                }
+            };
+            let _ = caller.send(result).await;
+            for waiter in waiters {
+                let _ = waiter.send(Keyed::answer(&outcome)).await;
+            }
+        });
+        true
+    }
+}
@@ -0,0 +1,85 @@
+use std::{collections::HashSet, str::FromStr};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use libp2p::{kad::RecordKey, multiaddr::Protocol, Multiaddr, PeerId, StreamProtocol};
+use sha2::{Digest, Sha256};
+
+pub const IPFS_KAD_PROTOCOL: StreamProtocol = libp2p::kad::PROTOCOL_NAME;
+pub const IPFS_BOOTSTRAP: [&str; 5] = [
+    "/dnsaddr/bootstrap.libp2p.io/p2p/QmNnooDu7bfjPFoTZYxMNLWUQJyrVwtbZg5gBMjTezGAJN",
+    "/dnsaddr/bootstrap.libp2p.io/p2p/QmQCU2EcMqAqQPR2i9bChDtGNJchTbq5TbXJJ16u19uLTa",
+    "/dnsaddr/bootstrap.libp2p.io/p2p/QmbLHAnMoJPWSCR5Zhtx6BHJX9KiKNN6tpvbUcqanj75Nb",
+    "/dnsaddr/bootstrap.libp2p.io/p2p/QmcZf59bWwK5XFi76CZX8cbJ4BhTzzA3gU1ZjYZcYW3dwt",
+    "/ip4/104.131.131.82/tcp/4001/p2p/QmaCpDMGvV2BGHeYERUEnRQAwe3N8SzbUtfsmvsqQLuvuJ",
+];
+
+const LOOKUP_INTERVAL: TimeDelta = TimeDelta::minutes(5);
+const SHA2_256: u8 = 0x12;
+
+pub fn bootstrap_peers() -> Vec<(PeerId, Multiaddr)> {
+    IPFS_BOOTSTRAP
+        .iter()
+        .filter_map(|address| {
+            let address = Multiaddr::from_str(address).ok()?;
+            match address.iter().last()? {
+                Protocol::P2p(peer) => Some((peer, address)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+pub fn group_key(group: &str) -> RecordKey {
+    let digest = Sha256::digest(format!("/modius/group/{group}"));
+    let mut multihash = vec![SHA2_256, digest.len() as u8];
+    multihash.extend_from_slice(&digest);
+    RecordKey::new(&multihash)
+}
+
+pub struct IpfsDiscovery {
+    key: RecordKey,
+    next_lookup: DateTime<Utc>,
+    pending: HashSet<PeerId>,
+}
+
+impl IpfsDiscovery {
+    pub fn new(group: &str) -> Self {
+        IpfsDiscovery {
+            key: group_key(group),
+            next_lookup: Utc::now(),
+            pending: HashSet::new(),
+        }
+    }
+
+    pub fn key(&self) -> RecordKey {
+        self.key.clone()
+    }
+
+    pub fn lookup_due(&mut self) -> Option<RecordKey> {
+        if Utc::now() < self.next_lookup {
+            return None;
+        }
+
+        self.next_lookup = Utc::now() + LOOKUP_INTERVAL;
+        Some(self.key())
+    }
+
+    pub fn is_group_key(&self, key: &RecordKey) -> bool {
+        key == &self.key
+    }
+
+    pub fn found(&mut self, providers: impl IntoIterator<Item = PeerId>) -> Vec<PeerId> {
+        providers
+            .into_iter()
+            .filter(|peer| self.pending.insert(*peer))
+            .collect()
+    }
+
+    pub fn on_connected(&mut self, peer: &PeerId) -> bool {
+        self.pending.remove(peer)
+    }
+
+    pub fn on_dial_failed(&mut self, peer: &PeerId) {
+        self.pending.remove(peer);
+    }
+}
@@ -0,0 +1,262 @@
+use std::{error::Error, sync::Arc};
+
+use async_channel::Sender;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    runtime::{Executor, Runtime},
+    storage::Storage,
+    util::Peer,
+};
+
+use super::{
+    command::CommandKind, multipath::Redundancy, schema::MessageTag, session::DeliveryGuarantee,
+};
+
+const JOURNAL: &str = "command_journal";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum JournaledCommand {
+    Send {
+        peer: PeerId,
+        payload: Bytes,
+        guarantee: DeliveryGuarantee,
+        tag: Option<MessageTag>,
+        redundancy: Redundancy,
+    },
+    ChannelSend {
+        peer: PeerId,
+        label: String,
+        payload: Bytes,
+    },
+    AddRendezvous(Peer),
+}
+
+impl JournaledCommand {
+    fn from_command(command: &CommandKind) -> Option<Self> {
+        match command.clone() {
+            CommandKind::Send {
+                peer,
+                payload,
+                guarantee,
+                tag,
+                redundancy,
+                ..
+            } => Some(JournaledCommand::Send {
+                peer,
+                payload,
+                guarantee,
+                tag,
+                redundancy,
+            }),
+            CommandKind::ChannelSend {
+                peer,
+                label,
+                payload,
+            } => Some(JournaledCommand::ChannelSend {
+                peer,
+                label,
+                payload,
+            }),
+            CommandKind::AddRendezvous(peer) => Some(JournaledCommand::AddRendezvous(peer)),
+            _ => None,
+        }
+    }
+
+    fn is_queued(&self) -> bool {
+        matches!(
+            self,
+            JournaledCommand::Send { .. } | JournaledCommand::ChannelSend { .. }
+        )
+    }
+
+    fn into_command(self) -> CommandKind {
+        match self {
+            JournaledCommand::Send {
+                peer,
+                payload,
+                guarantee,
+                tag,
+                redundancy,
+            } => CommandKind::Send {
+                peer,
+                payload,
+                guarantee,
+                tag,
+                redundancy,
+                #[cfg(feature = "opentelemetry")]
+                trace: None,
+            },
+            JournaledCommand::ChannelSend {
+                peer,
+                label,
+                payload,
+            } => CommandKind::ChannelSend {
+                peer,
+                label,
+                payload,
+            },
+            JournaledCommand::AddRendezvous(peer) => CommandKind::AddRendezvous(peer),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct JournalEntry {
+    id: Uuid,
+    command: JournaledCommand,
+    accepted: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+pub struct Journal {
+    storage: Option<Arc<dyn Storage>>,
+}
+
+impl Journal {
+    pub fn new(storage: Arc<dyn Storage>, enabled: bool) -> Self {
+        Journal {
+            storage: enabled.then_some(storage),
+        }
+    }
+
+    pub fn record(
+        &self,
+        id: Uuid,
+        command: &CommandKind,
+    ) -> Result<Option<Sender<()>>, Box<dyn Error + Send + Sync>> {
+        let Some(storage) = self.storage.clone() else {
+            return Ok(None);
+        };
+        let Some(command) = JournaledCommand::from_command(command) else {
+            return Ok(None);
+        };
+
+        storage.put_value(
+            JOURNAL,
+            id.as_bytes(),
+            &JournalEntry {
+                id,
+                command,
+                accepted: Utc::now(),
+            },
+        )?;
+        let (settled, receiver) = async_channel::bounded::<()>(1);
+        let journal = self.clone();
+        Runtime::spawn(async move {
+            if receiver.recv().await.is_ok() {
+                let _ = journal.settle(id);
+            }
+        });
+        Ok(Some(settled))
+    }
+
+    pub fn settle(&self, id: Uuid) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.storage.as_ref() {
+            Some(storage) => storage.delete(JOURNAL, id.as_bytes()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn settle_registration(
+        &self,
+        rendezvous: &PeerId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for entry in self.entries()? {
+            if matches!(&entry.command, JournaledCommand::AddRendezvous(peer) if peer.id == *rendezvous)
+            {
+                self.settle(entry.id)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn pending(&self) -> Result<Vec<(Uuid, CommandKind)>, Box<dyn Error + Send + Sync>> {
+        let mut entries = self.entries()?;
+        entries.sort_by_key(|entry| entry.accepted);
+        let mut pending = Vec::with_capacity(entries.len());
+        for entry in entries {
+            if entry.command.is_queued() {
+                self.settle(entry.id)?;
+            }
+            pending.push((entry.id, entry.command.into_command()));
+        }
+        Ok(pending)
+    }
+
+    fn entries(&self) -> Result<Vec<JournalEntry>, Box<dyn Error + Send + Sync>> {
+        match self.storage.as_ref() {
+            Some(storage) => storage.values::<JournalEntry>(JOURNAL),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use libp2p::identity::Keypair;
+
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    fn send(peer: PeerId) -> CommandKind {
+        CommandKind::Send {
+            peer,
+            payload: Bytes::from_static(b"hello"),
+            guarantee: DeliveryGuarantee::Ordered,
+            tag: None,
+            redundancy: Redundancy::Single,
+            #[cfg(feature = "opentelemetry")]
+            trace: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_stay_journaled_until_queued() {
+        let journal = Journal::new(Arc::new(MemoryStorage::new()), true);
+        let peer = Keypair::generate_ed25519().public().to_peer_id();
+        let id = Uuid::new_v4();
+        let queued = journal.record(id, &send(peer)).unwrap().unwrap();
+        journal
+            .record(
+                Uuid::new_v4(),
+                &CommandKind::ChannelSend {
+                    peer,
+                    label: String::from("chat"),
+                    payload: Bytes::from_static(b"hi"),
+                },
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(journal.entries().unwrap().len(), 2);
+
+        queued.send(()).await.unwrap();
+        for _ in 0..100 {
+            if journal.entries().unwrap().len() == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let pending = journal.pending().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert!(matches!(&pending[0].1, CommandKind::ChannelSend { label, .. } if label == "chat"));
+    }
+
+    #[test]
+    fn disabled_journal_records_nothing() {
+        let journal = Journal::new(Arc::new(MemoryStorage::new()), false);
+        let peer = Keypair::generate_ed25519().public().to_peer_id();
+        assert!(journal
+            .record(Uuid::new_v4(), &send(peer))
+            .unwrap()
+            .is_none());
+        assert!(journal.pending().unwrap().is_empty());
+    }
+}
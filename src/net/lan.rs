@@ -0,0 +1,211 @@
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use async_channel::Sender;
+#[cfg(not(target_arch = "wasm32"))]
+use hickory_proto::{
+    op::{Message, MessageType, Query},
+    rr::{rdata::TXT, Name, RData, Record, RecordType},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use socket2::{Domain, Protocol, Socket, Type};
+
+#[cfg(not(target_arch = "wasm32"))]
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+#[cfg(not(target_arch = "wasm32"))]
+const MDNS_PORT: u16 = 5353;
+#[cfg(not(target_arch = "wasm32"))]
+const SERVICE: &str = "_modius._udp.local.";
+#[cfg(not(target_arch = "wasm32"))]
+const RECORD_TTL: u32 = 120;
+#[cfg(not(target_arch = "wasm32"))]
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(20);
+#[cfg(not(target_arch = "wasm32"))]
+const POLL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LanIdentity {
+    pub peer: PeerId,
+    pub name: String,
+    pub group: String,
+    pub device: Option<Uuid>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn is_service(name: &Name) -> bool {
+    name.to_ascii()
+        .to_ascii_lowercase()
+        .trim_end_matches('.')
+        .ends_with(SERVICE.trim_end_matches('.'))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl LanIdentity {
+    fn encode(&self) -> Option<Vec<u8>> {
+        let name = Name::from_ascii(format!("{}.{SERVICE}", self.peer)).ok()?;
+        let mut entries = vec![
+            format!("peer={}", self.peer),
+            format!("name={}", self.name),
+            format!("group={}", self.group),
+        ];
+        if let Some(device) = self.device {
+            entries.push(format!("device={device}"));
+        }
+        let txt = TXT::new(entries);
+        let mut message = Message::new();
+        message
+            .set_message_type(MessageType::Response)
+            .set_authoritative(true)
+            .add_answer(Record::from_rdata(name, RECORD_TTL, RData::TXT(txt)));
+        message.to_vec().ok()
+    }
+
+    fn decode(record: &Record) -> Option<Self> {
+        if !is_service(record.name()) {
+            return None;
+        }
+        let Some(RData::TXT(txt)) = record.data() else {
+            return None;
+        };
+
+        let (mut peer, mut name, mut group, mut device) = (None, None, None, None);
+        for entry in txt.iter() {
+            let entry = String::from_utf8_lossy(entry);
+            match entry.split_once('=') {
+                Some(("peer", value)) => peer = value.parse::<PeerId>().ok(),
+                Some(("name", value)) => name = Some(value.to_string()),
+                Some(("group", value)) => group = Some(value.to_string()),
+                Some(("device", value)) => device = Uuid::parse_str(value).ok(),
+                _ => {}
+            }
+        }
+        Some(LanIdentity {
+            peer: peer?,
+            name: name?,
+            group: group?,
+            device,
+        })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn query() -> Option<Vec<u8>> {
+    let mut message = Message::new();
+    message
+        .set_message_type(MessageType::Query)
+        .add_query(Query::query(
+            Name::from_ascii(SERVICE).ok()?,
+            RecordType::PTR,
+        ));
+    message.to_vec().ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn bind() -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_read_timeout(Some(POLL))?;
+    Ok(socket.into())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+pub struct LanAnnouncer {
+    local: Arc<Mutex<LanIdentity>>,
+    paused: Arc<AtomicBool>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl LanAnnouncer {
+    pub fn new(local: LanIdentity) -> Self {
+        LanAnnouncer {
+            local: Arc::new(Mutex::new(local)),
+            paused: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn pause(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Release);
+    }
+
+    pub fn set_group(&self, group: &str) {
+        self.local.lock().unwrap_or_else(|e| e.into_inner()).group = group.to_string();
+    }
+
+    fn local(&self) -> LanIdentity {
+        self.local.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn spawn(&self, found: Sender<LanIdentity>) -> io::Result<()> {
+        let socket = bind()?;
+        let announcer = self.clone();
+        std::thread::Builder::new()
+            .name("modius-lan".to_string())
+            .spawn(move || announcer.run(socket, found))?;
+        Ok(())
+    }
+
+    fn run(self, socket: UdpSocket, found: Sender<LanIdentity>) {
+        let target = SocketAddrV4::new(MDNS_GROUP, MDNS_PORT);
+        if let Some(query) = query() {
+            let _ = socket.send_to(&query, target);
+        }
+        let mut next = Instant::now();
+        let mut buffer = [0u8; 9000];
+        while !found.is_closed() {
+            if self.paused.load(Ordering::Acquire) {
+                std::thread::sleep(POLL);
+                continue;
+            }
+            let local = self.local();
+            let Some(announcement) = local.encode() else {
+                return;
+            };
+            if Instant::now() >= next {
+                let _ = socket.send_to(&announcement, target);
+                next = Instant::now() + ANNOUNCE_INTERVAL;
+            }
+
+            let Ok((length, _)) = socket.recv_from(&mut buffer) else {
+                continue;
+            };
+            let Ok(message) = Message::from_vec(&buffer[..length]) else {
+                continue;
+            };
+            match message.message_type() {
+                MessageType::Query => {
+                    if message
+                        .queries()
+                        .iter()
+                        .any(|query| is_service(query.name()))
+                    {
+                        let _ = socket.send_to(&announcement, target);
+                    }
+                }
+                MessageType::Response => {
+                    for identity in message.answers().iter().filter_map(LanIdentity::decode) {
+                        if identity.peer != local.peer {
+                            let _ = found.try_send(identity);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
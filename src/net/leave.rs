@@ -0,0 +1,84 @@
+use std::time::Duration;
+
+use async_channel::{Receiver, Sender};
+use chrono::{DateTime, TimeDelta, Utc};
+use libp2p::{
+    futures::AsyncWriteExt,
+    identity::{Keypair, PublicKey},
+    PeerId, Stream, StreamProtocol,
+};
+use libp2p_stream::Control;
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::{Executor, Runtime};
+
+use super::wire::{read_frame, write_frame, Frame};
+
+pub const LEAVE_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/leave/1.0.0");
+pub const DEPARTURE_TIMEOUT: Duration = Duration::from_secs(2);
+const MAX_SKEW: TimeDelta = TimeDelta::seconds(60);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Departure {
+    pub group: String,
+    pub at: DateTime<Utc>,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+fn message(group: &str, at: &DateTime<Utc>) -> Vec<u8> {
+    format!("modius-leave:{group}:{}", at.timestamp_micros()).into_bytes()
+}
+
+impl Departure {
+    pub fn sign(key: &Keypair, group: &str) -> Option<Self> {
+        let at = Utc::now();
+        Some(Departure {
+            group: group.to_string(),
+            at,
+            public_key: key.public().encode_protobuf(),
+            signature: key.sign(&message(group, &at)).ok()?,
+        })
+    }
+
+    pub fn verify(&self, peer: &PeerId, group: &str) -> bool {
+        let Ok(key) = PublicKey::try_decode_protobuf(&self.public_key) else {
+            return false;
+        };
+
+        key.to_peer_id() == *peer
+            && self.group == group
+            && (Utc::now() - self.at).abs() <= MAX_SKEW
+            && key.verify(&message(&self.group, &self.at), &self.signature)
+    }
+}
+
+pub fn announce(control: Control, peers: Vec<PeerId>, notice: Departure) -> Receiver<()> {
+    let (done, finished) = async_channel::bounded::<()>(1);
+    for peer in peers {
+        let mut control = control.clone();
+        let notice = notice.clone();
+        let done = done.clone();
+        Runtime::spawn(async move {
+            if let Ok(mut stream) = control.open_stream(peer, LEAVE_PROTOCOL).await {
+                if write_frame(&mut stream, &Frame::Departure(notice))
+                    .await
+                    .is_ok()
+                {
+                    let _ = read_frame(&mut stream).await;
+                }
+            }
+            drop(done);
+        });
+    }
+    finished
+}
+
+pub fn receive(peer: PeerId, mut stream: Stream, departures: Sender<(PeerId, Departure)>) {
+    Runtime::spawn(async move {
+        if let Ok(Frame::Departure(notice)) = read_frame(&mut stream).await {
+            let _ = departures.send((peer, notice)).await;
+        }
+        let _ = stream.close().await;
+    });
+}
@@ -0,0 +1,88 @@
+use std::collections::{BTreeMap, HashMap};
+
+use libp2p::{core::transport::ListenerId, Multiaddr};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ListenerInfo {
+    pub id: u64,
+    pub requested: Multiaddr,
+    pub addresses: Vec<Multiaddr>,
+}
+
+struct Listener {
+    id: ListenerId,
+    requested: Multiaddr,
+    addresses: Vec<Multiaddr>,
+}
+
+#[derive(Default)]
+pub struct Listeners {
+    next_id: u64,
+    ids: HashMap<ListenerId, u64>,
+    listeners: BTreeMap<u64, Listener>,
+}
+
+impl Listeners {
+    pub fn new() -> Self {
+        Listeners::default()
+    }
+
+    pub fn opened(&mut self, listener: ListenerId, requested: Multiaddr) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.ids.insert(listener, id);
+        self.listeners.insert(
+            id,
+            Listener {
+                id: listener,
+                requested,
+                addresses: Vec::new(),
+            },
+        );
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<ListenerId> {
+        self.listeners.get(&id).map(|listener| listener.id)
+    }
+
+    pub fn id(&self, listener: &ListenerId) -> Option<u64> {
+        self.ids.get(listener).copied()
+    }
+
+    pub fn on_new_address(&mut self, listener: &ListenerId, address: &Multiaddr) -> Option<u64> {
+        let id = self.id(listener)?;
+        let entry = self.listeners.get_mut(&id)?;
+        if !entry.addresses.contains(address) {
+            entry.addresses.push(address.clone());
+        }
+        Some(id)
+    }
+
+    pub fn on_expired(&mut self, listener: &ListenerId, address: &Multiaddr) -> Option<u64> {
+        let id = self.id(listener)?;
+        self.listeners
+            .get_mut(&id)?
+            .addresses
+            .retain(|known| known != address);
+        Some(id)
+    }
+
+    pub fn on_closed(&mut self, listener: &ListenerId) -> Option<u64> {
+        let id = self.ids.remove(listener)?;
+        self.listeners.remove(&id);
+        Some(id)
+    }
+
+    pub fn list(&self) -> Vec<ListenerInfo> {
+        self.listeners
+            .iter()
+            .map(|(id, listener)| ListenerInfo {
+                id: *id,
+                requested: listener.requested.clone(),
+                addresses: listener.addresses.clone(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,53 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use libp2p::{gossipsub::IdentTopic, PeerId};
+use serde::{Deserialize, Serialize};
+
+const GOSSIP_INTERVAL: TimeDelta = TimeDelta::seconds(30);
+const MAX_SIGHTINGS: usize = 64;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Sighting {
+    pub peer: PeerId,
+    pub at: DateTime<Utc>,
+}
+
+pub struct LivenessGossip {
+    topic: IdentTopic,
+    next: DateTime<Utc>,
+}
+
+impl LivenessGossip {
+    pub fn new(group: &str) -> Self {
+        LivenessGossip {
+            topic: IdentTopic::new(format!("/modius/{group}/liveness")),
+            next: Utc::now() + GOSSIP_INTERVAL,
+        }
+    }
+
+    pub fn topic(&self) -> &IdentTopic {
+        &self.topic
+    }
+
+    pub fn due(&mut self) -> bool {
+        if Utc::now() < self.next {
+            return false;
+        }
+
+        self.next = Utc::now() + GOSSIP_INTERVAL;
+        true
+    }
+
+    pub fn encode(&self, mut sightings: Vec<Sighting>) -> Option<Vec<u8>> {
+        if sightings.is_empty() {
+            return None;
+        }
+
+        sightings.sort_by_key(|sighting| std::cmp::Reverse(sighting.at));
+        sightings.truncate(MAX_SIGHTINGS);
+        serde_json::to_vec(&sightings).ok()
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Vec<Sighting> {
+        serde_json::from_slice::<Vec<Sighting>>(data).unwrap_or_default()
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use async_channel::{Receiver, Sender};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Serialize;
+
+use crate::runtime::{Executor, Runtime};
+
+use super::event::Event;
+
+#[derive(Serialize)]
+struct LogLine<'a> {
+    timestamp: DateTime<Utc>,
+    event: &'a Event,
+}
+
+pub struct EventLog {
+    path: PathBuf,
+    max_bytes: u64,
+    max_age: TimeDelta,
+    file: File,
+    written: u64,
+    opened: DateTime<Utc>,
+}
+
+impl EventLog {
+    pub fn open(
+        path: PathBuf,
+        max_bytes: u64,
+        max_age: TimeDelta,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(EventLog {
+            path,
+            max_bytes,
+            max_age,
+            file,
+            written,
+            opened: Utc::now(),
+        })
+    }
+
+    fn rotate(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(format!(".{}", Utc::now().format("%Y%m%dT%H%M%S%.3f")));
+        fs::rename(&self.path, rotated)?;
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.written = 0;
+        self.opened = Utc::now();
+        Ok(())
+    }
+
+    pub fn write(&mut self, event: &Event) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let now = Utc::now();
+        if self.written >= self.max_bytes || now - self.opened >= self.max_age {
+            self.rotate()?;
+        }
+
+        let mut line = serde_json::to_vec(&LogLine {
+            timestamp: now,
+            event,
+        })?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    pub fn spawn(mut self, events: Receiver<Event>, forward: Sender<Event>) {
+        Runtime::spawn(async move {
+            while let Ok(event) = events.recv().await {
+                let _ = self.write(&event);
+                if forward.send(event).await.is_err() {
+                    break;
+                }
+            }
+            forward.close();
+        });
+    }
+}
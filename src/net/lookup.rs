@@ -0,0 +1,65 @@
+use std::{collections::HashMap, hash::Hash, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use super::stats::Stats;
+
+struct Cached<V> {
+    value: V,
+    expires: DateTime<Utc>,
+}
+
+pub struct LookupCache<K, V> {
+    ttl: TimeDelta,
+    entries: HashMap<K, Cached<V>>,
+    stats: Stats,
+}
+
+impl<K: Eq + Hash, V: Clone> LookupCache<K, V> {
+    pub fn new(ttl: Duration, stats: Stats) -> Self {
+        LookupCache {
+            ttl: TimeDelta::from_std(ttl).unwrap_or(TimeDelta::MAX),
+            entries: HashMap::new(),
+            stats,
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let now = Utc::now();
+        self.entries.retain(|_, cached| cached.expires > now);
+        let value = self.entries.get(key).map(|cached| cached.value.clone());
+        self.stats.record_cache(value.is_some());
+        value
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.ttl <= TimeDelta::zero() {
+            return;
+        }
+
+        self.entries.insert(
+            key,
+            Cached {
+                value,
+                expires: Utc::now()
+                    .checked_add_signed(self.ttl)
+                    .unwrap_or(DateTime::<Utc>::MAX_UTC),
+            },
+        );
+    }
+
+    pub fn update(&mut self, key: K, default: V, apply: impl FnOnce(&mut V)) {
+        let mut value = match self.entries.remove(&key) {
+            Some(cached) if cached.expires > Utc::now() => cached.value,
+            _ => default,
+        };
+        apply(&mut value);
+        self.insert(key, value);
+    }
+
+    pub fn flush(&mut self) -> usize {
+        let flushed = self.entries.len();
+        self.entries.clear();
+        flushed
+    }
+}
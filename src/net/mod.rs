@@ -1,3 +1,74 @@
+pub mod ack;
+pub mod acl;
+pub mod admin;
+pub mod audit;
+pub mod breaker;
+pub mod bootstrap;
+pub mod broadcast;
+pub mod budget;
+pub mod bus;
+#[cfg(all(feature = "capture", not(target_arch = "wasm32")))]
+pub mod capture;
+pub mod causal;
+pub mod channel;
+pub mod codec;
+pub mod coalesce;
 pub mod command;
+pub mod coordination;
+pub mod dead;
 pub mod event;
-pub mod client;
\ No newline at end of file
+pub mod fairness;
+pub mod groupkey;
+pub mod client;
+pub mod dial;
+pub mod dispatch;
+pub mod doctor;
+pub mod feed;
+pub mod history;
+pub mod horizon;
+pub mod idempotency;
+pub mod ipfs;
+pub mod journal;
+pub mod lan;
+pub mod leave;
+pub mod listen;
+pub mod liveness;
+#[cfg(all(feature = "mqtt", not(target_arch = "wasm32")))]
+pub mod mqtt;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod log;
+pub mod lookup;
+pub mod multipath;
+pub mod nat;
+pub mod privacy;
+pub mod probe;
+pub mod queue;
+#[cfg(feature = "ratchet")]
+pub mod ratchet;
+pub mod relay;
+pub mod replicate;
+pub mod room;
+pub mod routing;
+pub mod rpc;
+pub mod schedule;
+pub mod schema;
+pub mod session;
+pub mod signing;
+pub mod stats;
+pub mod sync;
+pub mod tracer;
+pub mod transport;
+pub mod version;
+#[cfg(feature = "opentelemetry")]
+pub mod trace;
+#[cfg(all(unix, feature = "tokio"))]
+pub mod unix;
+pub mod update;
+pub mod watchdog;
+#[cfg(all(feature = "webrtc", not(target_arch = "wasm32")))]
+pub mod webrtc;
+#[cfg(all(feature = "webhooks", not(target_arch = "wasm32")))]
+pub mod webhook;
+pub mod wire;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod socks;
\ No newline at end of file
@@ -0,0 +1,209 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use rumqttc::v5::{
+    mqttbytes::{
+        v5::{Filter, Packet, Publish, PublishProperties},
+        QoS,
+    },
+    AsyncClient, Event, MqttOptions,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::runtime::{sleep, Executor, Runtime};
+
+use super::room::{Room, RoomEvent};
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const ORIGIN: &str = "modius-bridge";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MqttBridgeConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub prefix: String,
+    pub rooms: Vec<String>,
+    pub keep_alive: Duration,
+}
+
+impl MqttBridgeConfig {
+    pub fn new<H: Into<String>>(host: H, client_id: &str) -> Self {
+        MqttBridgeConfig {
+            host: host.into(),
+            port: 1883,
+            client_id: client_id.to_string(),
+            prefix: String::from("modius"),
+            rooms: Vec::new(),
+            keep_alive: Duration::from_secs(30),
+        }
+    }
+
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn room(mut self, room: &str) -> Self {
+        self.rooms.push(room.to_string());
+        self
+    }
+
+    pub fn topic(&self, room: &str) -> String {
+        match self.prefix.is_empty() {
+            true => room.to_string(),
+            false => format!("{}/{room}", self.prefix),
+        }
+    }
+
+    pub fn room_for<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        match self.prefix.is_empty() {
+            true => Some(topic),
+            false => topic
+                .strip_prefix(self.prefix.as_str())
+                .and_then(|rest| rest.strip_prefix('/')),
+        }
+    }
+}
+
+fn marked(client_id: &str) -> PublishProperties {
+    PublishProperties {
+        user_properties: vec![(ORIGIN.to_string(), client_id.to_string())],
+        ..PublishProperties::default()
+    }
+}
+
+fn bridged(publish: &Publish) -> bool {
+    publish.properties.as_ref().is_some_and(|properties| {
+        properties
+            .user_properties
+            .iter()
+            .any(|(key, _)| key == ORIGIN)
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct MqttBridge {
+    client: AsyncClient,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MqttBridge {
+    pub fn start(config: MqttBridgeConfig, rooms: Vec<Room>) -> Self {
+        let mut options =
+            MqttOptions::new(config.client_id.clone(), config.host.clone(), config.port);
+        options.set_keep_alive(config.keep_alive);
+        let (client, mut eventloop) = AsyncClient::new(options, 64);
+        let stopped = Arc::new(AtomicBool::new(false));
+        let rooms: HashMap<String, Room> = rooms
+            .into_iter()
+            .map(|room| (room.name.clone(), room))
+            .collect();
+
+        for room in rooms.values() {
+            let client = client.clone();
+            let topic = config.topic(&room.name);
+            let client_id = config.client_id.clone();
+            let events = room.events();
+            Runtime::spawn(async move {
+                while let Ok(event) = events.recv().await {
+                    let RoomEvent::Message { payload, .. } = event else {
+                        continue;
+                    };
+                    if client
+                        .publish_with_properties(
+                            topic.as_str(),
+                            QoS::AtLeastOnce,
+                            false,
+                            payload.to_vec(),
+                            marked(&client_id),
+                        )
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let bridge = MqttBridge {
+            client: client.clone(),
+            stopped: stopped.clone(),
+        };
+        Runtime::spawn(async move {
+            loop {
+                match eventloop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        let _ = client.try_subscribe_many(rooms.keys().map(|room| Filter {
+                            nolocal: true,
+                            ..Filter::new(config.topic(room), QoS::AtLeastOnce)
+                        }));
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        if bridged(&publish) {
+                            continue;
+                        }
+                        let Some(room) = std::str::from_utf8(&publish.topic)
+                            .ok()
+                            .and_then(|topic| config.room_for(topic))
+                            .and_then(|name| rooms.get(name))
+                        else {
+                            continue;
+                        };
+                        let _ = room.send(publish.payload).await;
+                    }
+                    Ok(_) => {}
+                    Err(_) if stopped.load(Ordering::SeqCst) => break,
+                    Err(_) => sleep(RECONNECT_DELAY).await,
+                }
+            }
+        });
+        bridge
+    }
+
+    pub async fn stop(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.client.disconnect().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn publish(properties: Option<PublishProperties>) -> Publish {
+        Publish::new("modius/lobby", QoS::AtLeastOnce, "hello", properties)
+    }
+
+    #[test]
+    fn only_bridge_publishes_are_marked() {
+        assert!(bridged(&publish(Some(marked("node-a")))));
+        assert!(!bridged(&publish(None)));
+        assert!(!bridged(&publish(Some(PublishProperties {
+            user_properties: vec![(String::from("sensor"), String::from("kitchen"))],
+            ..PublishProperties::default()
+        }))));
+    }
+
+    #[test]
+    fn topics_map_to_rooms_under_the_prefix() {
+        let config = MqttBridgeConfig::new("localhost", "node-a").with_prefix("site/");
+        assert_eq!(config.topic("lobby"), "site/lobby");
+        assert_eq!(config.room_for("site/lobby"), Some("lobby"));
+        assert_eq!(config.room_for("other/lobby"), None);
+    }
+}
@@ -0,0 +1,117 @@
+use std::task::{Context, Poll};
+
+use either::Either;
+use libp2p::{
+    core::{transport::PortUse, Endpoint},
+    swarm::{
+        dummy, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler,
+        THandlerInEvent, THandlerOutEvent, ToSwarm,
+    },
+    Multiaddr, PeerId,
+};
+use libp2p_stream::Control;
+use serde::{Deserialize, Serialize};
+
+use super::dial::is_relayed;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Redundancy {
+    #[default]
+    Single,
+    Multipath,
+}
+
+pub struct RelayPath {
+    inner: libp2p_stream::Behaviour,
+}
+
+impl Default for RelayPath {
+    fn default() -> Self {
+        RelayPath::new()
+    }
+}
+
+impl RelayPath {
+    pub fn new() -> Self {
+        RelayPath {
+            inner: libp2p_stream::Behaviour::new(),
+        }
+    }
+
+    pub fn new_control(&self) -> Control {
+        self.inner.new_control()
+    }
+}
+
+impl NetworkBehaviour for RelayPath {
+    type ConnectionHandler =
+        Either<THandler<libp2p_stream::Behaviour>, dummy::ConnectionHandler>;
+    type ToSwarm = ();
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        local_addr: &Multiaddr,
+        remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        if !is_relayed(local_addr) {
+            return Ok(Either::Right(dummy::ConnectionHandler));
+        }
+
+        self.inner
+            .handle_established_inbound_connection(connection_id, peer, local_addr, remote_addr)
+            .map(Either::Left)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        connection_id: ConnectionId,
+        peer: PeerId,
+        addr: &Multiaddr,
+        role_override: Endpoint,
+        port_use: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        if !is_relayed(addr) {
+            return Ok(Either::Right(dummy::ConnectionHandler));
+        }
+
+        self.inner
+            .handle_established_outbound_connection(
+                connection_id,
+                peer,
+                addr,
+                role_override,
+                port_use,
+            )
+            .map(Either::Left)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        if let FromSwarm::ConnectionEstablished(established) = &event {
+            if !established.endpoint.is_relayed() {
+                return;
+            }
+        }
+        self.inner.on_swarm_event(event);
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        peer_id: PeerId,
+        connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {
+            Either::Left(event) => {
+                self.inner
+                    .on_connection_handler_event(peer_id, connection_id, event)
+            }
+            Either::Right(never) => match never {},
+        }
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) -> Poll<ToSwarm<(), THandlerInEvent<Self>>> {
+        self.inner.poll(cx).map(|event| event.map_in(Either::Left))
+    }
+}
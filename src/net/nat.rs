@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+
+use libp2p::{autonat, Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum UpnpState {
+    Unavailable,
+    Pending,
+    Mapped(Multiaddr),
+    Expired(Multiaddr),
+    GatewayNotFound,
+    NonRoutableGateway,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Reachability {
+    Unknown,
+    Public(Multiaddr),
+    Private,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NatReport {
+    pub upnp: UpnpState,
+    pub reachability: Reachability,
+    pub relay_reservations: Vec<PeerId>,
+    pub verified_addresses: Vec<Multiaddr>,
+    pub hole_punch_attempts: u64,
+    pub hole_punch_successes: u64,
+    pub diagnosis: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct NatState {
+    upnp: UpnpState,
+    reachability: Reachability,
+    reservations: HashSet<PeerId>,
+    verified: HashSet<Multiaddr>,
+    hole_punch_attempts: u64,
+    hole_punch_successes: u64,
+}
+
+impl NatState {
+    pub fn new(upnp: bool) -> Self {
+        NatState {
+            upnp: match upnp {
+                true => UpnpState::Pending,
+                false => UpnpState::Unavailable,
+            },
+            reachability: Reachability::Unknown,
+            reservations: HashSet::new(),
+            verified: HashSet::new(),
+            hole_punch_attempts: 0,
+            hole_punch_successes: 0,
+        }
+    }
+
+    #[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+    pub fn on_upnp(&mut self, event: libp2p::upnp::Event) {
+        self.upnp = match event {
+            libp2p::upnp::Event::NewExternalAddr(address) => UpnpState::Mapped(address),
+            libp2p::upnp::Event::ExpiredExternalAddr(address) => UpnpState::Expired(address),
+            libp2p::upnp::Event::GatewayNotFound => UpnpState::GatewayNotFound,
+            libp2p::upnp::Event::NonRoutableGateway => UpnpState::NonRoutableGateway,
+        };
+    }
+
+    pub fn on_address_verified(&mut self, address: Multiaddr) {
+        self.verified.insert(address);
+    }
+
+    pub fn on_address_expired(&mut self, address: &Multiaddr) {
+        self.verified.remove(address);
+    }
+
+    pub fn is_verified(&self, address: &Multiaddr) -> bool {
+        self.verified.contains(address)
+    }
+
+    pub fn on_autonat(&mut self, status: autonat::NatStatus) {
+        self.reachability = match status {
+            autonat::NatStatus::Public(address) => Reachability::Public(address),
+            autonat::NatStatus::Private => Reachability::Private,
+            autonat::NatStatus::Unknown => Reachability::Unknown,
+        };
+    }
+
+    pub fn on_reservation(&mut self, relay: PeerId) {
+        self.reservations.insert(relay);
+    }
+
+    pub fn on_relay_lost(&mut self, relay: &PeerId) {
+        self.reservations.remove(relay);
+    }
+
+    pub fn has_reservation(&self) -> bool {
+        !self.reservations.is_empty()
+    }
+
+    pub fn on_hole_punch(&mut self, success: bool) {
+        self.hole_punch_attempts += 1;
+        if success {
+            self.hole_punch_successes += 1;
+        }
+    }
+
+    fn diagnose(&self) -> String {
+        let punching = match self.hole_punch_attempts {
+            0 => None,
+            attempts => Some(self.hole_punch_successes as f64 / attempts as f64),
+        };
+
+        match (&self.reachability, &self.upnp) {
+            (Reachability::Public(address), _) => {
+                format!("You are publicly reachable at {address}; peers can dial you directly.")
+            }
+            (_, UpnpState::Mapped(address)) => format!(
+                "UPnP mapped {address}, but reachability has not been confirmed by other peers yet."
+            ),
+            (Reachability::Private, _) => match (punching, self.reservations.is_empty()) {
+                (Some(_), _) if self.hole_punch_successes == 0 => String::from(
+                    "You are symmetric-NAT'd: every hole punch failed; relays are required.",
+                ),
+                (Some(rate), _) if rate < 0.5 => format!(
+                    "You are behind a restrictive NAT: only {:.0}% of hole punches succeed; keep a relay reservation.",
+                    rate * 100.0
+                ),
+                (Some(_), _) => String::from(
+                    "You are behind a NAT, but hole punching upgrades relayed connections reliably.",
+                ),
+                (None, true) => String::from(
+                    "You are behind a NAT with no relay reservation; add a relay so peers can reach you.",
+                ),
+                (None, false) => String::from(
+                    "You are behind a NAT and reachable through your relay; no hole punch has been attempted yet.",
+                ),
+            },
+            (Reachability::Unknown, _) => String::from(
+                "Reachability is still unknown; connect to more peers so AutoNAT can probe you.",
+            ),
+        }
+    }
+
+    pub fn report(&self) -> NatReport {
+        NatReport {
+            upnp: self.upnp.clone(),
+            reachability: self.reachability.clone(),
+            relay_reservations: self.reservations.iter().cloned().collect(),
+            verified_addresses: self.verified.iter().cloned().collect(),
+            hole_punch_attempts: self.hole_punch_attempts,
+            hole_punch_successes: self.hole_punch_successes,
+            diagnosis: self.diagnose(),
+        }
+    }
+}
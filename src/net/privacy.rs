@@ -0,0 +1,119 @@
+use std::{error::Error, net::IpAddr, sync::Arc};
+
+use async_channel::{Receiver, Sender};
+use libp2p::{multiaddr::Protocol, Multiaddr};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    runtime::{Executor, Runtime},
+    storage::Storage,
+};
+
+use super::event::Event;
+
+const PRIVACY: &str = "privacy";
+const SALT: &[u8] = b"salt";
+
+#[derive(Clone, Debug)]
+pub struct Redactor {
+    salt: [u8; 32],
+}
+
+impl Redactor {
+    pub fn load(storage: &Arc<dyn Storage>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let salt = match storage.get_value::<[u8; 32]>(PRIVACY, SALT)? {
+            Some(salt) => salt,
+            None => {
+                let salt: [u8; 32] = rand::random();
+                storage.put_value(PRIVACY, SALT, &salt)?;
+                salt
+            }
+        };
+        Ok(Redactor { salt })
+    }
+
+    pub fn label(&self, ip: &IpAddr) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt);
+        match ip {
+            IpAddr::V4(ip) => hasher.update(ip.octets()),
+            IpAddr::V6(ip) => hasher.update(ip.octets()),
+        }
+        let digest = hasher.finalize();
+        let hex: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+        format!("ip-{hex}.redacted")
+    }
+
+    pub fn address(&self, address: &Multiaddr) -> Multiaddr {
+        address
+            .iter()
+            .map(|protocol| match protocol {
+                Protocol::Ip4(ip) => Protocol::Dns(self.label(&IpAddr::V4(ip)).into()),
+                Protocol::Ip6(ip) => Protocol::Dns(self.label(&IpAddr::V6(ip)).into()),
+                other => other,
+            })
+            .collect()
+    }
+
+    fn text(&self, text: &str) -> String {
+        if let Ok(address) = text.parse::<Multiaddr>() {
+            return self.address(&address).to_string();
+        }
+
+        let mut redacted = String::with_capacity(text.len());
+        let mut token = String::new();
+        for c in text.chars().chain(std::iter::once(' ')) {
+            if c.is_ascii_hexdigit() || c == '.' || c == ':' {
+                token.push(c);
+                continue;
+            }
+            redacted.push_str(&self.token(&token));
+            token.clear();
+            redacted.push(c);
+        }
+        redacted.pop();
+        redacted
+    }
+
+    fn token(&self, token: &str) -> String {
+        let trimmed = token.trim_end_matches(['.', ':']);
+        if let Ok(ip) = trimmed.parse::<IpAddr>() {
+            return format!("{}{}", self.label(&ip), &token[trimmed.len()..]);
+        }
+        if let Some((host, port)) = trimmed.rsplit_once(':') {
+            if let Ok(ip) = host.parse::<IpAddr>() {
+                return format!("{}:{port}{}", self.label(&ip), &token[trimmed.len()..]);
+            }
+        }
+        token.to_string()
+    }
+
+    fn value(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.text(text),
+            Value::Array(values) => values.iter_mut().for_each(|value| self.value(value)),
+            Value::Object(map) => map.values_mut().for_each(|value| self.value(value)),
+            _ => {}
+        }
+    }
+
+    pub fn event(&self, event: Event) -> Event {
+        let Ok(mut value) = serde_json::to_value(&event) else {
+            return event;
+        };
+        self.value(&mut value);
+        serde_json::from_value(value).unwrap_or(event)
+    }
+
+    pub fn spawn(self, events: Receiver<Event>, forward: Sender<Event>) {
+        Runtime::spawn(async move {
+            while let Ok(event) = events.recv().await {
+                if forward.send(self.event(event)).await.is_err() {
+                    break;
+                }
+            }
+            forward.close();
+        });
+    }
+}
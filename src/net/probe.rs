@@ -0,0 +1,138 @@
+use std::{io, time::Duration};
+
+use bytes::Bytes;
+use chrono::Utc;
+use libp2p::{futures::AsyncWriteExt, PeerId, Stream, StreamProtocol};
+use libp2p_stream::Control;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    peers::PeerStore,
+    runtime::{Executor, Runtime},
+};
+
+use super::{
+    command::CommandWrapper,
+    wire::{read_frame, write_frame, Frame},
+};
+
+pub const PROBE_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/probe/1.0.0");
+pub const MAX_PROBE_DURATION: Duration = Duration::from_secs(60);
+const CHUNK_SIZE: usize = 64 * 1024;
+const CHUNKS_PER_ECHO: u64 = 16;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ThroughputReport {
+    pub peer: PeerId,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub bytes_per_second: f64,
+    pub rtt_ms: Option<f64>,
+    pub rtt_samples: usize,
+}
+
+fn unexpected() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Unexpected probe response")
+}
+
+pub fn serve(stream: Stream) {
+    Runtime::spawn(async move {
+        let _ = respond(stream).await;
+    });
+}
+
+async fn respond(mut stream: Stream) -> io::Result<()> {
+    let mut received = 0u64;
+    loop {
+        match read_frame(&mut stream).await? {
+            Frame::ProbeData { data } => received += data.len() as u64,
+            Frame::ProbeEcho { nonce } => {
+                write_frame(&mut stream, &Frame::ProbeEcho { nonce }).await?
+            }
+            Frame::ProbeEnd => {
+                write_frame(&mut stream, &Frame::ProbeReceived { bytes: received }).await?;
+                return stream.close().await;
+            }
+            _ => return Err(unexpected()),
+        }
+    }
+}
+
+pub fn measure(
+    command: CommandWrapper,
+    control: Control,
+    peers: PeerStore,
+    peer: PeerId,
+    duration: Duration,
+) {
+    Runtime::spawn(async move {
+        let report = run(control, peer, duration.min(MAX_PROBE_DURATION)).await;
+        if let Ok(report) = report.as_ref() {
+            let _ = peers.record_throughput(&peer, report.bytes as usize, report.elapsed);
+            if let Some(rtt) = report.rtt_ms {
+                let _ = peers.record_rtt(&peer, Duration::from_secs_f64(rtt / 1000.0));
+            }
+        }
+        let _ = command.respond(report).await;
+    });
+}
+
+async fn run(
+    mut control: Control,
+    peer: PeerId,
+    duration: Duration,
+) -> io::Result<ThroughputReport> {
+    let mut stream = control
+        .open_stream(peer, PROBE_PROTOCOL)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::ConnectionRefused, e))?;
+
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    rand::thread_rng().fill_bytes(&mut chunk);
+    let chunk = Bytes::from(chunk);
+
+    let started = Utc::now();
+    let deadline = started + duration;
+    let mut samples = Vec::new();
+    let mut sent = 0u64;
+    while Utc::now() < deadline {
+        write_frame(
+            &mut stream,
+            &Frame::ProbeData {
+                data: chunk.clone(),
+            },
+        )
+        .await?;
+        sent += 1;
+        if sent.is_multiple_of(CHUNKS_PER_ECHO) {
+            let echoed = Utc::now();
+            write_frame(&mut stream, &Frame::ProbeEcho { nonce: sent }).await?;
+            let Frame::ProbeEcho { nonce } = read_frame(&mut stream).await? else {
+                return Err(unexpected());
+            };
+            if nonce != sent {
+                return Err(unexpected());
+            }
+            let rtt = Utc::now() - echoed;
+            samples.push(rtt.num_microseconds().unwrap_or(0) as f64 / 1000.0);
+        }
+    }
+
+    write_frame(&mut stream, &Frame::ProbeEnd).await?;
+    let Frame::ProbeReceived { bytes } = read_frame(&mut stream).await? else {
+        return Err(unexpected());
+    };
+    let elapsed = (Utc::now() - started).to_std().unwrap_or_default();
+    let _ = stream.close().await;
+
+    samples.sort_by(f64::total_cmp);
+    Ok(ThroughputReport {
+        peer,
+        bytes,
+        elapsed,
+        bytes_per_second: bytes as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        rtt_ms: samples.get(samples.len() / 2).copied(),
+        rtt_samples: samples.len(),
+    })
+}
@@ -0,0 +1,499 @@
+use std::{
+    error::Error,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use libp2p::{
+    futures::{future, FutureExt},
+    PeerId,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::storage::Storage;
+
+use super::rpc::{Caller, Handler, RpcError, RpcErrorKind, Services};
+
+pub const CLAIM: &str = "queue.claim";
+pub const RENEW: &str = "queue.renew";
+pub const COMPLETE: &str = "queue.complete";
+pub const FAIL: &str = "queue.fail";
+
+const JOBS: &str = "queue_jobs";
+const COUNTERS: &str = "queue_counters";
+const DEAD: &str = "queue_dead";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueueConfig {
+    pub max_lease: Duration,
+    pub max_attempts: u32,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            max_lease: Duration::from_secs(15 * 60),
+            max_attempts: 5,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Job {
+    pub id: Uuid,
+    pub queue: String,
+    pub payload: Value,
+    pub attempts: u32,
+    pub enqueued: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LeasedJob {
+    pub job: Job,
+    pub expires: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct QueueStats {
+    pub pending: usize,
+    pub leased: usize,
+    pub completed: u64,
+    pub requeued: u64,
+    pub dead: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClaimRequest {
+    pub queue: String,
+    pub lease: Duration,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JobRequest {
+    pub queue: String,
+    pub job: Uuid,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RenewRequest {
+    pub queue: String,
+    pub job: Uuid,
+    pub lease: Duration,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Lease {
+    worker: PeerId,
+    expires: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct StoredJob {
+    job: Job,
+    ready: DateTime<Utc>,
+    lease: Option<Lease>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Counters {
+    completed: u64,
+    requeued: u64,
+}
+
+fn expiry(lease: Duration, max: Duration) -> DateTime<Utc> {
+    let lease = TimeDelta::from_std(lease.min(max)).unwrap_or(TimeDelta::MAX);
+    Utc::now()
+        .checked_add_signed(lease)
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+#[derive(Clone, Debug)]
+pub struct Queues {
+    storage: Arc<dyn Storage>,
+    config: QueueConfig,
+    lock: Arc<Mutex<()>>,
+}
+
+impl Queues {
+    pub fn new(storage: Arc<dyn Storage>, config: QueueConfig) -> Self {
+        Queues {
+            storage,
+            config,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn store(&self, stored: &StoredJob) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.storage
+            .put_value(JOBS, stored.job.id.as_bytes(), stored)
+    }
+
+    fn counters(&self, queue: &str) -> Result<Counters, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .storage
+            .get_value::<Counters>(COUNTERS, queue.as_bytes())?
+            .unwrap_or_default())
+    }
+
+    fn count(
+        &self,
+        queue: &str,
+        update: impl FnOnce(&mut Counters),
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut counters = self.counters(queue)?;
+        update(&mut counters);
+        self.storage
+            .put_value(COUNTERS, queue.as_bytes(), &counters)
+    }
+
+    fn release(&self, mut stored: StoredJob) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let id = stored.job.id;
+        if stored.job.attempts >= self.config.max_attempts.max(1) {
+            self.storage.delete(JOBS, id.as_bytes())?;
+            return self.storage.put_value(DEAD, id.as_bytes(), &stored.job);
+        }
+
+        stored.lease = None;
+        stored.ready = Utc::now();
+        self.store(&stored)?;
+        self.count(&stored.job.queue, |counters| counters.requeued += 1)
+    }
+
+    fn queued(&self, queue: &str) -> Result<Vec<StoredJob>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .storage
+            .values::<StoredJob>(JOBS)?
+            .into_iter()
+            .filter(|stored| stored.job.queue == queue)
+            .collect())
+    }
+
+    fn jobs(&self, queue: &str) -> Result<Vec<StoredJob>, Box<dyn Error + Send + Sync>> {
+        let now = Utc::now();
+        for stored in self.queued(queue)? {
+            if stored
+                .lease
+                .as_ref()
+                .is_some_and(|lease| lease.expires <= now)
+            {
+                self.release(stored)?;
+            }
+        }
+
+        let mut jobs = self.queued(queue)?;
+        jobs.sort_by_key(|stored| stored.ready);
+        Ok(jobs)
+    }
+
+    fn held(
+        &self,
+        queue: &str,
+        job: &Uuid,
+        worker: &PeerId,
+    ) -> Result<Option<StoredJob>, Box<dyn Error + Send + Sync>> {
+        Ok(self.jobs(queue)?.into_iter().find(|stored| {
+            stored.job.id == *job
+                && stored
+                    .lease
+                    .as_ref()
+                    .is_some_and(|lease| lease.worker == *worker)
+        }))
+    }
+
+    pub fn enqueue(
+        &self,
+        queue: &str,
+        payload: Value,
+    ) -> Result<Uuid, Box<dyn Error + Send + Sync>> {
+        let _guard = self.lock();
+        let enqueued = Utc::now();
+        let job = Job {
+            id: Uuid::new_v4(),
+            queue: queue.to_string(),
+            payload,
+            attempts: 0,
+            enqueued,
+        };
+        let id = job.id;
+        self.store(&StoredJob {
+            job,
+            ready: enqueued,
+            lease: None,
+        })?;
+        Ok(id)
+    }
+
+    pub fn claim(
+        &self,
+        queue: &str,
+        worker: PeerId,
+        lease: Duration,
+    ) -> Result<Option<LeasedJob>, Box<dyn Error + Send + Sync>> {
+        let _guard = self.lock();
+        let Some(mut stored) = self
+            .jobs(queue)?
+            .into_iter()
+            .find(|stored| stored.lease.is_none())
+        else {
+            return Ok(None);
+        };
+
+        stored.job.attempts += 1;
+        let expires = expiry(lease, self.config.max_lease);
+        stored.lease = Some(Lease { worker, expires });
+        self.store(&stored)?;
+        Ok(Some(LeasedJob {
+            job: stored.job,
+            expires,
+        }))
+    }
+
+    pub fn renew(
+        &self,
+        queue: &str,
+        job: &Uuid,
+        worker: &PeerId,
+        lease: Duration,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let _guard = self.lock();
+        let Some(mut stored) = self.held(queue, job, worker)? else {
+            return Ok(false);
+        };
+        stored.lease = Some(Lease {
+            worker: *worker,
+            expires: expiry(lease, self.config.max_lease),
+        });
+        self.store(&stored)?;
+        Ok(true)
+    }
+
+    pub fn complete(
+        &self,
+        queue: &str,
+        job: &Uuid,
+        worker: &PeerId,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let _guard = self.lock();
+        let done = self.jobs(queue)?.into_iter().any(|stored| {
+            stored.job.id == *job
+                && stored
+                    .lease
+                    .as_ref()
+                    .is_none_or(|lease| lease.worker == *worker)
+        });
+        if done {
+            self.storage.delete(JOBS, job.as_bytes())?;
+            self.count(queue, |counters| counters.completed += 1)?;
+        }
+        Ok(done)
+    }
+
+    pub fn fail(
+        &self,
+        queue: &str,
+        job: &Uuid,
+        worker: &PeerId,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let _guard = self.lock();
+        match self.held(queue, job, worker)? {
+            Some(stored) => self.release(stored).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    pub fn dead(&self, queue: &str) -> Result<Vec<Job>, Box<dyn Error + Send + Sync>> {
+        let mut dead: Vec<Job> = self
+            .storage
+            .values::<Job>(DEAD)?
+            .into_iter()
+            .filter(|job| job.queue == queue)
+            .collect();
+        dead.sort_by_key(|job| job.enqueued);
+        Ok(dead)
+    }
+
+    pub fn stats(&self, queue: &str) -> Result<QueueStats, Box<dyn Error + Send + Sync>> {
+        let _guard = self.lock();
+        let jobs = self.jobs(queue)?;
+        let counters = self.counters(queue)?;
+        let leased = jobs.iter().filter(|stored| stored.lease.is_some()).count();
+        Ok(QueueStats {
+            pending: jobs.len() - leased,
+            leased,
+            completed: counters.completed,
+            requeued: counters.requeued,
+            dead: self.dead(queue)?.len(),
+        })
+    }
+
+    pub fn serve(&self, services: &Services) {
+        let queues = self.clone();
+        services.serve(
+            CLAIM,
+            handler(move |worker, request: ClaimRequest| {
+                queues.claim(&request.queue, worker, request.lease)
+            }),
+        );
+        let queues = self.clone();
+        services.serve(
+            RENEW,
+            handler(move |worker, request: RenewRequest| {
+                queues.renew(&request.queue, &request.job, &worker, request.lease)
+            }),
+        );
+        let queues = self.clone();
+        services.serve(
+            COMPLETE,
+            handler(move |worker, request: JobRequest| {
+                queues.complete(&request.queue, &request.job, &worker)
+            }),
+        );
+        let queues = self.clone();
+        services.serve(
+            FAIL,
+            handler(move |worker, request: JobRequest| {
+                queues.fail(&request.queue, &request.job, &worker)
+            }),
+        );
+    }
+}
+
+fn handler<P, R, F>(f: F) -> Handler
+where
+    P: DeserializeOwned,
+    R: Serialize,
+    F: Fn(PeerId, P) -> Result<R, Box<dyn Error + Send + Sync>> + Send + Sync + 'static,
+{
+    Arc::new(move |caller: Caller, params| {
+        let result = serde_json::from_value::<P>(params)
+            .map_err(|e| RpcError::invalid_params("", e))
+            .and_then(|params| {
+                f(caller.peer, params)
+                    .map_err(|e| e.to_string())
+                    .and_then(|result| serde_json::to_value(result).map_err(|e| e.to_string()))
+                    .map_err(|e| RpcError::new(RpcErrorKind::Failed, "", e))
+            });
+        future::ready(result).boxed()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::identity::Keypair;
+    use serde_json::json;
+
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    fn worker() -> PeerId {
+        Keypair::generate_ed25519().public().to_peer_id()
+    }
+
+    fn queues(config: QueueConfig) -> Queues {
+        Queues::new(Arc::new(MemoryStorage::new()), config)
+    }
+
+    #[test]
+    fn claims_in_order_and_only_the_holder_settles() {
+        let queues = queues(QueueConfig::default());
+        let (first, second) = (worker(), worker());
+        let job = queues.enqueue("jobs", json!(1)).unwrap();
+        queues.enqueue("jobs", json!(2)).unwrap();
+
+        let leased = queues
+            .claim("jobs", first, Duration::from_secs(60))
+            .unwrap()
+            .unwrap();
+        assert_eq!(leased.job.id, job);
+        assert_eq!(leased.job.attempts, 1);
+
+        assert!(!queues
+            .renew("jobs", &job, &second, Duration::from_secs(60))
+            .unwrap());
+        assert!(!queues.complete("jobs", &job, &second).unwrap());
+        assert!(queues
+            .renew("jobs", &job, &first, Duration::from_secs(60))
+            .unwrap());
+        assert!(queues.complete("jobs", &job, &first).unwrap());
+
+        let stats = queues.stats("jobs").unwrap();
+        assert_eq!((stats.pending, stats.leased, stats.completed), (1, 0, 1));
+    }
+
+    #[test]
+    fn expired_leases_are_requeued() {
+        let queues = queues(QueueConfig::default());
+        let job = queues.enqueue("jobs", json!(null)).unwrap();
+        queues.claim("jobs", worker(), Duration::ZERO).unwrap();
+
+        let reclaimed = queues
+            .claim("jobs", worker(), Duration::from_secs(60))
+            .unwrap()
+            .unwrap();
+        assert_eq!(reclaimed.job.id, job);
+        assert_eq!(reclaimed.job.attempts, 2);
+        assert_eq!(queues.stats("jobs").unwrap().requeued, 1);
+    }
+
+    #[test]
+    fn failed_jobs_are_dead_lettered_after_max_attempts() {
+        let queues = queues(QueueConfig {
+            max_attempts: 2,
+            ..QueueConfig::default()
+        });
+        let holder = worker();
+        let job = queues.enqueue("jobs", json!(null)).unwrap();
+        for _ in 0..2 {
+            queues
+                .claim("jobs", holder, Duration::from_secs(60))
+                .unwrap()
+                .unwrap();
+            assert!(queues.fail("jobs", &job, &holder).unwrap());
+        }
+
+        assert!(queues
+            .claim("jobs", holder, Duration::from_secs(60))
+            .unwrap()
+            .is_none());
+        assert_eq!(queues.dead("jobs").unwrap()[0].id, job);
+        assert_eq!(queues.stats("jobs").unwrap().dead, 1);
+    }
+
+    #[test]
+    fn leases_are_clamped_to_the_maximum() {
+        let queues = queues(QueueConfig {
+            max_lease: Duration::from_secs(60),
+            ..QueueConfig::default()
+        });
+        queues.enqueue("jobs", json!(null)).unwrap();
+
+        let leased = queues
+            .claim("jobs", worker(), Duration::MAX)
+            .unwrap()
+            .unwrap();
+        assert!(leased.expires <= Utc::now() + TimeDelta::seconds(60));
+    }
+
+    #[test]
+    fn jobs_survive_a_restart() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let job = Queues::new(storage.clone(), QueueConfig::default())
+            .enqueue("jobs", json!(null))
+            .unwrap();
+
+        let restarted = Queues::new(storage, QueueConfig::default());
+        let leased = restarted
+            .claim("jobs", worker(), Duration::from_secs(60))
+            .unwrap()
+            .unwrap();
+        assert_eq!(leased.job.id, job);
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use libp2p::{futures::AsyncWriteExt, PeerId, Stream, StreamProtocol};
+use libp2p_stream::{Control, IncomingStreams};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::runtime::{Executor, Runtime};
+
+use super::wire::{read_frame, write_frame, Envelope, Frame};
+
+pub const RATCHET_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/ratchet/1.0.0");
+const ROOT_INFO: &[u8] = b"modius-ratchet-root";
+const CHAIN_INFO: &[u8] = b"modius-ratchet-chain";
+const MAX_SKIP: u32 = 1000;
+const SKIPPED_CAPACITY: usize = 2048;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RatchetHeader {
+    pub public: [u8; 32],
+    pub previous: u32,
+    pub n: u32,
+}
+
+impl RatchetHeader {
+    fn associated_data(&self) -> Vec<u8> {
+        let mut data = self.public.to_vec();
+        data.extend_from_slice(&self.previous.to_be_bytes());
+        data.extend_from_slice(&self.n.to_be_bytes());
+        data
+    }
+}
+
+fn kdf_root(root: &[u8; 32], shared: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let mut output = [0u8; 64];
+    Hkdf::<Sha256>::new(Some(root), shared)
+        .expand(CHAIN_INFO, &mut output)
+        .expect("64 bytes is a valid HKDF output length");
+
+    let (root, chain) = output.split_at(32);
+    (
+        root.try_into().expect("32 byte root key"),
+        chain.try_into().expect("32 byte chain key"),
+    )
+}
+
+fn kdf_chain(chain: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let step = |constant: u8| -> [u8; 32] {
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(chain).expect("HMAC accepts keys of any length");
+        mac.update(&[constant]);
+        mac.finalize().into_bytes().into()
+    };
+    (step(0x02), step(0x01))
+}
+
+fn cipher(key: &[u8; 32]) -> ChaCha20Poly1305 {
+    ChaCha20Poly1305::new(Key::from_slice(key))
+}
+
+#[derive(Clone)]
+struct Session {
+    remote_prekey: [u8; 32],
+    root: [u8; 32],
+    dh_self: StaticSecret,
+    dh_remote: PublicKey,
+    send: Option<[u8; 32]>,
+    recv: Option<[u8; 32]>,
+    send_n: u32,
+    recv_n: u32,
+    previous: u32,
+    skipped: HashMap<([u8; 32], u32), [u8; 32]>,
+    skipped_order: VecDeque<([u8; 32], u32)>,
+}
+
+impl Session {
+    fn new(initiator: bool, prekey: &StaticSecret, remote_prekey: [u8; 32]) -> Self {
+        let remote = PublicKey::from(remote_prekey);
+        let shared = prekey.diffie_hellman(&remote).to_bytes();
+        let mut root = [0u8; 32];
+        Hkdf::<Sha256>::new(None, &shared)
+            .expand(ROOT_INFO, &mut root)
+            .expect("32 bytes is a valid HKDF output length");
+
+        let (root, chain) = kdf_root(&root, &shared);
+        Session {
+            remote_prekey,
+            root,
+            dh_self: prekey.clone(),
+            dh_remote: remote,
+            send: initiator.then_some(chain),
+            recv: (!initiator).then_some(chain),
+            send_n: 0,
+            recv_n: 0,
+            previous: 0,
+            skipped: HashMap::new(),
+            skipped_order: VecDeque::new(),
+        }
+    }
+
+    fn step_send(&mut self) {
+        self.previous = self.send_n;
+        self.send_n = 0;
+        self.dh_self = StaticSecret::from(rand::random::<[u8; 32]>());
+        let shared = self.dh_self.diffie_hellman(&self.dh_remote).to_bytes();
+        let (root, chain) = kdf_root(&self.root, &shared);
+        self.root = root;
+        self.send = Some(chain);
+    }
+
+    fn step_recv(&mut self, remote: PublicKey) {
+        self.dh_remote = remote;
+        self.recv_n = 0;
+        let shared = self.dh_self.diffie_hellman(&self.dh_remote).to_bytes();
+        let (root, chain) = kdf_root(&self.root, &shared);
+        self.root = root;
+        self.recv = Some(chain);
+        self.step_send();
+    }
+
+    fn skip_until(&mut self, until: u32) -> Result<(), &'static str> {
+        let Some(mut chain) = self.recv else {
+            return Ok(());
+        };
+        if until > self.recv_n + MAX_SKIP {
+            return Err("Too many skipped messages");
+        }
+
+        while self.recv_n < until {
+            let (next, message) = kdf_chain(&chain);
+            let index = (self.dh_remote.to_bytes(), self.recv_n);
+            self.skipped.insert(index, message);
+            self.skipped_order.push_back(index);
+            if self.skipped_order.len() > SKIPPED_CAPACITY {
+                if let Some(expired) = self.skipped_order.pop_front() {
+                    self.skipped.remove(&expired);
+                }
+            }
+            chain = next;
+            self.recv_n += 1;
+        }
+        self.recv = Some(chain);
+        Ok(())
+    }
+
+    fn encrypt(&mut self, payload: &[u8]) -> Result<(RatchetHeader, Bytes), &'static str> {
+        if self.send.is_none() {
+            self.step_send();
+        }
+        let chain = self.send.ok_or("Missing sending chain")?;
+        let (next, message) = kdf_chain(&chain);
+        self.send = Some(next);
+
+        let header = RatchetHeader {
+            public: PublicKey::from(&self.dh_self).to_bytes(),
+            previous: self.previous,
+            n: self.send_n,
+        };
+        self.send_n += 1;
+        let sealed = cipher(&message)
+            .encrypt(
+                Nonce::from_slice(&[0u8; 12]),
+                Payload {
+                    msg: payload,
+                    aad: &header.associated_data(),
+                },
+            )
+            .map_err(|_| "Unable to encrypt payload")?;
+        Ok((header, Bytes::from(sealed)))
+    }
+
+    fn decrypt(&mut self, header: &RatchetHeader, payload: &[u8]) -> Result<Bytes, &'static str> {
+        let message = match self.skipped.remove(&(header.public, header.n)) {
+            Some(message) => message,
+            None => {
+                if header.public != self.dh_remote.to_bytes() {
+                    self.skip_until(header.previous)?;
+                    self.step_recv(PublicKey::from(header.public));
+                }
+                self.skip_until(header.n)?;
+                let chain = self.recv.ok_or("Missing receiving chain")?;
+                let (next, message) = kdf_chain(&chain);
+                self.recv = Some(next);
+                self.recv_n += 1;
+                message
+            }
+        };
+
+        cipher(&message)
+            .decrypt(
+                Nonce::from_slice(&[0u8; 12]),
+                Payload {
+                    msg: payload,
+                    aad: &header.associated_data(),
+                },
+            )
+            .map(Bytes::from)
+            .map_err(|_| "Unable to decrypt payload")
+    }
+}
+
+#[derive(Clone)]
+pub struct Ratchets {
+    local: PeerId,
+    prekey: StaticSecret,
+    sessions: Arc<Mutex<HashMap<PeerId, Session>>>,
+}
+
+impl Ratchets {
+    pub fn new(local: PeerId) -> Self {
+        Ratchets {
+            local,
+            prekey: StaticSecret::from(rand::random::<[u8; 32]>()),
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn hello(&self) -> Frame {
+        Frame::RatchetHello(PublicKey::from(&self.prekey).to_bytes())
+    }
+
+    fn establish(&self, peer: PeerId, remote_prekey: [u8; 32]) {
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        if sessions
+            .get(&peer)
+            .is_some_and(|session| session.remote_prekey == remote_prekey)
+        {
+            return;
+        }
+
+        let session = Session::new(self.local < peer, &self.prekey, remote_prekey);
+        sessions.insert(peer, session);
+    }
+
+    fn has_session(&self, peer: &PeerId) -> bool {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(peer)
+    }
+
+    async fn handshake(&self, control: &mut Control, peer: PeerId) -> io::Result<()> {
+        let mut stream = control
+            .open_stream(peer, RATCHET_PROTOCOL)
+            .await
+            .map_err(io::Error::other)?;
+        write_frame(&mut stream, &self.hello()).await?;
+        match read_frame(&mut stream).await? {
+            Frame::RatchetHello(remote_prekey) => {
+                self.establish(peer, remote_prekey);
+                Ok(())
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unexpected ratchet handshake",
+            )),
+        }
+    }
+
+    pub async fn seal(
+        &self,
+        control: &mut Control,
+        peer: PeerId,
+        envelope: &mut Envelope,
+    ) -> io::Result<()> {
+        if envelope.ratchet.is_some() {
+            return Ok(());
+        }
+        if !self.has_session(&peer) {
+            self.handshake(control, peer).await?;
+        }
+
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let session = sessions
+            .get_mut(&peer)
+            .ok_or_else(|| io::Error::other("Missing ratchet session"))?;
+        let (header, payload) = session
+            .encrypt(&envelope.payload)
+            .map_err(io::Error::other)?;
+        envelope.ratchet = Some(header);
+        envelope.payload = payload;
+        Ok(())
+    }
+
+    pub fn open(&self, peer: PeerId, envelope: &mut Envelope) -> Result<(), &'static str> {
+        let header = envelope.ratchet.take().ok_or("Unencrypted payload")?;
+        let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+        let session = sessions.get_mut(&peer).ok_or("Missing ratchet session")?;
+
+        let mut next = session.clone();
+        envelope.payload = next.decrypt(&header, &envelope.payload)?;
+        *session = next;
+        Ok(())
+    }
+
+    pub fn refresh(&self, mut control: Control, peer: PeerId) {
+        let ratchets = self.clone();
+        Runtime::spawn(async move {
+            let _ = ratchets.handshake(&mut control, peer).await;
+        });
+    }
+
+    async fn respond(&self, peer: PeerId, mut stream: Stream) -> io::Result<()> {
+        if let Frame::RatchetHello(remote_prekey) = read_frame(&mut stream).await? {
+            self.establish(peer, remote_prekey);
+            write_frame(&mut stream, &self.hello()).await?;
+        }
+        stream.close().await
+    }
+
+    pub fn serve(&self, mut incoming: IncomingStreams) {
+        let ratchets = self.clone();
+        Runtime::spawn(async move {
+            use libp2p::futures::StreamExt;
+
+            while let Some((peer, stream)) = incoming.next().await {
+                let ratchets = ratchets.clone();
+                Runtime::spawn(async move {
+                    let _ = ratchets.respond(peer, stream).await;
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+
+use libp2p::{core::transport::ListenerId, PeerId};
+
+use crate::util::Peer;
+
+#[derive(Default)]
+pub struct RelaySelector {
+    active: Vec<(PeerId, ListenerId)>,
+    failed: HashSet<PeerId>,
+}
+
+impl RelaySelector {
+    pub fn new() -> Self {
+        RelaySelector::default()
+    }
+
+    pub fn active(&self) -> Option<PeerId> {
+        self.active.first().map(|(relay, _)| *relay)
+    }
+
+    pub fn count(&self) -> usize {
+        self.active.len()
+    }
+
+    pub fn is_active(&self, relay: &PeerId) -> bool {
+        self.active.iter().any(|(active, _)| active == relay)
+    }
+
+    pub fn pick(&self, mut candidates: Vec<Peer>) -> Vec<Peer> {
+        candidates.retain(|peer| !self.is_active(&peer.id) && !self.failed.contains(&peer.id));
+        candidates.sort_by(|a, b| {
+            let a = a.stats.score().unwrap_or(f64::INFINITY);
+            let b = b.stats.score().unwrap_or(f64::INFINITY);
+            a.total_cmp(&b)
+        });
+        candidates
+    }
+
+    pub fn activate(&mut self, relay: PeerId, listener: ListenerId) {
+        self.active.push((relay, listener));
+    }
+
+    pub fn on_accepted(&mut self, relay: &PeerId) {
+        if self.is_active(relay) {
+            self.failed.clear();
+        }
+    }
+
+    pub fn on_lost_peer(&mut self, relay: &PeerId) -> Option<PeerId> {
+        let index = self.active.iter().position(|(active, _)| active == relay)?;
+        self.fail(index)
+    }
+
+    pub fn on_lost_listener(&mut self, listener: &ListenerId) -> Option<PeerId> {
+        let index = self
+            .active
+            .iter()
+            .position(|(_, active)| active == listener)?;
+        self.fail(index)
+    }
+
+    fn fail(&mut self, index: usize) -> Option<PeerId> {
+        let (relay, _) = self.active.remove(index);
+        self.failed.insert(relay);
+        Some(relay)
+    }
+
+    pub fn exhausted(&mut self) {
+        self.failed.clear();
+    }
+}
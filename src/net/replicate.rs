@@ -0,0 +1,493 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_channel::Sender;
+use bytes::Bytes;
+use libp2p::{futures::AsyncWriteExt, kad::KBucketKey, PeerId, Stream, StreamProtocol};
+use libp2p_stream::Control;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blobs::BlobStore,
+    runtime::{Executor, Runtime},
+    storage::Storage,
+};
+
+use super::{
+    command::CommandWrapper,
+    event::Event,
+    wire::{read_frame, write_frame, Frame},
+};
+
+pub const SHARD_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/shard/1.0.0");
+const PLACEMENTS: &str = "shard_placements";
+const SHARDS: &str = "shards";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplicationPolicy {
+    pub data_shards: usize,
+    pub parity_shards: usize,
+    pub repair_after: Duration,
+}
+
+impl Default for ReplicationPolicy {
+    fn default() -> Self {
+        ReplicationPolicy {
+            data_shards: 4,
+            parity_shards: 2,
+            repair_after: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+impl ReplicationPolicy {
+    pub fn total(&self) -> usize {
+        self.data_shards + self.parity_shards
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShardPlacement {
+    pub blob: String,
+    pub size: u64,
+    pub data_shards: usize,
+    pub parity_shards: usize,
+    pub holders: Vec<PeerId>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ShardStore {
+    storage: Arc<dyn Storage>,
+}
+
+fn shard_key(blob: &str, index: usize) -> Vec<u8> {
+    format!("{blob}:{index}").into_bytes()
+}
+
+impl ShardStore {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        ShardStore { storage }
+    }
+
+    pub fn placement(
+        &self,
+        blob: &str,
+    ) -> Result<Option<ShardPlacement>, Box<dyn Error + Send + Sync>> {
+        self.storage.get_value(PLACEMENTS, blob.as_bytes())
+    }
+
+    pub fn placements(&self) -> Result<Vec<ShardPlacement>, Box<dyn Error + Send + Sync>> {
+        self.storage.values(PLACEMENTS)
+    }
+
+    pub fn place(&self, placement: &ShardPlacement) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.storage
+            .put_value(PLACEMENTS, placement.blob.as_bytes(), placement)
+    }
+
+    pub fn hold(
+        &self,
+        blob: &str,
+        index: usize,
+        data: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.storage.put(SHARDS, &shard_key(blob, index), data)
+    }
+
+    pub fn held(
+        &self,
+        blob: &str,
+        index: usize,
+    ) -> Result<Option<Vec<u8>>, Box<dyn Error + Send + Sync>> {
+        self.storage.get(SHARDS, &shard_key(blob, index))
+    }
+}
+
+pub fn encode(
+    data: &[u8],
+    policy: &ReplicationPolicy,
+) -> Result<Vec<Vec<u8>>, Box<dyn Error + Send + Sync>> {
+    let codec = ReedSolomon::new(policy.data_shards, policy.parity_shards)?;
+    let shard_size = data.len().div_ceil(policy.data_shards).max(1);
+    let mut shards: Vec<Vec<u8>> = (0..policy.total())
+        .map(|index| {
+            let start = (index * shard_size).min(data.len());
+            let end = ((index + 1) * shard_size).min(data.len());
+            let mut shard = data[start..end].to_vec();
+            shard.resize(shard_size, 0);
+            shard
+        })
+        .collect();
+    codec.encode(&mut shards)?;
+    Ok(shards)
+}
+
+pub fn reconstruct(
+    placement: &ShardPlacement,
+    shards: &mut [Option<Vec<u8>>],
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let codec = ReedSolomon::new(placement.data_shards, placement.parity_shards)?;
+    codec.reconstruct(shards)?;
+    Ok(())
+}
+
+pub fn assemble(placement: &ShardPlacement, shards: Vec<Option<Vec<u8>>>) -> Vec<u8> {
+    let mut data: Vec<u8> = shards
+        .into_iter()
+        .take(placement.data_shards)
+        .flat_map(|shard| shard.unwrap_or_default())
+        .collect();
+    data.truncate(placement.size as usize);
+    data
+}
+
+pub fn select(blob: &str, candidates: Vec<PeerId>, count: usize) -> Vec<PeerId> {
+    let target = KBucketKey::new(blob.as_bytes().to_vec());
+    let mut candidates: Vec<(KBucketKey<PeerId>, PeerId)> = candidates
+        .into_iter()
+        .map(|peer| (KBucketKey::from(peer), peer))
+        .collect();
+    candidates.sort_by_key(|(key, _)| key.distance(&target));
+    candidates
+        .into_iter()
+        .take(count)
+        .map(|(_, peer)| peer)
+        .collect()
+}
+
+async fn store_shard(
+    mut control: Control,
+    peer: PeerId,
+    blob: String,
+    index: usize,
+    data: Bytes,
+) -> io::Result<()> {
+    let mut stream = control
+        .open_stream(peer, SHARD_PROTOCOL)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::ConnectionRefused, e))?;
+    write_frame(&mut stream, &Frame::Shard { blob, index, data }).await?;
+    match read_frame(&mut stream).await? {
+        Frame::ShardStored { .. } => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unexpected shard reply",
+        )),
+    }
+}
+
+async fn fetch_shard(
+    mut control: Control,
+    peer: PeerId,
+    blob: String,
+    index: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut stream = control
+        .open_stream(peer, SHARD_PROTOCOL)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::ConnectionRefused, e))?;
+    write_frame(&mut stream, &Frame::ShardRequest { blob, index }).await?;
+    match read_frame(&mut stream).await? {
+        Frame::Shard { data, .. } => Ok(Some(data.to_vec())),
+        _ => Ok(None),
+    }
+}
+
+async fn collect(
+    control: &Control,
+    placement: &ShardPlacement,
+    online: impl Fn(&PeerId) -> bool,
+) -> Vec<Option<Vec<u8>>> {
+    let mut shards = Vec::with_capacity(placement.holders.len());
+    let mut found = 0;
+    for (index, holder) in placement.holders.iter().enumerate() {
+        if found >= placement.data_shards || !online(holder) {
+            shards.push(None);
+            continue;
+        }
+        let shard = fetch_shard(control.clone(), *holder, placement.blob.clone(), index)
+            .await
+            .ok()
+            .flatten();
+        found += shard.is_some() as usize;
+        shards.push(shard);
+    }
+    shards
+}
+
+#[derive(Clone)]
+pub struct Replicator {
+    control: Control,
+    shards: ShardStore,
+    blobs: BlobStore,
+    policy: ReplicationPolicy,
+    repairing: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Replicator {
+    pub fn new(
+        control: Control,
+        shards: ShardStore,
+        blobs: BlobStore,
+        policy: ReplicationPolicy,
+    ) -> Self {
+        Replicator {
+            control,
+            shards,
+            blobs,
+            policy,
+            repairing: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    pub fn policy(&self) -> &ReplicationPolicy {
+        &self.policy
+    }
+
+    pub fn placements(&self) -> Result<Vec<ShardPlacement>, Box<dyn Error + Send + Sync>> {
+        self.shards.placements()
+    }
+
+    pub fn serve(&self, stream: Stream) {
+        serve(self.shards.clone(), stream);
+    }
+
+    pub fn replicate(&self, command: CommandWrapper, blob: String, candidates: Vec<PeerId>) {
+        let replicator = self.clone();
+        Runtime::spawn(async move {
+            let result = replicator.distribute(blob, candidates).await;
+            let _ = command.respond(result).await;
+        });
+    }
+
+    async fn distribute(
+        &self,
+        blob: String,
+        candidates: Vec<PeerId>,
+    ) -> Result<ShardPlacement, Box<dyn Error + Send + Sync>> {
+        let data = self.blobs.get(&blob)?.ok_or("Unknown blob")?;
+        let holders = select(&blob, candidates, self.policy.total());
+        if holders.len() < self.policy.total() {
+            return Err("Not enough group peers to place every shard".into());
+        }
+
+        let shards = encode(&data, &self.policy)?;
+        for (index, (holder, shard)) in holders.iter().zip(shards).enumerate() {
+            store_shard(
+                self.control.clone(),
+                *holder,
+                blob.clone(),
+                index,
+                Bytes::from(shard),
+            )
+            .await?;
+        }
+
+        let placement = ShardPlacement {
+            blob,
+            size: data.len() as u64,
+            data_shards: self.policy.data_shards,
+            parity_shards: self.policy.parity_shards,
+            holders,
+        };
+        self.shards.place(&placement)?;
+        Ok(placement)
+    }
+
+    pub fn restore(&self, command: CommandWrapper, blob: String, connected: HashSet<PeerId>) {
+        let replicator = self.clone();
+        Runtime::spawn(async move {
+            let result = replicator.recover(blob, connected).await;
+            let _ = command.respond(result).await;
+        });
+    }
+
+    async fn recover(
+        &self,
+        blob: String,
+        connected: HashSet<PeerId>,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let placement = self
+            .shards
+            .placement(&blob)?
+            .ok_or("Blob is not replicated")?;
+        let mut shards = collect(&self.control, &placement, |peer| connected.contains(peer)).await;
+        reconstruct(&placement, &mut shards)?;
+        self.blobs.put(&assemble(&placement, shards))
+    }
+
+    pub fn repair(
+        &self,
+        placement: ShardPlacement,
+        offline: Vec<usize>,
+        connected: HashSet<PeerId>,
+        events: Sender<Event>,
+    ) {
+        {
+            let mut repairing = self.repairing.lock().unwrap_or_else(|e| e.into_inner());
+            if !repairing.insert(placement.blob.clone()) {
+                return;
+            }
+        }
+
+        let replicator = self.clone();
+        Runtime::spawn(async move {
+            let blob = placement.blob.clone();
+            if let Ok(Some(repaired)) = replicator.rebuild(placement, &offline, connected).await {
+                let _ = events
+                    .send(Event::ShardsRepaired {
+                        blob: blob.clone(),
+                        shards: repaired,
+                    })
+                    .await;
+            }
+            replicator
+                .repairing
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .remove(&blob);
+        });
+    }
+
+    async fn rebuild(
+        &self,
+        mut placement: ShardPlacement,
+        offline: &[usize],
+        connected: HashSet<PeerId>,
+    ) -> Result<Option<Vec<usize>>, Box<dyn Error + Send + Sync>> {
+        let candidates: Vec<PeerId> = connected
+            .iter()
+            .filter(|peer| !placement.holders.contains(peer))
+            .copied()
+            .collect();
+        let replacements = select(&placement.blob, candidates, offline.len());
+        if replacements.is_empty() {
+            return Ok(None);
+        }
+
+        let mut shards = collect(&self.control, &placement, |peer| connected.contains(peer)).await;
+        reconstruct(&placement, &mut shards)?;
+
+        let mut repaired = Vec::new();
+        for (index, replacement) in offline.iter().zip(replacements) {
+            let Some(shard) = shards.get(*index).cloned().flatten() else {
+                continue;
+            };
+            store_shard(
+                self.control.clone(),
+                replacement,
+                placement.blob.clone(),
+                *index,
+                Bytes::from(shard),
+            )
+            .await?;
+            placement.holders[*index] = replacement;
+            repaired.push(*index);
+        }
+
+        self.shards.place(&placement)?;
+        Ok(Some(repaired))
+    }
+}
+
+fn serve(shards: ShardStore, mut stream: Stream) {
+    Runtime::spawn(async move {
+        let reply = match read_frame(&mut stream).await {
+            Ok(Frame::Shard { blob, index, data }) => match shards.hold(&blob, index, &data) {
+                Ok(()) => Frame::ShardStored { blob, index },
+                Err(_) => return,
+            },
+            Ok(Frame::ShardRequest { blob, index }) => match shards.held(&blob, index) {
+                Ok(Some(data)) => Frame::Shard {
+                    blob,
+                    index,
+                    data: Bytes::from(data),
+                },
+                _ => Frame::ShardMissing { blob, index },
+            },
+            _ => return,
+        };
+
+        if write_frame(&mut stream, &reply).await.is_ok() {
+            let _ = stream.close().await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placement(data: &[u8], policy: &ReplicationPolicy) -> ShardPlacement {
+        ShardPlacement {
+            blob: String::from("blob"),
+            size: data.len() as u64,
+            data_shards: policy.data_shards,
+            parity_shards: policy.parity_shards,
+            holders: (0..policy.total()).map(|_| PeerId::random()).collect(),
+        }
+    }
+
+    #[test]
+    fn reconstructs_from_exactly_k_shards() {
+        let policy = ReplicationPolicy::default();
+        let data: Vec<u8> = (0..1001).map(|i| (i * 7 % 251) as u8).collect();
+        let placement = placement(&data, &policy);
+        let encoded = encode(&data, &policy).unwrap();
+        assert_eq!(encoded.len(), policy.total());
+
+        for missing in [[0, 1], [2, 5], [4, 5], [1, 3]] {
+            let mut shards: Vec<Option<Vec<u8>>> = encoded.iter().cloned().map(Some).collect();
+            for index in missing {
+                shards[index] = None;
+            }
+            assert_eq!(
+                shards.iter().flatten().count(),
+                policy.data_shards,
+                "exactly k shards survive"
+            );
+
+            reconstruct(&placement, &mut shards).unwrap();
+            assert_eq!(assemble(&placement, shards), data);
+        }
+    }
+
+    #[test]
+    fn fails_cleanly_below_k_shards() {
+        let policy = ReplicationPolicy::default();
+        let data = b"not enough shards to rebuild this".to_vec();
+        let placement = placement(&data, &policy);
+        let mut shards: Vec<Option<Vec<u8>>> = encode(&data, &policy)
+            .unwrap()
+            .into_iter()
+            .map(Some)
+            .collect();
+        for index in [0, 2, 4] {
+            shards[index] = None;
+        }
+
+        assert!(reconstruct(&placement, &mut shards).is_err());
+        assert_eq!(shards.iter().flatten().count(), policy.data_shards - 1);
+    }
+
+    #[test]
+    fn encodes_empty_blobs() {
+        let policy = ReplicationPolicy::default();
+        let placement = placement(&[], &policy);
+        let mut shards: Vec<Option<Vec<u8>>> = encode(&[], &policy)
+            .unwrap()
+            .into_iter()
+            .map(Some)
+            .collect();
+        shards[0] = None;
+
+        reconstruct(&placement, &mut shards).unwrap();
+        assert!(assemble(&placement, shards).is_empty());
+    }
+}
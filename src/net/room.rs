@@ -0,0 +1,176 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+use async_channel::{Receiver, Sender};
+use bytes::Bytes;
+use libp2p::{
+    gossipsub::{IdentTopic, TopicHash},
+    PeerId,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    command::{CommandKind, CommandWrapper},
+    history::HistoryEntry,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum RoomEvent {
+    Joined(PeerId),
+    Left(PeerId),
+    Message {
+        peer: Option<PeerId>,
+        payload: Bytes,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Slot {
+    sender: Sender<RoomEvent>,
+    receiver: Receiver<RoomEvent>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Rooms {
+    slots: Arc<Mutex<HashMap<String, Slot>>>,
+}
+
+impl Rooms {
+    pub fn new() -> Self {
+        Rooms::default()
+    }
+
+    pub fn topic(group: &str, room: &str) -> IdentTopic {
+        IdentTopic::new(format!("/modius/{group}/room/{room}"))
+    }
+
+    pub fn open(&self, room: &str) -> Receiver<RoomEvent> {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots
+            .entry(room.to_string())
+            .or_insert_with(|| {
+                let (sender, receiver) = async_channel::unbounded::<RoomEvent>();
+                Slot { sender, receiver }
+            })
+            .receiver
+            .clone()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots.keys().cloned().collect()
+    }
+
+    pub fn deliver(&self, room: &str, event: RoomEvent) {
+        let slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(slot) = slots.get(room) {
+            let _ = slot.sender.try_send(event);
+        }
+    }
+
+    pub fn close(&self, room: &str) {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(slot) = slots.remove(room) {
+            slot.sender.close();
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Room {
+    pub name: String,
+    commands: Sender<CommandWrapper>,
+    events: Receiver<RoomEvent>,
+}
+
+impl Room {
+    pub fn new(
+        name: String,
+        commands: Sender<CommandWrapper>,
+        events: Receiver<RoomEvent>,
+    ) -> Self {
+        Room {
+            name,
+            commands,
+            events,
+        }
+    }
+
+    pub fn events(&self) -> Receiver<RoomEvent> {
+        self.events.clone()
+    }
+
+    pub async fn send<P: Into<Bytes>>(
+        &self,
+        payload: P,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        CommandKind::RoomSend {
+            room: self.name.clone(),
+            payload: payload.into(),
+        }
+        .send::<()>(self.commands.clone())
+        .await
+    }
+
+    pub async fn members(&self) -> Result<Vec<PeerId>, Box<dyn Error + Send + Sync>> {
+        CommandKind::RoomMembers(self.name.clone())
+            .send::<Vec<PeerId>>(self.commands.clone())
+            .await
+    }
+
+    pub async fn backfill(
+        &self,
+        count: usize,
+    ) -> Result<Vec<HistoryEntry>, Box<dyn Error + Send + Sync>> {
+        CommandKind::RoomHistory {
+            room: self.name.clone(),
+            count,
+        }
+        .send::<Vec<HistoryEntry>>(self.commands.clone())
+        .await
+    }
+
+    pub async fn leave(self) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        CommandKind::LeaveRoom(self.name.clone())
+            .send::<bool>(self.commands.clone())
+            .await
+    }
+}
+
+#[derive(Default)]
+pub struct Presence {
+    members: HashMap<TopicHash, HashSet<PeerId>>,
+}
+
+impl Presence {
+    pub fn new() -> Self {
+        Presence::default()
+    }
+
+    pub fn join(&mut self, topic: TopicHash, peer: PeerId) -> bool {
+        self.members.entry(topic).or_default().insert(peer)
+    }
+
+    pub fn leave(&mut self, topic: &TopicHash, peer: &PeerId) -> bool {
+        self.members
+            .get_mut(topic)
+            .is_some_and(|members| members.remove(peer))
+    }
+
+    pub fn disconnect(&mut self, peer: &PeerId) -> Vec<TopicHash> {
+        self.members
+            .iter_mut()
+            .filter_map(|(topic, members)| members.remove(peer).then(|| topic.clone()))
+            .collect()
+    }
+
+    pub fn members(&self, topic: &TopicHash) -> Vec<PeerId> {
+        self.members
+            .get(topic)
+            .map(|members| members.iter().copied().collect())
+            .unwrap_or_default()
+    }
+}
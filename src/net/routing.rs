@@ -0,0 +1,78 @@
+use std::{error::Error, sync::Arc};
+
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::storage::Storage;
+
+const ROUTING: &str = "routing";
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RoutingEntry {
+    pub peer: PeerId,
+    pub addresses: Vec<Multiaddr>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RoutingTable {
+    storage: Arc<dyn Storage>,
+}
+
+impl RoutingTable {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        RoutingTable { storage }
+    }
+
+    pub fn entries(&self) -> Result<Vec<RoutingEntry>, Box<dyn Error + Send + Sync>> {
+        self.storage.values::<RoutingEntry>(ROUTING)
+    }
+
+    pub fn record(&self, entry: &RoutingEntry) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.storage
+            .put_value(ROUTING, &entry.peer.to_bytes(), entry)
+    }
+
+    pub fn evict(&self, peer: &PeerId) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.storage.delete(ROUTING, &peer.to_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::identity::Keypair;
+
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[test]
+    fn records_replace_and_evict_entries() {
+        let table = RoutingTable::new(Arc::new(MemoryStorage::new()));
+        let peer = Keypair::generate_ed25519().public().to_peer_id();
+        let first: Multiaddr = "/ip4/10.0.0.1/tcp/4001".parse().unwrap();
+        let second: Multiaddr = "/ip4/10.0.0.2/tcp/4001".parse().unwrap();
+
+        table
+            .record(&RoutingEntry {
+                peer,
+                addresses: vec![first],
+            })
+            .unwrap();
+        table
+            .record(&RoutingEntry {
+                peer,
+                addresses: vec![second.clone()],
+            })
+            .unwrap();
+        assert_eq!(
+            table.entries().unwrap(),
+            vec![RoutingEntry {
+                peer,
+                addresses: vec![second]
+            }]
+        );
+
+        table.evict(&peer).unwrap();
+        assert!(table.entries().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,1152 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt,
+    future::Future,
+    io,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_channel::{Receiver, Sender};
+use libp2p::{
+    futures::{
+        future::{self, BoxFuture, Either},
+        stream::{self, BoxStream},
+        AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, FutureExt, StreamExt,
+    },
+    PeerId, Stream, StreamProtocol,
+};
+use libp2p_stream::Control;
+use rand::seq::index;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::runtime::{sleep, Executor, Runtime};
+
+use super::{
+    command::{CommandError, CommandWrapper},
+    wire::{read_frame, write_frame, Frame},
+};
+
+pub const RPC_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/rpc/1.0.0");
+const STREAM_WINDOW: u32 = 32;
+
+pub fn service_name(method: &str) -> &str {
+    method
+        .split_once('.')
+        .map_or(method, |(service, _)| service)
+}
+
+pub fn service_protocol(service: &str) -> Option<StreamProtocol> {
+    StreamProtocol::try_from_owned(format!("{RPC_PROTOCOL}/{service}")).ok()
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RpcErrorKind {
+    MethodNotFound,
+    InvalidParams,
+    Failed,
+    Unavailable,
+    Timeout,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct RpcError {
+    pub kind: RpcErrorKind,
+    pub method: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl RpcError {
+    pub fn new<M: Into<String>>(kind: RpcErrorKind, method: &str, message: M) -> Self {
+        RpcError {
+            kind,
+            method: method.to_string(),
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    pub fn failed<M: Into<String>>(message: M) -> Self {
+        RpcError::new(RpcErrorKind::Failed, "", message)
+    }
+
+    pub fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    pub fn method_not_found(method: &str) -> Self {
+        RpcError::new(
+            RpcErrorKind::MethodNotFound,
+            method,
+            format!("Unknown method {method}"),
+        )
+    }
+
+    pub fn invalid_params<E: fmt::Display>(method: &str, error: E) -> Self {
+        RpcError::new(RpcErrorKind::InvalidParams, method, error.to_string())
+    }
+
+    pub fn from_command(method: &str, error: Box<dyn Error + Send + Sync>) -> Self {
+        let error = match error.downcast::<CommandError>() {
+            Ok(command) => command.error,
+            Err(error) => error,
+        };
+        match error.downcast::<RpcError>() {
+            Ok(error) => *error,
+            Err(error) => RpcError::new(RpcErrorKind::Unavailable, method, error.to_string()),
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        matches!(self.kind, RpcErrorKind::Unavailable | RpcErrorKind::Timeout)
+    }
+
+    fn for_method(mut self, method: &str) -> Self {
+        if self.method.is_empty() {
+            self.method = method.to_string();
+        }
+        self
+    }
+}
+
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} calling {}: {}",
+            self.kind, self.method, self.message
+        )
+    }
+}
+
+impl Error for RpcError {}
+
+pub type RpcResult = Result<Value, RpcError>;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Hedge {
+    pub peer: PeerId,
+    pub after: Duration,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CallOptions {
+    pub timeout: Duration,
+    pub retries: u32,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    pub hedge: Option<Hedge>,
+}
+
+impl Default for CallOptions {
+    fn default() -> Self {
+        CallOptions {
+            timeout: Duration::from_secs(30),
+            retries: 0,
+            backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            hedge: None,
+        }
+    }
+}
+
+impl CallOptions {
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_hedge(mut self, peer: PeerId, after: Duration) -> Self {
+        self.hedge = Some(Hedge { peer, after });
+        self
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        self.backoff
+            .checked_mul(2u32.saturating_pow(retry))
+            .unwrap_or(Duration::MAX)
+            .min(self.max_backoff)
+    }
+}
+
+pub type Handler = Arc<dyn Fn(Caller, Value) -> BoxFuture<'static, RpcResult> + Send + Sync>;
+
+pub type StreamHandler = Arc<dyn Fn(PeerId, Value) -> BoxStream<'static, RpcResult> + Send + Sync>;
+
+pub trait Service: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn methods(&self) -> Vec<String>;
+
+    fn call(&self, caller: Caller, method: &str, params: Value) -> BoxFuture<'static, RpcResult>;
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Balance {
+    #[default]
+    RoundRobin,
+    LeastLatency,
+    PowerOfTwo,
+}
+
+pub type Provider = (PeerId, Option<f64>);
+
+#[derive(Debug, Default)]
+pub struct Balancer {
+    cursors: HashMap<String, usize>,
+}
+
+fn latency(provider: &Provider) -> f64 {
+    provider.1.unwrap_or(f64::INFINITY)
+}
+
+impl Balancer {
+    pub fn new() -> Self {
+        Balancer::default()
+    }
+
+    pub fn pick(
+        &mut self,
+        service: &str,
+        balance: Balance,
+        providers: Vec<Provider>,
+    ) -> Option<PeerId> {
+        self.order(service, balance, providers).first().copied()
+    }
+
+    pub fn order(
+        &mut self,
+        service: &str,
+        balance: Balance,
+        mut providers: Vec<Provider>,
+    ) -> Vec<PeerId> {
+        if providers.is_empty() {
+            return Vec::new();
+        }
+        providers.sort_by_key(|(peer, _)| *peer);
+        let measured = providers.iter().any(|(_, rtt)| rtt.is_some());
+
+        let first = match balance {
+            Balance::LeastLatency if measured => {
+                providers.sort_by(|a, b| latency(a).total_cmp(&latency(b)));
+                0
+            }
+            Balance::PowerOfTwo if measured => index::sample(
+                &mut rand::thread_rng(),
+                providers.len(),
+                2.min(providers.len()),
+            )
+            .into_iter()
+            .min_by(|a, b| latency(&providers[*a]).total_cmp(&latency(&providers[*b])))
+            .unwrap_or_default(),
+            _ => {
+                let cursor = self.cursors.entry(service.to_string()).or_default();
+                let first = *cursor % providers.len();
+                *cursor = cursor.wrapping_add(1);
+                first
+            }
+        };
+        let mut peers: Vec<PeerId> = providers.into_iter().map(|(peer, _)| peer).collect();
+        peers.rotate_left(first);
+        peers
+    }
+}
+
+pub type Callback = Arc<dyn Fn(Value) -> BoxFuture<'static, RpcResult> + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct Callbacks {
+    handlers: HashMap<String, Callback>,
+}
+
+impl fmt::Debug for Callbacks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Callbacks")
+            .field("names", &self.names())
+            .finish()
+    }
+}
+
+impl Callbacks {
+    pub fn new() -> Self {
+        Callbacks::default()
+    }
+
+    pub fn on<P, R, F, Fut>(mut self, name: &str, callback: F) -> Self
+    where
+        P: DeserializeOwned + Send + 'static,
+        R: Serialize,
+        F: Fn(P) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<R, RpcError>> + Send + 'static,
+    {
+        let callback = Arc::new(callback);
+        let method = name.to_string();
+        let handler: Callback = Arc::new(move |params| {
+            let callback = callback.clone();
+            let method = method.clone();
+            Box::pin(async move {
+                let params = serde_json::from_value::<P>(params)
+                    .map_err(|e| RpcError::invalid_params(&method, e))?;
+                let result = callback(params).await?;
+                serde_json::to_value(result)
+                    .map_err(|e| RpcError::new(RpcErrorKind::Failed, &method, e.to_string()))
+            })
+        });
+        self.handlers.insert(name.to_string(), handler);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.handlers.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    async fn invoke(&self, name: &str, params: Value) -> RpcResult {
+        match self.handlers.get(name) {
+            Some(callback) => callback(params).await.map_err(|e| e.for_method(name)),
+            None => Err(RpcError::method_not_found(name)),
+        }
+    }
+}
+
+struct CallbackRequest {
+    name: String,
+    params: Value,
+    reply: Sender<RpcResult>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Caller {
+    pub peer: PeerId,
+    callbacks: Vec<String>,
+    requests: Sender<CallbackRequest>,
+}
+
+impl Caller {
+    pub fn callbacks(&self) -> &[String] {
+        &self.callbacks
+    }
+
+    pub async fn callback<P: Serialize, R: DeserializeOwned>(
+        &self,
+        name: &str,
+        params: P,
+    ) -> Result<R, RpcError> {
+        let completed = || {
+            RpcError::new(
+                RpcErrorKind::Unavailable,
+                name,
+                "The originating call has completed",
+            )
+        };
+        let params = serde_json::to_value(params).map_err(|e| RpcError::invalid_params(name, e))?;
+        let (reply, replied) = async_channel::bounded::<RpcResult>(1);
+        let request = CallbackRequest {
+            name: name.to_string(),
+            params,
+            reply,
+        };
+        self.requests.send(request).await.map_err(|_| completed())?;
+        let value = replied.recv().await.map_err(|_| completed())??;
+        serde_json::from_value::<R>(value)
+            .map_err(|e| RpcError::new(RpcErrorKind::Failed, name, e.to_string()))
+    }
+}
+
+pub struct RpcStream<R> {
+    method: String,
+    items: Receiver<RpcResult>,
+    _cancel: Sender<()>,
+    item: PhantomData<fn() -> R>,
+}
+
+impl<R: DeserializeOwned> RpcStream<R> {
+    pub async fn next(&mut self) -> Option<Result<R, RpcError>> {
+        let item = self.items.recv().await.ok()?;
+        Some(item.and_then(|value| {
+            serde_json::from_value::<R>(value)
+                .map_err(|e| RpcError::new(RpcErrorKind::Failed, &self.method, e.to_string()))
+        }))
+    }
+
+    pub fn cancel(self) {}
+}
+
+struct PendingStream {
+    items: Sender<RpcResult>,
+    cancelled: Receiver<()>,
+}
+
+impl PendingStream {
+    async fn forward(self, method: &str, mut stream: Stream) {
+        if let Err(e) = self.relay(&mut stream).await {
+            let error = RpcError::new(RpcErrorKind::Unavailable, method, e.to_string());
+            let _ = self.items.send(Err(error)).await;
+        }
+        let _ = stream.close().await;
+    }
+
+    async fn relay<S: AsyncRead + AsyncWrite + Unpin>(&self, stream: &mut S) -> io::Result<()> {
+        let mut delivered = 0;
+        loop {
+            let frame = match future::select(
+                Box::pin(read_frame(stream)),
+                Box::pin(self.cancelled.recv()),
+            )
+            .await
+            {
+                Either::Left((frame, _)) => frame?,
+                Either::Right(_) => break,
+            };
+            match frame {
+                Frame::RpcItem(item) => {
+                    if self.items.send(item).await.is_err() {
+                        break;
+                    }
+                    delivered += 1;
+                    if delivered >= STREAM_WINDOW / 2 {
+                        write_frame(stream, &Frame::RpcCredit(delivered)).await?;
+                        delivered = 0;
+                    }
+                }
+                Frame::RpcEnd => return Ok(()),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Unexpected RPC stream frame",
+                    ))
+                }
+            }
+        }
+        write_frame(stream, &Frame::RpcCancel).await
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Services {
+    handlers: Arc<Mutex<HashMap<String, Handler>>>,
+    streams: Arc<Mutex<HashMap<String, StreamHandler>>>,
+    pending: Arc<Mutex<HashMap<u64, PendingStream>>>,
+    callbacks: Arc<Mutex<HashMap<u64, Callbacks>>>,
+    next_call: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for Services {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Services")
+            .field("methods", &self.methods())
+            .finish()
+    }
+}
+
+impl Services {
+    pub fn new() -> Self {
+        Services::default()
+    }
+
+    pub fn serve(&self, method: &str, handler: Handler) {
+        let mut handlers = self.handlers.lock().unwrap_or_else(|e| e.into_inner());
+        handlers.insert(method.to_string(), handler);
+    }
+
+    pub fn serve_streaming(&self, method: &str, handler: StreamHandler) {
+        let mut streams = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        streams.insert(method.to_string(), handler);
+    }
+
+    pub fn register(&self, service: Arc<dyn Service>) {
+        for method in service.methods() {
+            let service = service.clone();
+            let name = format!("{}.{method}", service.name());
+            self.serve(
+                &name,
+                Arc::new(move |caller, params| service.call(caller, &method, params)),
+            );
+        }
+    }
+
+    pub fn unserve(&self, method: &str) -> bool {
+        let mut handlers = self.handlers.lock().unwrap_or_else(|e| e.into_inner());
+        let mut streams = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        handlers.remove(method).is_some() | streams.remove(method).is_some()
+    }
+
+    pub fn methods(&self) -> Vec<String> {
+        let handlers = self.handlers.lock().unwrap_or_else(|e| e.into_inner());
+        let streams = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        let mut methods: Vec<String> = handlers.keys().chain(streams.keys()).cloned().collect();
+        methods.sort();
+        methods.dedup();
+        methods
+    }
+
+    pub fn service_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .methods()
+            .iter()
+            .map(|method| service_name(method).to_string())
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    fn get(&self, method: &str) -> Option<Handler> {
+        let handlers = self.handlers.lock().unwrap_or_else(|e| e.into_inner());
+        handlers.get(method).cloned()
+    }
+
+    fn get_streaming(&self, method: &str) -> Option<StreamHandler> {
+        let streams = self.streams.lock().unwrap_or_else(|e| e.into_inner());
+        streams.get(method).cloned()
+    }
+
+    pub fn open_stream<R>(&self, method: &str) -> (u64, RpcStream<R>) {
+        let call = self.next_call.fetch_add(1, Ordering::Relaxed);
+        let (items, received) = async_channel::bounded::<RpcResult>(STREAM_WINDOW as usize);
+        let (cancel, cancelled) = async_channel::bounded::<()>(1);
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.insert(call, PendingStream { items, cancelled });
+        let stream = RpcStream {
+            method: method.to_string(),
+            items: received,
+            _cancel: cancel,
+            item: PhantomData,
+        };
+        (call, stream)
+    }
+
+    pub fn attach(&self, callbacks: Callbacks) -> u64 {
+        let call = self.next_call.fetch_add(1, Ordering::Relaxed);
+        let mut attached = self.callbacks.lock().unwrap_or_else(|e| e.into_inner());
+        attached.insert(call, callbacks);
+        call
+    }
+
+    pub fn detach(&self, call: u64) -> Option<Callbacks> {
+        let mut attached = self.callbacks.lock().unwrap_or_else(|e| e.into_inner());
+        attached.remove(&call)
+    }
+
+    pub fn close_stream(&self, call: u64) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.remove(&call);
+    }
+
+    fn take_stream(&self, call: u64) -> Option<PendingStream> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending.remove(&call)
+    }
+
+    pub fn dispatch(
+        &self,
+        caller: Caller,
+        method: &str,
+        params: Value,
+    ) -> BoxFuture<'static, RpcResult> {
+        let Some(handler) = self.get(method) else {
+            return future::ready(Err(RpcError::method_not_found(method))).boxed();
+        };
+        let method = method.to_string();
+        handler(caller, params)
+            .map(move |result| result.map_err(|e| e.for_method(&method)))
+            .boxed()
+    }
+
+    pub fn accept(&self, peer: PeerId, stream: Stream) {
+        let services = self.clone();
+        Runtime::spawn(async move {
+            let _ = services.respond(peer, stream).await;
+        });
+    }
+
+    async fn respond(&self, peer: PeerId, mut stream: Stream) -> io::Result<()> {
+        match read_frame(&mut stream).await? {
+            Frame::RpcRequest {
+                method,
+                params,
+                callbacks,
+            } => {
+                let (requests, pending) = async_channel::unbounded::<CallbackRequest>();
+                let caller = Caller {
+                    peer,
+                    callbacks,
+                    requests,
+                };
+                let mut call = self.dispatch(caller, &method, params);
+                let result = loop {
+                    match future::select(&mut call, Box::pin(pending.recv())).await {
+                        Either::Left((result, _)) => break result,
+                        Either::Right((Ok(request), _)) => {
+                            let reply = callback(&mut stream, request.name, request.params).await;
+                            let _ = request.reply.try_send(reply);
+                        }
+                        Either::Right((Err(_), call)) => break call.await,
+                    }
+                };
+                drop(pending);
+                write_frame(&mut stream, &Frame::RpcResponse(result)).await?;
+                stream.close().await
+            }
+            Frame::RpcStreamRequest {
+                method,
+                params,
+                window,
+            } => self.stream_out(peer, stream, method, params, window).await,
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unexpected RPC request",
+            )),
+        }
+    }
+
+    async fn stream_out<S: AsyncRead + AsyncWrite + Send + 'static>(
+        &self,
+        peer: PeerId,
+        stream: S,
+        method: String,
+        params: Value,
+        window: u32,
+    ) -> io::Result<()> {
+        let (mut reader, mut writer) = stream.split();
+        let (credits, granted) = async_channel::unbounded::<u32>();
+        Runtime::spawn(async move {
+            while let Ok(Frame::RpcCredit(credit)) = read_frame(&mut reader).await {
+                if credits.send(credit).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut items = match self.get_streaming(&method) {
+            Some(handler) => handler(peer, params),
+            None => stream::once(future::ready(Err(RpcError::method_not_found(&method)))).boxed(),
+        };
+        let mut credit = window;
+        loop {
+            let item = match future::select(items.next(), Box::pin(granted.recv())).await {
+                Either::Left((Some(item), _)) => item,
+                Either::Left((None, _)) => break,
+                Either::Right((Ok(more), _)) => {
+                    credit = credit.saturating_add(more);
+                    continue;
+                }
+                Either::Right((Err(_), _)) => return writer.close().await,
+            };
+            while credit == 0 {
+                match granted.recv().await {
+                    Ok(more) => credit = credit.saturating_add(more),
+                    Err(_) => return writer.close().await,
+                }
+            }
+            let item = item.map_err(|e| e.for_method(&method));
+            write_frame(&mut writer, &Frame::RpcItem(item)).await?;
+            credit -= 1;
+        }
+        write_frame(&mut writer, &Frame::RpcEnd).await?;
+        writer.close().await
+    }
+
+    pub fn call_streaming(
+        &self,
+        command: CommandWrapper,
+        mut control: Control,
+        peer: PeerId,
+        method: String,
+        params: Value,
+        call: u64,
+    ) {
+        let pending = self.take_stream(call);
+        Runtime::spawn(async move {
+            let Some(pending) = pending else {
+                let _ = command
+                    .respond::<(), _>(Err(RpcError::new(
+                        RpcErrorKind::Unavailable,
+                        &method,
+                        "Streaming call was already closed",
+                    )))
+                    .await;
+                return;
+            };
+            let opened = async {
+                let mut stream = control.open_stream(peer, RPC_PROTOCOL).await.map_err(|e| {
+                    RpcError::new(RpcErrorKind::Unavailable, &method, e.to_string())
+                })?;
+                let request = Frame::RpcStreamRequest {
+                    method: method.clone(),
+                    params,
+                    window: STREAM_WINDOW,
+                };
+                write_frame(&mut stream, &request).await.map_err(|e| {
+                    RpcError::new(RpcErrorKind::Unavailable, &method, e.to_string())
+                })?;
+                Ok::<Stream, RpcError>(stream)
+            }
+            .await;
+
+            match opened {
+                Ok(stream) => {
+                    let _ = command.reply(()).await;
+                    pending.forward(&method, stream).await;
+                }
+                Err(e) => {
+                    let _ = command.respond::<(), _>(Err(e)).await;
+                }
+            }
+        });
+    }
+}
+
+async fn callback(stream: &mut Stream, name: String, params: Value) -> RpcResult {
+    let unavailable =
+        |e: &dyn fmt::Display| RpcError::new(RpcErrorKind::Unavailable, &name, e.to_string());
+    let request = Frame::RpcCallback {
+        name: name.clone(),
+        params,
+    };
+    write_frame(stream, &request)
+        .await
+        .map_err(|e| unavailable(&e))?;
+    match read_frame(stream).await.map_err(|e| unavailable(&e))? {
+        Frame::RpcCallbackResult(result) => result,
+        _ => Err(unavailable(&"Unexpected callback result")),
+    }
+}
+
+async fn request(
+    mut control: Control,
+    peer: PeerId,
+    method: &str,
+    params: Value,
+    callbacks: Callbacks,
+) -> RpcResult {
+    let unavailable =
+        |e: &dyn fmt::Display| RpcError::new(RpcErrorKind::Unavailable, method, e.to_string());
+    let mut stream = control
+        .open_stream(peer, RPC_PROTOCOL)
+        .await
+        .map_err(|e| unavailable(&e))?;
+    let call = Frame::RpcRequest {
+        method: method.to_string(),
+        params,
+        callbacks: callbacks.names(),
+    };
+    write_frame(&mut stream, &call)
+        .await
+        .map_err(|e| unavailable(&e))?;
+    loop {
+        match read_frame(&mut stream).await.map_err(|e| unavailable(&e))? {
+            Frame::RpcResponse(result) => {
+                let _ = stream.close().await;
+                return result;
+            }
+            Frame::RpcCallback { name, params } => {
+                let result = Frame::RpcCallbackResult(callbacks.invoke(&name, params).await);
+                write_frame(&mut stream, &result)
+                    .await
+                    .map_err(|e| unavailable(&e))?;
+            }
+            _ => return Err(unavailable(&"Unexpected RPC response")),
+        }
+    }
+}
+
+async fn timed(call: BoxFuture<'_, RpcResult>, timeout: Duration, method: &str) -> RpcResult {
+    match future::select(call, Box::pin(sleep(timeout))).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(RpcError::new(
+            RpcErrorKind::Timeout,
+            method,
+            "No response before the deadline",
+        )),
+    }
+}
+
+async fn attempt(
+    control: &Control,
+    peer: PeerId,
+    method: &str,
+    params: &Value,
+    callbacks: &Callbacks,
+    options: &CallOptions,
+) -> RpcResult {
+    let send = |peer: PeerId| {
+        request(
+            control.clone(),
+            peer,
+            method,
+            params.clone(),
+            callbacks.clone(),
+        )
+    };
+    let primary = timed(send(peer).boxed(), options.timeout, method).boxed();
+    let Some(hedge) = &options.hedge else {
+        return primary.await;
+    };
+    let hedged = async move {
+        sleep(hedge.after).await;
+        timed(send(hedge.peer).boxed(), options.timeout, method).await
+    }
+    .boxed();
+
+    match future::select(primary, hedged).await {
+        Either::Left((result, other)) | Either::Right((result, other)) => match result {
+            Err(e) if e.is_retryable() => other.await,
+            result => result,
+        },
+    }
+}
+
+async fn retrying<F, Fut>(options: &CallOptions, mut attempt: F) -> RpcResult
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = RpcResult>,
+{
+    let mut retries = 0;
+    loop {
+        match attempt(retries).await {
+            Err(e) if e.is_retryable() && retries < options.retries => {
+                sleep(options.delay(retries)).await;
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn spawn_call(
+    command: CommandWrapper,
+    control: Control,
+    peers: Vec<PeerId>,
+    method: String,
+    params: Value,
+    callbacks: Callbacks,
+    options: CallOptions,
+) {
+    Runtime::spawn(async move {
+        let result = retrying(&options, |retry| {
+            let peer = peers[retry as usize % peers.len()];
+            attempt(&control, peer, &method, &params, &callbacks, &options)
+        })
+        .await;
+        let _ = command.respond(result).await;
+    });
+}
+
+pub fn call(
+    command: CommandWrapper,
+    control: Control,
+    peer: PeerId,
+    method: String,
+    params: Value,
+    callbacks: Callbacks,
+    options: CallOptions,
+) {
+    spawn_call(
+        command,
+        control,
+        vec![peer],
+        method,
+        params,
+        callbacks,
+        options,
+    );
+}
+
+pub fn call_any(
+    command: CommandWrapper,
+    control: Control,
+    peers: Vec<PeerId>,
+    method: String,
+    params: Value,
+    options: CallOptions,
+) {
+    spawn_call(
+        command,
+        control,
+        peers,
+        method,
+        params,
+        Callbacks::new(),
+        options,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    use super::*;
+
+    struct Duplex {
+        reader: piper::Reader,
+        writer: piper::Writer,
+    }
+
+    impl AsyncRead for Duplex {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.reader).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for Duplex {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.writer).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.writer).poll_flush(cx)
+        }
+
+        fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.writer).poll_close(cx)
+        }
+    }
+
+    fn duplex() -> (Duplex, Duplex) {
+        let (left_reader, right_writer) = piper::pipe(4096);
+        let (right_reader, left_writer) = piper::pipe(4096);
+        (
+            Duplex {
+                reader: left_reader,
+                writer: left_writer,
+            },
+            Duplex {
+                reader: right_reader,
+                writer: right_writer,
+            },
+        )
+    }
+
+    fn counting(count: u64) -> Services {
+        let services = Services::new();
+        services.serve_streaming(
+            "numbers.count",
+            Arc::new(move |_, _| stream::iter((0..count).map(|n| Ok(Value::from(n)))).boxed()),
+        );
+        services
+    }
+
+    fn peers(count: usize) -> Vec<PeerId> {
+        let mut peers: Vec<PeerId> = (0..count).map(|_| PeerId::random()).collect();
+        peers.sort();
+        peers
+    }
+
+    #[test]
+    fn round_robin_rotates_per_service() {
+        let mut balancer = Balancer::new();
+        let peers = peers(3);
+        let providers: Vec<Provider> = peers.iter().rev().map(|peer| (*peer, None)).collect();
+        let picked: Vec<PeerId> = (0..4)
+            .filter_map(|_| balancer.pick("kv", Balance::RoundRobin, providers.clone()))
+            .collect();
+        assert_eq!(picked, vec![peers[0], peers[1], peers[2], peers[0]]);
+        assert_eq!(
+            balancer.pick("other", Balance::RoundRobin, providers),
+            Some(peers[0])
+        );
+        assert_eq!(balancer.pick("kv", Balance::RoundRobin, Vec::new()), None);
+    }
+
+    #[test]
+    fn least_latency_prefers_measured_providers() {
+        let mut balancer = Balancer::new();
+        let peers = peers(3);
+        let providers = vec![
+            (peers[0], None),
+            (peers[1], Some(40.0)),
+            (peers[2], Some(10.0)),
+        ];
+        assert_eq!(
+            balancer.order("kv", Balance::LeastLatency, providers),
+            vec![peers[2], peers[1], peers[0]]
+        );
+    }
+
+    #[test]
+    fn least_latency_round_robins_without_measurements() {
+        let mut balancer = Balancer::new();
+        let peers = peers(2);
+        let providers: Vec<Provider> = peers.iter().map(|peer| (*peer, None)).collect();
+        let picked: Vec<PeerId> = (0..3)
+            .filter_map(|_| balancer.pick("kv", Balance::LeastLatency, providers.clone()))
+            .collect();
+        assert_eq!(picked, vec![peers[0], peers[1], peers[0]]);
+    }
+
+    #[test]
+    fn power_of_two_picks_the_faster_of_two() {
+        let mut balancer = Balancer::new();
+        let peers = peers(2);
+        let providers = vec![(peers[0], Some(50.0)), (peers[1], Some(5.0))];
+        for _ in 0..8 {
+            assert_eq!(
+                balancer.pick("kv", Balance::PowerOfTwo, providers.clone()),
+                Some(peers[1])
+            );
+        }
+    }
+
+    #[test]
+    fn order_lists_every_provider_once_starting_at_the_pick() {
+        let mut balancer = Balancer::new();
+        let peers = peers(3);
+        let providers: Vec<Provider> = peers.iter().map(|peer| (*peer, None)).collect();
+        balancer.pick("kv", Balance::RoundRobin, providers.clone());
+        assert_eq!(
+            balancer.order("kv", Balance::RoundRobin, providers),
+            vec![peers[1], peers[2], peers[0]]
+        );
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let options = CallOptions::default().with_retries(5, Duration::from_millis(200));
+        let delays: Vec<u64> = (0..6)
+            .map(|retry| options.delay(retry).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![200, 400, 800, 1600, 3200, 5000]);
+        assert_eq!(options.delay(u32::MAX), options.max_backoff);
+    }
+
+    #[tokio::test]
+    async fn retries_only_retryable_errors_up_to_the_limit() {
+        let options = CallOptions::default().with_retries(2, Duration::ZERO);
+        let mut attempts = Vec::new();
+        let result = retrying(&options, |retry| {
+            attempts.push(retry);
+            future::ready(Err(RpcError::new(
+                RpcErrorKind::Unavailable,
+                "kv.get",
+                "down",
+            )))
+        })
+        .await;
+        assert_eq!(attempts, vec![0, 1, 2]);
+        assert_eq!(result.unwrap_err().kind, RpcErrorKind::Unavailable);
+
+        let mut attempts = 0;
+        let result = retrying(&options, |_| {
+            attempts += 1;
+            future::ready(Err(RpcError::failed("broken")))
+        })
+        .await;
+        assert_eq!(attempts, 1);
+        assert_eq!(result.unwrap_err().kind, RpcErrorKind::Failed);
+
+        let result = retrying(&options, |retry| {
+            future::ready(match retry {
+                0 => Err(RpcError::new(RpcErrorKind::Timeout, "kv.get", "slow")),
+                _ => Ok(Value::from(retry)),
+            })
+        })
+        .await;
+        assert_eq!(result, Ok(Value::from(1)));
+    }
+
+    #[tokio::test]
+    async fn stream_out_waits_for_credit() {
+        let services = counting(6);
+        let (server, mut client) = duplex();
+        Runtime::spawn(async move {
+            let _ = services
+                .stream_out(
+                    PeerId::random(),
+                    server,
+                    String::from("numbers.count"),
+                    Value::Null,
+                    4,
+                )
+                .await;
+        });
+
+        for n in 0..4 {
+            assert!(
+                matches!(read_frame(&mut client).await.unwrap(), Frame::RpcItem(Ok(value)) if value == n)
+            );
+        }
+        let stalled = matches!(
+            future::select(
+                Box::pin(read_frame(&mut client)),
+                Box::pin(sleep(Duration::from_millis(50))),
+            )
+            .await,
+            Either::Right(_)
+        );
+        assert!(stalled);
+
+        write_frame(&mut client, &Frame::RpcCredit(2))
+            .await
+            .unwrap();
+        for n in 4..6 {
+            assert!(
+                matches!(read_frame(&mut client).await.unwrap(), Frame::RpcItem(Ok(value)) if value == n)
+            );
+        }
+        assert!(matches!(
+            read_frame(&mut client).await.unwrap(),
+            Frame::RpcEnd
+        ));
+    }
+
+    #[tokio::test]
+    async fn relay_grants_credit_as_items_are_consumed() {
+        let services = counting(STREAM_WINDOW as u64 * 3);
+        let (server, mut client) = duplex();
+        let serving = services.clone();
+        Runtime::spawn(async move {
+            let _ = serving
+                .stream_out(
+                    PeerId::random(),
+                    server,
+                    String::from("numbers.count"),
+                    Value::Null,
+                    STREAM_WINDOW,
+                )
+                .await;
+        });
+
+        let (call, mut items) = services.open_stream::<u64>("numbers.count");
+        let pending = services.take_stream(call).unwrap();
+        Runtime::spawn(async move {
+            let _ = pending.relay(&mut client).await;
+        });
+
+        for n in 0..STREAM_WINDOW as u64 * 3 {
+            assert_eq!(items.next().await.unwrap().unwrap(), n);
+        }
+        assert!(items.next().await.is_none());
+    }
+}
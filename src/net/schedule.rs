@@ -0,0 +1,59 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use super::command::CommandKind;
+
+struct ScheduledTask {
+    interval: TimeDelta,
+    next: DateTime<Utc>,
+    command: CommandKind,
+}
+
+fn after(now: DateTime<Utc>, interval: TimeDelta) -> DateTime<Utc> {
+    now.checked_add_signed(interval)
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    next_id: u64,
+    tasks: HashMap<u64, ScheduledTask>,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    pub fn schedule(&mut self, interval: Duration, command: CommandKind) -> u64 {
+        let interval = TimeDelta::from_std(interval).unwrap_or(TimeDelta::MAX);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.tasks.insert(
+            id,
+            ScheduledTask {
+                interval,
+                next: after(Utc::now(), interval),
+                command,
+            },
+        );
+        id
+    }
+
+    pub fn cancel(&mut self, id: u64) -> bool {
+        self.tasks.remove(&id).is_some()
+    }
+
+    pub fn due(&mut self) -> Vec<CommandKind> {
+        let now = Utc::now();
+        self.tasks
+            .values_mut()
+            .filter(|task| task.next <= now)
+            .map(|task| {
+                task.next = after(now, task.interval);
+                task.command.clone()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use super::event::Event;
+
+pub trait MessageType: Serialize + DeserializeOwned {
+    const NAME: &'static str;
+    const VERSION: u32;
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MessageTag {
+    pub name: String,
+    pub version: u32,
+}
+
+impl MessageTag {
+    pub fn of<M: MessageType>() -> Self {
+        MessageTag {
+            name: M::NAME.to_string(),
+            version: M::VERSION,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Schemas {
+    types: Arc<Mutex<HashMap<String, u32>>>,
+}
+
+impl Schemas {
+    pub fn new() -> Self {
+        Schemas::default()
+    }
+
+    pub fn register<M: MessageType>(&self) {
+        let mut types = self.types.lock().unwrap_or_else(|e| e.into_inner());
+        types.insert(M::NAME.to_string(), M::VERSION);
+    }
+
+    pub fn knows(&self, tag: &MessageTag) -> bool {
+        let types = self.types.lock().unwrap_or_else(|e| e.into_inner());
+        types.get(&tag.name) == Some(&tag.version)
+    }
+}
+
+type Handler<T> = Box<dyn Fn(Value) -> Option<T> + Send + Sync>;
+
+pub struct TypedDispatcher<T> {
+    handlers: HashMap<(String, u32), Handler<T>>,
+}
+
+impl<T> Default for TypedDispatcher<T> {
+    fn default() -> Self {
+        TypedDispatcher {
+            handlers: HashMap::new(),
+        }
+    }
+}
+
+impl<T> TypedDispatcher<T> {
+    pub fn new() -> Self {
+        TypedDispatcher::default()
+    }
+
+    pub fn on<M: MessageType, F: Fn(M) -> T + Send + Sync + 'static>(mut self, handler: F) -> Self {
+        self.handlers.insert(
+            (M::NAME.to_string(), M::VERSION),
+            Box::new(move |value| serde_json::from_value::<M>(value).ok().map(&handler)),
+        );
+        self
+    }
+
+    pub fn dispatch(&self, event: &Event) -> Option<T> {
+        let Event::TypedMessage { tag, value, .. } = event else {
+            return None;
+        };
+
+        self.handlers
+            .get(&(tag.name.clone(), tag.version))
+            .and_then(|handler| handler(value.clone()))
+    }
+}
@@ -0,0 +1,1299 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    error::Error,
+    fmt, io,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_channel::{Receiver, Sender};
+use async_lock::Semaphore;
+use bytes::Bytes;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use chrono::{TimeDelta, Utc};
+use libp2p::{identity::Keypair, PeerId, Stream, StreamProtocol};
+use libp2p_stream::Control;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    runtime::{sleep, Executor, Runtime},
+    storage::Storage,
+};
+
+#[cfg(feature = "ratchet")]
+use super::ratchet::Ratchets;
+#[cfg(feature = "opentelemetry")]
+use super::trace::TraceContext;
+use super::{
+    acl::{AccessControl, Permission},
+    breaker::CircuitBreaker,
+    channel::Channels,
+    dead::{DeadLetters, DeadReason},
+    event::Event,
+    fairness::{FairScheduler, SendScheduling},
+    multipath::Redundancy,
+    schema::{MessageTag, Schemas},
+    stats::Stats,
+    wire::{
+        frame_limit, is_oversized, read_frame, read_frame_limited, write_frame, write_frame_fair,
+        Envelope, Frame, MAX_FRAME,
+    },
+};
+
+const RETRY: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+const MAX_FAILURES: u32 = 8;
+const SEND_WINDOW: usize = 64;
+const DEDUP_CAPACITY: usize = 4096;
+const DUPLICATE_TIMEOUT: TimeDelta = TimeDelta::seconds(10);
+const REPLAY_WINDOW: u64 = 120_000_000;
+const BACKLOG: &str = "outbox";
+const BACKLOG_SEALING: &[u8] = b"modius-outbox";
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DeliveryGuarantee {
+    #[default]
+    Ordered,
+    ExactlyOnce,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Receipt {
+    pub seq: u64,
+    pub id: Option<Uuid>,
+}
+
+struct Outgoing {
+    key: [u8; 16],
+    envelope: Envelope,
+    acked: Option<Sender<()>>,
+    settled: Option<Arc<AtomicBool>>,
+    attempts: u32,
+    mirrored: bool,
+}
+
+struct Draft {
+    id: Option<Uuid>,
+    payload: Bytes,
+    guarantee: DeliveryGuarantee,
+    channel: Option<String>,
+    tag: Option<MessageTag>,
+    redundancy: Redundancy,
+    #[cfg(feature = "opentelemetry")]
+    trace: Option<TraceContext>,
+}
+
+impl Draft {
+    fn new(payload: Bytes, guarantee: DeliveryGuarantee) -> Self {
+        Draft {
+            id: None,
+            payload,
+            guarantee,
+            channel: None,
+            tag: None,
+            redundancy: Redundancy::Single,
+            #[cfg(feature = "opentelemetry")]
+            trace: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingMessage {
+    pub peer: PeerId,
+    pub envelope: Envelope,
+    pub payload: Bytes,
+    #[serde(default)]
+    pub redundancy: Redundancy,
+}
+
+#[derive(Clone)]
+struct Sealing([u8; 32]);
+
+impl fmt::Debug for Sealing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Sealing(..)")
+    }
+}
+
+impl Sealing {
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.0))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Backlog {
+    storage: Option<Arc<dyn Storage>>,
+    sealing: Option<Sealing>,
+    frozen: Arc<AtomicBool>,
+}
+
+impl Backlog {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Backlog {
+            storage: Some(storage),
+            sealing: None,
+            frozen: Arc::default(),
+        }
+    }
+
+    pub fn for_node(storage: Arc<dyn Storage>, key: &Keypair) -> Self {
+        Backlog {
+            sealing: cfg!(feature = "ratchet")
+                .then(|| key.derive_secret(BACKLOG_SEALING).map(Sealing))
+                .flatten(),
+            ..Backlog::new(storage)
+        }
+    }
+
+    fn encode(&self, message: &PendingMessage) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let plain = serde_json::to_vec(message)?;
+        let Some(sealing) = self.sealing.as_ref() else {
+            return Ok(plain);
+        };
+        let nonce: [u8; 12] = rand::random();
+        let sealed = sealing
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), plain.as_slice())
+            .map_err(|_| "Could not seal an outbox entry")?;
+        Ok([nonce.as_slice(), &sealed].concat())
+    }
+
+    fn decode(&self, raw: &[u8]) -> Result<PendingMessage, Box<dyn Error + Send + Sync>> {
+        let Some(sealing) = self.sealing.as_ref() else {
+            return Ok(serde_json::from_slice(raw)?);
+        };
+        let (nonce, sealed) = raw.split_at_checked(12).ok_or("Truncated outbox entry")?;
+        let plain = sealing
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| "Could not open an outbox entry")?;
+        Ok(serde_json::from_slice(&plain)?)
+    }
+
+    fn keep(&self, key: &[u8], message: &PendingMessage) {
+        if let (Some(storage), Ok(raw)) = (self.storage.as_ref(), self.encode(message)) {
+            let _ = storage.put(BACKLOG, key, &raw);
+        }
+    }
+
+    fn release(&self, key: &[u8]) {
+        if self.frozen.load(Ordering::Acquire) {
+            return;
+        }
+        if let Some(storage) = self.storage.as_ref() {
+            let _ = storage.delete(BACKLOG, key);
+        }
+    }
+
+    fn freeze(&self) {
+        self.frozen.store(true, Ordering::Release);
+    }
+
+    pub fn seed(&self, messages: &[PendingMessage]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(storage) = self.storage.as_ref() else {
+            return Ok(());
+        };
+        for (index, message) in messages.iter().enumerate() {
+            let mut key = [0u8; 16];
+            key[8..].copy_from_slice(&(index as u64).to_be_bytes());
+            storage.put(BACKLOG, &key, &self.encode(message)?)?;
+        }
+        Ok(())
+    }
+
+    pub fn pending(&self) -> Result<Vec<PendingMessage>, Box<dyn Error + Send + Sync>> {
+        match self.storage.as_ref() {
+            Some(storage) => storage
+                .iterate(BACKLOG)?
+                .iter()
+                .map(|(_, raw)| self.decode(raw))
+                .collect(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn take(&self) -> Result<Vec<PendingMessage>, Box<dyn Error + Send + Sync>> {
+        let Some(storage) = self.storage.as_ref() else {
+            return Ok(Vec::new());
+        };
+        let mut pending = Vec::new();
+        for (key, raw) in storage.iterate(BACKLOG)? {
+            pending.push(self.decode(&raw)?);
+            storage.delete(BACKLOG, &key)?;
+        }
+        Ok(pending)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Nonces(Arc<AtomicU64>);
+
+impl Nonces {
+    fn next(&self) -> u64 {
+        let now = Utc::now().timestamp_micros() as u64;
+        let step = |last: u64| (last + 1).max(now);
+        match self
+            .0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |last| Some(step(last)))
+        {
+            Ok(last) | Err(last) => step(last),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Ledger {
+    dead: DeadLetters,
+    stats: Stats,
+    breaker: CircuitBreaker,
+    fairness: FairScheduler,
+    nonces: Nonces,
+    backlog: Backlog,
+}
+
+struct PeerQueue {
+    next_seq: u64,
+    sender: Sender<Outgoing>,
+}
+
+pub struct Outbox {
+    session: u64,
+    queued: u64,
+    protocol: StreamProtocol,
+    control: Control,
+    relay_path: Option<Control>,
+    queues: HashMap<(PeerId, Option<String>), PeerQueue>,
+    ledger: Ledger,
+    #[cfg(feature = "ratchet")]
+    ratchets: Option<Ratchets>,
+}
+
+impl Outbox {
+    pub fn new(control: Control, protocol: StreamProtocol, dead: DeadLetters) -> Self {
+        Outbox {
+            session: Utc::now().timestamp_micros() as u64,
+            queued: 0,
+            protocol,
+            control,
+            relay_path: None,
+            queues: HashMap::new(),
+            ledger: Ledger {
+                dead,
+                stats: Stats::default(),
+                breaker: CircuitBreaker::default(),
+                fairness: FairScheduler::default(),
+                nonces: Nonces::default(),
+                backlog: Backlog::default(),
+            },
+            #[cfg(feature = "ratchet")]
+            ratchets: None,
+        }
+    }
+
+    pub fn with_stats(mut self, stats: Stats) -> Self {
+        self.ledger.stats = stats;
+        self
+    }
+
+    pub fn with_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.ledger.breaker = breaker;
+        self
+    }
+
+    pub fn with_backlog(mut self, backlog: Backlog) -> Self {
+        self.ledger.backlog = backlog;
+        self
+    }
+
+    pub fn with_relay_path(mut self, control: Control) -> Self {
+        self.relay_path = Some(control);
+        self
+    }
+
+    pub fn with_scheduling(mut self, scheduling: SendScheduling) -> Self {
+        self.ledger.fairness = FairScheduler::new(scheduling);
+        self
+    }
+
+    #[cfg(feature = "ratchet")]
+    pub fn with_ratchets(mut self, ratchets: Ratchets) -> Self {
+        self.ratchets = Some(ratchets);
+        self
+    }
+
+    #[cfg_attr(feature = "opentelemetry", allow(clippy::too_many_arguments))]
+    pub fn push(
+        &mut self,
+        peer: PeerId,
+        payload: Bytes,
+        guarantee: DeliveryGuarantee,
+        tag: Option<MessageTag>,
+        redundancy: Redundancy,
+        acked: Option<Sender<()>>,
+        #[cfg(feature = "opentelemetry")] trace: Option<TraceContext>,
+    ) -> Receipt {
+        self.enqueue(
+            peer,
+            Draft {
+                tag,
+                redundancy,
+                #[cfg(feature = "opentelemetry")]
+                trace,
+                ..Draft::new(payload, guarantee)
+            },
+            acked,
+        )
+    }
+
+    pub fn push_channel(
+        &mut self,
+        peer: PeerId,
+        label: String,
+        payload: Bytes,
+        acked: Option<Sender<()>>,
+    ) -> Receipt {
+        self.enqueue(
+            peer,
+            Draft {
+                channel: Some(label),
+                ..Draft::new(payload, DeliveryGuarantee::Ordered)
+            },
+            acked,
+        )
+    }
+
+    pub fn push_tracked(
+        &mut self,
+        peer: PeerId,
+        payload: Bytes,
+        guarantee: DeliveryGuarantee,
+    ) -> (Receipt, Receiver<()>) {
+        let (acked, receiver) = async_channel::bounded::<()>(1);
+        (
+            self.enqueue(peer, Draft::new(payload, guarantee), Some(acked)),
+            receiver,
+        )
+    }
+
+    pub fn recover(&mut self) -> Result<usize, Box<dyn Error + Send + Sync>> {
+        let pending = self.ledger.backlog.take()?;
+        let recovered = pending.len();
+        for message in pending {
+            let envelope = message.envelope;
+            let guarantee = match envelope.id {
+                Some(_) => DeliveryGuarantee::ExactlyOnce,
+                None => DeliveryGuarantee::Ordered,
+            };
+            self.enqueue(
+                message.peer,
+                Draft {
+                    id: envelope.id,
+                    channel: envelope.channel,
+                    tag: envelope.tag,
+                    redundancy: message.redundancy,
+                    #[cfg(feature = "opentelemetry")]
+                    trace: envelope.trace,
+                    ..Draft::new(message.payload, guarantee)
+                },
+                None,
+            );
+        }
+        Ok(recovered)
+    }
+
+    fn enqueue(&mut self, peer: PeerId, draft: Draft, acked: Option<Sender<()>>) -> Receipt {
+        let queue = self
+            .queues
+            .entry((peer, draft.channel.clone()))
+            .or_insert_with(|| {
+                let (sender, receiver) = async_channel::unbounded::<Outgoing>();
+                Runtime::spawn(deliver(
+                    self.control.clone(),
+                    self.relay_path.clone(),
+                    self.protocol.clone(),
+                    peer,
+                    receiver,
+                    self.ledger.clone(),
+                    #[cfg(feature = "ratchet")]
+                    self.ratchets.clone(),
+                ));
+                PeerQueue {
+                    next_seq: 0,
+                    sender,
+                }
+            });
+
+        let seq = queue.next_seq;
+        queue.next_seq += 1;
+        let id = draft
+            .id
+            .or_else(|| match (draft.guarantee, draft.redundancy) {
+                (DeliveryGuarantee::Ordered, Redundancy::Single) => None,
+                _ => Some(Uuid::new_v4()),
+            });
+        let envelope = Envelope {
+            session: self.session,
+            seq,
+            id,
+            nonce: None,
+            channel: draft.channel,
+            tag: draft.tag,
+            #[cfg(feature = "opentelemetry")]
+            trace: draft.trace,
+            #[cfg(feature = "ratchet")]
+            ratchet: None,
+            payload: draft.payload,
+        };
+
+        let mut key = [0u8; 16];
+        key[..8].copy_from_slice(&self.session.to_be_bytes());
+        key[8..].copy_from_slice(&self.queued.to_be_bytes());
+        self.queued += 1;
+        self.ledger.backlog.keep(
+            &key,
+            &PendingMessage {
+                peer,
+                envelope: envelope.clone(),
+                payload: envelope.payload.clone(),
+                redundancy: draft.redundancy,
+            },
+        );
+
+        let settled = (draft.redundancy == Redundancy::Multipath && self.relay_path.is_some())
+            .then(|| Arc::new(AtomicBool::new(false)));
+        let _ = queue.sender.try_send(Outgoing {
+            key,
+            envelope,
+            acked,
+            settled,
+            attempts: 0,
+            mirrored: false,
+        });
+        Receipt { seq, id }
+    }
+
+    pub fn forget(&mut self, peer: &PeerId) {
+        self.queues.retain(|(queued, _), queue| {
+            if queued == peer {
+                queue.sender.close();
+            }
+            queued != peer
+        });
+    }
+
+    pub fn close(&mut self) {
+        self.ledger.backlog.freeze();
+        for (_, queue) in self.queues.drain() {
+            queue.sender.close();
+        }
+    }
+}
+
+async fn exchange(
+    stream: &mut Stream,
+    envelope: &Envelope,
+    fairness: &FairScheduler,
+) -> io::Result<()> {
+    write_frame_fair(stream, &Frame::Message(envelope.clone()), fairness).await?;
+    match read_frame(stream).await? {
+        Frame::Ack { session, seq } if session == envelope.session && seq == envelope.seq => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Unexpected acknowledgement",
+        )),
+    }
+}
+
+impl Outgoing {
+    fn is_settled(&self) -> bool {
+        self.settled
+            .as_ref()
+            .is_some_and(|settled| settled.load(Ordering::Acquire))
+    }
+
+    fn settle(self, stats: &Stats, backlog: &Backlog) {
+        backlog.release(&self.key);
+        if self
+            .settled
+            .as_ref()
+            .is_none_or(|settled| !settled.swap(true, Ordering::AcqRel))
+        {
+            stats.record_sent(self.envelope.payload.len());
+            if let Some(acked) = self.acked.as_ref() {
+                let _ = acked.try_send(());
+            }
+        }
+    }
+}
+
+struct Mirror {
+    control: Control,
+    protocol: StreamProtocol,
+    peer: PeerId,
+    stats: Stats,
+    nonces: Nonces,
+    #[cfg(feature = "ratchet")]
+    ratchets: Option<Ratchets>,
+}
+
+impl Mirror {
+    fn lead(&self, window: &mut VecDeque<Outgoing>) {
+        let Some(outgoing) = window.front_mut() else {
+            return;
+        };
+        let Some(settled) = outgoing.settled.clone() else {
+            return;
+        };
+        if outgoing.mirrored {
+            return;
+        }
+
+        outgoing.mirrored = true;
+        Runtime::spawn(
+            Duplicate {
+                control: self.control.clone(),
+                protocol: self.protocol.clone(),
+                peer: self.peer,
+                envelope: outgoing.envelope.clone(),
+                settled,
+                acked: outgoing.acked.clone(),
+                stats: self.stats.clone(),
+                nonces: self.nonces.clone(),
+            }
+            .send(
+                #[cfg(feature = "ratchet")]
+                self.ratchets.clone(),
+            ),
+        );
+    }
+}
+
+async fn transmit(
+    stream: &mut Stream,
+    window: &mut VecDeque<Outgoing>,
+    mirror: Option<&Mirror>,
+    fairness: &FairScheduler,
+    stats: &Stats,
+    nonces: &Nonces,
+    backlog: &Backlog,
+) -> io::Result<()> {
+    let mut last = None;
+    for outgoing in window.iter_mut() {
+        if outgoing.is_settled() {
+            continue;
+        }
+        outgoing.envelope.nonce = Some(nonces.next());
+        write_frame_fair(stream, &Frame::Message(outgoing.envelope.clone()), fairness).await?;
+        last = Some((outgoing.envelope.session, outgoing.envelope.seq));
+    }
+    let Some((session, last)) = last else {
+        for outgoing in window.drain(..) {
+            outgoing.settle(stats, backlog);
+        }
+        return Ok(());
+    };
+
+    loop {
+        let acked = match read_frame(stream).await? {
+            Frame::Ack {
+                session: acked,
+                seq,
+            } if acked == session && seq <= last => seq,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Unexpected acknowledgement",
+                ))
+            }
+        };
+        while window
+            .front()
+            .is_some_and(|outgoing| outgoing.envelope.seq <= acked)
+        {
+            if let Some(outgoing) = window.pop_front() {
+                outgoing.settle(stats, backlog);
+            }
+        }
+        if let Some(mirror) = mirror {
+            mirror.lead(window);
+        }
+        if acked == last {
+            for outgoing in window.drain(..) {
+                outgoing.settle(stats, backlog);
+            }
+            return Ok(());
+        }
+    }
+}
+
+fn abandon(
+    peer: PeerId,
+    window: &mut VecDeque<Outgoing>,
+    queue: &Receiver<Outgoing>,
+    dead: &DeadLetters,
+    backlog: &Backlog,
+    reason: impl Fn(u32) -> DeadReason,
+) {
+    for outgoing in window.drain(..) {
+        backlog.release(&outgoing.key);
+        dead.bury(peer, outgoing.envelope, reason(outgoing.attempts));
+    }
+    while let Ok(rest) = queue.try_recv() {
+        backlog.release(&rest.key);
+        dead.bury(peer, rest.envelope, reason(rest.attempts));
+    }
+}
+
+async fn deliver(
+    mut control: Control,
+    relay_path: Option<Control>,
+    protocol: StreamProtocol,
+    peer: PeerId,
+    queue: Receiver<Outgoing>,
+    ledger: Ledger,
+    #[cfg(feature = "ratchet")] ratchets: Option<Ratchets>,
+) {
+    let Ledger {
+        dead,
+        stats,
+        breaker,
+        fairness,
+        nonces,
+        backlog,
+    } = ledger;
+    let mirror = relay_path.map(|control| Mirror {
+        control,
+        protocol: protocol.clone(),
+        peer,
+        stats: stats.clone(),
+        nonces: nonces.clone(),
+        #[cfg(feature = "ratchet")]
+        ratchets: ratchets.clone(),
+    });
+    let mut stream: Option<Stream> = None;
+    let mut window: VecDeque<Outgoing> = VecDeque::new();
+    let mut backoff = RETRY;
+    let mut failures = 0;
+    let mut cause = None;
+    loop {
+        if window.is_empty() {
+            match queue.recv().await {
+                Ok(outgoing) => window.push_back(outgoing),
+                Err(_) => return,
+            }
+        }
+        while window.len() < SEND_WINDOW {
+            match queue.try_recv() {
+                Ok(outgoing) => window.push_back(outgoing),
+                Err(_) => break,
+            }
+        }
+        window.retain(|outgoing| {
+            let settled = outgoing.is_settled();
+            if settled {
+                backlog.release(&outgoing.key);
+            }
+            !settled
+        });
+        if window.is_empty() {
+            continue;
+        }
+        if queue.is_closed() {
+            abandon(peer, &mut window, &queue, &dead, &backlog, |attempts| {
+                DeadReason::Undeliverable { attempts }
+            });
+            return;
+        }
+        if failures >= MAX_FAILURES {
+            abandon(peer, &mut window, &queue, &dead, &backlog, |attempts| {
+                cause
+                    .clone()
+                    .unwrap_or(DeadReason::Undeliverable { attempts })
+            });
+            stream = None;
+            failures = 0;
+            backoff = RETRY;
+            continue;
+        }
+        if !breaker.allow(&peer) {
+            failures += 1;
+            cause = Some(DeadReason::CircuitOpen);
+            sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            continue;
+        }
+
+        #[cfg(feature = "ratchet")]
+        if let Some(ratchets) = ratchets.as_ref() {
+            let mut sealed = true;
+            for outgoing in window.iter_mut() {
+                if ratchets
+                    .seal(&mut control, peer, &mut outgoing.envelope)
+                    .await
+                    .is_err()
+                {
+                    sealed = false;
+                    break;
+                }
+            }
+            if !sealed {
+                failures += 1;
+                cause = Some(DeadReason::HandshakeFailed);
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                continue;
+            }
+        }
+
+        if let Some(mirror) = mirror.as_ref() {
+            mirror.lead(&mut window);
+        }
+
+        let current = match stream.as_mut() {
+            Some(current) => current,
+            None => match control.open_stream(peer, protocol.clone()).await {
+                Ok(opened) => stream.insert(opened),
+                Err(_) => {
+                    breaker.failure(&peer);
+                    failures += 1;
+                    cause = None;
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                    continue;
+                }
+            },
+        };
+
+        for outgoing in window.iter_mut() {
+            outgoing.attempts += 1;
+        }
+        match transmit(
+            current,
+            &mut window,
+            mirror.as_ref(),
+            &fairness,
+            &stats,
+            &nonces,
+            &backlog,
+        )
+        .await
+        {
+            Ok(()) => {
+                breaker.success(&peer);
+                backoff = RETRY;
+                failures = 0;
+            }
+            Err(_) => {
+                breaker.failure(&peer);
+                failures += 1;
+                cause = None;
+                stream = None;
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+struct Duplicate {
+    control: Control,
+    protocol: StreamProtocol,
+    peer: PeerId,
+    envelope: Envelope,
+    settled: Arc<AtomicBool>,
+    acked: Option<Sender<()>>,
+    stats: Stats,
+    nonces: Nonces,
+}
+
+impl Duplicate {
+    async fn send(mut self, #[cfg(feature = "ratchet")] ratchets: Option<Ratchets>) {
+        let deadline = Utc::now() + DUPLICATE_TIMEOUT;
+        while !self.settled.load(Ordering::Acquire) && Utc::now() < deadline {
+            #[cfg(feature = "ratchet")]
+            if let Some(ratchets) = ratchets.as_ref() {
+                if ratchets
+                    .seal(&mut self.control, self.peer, &mut self.envelope)
+                    .await
+                    .is_err()
+                {
+                    sleep(RETRY).await;
+                    continue;
+                }
+            }
+
+            let delivered = match self
+                .control
+                .open_stream(self.peer, self.protocol.clone())
+                .await
+            {
+                Ok(mut stream) => {
+                    self.envelope.nonce = Some(self.nonces.next());
+                    exchange(&mut stream, &self.envelope, &FairScheduler::default())
+                        .await
+                        .is_ok()
+                }
+                Err(_) => false,
+            };
+            if delivered {
+                if !self.settled.swap(true, Ordering::AcqRel) {
+                    self.stats.record_sent(self.envelope.payload.len());
+                    if let Some(acked) = self.acked.as_ref() {
+                        let _ = acked.try_send(());
+                    }
+                }
+                return;
+            }
+            sleep(RETRY).await;
+        }
+    }
+}
+
+#[derive(Default)]
+struct ReplayWindow {
+    highest: u64,
+    recent: BTreeSet<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Freshness {
+    New,
+    Duplicate,
+    Replayed,
+}
+
+impl ReplayWindow {
+    fn check(&mut self, nonce: u64) -> Freshness {
+        if nonce < self.highest.saturating_sub(REPLAY_WINDOW) {
+            return Freshness::Replayed;
+        }
+        if !self.recent.insert(nonce) {
+            return Freshness::Duplicate;
+        }
+
+        self.highest = self.highest.max(nonce);
+        self.recent = self
+            .recent
+            .split_off(&self.highest.saturating_sub(REPLAY_WINDOW));
+        Freshness::New
+    }
+}
+
+#[derive(Default)]
+struct Seen {
+    sequences: HashMap<(PeerId, Option<String>), (u64, u64)>,
+    ids: HashSet<Uuid>,
+    order: VecDeque<Uuid>,
+    windows: HashMap<PeerId, ReplayWindow>,
+}
+
+impl Seen {
+    fn remember(&mut self, id: Uuid) -> bool {
+        if !self.ids.insert(id) {
+            return false;
+        }
+
+        self.order.push_back(id);
+        if self.order.len() > DEDUP_CAPACITY {
+            if let Some(expired) = self.order.pop_front() {
+                self.ids.remove(&expired);
+            }
+        }
+        true
+    }
+
+    fn advance(&mut self, peer: PeerId, envelope: &Envelope) -> bool {
+        let key = (peer, envelope.channel.clone());
+        let current = (envelope.session, envelope.seq);
+        if self
+            .sequences
+            .get(&key)
+            .is_some_and(|last| *last >= current)
+        {
+            return false;
+        }
+        self.sequences.insert(key, current);
+        true
+    }
+}
+
+#[derive(Clone)]
+pub struct Delivery {
+    seen: Arc<Mutex<Seen>>,
+    events: Sender<Event>,
+    reports: Sender<StreamReport>,
+    limit: Arc<Semaphore>,
+    channels: Channels,
+    schemas: Schemas,
+    dead: DeadLetters,
+    acl: AccessControl,
+    stats: Stats,
+    max_message: usize,
+    #[cfg(feature = "ratchet")]
+    ratchets: Option<Ratchets>,
+}
+
+#[derive(Clone, Debug)]
+pub struct StreamReport {
+    pub peer: PeerId,
+    pub frames: u64,
+    pub bytes: u64,
+    pub replays: u64,
+    pub oversized: u64,
+    pub error: Option<String>,
+}
+
+impl Delivery {
+    pub fn new(
+        events: Sender<Event>,
+        channels: Channels,
+        schemas: Schemas,
+        dead: DeadLetters,
+        acl: AccessControl,
+        concurrency: usize,
+    ) -> (Self, Receiver<StreamReport>) {
+        let (reports, receiver) = async_channel::unbounded::<StreamReport>();
+        (
+            Delivery {
+                seen: Arc::new(Mutex::new(Seen::default())),
+                events,
+                reports,
+                limit: Arc::new(Semaphore::new(concurrency.max(1))),
+                channels,
+                schemas,
+                dead,
+                acl,
+                stats: Stats::default(),
+                max_message: MAX_FRAME,
+                #[cfg(feature = "ratchet")]
+                ratchets: None,
+            },
+            receiver,
+        )
+    }
+
+    pub fn with_stats(mut self, stats: Stats) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    pub fn with_max_message(mut self, max_message: usize) -> Self {
+        self.max_message = max_message;
+        self
+    }
+
+    #[cfg(feature = "ratchet")]
+    pub fn with_ratchets(mut self, ratchets: Ratchets) -> Self {
+        self.ratchets = Some(ratchets);
+        self
+    }
+
+    #[cfg(feature = "ratchet")]
+    fn unseal(&self, peer: PeerId, mut envelope: Envelope) -> Option<Envelope> {
+        let Some(ratchets) = self.ratchets.as_ref() else {
+            return Some(envelope);
+        };
+
+        match ratchets.open(peer, &mut envelope) {
+            Ok(()) => Some(envelope),
+            Err(e) => {
+                self.dead
+                    .bury(peer, envelope, DeadReason::Undecodable(e.to_string()));
+                None
+            }
+        }
+    }
+
+    fn is_new(&self, peer: PeerId, envelope: &Envelope) -> Freshness {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(nonce) = envelope.nonce {
+            match seen.windows.entry(peer).or_default().check(nonce) {
+                Freshness::New => {}
+                freshness => return freshness,
+            }
+        }
+        let fresh = match envelope.id {
+            Some(id) => seen.remember(id),
+            None => seen.advance(peer, envelope),
+        };
+        match fresh {
+            true => Freshness::New,
+            false => Freshness::Duplicate,
+        }
+    }
+
+    fn decode(&self, peer: PeerId, envelope: Envelope) -> Option<Event> {
+        let Some(tag) = envelope.tag.clone() else {
+            return Some(Event::Message {
+                peer,
+                seq: envelope.seq,
+                id: envelope.id,
+                #[cfg(feature = "opentelemetry")]
+                trace: envelope.trace,
+                payload: envelope.payload,
+            });
+        };
+
+        if !self
+            .acl
+            .check(peer, Permission::MessageType(tag.name.clone()))
+        {
+            self.dead.bury(peer, envelope, DeadReason::Forbidden);
+            return None;
+        }
+        if !self.schemas.knows(&tag) {
+            self.dead.bury(peer, envelope, DeadReason::UnknownType);
+            return None;
+        }
+        match serde_json::from_slice::<Value>(&envelope.payload) {
+            Ok(value) => Some(Event::TypedMessage {
+                peer,
+                seq: envelope.seq,
+                id: envelope.id,
+                tag,
+                value,
+            }),
+            Err(e) => {
+                self.dead
+                    .bury(peer, envelope, DeadReason::Undecodable(e.to_string()));
+                None
+            }
+        }
+    }
+
+    pub fn accept(&self, peer: PeerId, stream: Stream) {
+        Runtime::spawn(self.clone().receive(peer, stream));
+    }
+
+    async fn receive(self, peer: PeerId, mut stream: Stream) {
+        let _permit = self.limit.acquire().await;
+
+        let mut report = StreamReport {
+            peer,
+            frames: 0,
+            bytes: 0,
+            replays: 0,
+            oversized: 0,
+            error: None,
+        };
+        let limit = frame_limit(self.max_message);
+        loop {
+            let frame = match read_frame_limited(&mut stream, limit).await {
+                Ok(frame) => frame,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => {
+                    if is_oversized(&e) {
+                        report.oversized += 1;
+                    }
+                    report.error = Some(e.to_string());
+                    break;
+                }
+            };
+            let Frame::Message(envelope) = frame else {
+                continue;
+            };
+            if envelope.payload.len() > self.max_message {
+                report.oversized += 1;
+                report.error = Some("Payload exceeds the maximum message size".to_string());
+                break;
+            }
+            report.frames += 1;
+            report.bytes += envelope.payload.len() as u64;
+            self.stats.record_received(envelope.payload.len());
+
+            let ack = Frame::Ack {
+                session: envelope.session,
+                seq: envelope.seq,
+            };
+            let fresh = match self.is_new(peer, &envelope) {
+                Freshness::Replayed => {
+                    report.replays += 1;
+                    report.error = Some("Replayed frame".to_string());
+                    let _ = self.events.send(Event::ReplayDetected { peer }).await;
+                    break;
+                }
+                freshness => freshness == Freshness::New,
+            };
+            #[cfg(feature = "ratchet")]
+            let envelope = match fresh.then(|| self.unseal(peer, envelope)).flatten() {
+                Some(envelope) => envelope,
+                None => {
+                    if let Err(e) = write_frame(&mut stream, &ack).await {
+                        report.error = Some(e.to_string());
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let mut envelope = envelope;
+            match (fresh, envelope.channel.take()) {
+                (false, _) => {}
+                (true, Some(label)) => self.channels.deliver(peer, label, envelope.payload),
+                (true, None) => {
+                    if let Some(event) = self.decode(peer, envelope) {
+                        let _ = self.events.send(event).await;
+                    }
+                }
+            }
+
+            if let Err(e) = write_frame(&mut stream, &ack).await {
+                report.error = Some(e.to_string());
+                break;
+            }
+        }
+
+        let _ = self.reports.send(report).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    fn pending(seq: u64) -> PendingMessage {
+        PendingMessage {
+            peer: PeerId::random(),
+            envelope: Envelope {
+                session: 1,
+                seq,
+                id: None,
+                nonce: None,
+                channel: None,
+                tag: None,
+                #[cfg(feature = "opentelemetry")]
+                trace: None,
+                #[cfg(feature = "ratchet")]
+                ratchet: None,
+                payload: Bytes::new(),
+            },
+            payload: Bytes::from(seq.to_be_bytes().to_vec()),
+            redundancy: Redundancy::Single,
+        }
+    }
+
+    #[test]
+    fn backlog_keeps_messages_until_released() {
+        let backlog = Backlog::new(Arc::new(MemoryStorage::new()));
+        backlog.keep(&[0, 1], &pending(1));
+        backlog.keep(&[0, 2], &pending(2));
+        backlog.release(&[0, 1]);
+        assert_eq!(backlog.pending().unwrap().len(), 1);
+
+        backlog.freeze();
+        backlog.release(&[0, 2]);
+        let taken = backlog.take().unwrap();
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].envelope.seq, 2);
+        assert_eq!(taken[0].payload.as_ref(), &2u64.to_be_bytes());
+        assert!(backlog.pending().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "ratchet")]
+    #[test]
+    fn backlog_is_sealed_at_rest() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let key = Keypair::generate_ed25519();
+        let backlog = Backlog::for_node(storage.clone(), &key);
+        backlog.keep(&[0, 7], &pending(7));
+
+        let (_, raw) = storage.iterate(BACKLOG).unwrap().remove(0);
+        assert!(serde_json::from_slice::<PendingMessage>(&raw).is_err());
+        assert_eq!(backlog.pending().unwrap()[0].envelope.seq, 7);
+        assert!(Backlog::for_node(storage, &Keypair::generate_ed25519())
+            .pending()
+            .is_err());
+    }
+
+    #[test]
+    fn seeded_messages_are_recovered_in_order() {
+        let backlog = Backlog::new(Arc::new(MemoryStorage::new()));
+        backlog.seed(&[pending(3), pending(1), pending(2)]).unwrap();
+        let taken: Vec<u64> = backlog
+            .take()
+            .unwrap()
+            .iter()
+            .map(|message| message.envelope.seq)
+            .collect();
+        assert_eq!(taken, vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn sequences_keep_only_the_latest_session() {
+        let mut seen = Seen::default();
+        let peer = PeerId::random();
+        let envelope = |session, seq| Envelope {
+            session,
+            seq,
+            ..pending(0).envelope
+        };
+
+        assert!(seen.advance(peer, &envelope(1, 1)));
+        assert!(!seen.advance(peer, &envelope(1, 1)));
+        assert!(seen.advance(peer, &envelope(2, 1)));
+        assert!(!seen.advance(peer, &envelope(1, 2)));
+        assert_eq!(seen.sequences.len(), 1);
+    }
+
+    #[test]
+    fn replay_window_accepts_the_boundary() {
+        let mut window = ReplayWindow::default();
+        let highest = REPLAY_WINDOW * 2;
+        assert_eq!(window.check(highest), Freshness::New);
+        assert_eq!(window.check(highest - REPLAY_WINDOW), Freshness::New);
+        assert_eq!(
+            window.check(highest - REPLAY_WINDOW - 1),
+            Freshness::Replayed
+        );
+    }
+
+    #[test]
+    fn replay_window_flags_duplicates() {
+        let mut window = ReplayWindow::default();
+        assert_eq!(window.check(10), Freshness::New);
+        assert_eq!(window.check(5), Freshness::New);
+        assert_eq!(window.check(10), Freshness::Duplicate);
+        assert_eq!(window.check(5), Freshness::Duplicate);
+        assert_eq!(window.check(11), Freshness::New);
+    }
+
+    #[test]
+    fn replay_window_prunes_expired_nonces() {
+        let mut window = ReplayWindow::default();
+        for nonce in 1..=10 {
+            assert_eq!(window.check(nonce), Freshness::New);
+        }
+        assert_eq!(window.recent.len(), 10);
+
+        let highest = REPLAY_WINDOW + 5;
+        assert_eq!(window.check(highest), Freshness::New);
+        assert_eq!(
+            window.recent.iter().copied().collect::<Vec<_>>(),
+            vec![5, 6, 7, 8, 9, 10, highest]
+        );
+        assert_eq!(window.check(4), Freshness::Replayed);
+        assert_eq!(window.check(5), Freshness::Duplicate);
+    }
+
+    #[test]
+    fn nonces_increase_across_clones() {
+        let before = Utc::now().timestamp_micros() as u64;
+        let nonces = Nonces::default();
+        let other = nonces.clone();
+        let first = nonces.next();
+        let second = other.next();
+        let third = nonces.next();
+        assert!(before <= first && first < second && second < third);
+    }
+}
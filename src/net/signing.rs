@@ -0,0 +1,36 @@
+use libp2p::{
+    identity::{Keypair, PublicKey, SigningError},
+    PeerId,
+};
+use serde::{Deserialize, Serialize};
+
+const APPLICATION_DOMAIN: &str = "modius-app";
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Signature {
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+fn message(domain: &str, data: &[u8]) -> Vec<u8> {
+    let mut message = format!("{APPLICATION_DOMAIN}:{}:{domain}:", domain.len()).into_bytes();
+    message.extend_from_slice(data);
+    message
+}
+
+impl Signature {
+    pub fn sign(key: &Keypair, domain: &str, data: &[u8]) -> Result<Self, SigningError> {
+        Ok(Signature {
+            public_key: key.public().encode_protobuf(),
+            signature: key.sign(&message(domain, data))?,
+        })
+    }
+
+    pub fn verify(&self, peer: &PeerId, domain: &str, data: &[u8]) -> bool {
+        let Ok(key) = PublicKey::try_decode_protobuf(&self.public_key) else {
+            return false;
+        };
+
+        key.to_peer_id() == *peer && key.verify(&message(domain, data), &self.signature)
+    }
+}
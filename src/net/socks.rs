@@ -0,0 +1,176 @@
+use std::{
+    error::Error,
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    str::FromStr,
+    task::{Context, Poll},
+};
+
+use libp2p::{
+    core::{
+        multiaddr::Protocol,
+        transport::{DialOpts, ListenerId, TransportError, TransportEvent},
+        Transport,
+    },
+    futures::{future::BoxFuture, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, FutureExt},
+    Multiaddr,
+};
+
+enum Host {
+    Ip(IpAddr),
+    Domain(String),
+}
+
+pub struct Socks5Transport<T> {
+    inner: T,
+    proxy: Multiaddr,
+}
+
+impl<T> Socks5Transport<T> {
+    pub fn new(inner: T, proxy: Multiaddr) -> Self {
+        Socks5Transport { inner, proxy }
+    }
+}
+
+pub fn proxy_address<A: AsRef<str>>(proxy: A) -> Result<Multiaddr, Box<dyn Error + Send + Sync>> {
+    if let Ok(address) = SocketAddr::from_str(proxy.as_ref()) {
+        Ok(Multiaddr::empty()
+            .with(address.ip().into())
+            .with(Protocol::Tcp(address.port())))
+    } else {
+        Ok(Multiaddr::from_str(proxy.as_ref())?)
+    }
+}
+
+fn target(address: &Multiaddr) -> Option<(Host, u16)> {
+    let mut protocols = address.iter();
+    let host = match protocols.next()? {
+        Protocol::Ip4(ip) => Host::Ip(ip.into()),
+        Protocol::Ip6(ip) => Host::Ip(ip.into()),
+        Protocol::Dns(name) | Protocol::Dns4(name) | Protocol::Dns6(name) => {
+            Host::Domain(name.to_string())
+        }
+        onion @ Protocol::Onion3(_) => {
+            let encoded = onion.to_string();
+            let (hash, port) = encoded.strip_prefix("/onion3/")?.split_once(':')?;
+            return Some((
+                Host::Domain(format!("{}.onion", hash.to_lowercase())),
+                port.parse().ok()?,
+            ));
+        }
+        _ => return None,
+    };
+
+    match protocols.next()? {
+        Protocol::Tcp(port) => Some((host, port)),
+        _ => None,
+    }
+}
+
+async fn connect<S: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: S,
+    host: Host,
+    port: u16,
+) -> io::Result<S> {
+    stream.write_all(&[5, 1, 0]).await?;
+    let mut method = [0u8; 2];
+    stream.read_exact(&mut method).await?;
+    if method != [5, 0] {
+        return Err(io::Error::other(
+            "SOCKS5 proxy refused unauthenticated access",
+        ));
+    }
+
+    let mut request = vec![5, 1, 0];
+    match host {
+        Host::Ip(IpAddr::V4(ip)) => {
+            request.push(1);
+            request.extend(ip.octets());
+        }
+        Host::Ip(IpAddr::V6(ip)) => {
+            request.push(4);
+            request.extend(ip.octets());
+        }
+        Host::Domain(name) => {
+            let length = u8::try_from(name.len())
+                .map_err(|_| io::Error::other("SOCKS5 host name is too long"))?;
+            request.push(3);
+            request.push(length);
+            request.extend(name.as_bytes());
+        }
+    }
+    request.extend(port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != 0 {
+        return Err(io::Error::other(format!(
+            "SOCKS5 proxy failed to connect (reply code {})",
+            reply[1]
+        )));
+    }
+
+    let bound = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut length = [0u8; 1];
+            stream.read_exact(&mut length).await?;
+            length[0] as usize
+        }
+        _ => {
+            return Err(io::Error::other(
+                "SOCKS5 proxy sent an invalid address type",
+            ))
+        }
+    };
+    let mut remainder = vec![0u8; bound + 2];
+    stream.read_exact(&mut remainder).await?;
+
+    Ok(stream)
+}
+
+impl<T> Transport for Socks5Transport<T>
+where
+    T: Transport<Error = io::Error> + Unpin,
+    T::Dial: Send + 'static,
+    T::Output: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    type Output = T::Output;
+    type Error = io::Error;
+    type ListenerUpgrade = T::ListenerUpgrade;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(
+        &mut self,
+        id: ListenerId,
+        addr: Multiaddr,
+    ) -> Result<(), TransportError<Self::Error>> {
+        self.inner.listen_on(id, addr)
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        self.inner.remove_listener(id)
+    }
+
+    fn dial(
+        &mut self,
+        addr: Multiaddr,
+        opts: DialOpts,
+    ) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let Some((host, port)) = target(&addr) else {
+            return Err(TransportError::MultiaddrNotSupported(addr));
+        };
+        let connection = self.inner.dial(self.proxy.clone(), opts)?;
+        Ok(async move { connect(connection.await?, host, port).await }.boxed())
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll(cx)
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+const RETAINED_SECONDS: i64 = 60 * 60;
+const MAX_SAMPLES: usize = 100_000;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Bucket {
+    second: i64,
+    messages_sent: u64,
+    messages_received: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    connects: u64,
+    disconnects: u64,
+    cache_hits: u64,
+    cache_misses: u64,
+}
+
+#[derive(Default)]
+struct Recorded {
+    buckets: VecDeque<Bucket>,
+    latencies: VecDeque<(i64, f64)>,
+    lags: VecDeque<(i64, f64)>,
+}
+
+impl Recorded {
+    fn bucket(&mut self, second: i64) -> &mut Bucket {
+        if self
+            .buckets
+            .back()
+            .is_none_or(|bucket| bucket.second != second)
+        {
+            self.buckets.push_back(Bucket {
+                second,
+                ..Bucket::default()
+            });
+        }
+        self.expire(second);
+        self.buckets.back_mut().expect("A bucket was just pushed")
+    }
+
+    fn sample(samples: &mut VecDeque<(i64, f64)>, second: i64, value: f64) {
+        samples.push_back((second, value));
+        while samples.len() > MAX_SAMPLES
+            || samples
+                .front()
+                .is_some_and(|(at, _)| second - at >= RETAINED_SECONDS)
+        {
+            samples.pop_front();
+        }
+    }
+
+    fn expire(&mut self, second: i64) {
+        while self
+            .buckets
+            .front()
+            .is_some_and(|bucket| second - bucket.second >= RETAINED_SECONDS)
+        {
+            self.buckets.pop_front();
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StatsWindow {
+    pub seconds: i64,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub connects: u64,
+    pub disconnects: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    pub command_latency_p50_ms: Option<f64>,
+    pub command_latency_p95_ms: Option<f64>,
+    pub loop_lag_p50_ms: Option<f64>,
+    pub loop_lag_p95_ms: Option<f64>,
+    pub loop_lag_max_ms: Option<f64>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NodeStats {
+    pub one_minute: StatsWindow,
+    pub five_minutes: StatsWindow,
+    pub one_hour: StatsWindow,
+}
+
+fn percentile(sorted: &[f64], fraction: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    sorted
+        .get(((last as f64) * fraction).round() as usize)
+        .copied()
+}
+
+fn window_samples(samples: &VecDeque<(i64, f64)>, since: i64) -> Vec<f64> {
+    let mut values: Vec<f64> = samples
+        .iter()
+        .filter(|(at, _)| *at > since)
+        .map(|(_, value)| *value)
+        .collect();
+    values.sort_by(f64::total_cmp);
+    values
+}
+
+fn millis(delta: TimeDelta) -> f64 {
+    delta.num_microseconds().unwrap_or(i64::MAX) as f64 / 1000.0
+}
+
+#[derive(Clone, Default)]
+pub struct Stats {
+    recorded: Arc<Mutex<Recorded>>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats::default()
+    }
+
+    fn update(&self, apply: impl FnOnce(&mut Bucket)) {
+        let mut recorded = self.recorded.lock().unwrap_or_else(|e| e.into_inner());
+        apply(recorded.bucket(Utc::now().timestamp()));
+    }
+
+    pub fn record_sent(&self, bytes: usize) {
+        self.update(|bucket| {
+            bucket.messages_sent += 1;
+            bucket.bytes_sent += bytes as u64;
+        });
+    }
+
+    pub fn record_received(&self, bytes: usize) {
+        self.update(|bucket| {
+            bucket.messages_received += 1;
+            bucket.bytes_received += bytes as u64;
+        });
+    }
+
+    pub fn record_connect(&self) {
+        self.update(|bucket| bucket.connects += 1);
+    }
+
+    pub fn record_disconnect(&self) {
+        self.update(|bucket| bucket.disconnects += 1);
+    }
+
+    pub fn record_cache(&self, hit: bool) {
+        self.update(|bucket| match hit {
+            true => bucket.cache_hits += 1,
+            false => bucket.cache_misses += 1,
+        });
+    }
+
+    pub fn record_latency(&self, latency: TimeDelta) {
+        let mut recorded = self.recorded.lock().unwrap_or_else(|e| e.into_inner());
+        Recorded::sample(
+            &mut recorded.latencies,
+            Utc::now().timestamp(),
+            millis(latency),
+        );
+    }
+
+    pub fn record_lag(&self, lag: TimeDelta) {
+        let mut recorded = self.recorded.lock().unwrap_or_else(|e| e.into_inner());
+        Recorded::sample(
+            &mut recorded.lags,
+            Utc::now().timestamp(),
+            millis(lag.max(TimeDelta::zero())),
+        );
+    }
+
+    fn window(&self, recorded: &Recorded, now: i64, seconds: i64) -> StatsWindow {
+        let since = now - seconds;
+        let mut window = StatsWindow {
+            seconds,
+            ..StatsWindow::default()
+        };
+        for bucket in recorded
+            .buckets
+            .iter()
+            .filter(|bucket| bucket.second > since)
+        {
+            window.messages_sent += bucket.messages_sent;
+            window.messages_received += bucket.messages_received;
+            window.bytes_sent += bucket.bytes_sent;
+            window.bytes_received += bucket.bytes_received;
+            window.connects += bucket.connects;
+            window.disconnects += bucket.disconnects;
+            window.cache_hits += bucket.cache_hits;
+            window.cache_misses += bucket.cache_misses;
+        }
+
+        let latencies = window_samples(&recorded.latencies, since);
+        window.command_latency_p50_ms = percentile(&latencies, 0.5);
+        window.command_latency_p95_ms = percentile(&latencies, 0.95);
+        let lags = window_samples(&recorded.lags, since);
+        window.loop_lag_p50_ms = percentile(&lags, 0.5);
+        window.loop_lag_p95_ms = percentile(&lags, 0.95);
+        window.loop_lag_max_ms = lags.last().copied();
+        window
+    }
+
+    pub fn snapshot(&self) -> NodeStats {
+        let now = Utc::now().timestamp();
+        let recorded = self.recorded.lock().unwrap_or_else(|e| e.into_inner());
+        NodeStats {
+            one_minute: self.window(&recorded, now, 60),
+            five_minutes: self.window(&recorded, now, 5 * 60),
+            one_hour: self.window(&recorded, now, RETAINED_SECONDS),
+        }
+    }
+}
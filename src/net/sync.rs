@@ -0,0 +1,492 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io,
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use libp2p::{futures::AsyncWriteExt, PeerId, Stream, StreamProtocol};
+use libp2p_stream::Control;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blobs::{digest, CHUNK_SIZE},
+    runtime::{Executor, Runtime},
+};
+
+use super::{
+    command::CommandWrapper,
+    wire::{read_frame, write_frame, Frame},
+};
+
+pub const SYNC_PROTOCOL: StreamProtocol = StreamProtocol::new("/modius/sync/1.0.0");
+
+pub trait SyncSource: Send + Sync {
+    fn list(&self) -> io::Result<Vec<String>>;
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()>;
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MemorySource {
+    files: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl MemorySource {
+    pub fn new() -> Self {
+        MemorySource::default()
+    }
+}
+
+impl SyncSource for MemorySource {
+    fn list(&self) -> io::Result<Vec<String>> {
+        let files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(files.keys().cloned().collect())
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.to_string()))
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        let mut files = self.files.lock().unwrap_or_else(|e| e.into_inner());
+        files.insert(path.to_string(), data.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub struct DirectorySource {
+    root: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DirectorySource {
+    pub fn new<P: Into<std::path::PathBuf>>(root: P) -> Self {
+        DirectorySource { root: root.into() }
+    }
+
+    fn resolve(&self, path: &str) -> io::Result<std::path::PathBuf> {
+        let relative = std::path::Path::new(path);
+        if relative
+            .components()
+            .any(|component| !matches!(component, std::path::Component::Normal(_)))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Sync paths must be relative",
+            ));
+        }
+        Ok(self.root.join(relative))
+    }
+
+    fn walk(&self, directory: &std::path::Path, files: &mut Vec<String>) -> io::Result<()> {
+        for entry in std::fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.walk(&path, files)?;
+            } else if let Ok(relative) = path.strip_prefix(&self.root) {
+                let parts: Vec<String> = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                    .collect();
+                files.push(parts.join("/"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SyncSource for DirectorySource {
+    fn list(&self) -> io::Result<Vec<String>> {
+        let mut files = Vec::new();
+        self.walk(&self.root, &mut files)?;
+        Ok(files)
+    }
+
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        std::fs::read(self.resolve(path)?)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> io::Result<()> {
+        let target = self.resolve(path)?;
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(target, data)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FileEntry {
+    pub path: String,
+    pub size: u64,
+    pub hash: String,
+    pub chunks: Vec<String>,
+}
+
+impl FileEntry {
+    fn build(path: String, data: &[u8]) -> Self {
+        let chunks: Vec<String> = data.chunks(CHUNK_SIZE).map(digest).collect();
+        FileEntry {
+            path,
+            size: data.len() as u64,
+            hash: merkle_root(chunks.clone()),
+            chunks,
+        }
+    }
+
+    pub fn missing_from(&self, existing: Option<&FileEntry>) -> Vec<usize> {
+        let known: HashSet<&str> = existing
+            .iter()
+            .flat_map(|file| file.chunks.iter().map(String::as_str))
+            .collect();
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, hash)| !known.contains(hash.as_str()))
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    pub root: String,
+    pub files: Vec<FileEntry>,
+}
+
+fn merkle_root(mut level: Vec<String>) -> String {
+    if level.is_empty() {
+        return digest(&[]);
+    }
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| digest(pair.concat().as_bytes()))
+            .collect();
+    }
+    level.remove(0)
+}
+
+impl Manifest {
+    pub fn build(source: &dyn SyncSource) -> io::Result<Self> {
+        let mut paths = source.list()?;
+        paths.sort();
+
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            let data = source.read(&path)?;
+            files.push(FileEntry::build(path, &data));
+        }
+
+        let leaves = files
+            .iter()
+            .map(|file| digest(format!("{}:{}", file.path, file.hash).as_bytes()))
+            .collect();
+        Ok(Manifest {
+            root: merkle_root(leaves),
+            files,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub root: String,
+    pub updated: Vec<String>,
+    pub unchanged: usize,
+    pub stale: Vec<String>,
+    pub chunks_fetched: usize,
+    pub bytes_fetched: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct Shares {
+    sources: Arc<Mutex<HashMap<String, Arc<dyn SyncSource>>>>,
+}
+
+impl std::fmt::Debug for Shares {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shares")
+            .field("names", &self.names())
+            .finish()
+    }
+}
+
+impl Shares {
+    pub fn new() -> Self {
+        Shares::default()
+    }
+
+    pub fn share(&self, name: &str, source: Arc<dyn SyncSource>) {
+        let mut sources = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+        sources.insert(name.to_string(), source);
+    }
+
+    pub fn unshare(&self, name: &str) -> bool {
+        let mut sources = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+        sources.remove(name).is_some()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let sources = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+        sources.keys().cloned().collect()
+    }
+
+    fn get(&self, name: &str) -> Option<Arc<dyn SyncSource>> {
+        let sources = self.sources.lock().unwrap_or_else(|e| e.into_inner());
+        sources.get(name).cloned()
+    }
+
+    pub fn serve(&self, stream: Stream) {
+        let shares = self.clone();
+        Runtime::spawn(async move {
+            let _ = shares.respond(stream).await;
+        });
+    }
+
+    async fn respond(&self, mut stream: Stream) -> io::Result<()> {
+        let mut source = None;
+        loop {
+            match read_frame(&mut stream).await? {
+                Frame::SyncRequest { share, root } => {
+                    source = self.get(&share);
+                    let reply = match source.as_ref().map(|source| Manifest::build(&**source)) {
+                        None => Frame::SyncError(format!("Unknown share {share}")),
+                        Some(Err(e)) => Frame::SyncError(e.to_string()),
+                        Some(Ok(manifest)) if manifest.root == root => Frame::SyncUpToDate,
+                        Some(Ok(manifest)) => Frame::SyncManifest(manifest),
+                    };
+                    write_frame(&mut stream, &reply).await?;
+                }
+                Frame::SyncChunks { path, indexes } => {
+                    let source = source.as_ref().ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "No share selected")
+                    })?;
+                    let data = source.read(&path)?;
+                    for index in indexes {
+                        let start = (index * CHUNK_SIZE).min(data.len());
+                        let end = ((index + 1) * CHUNK_SIZE).min(data.len());
+                        let chunk = Frame::SyncChunk {
+                            index,
+                            data: Bytes::copy_from_slice(&data[start..end]),
+                        };
+                        write_frame(&mut stream, &chunk).await?;
+                    }
+                }
+                _ => break,
+            }
+        }
+        stream.close().await
+    }
+
+    pub fn pull(&self, command: CommandWrapper, control: Control, peer: PeerId, share: String) {
+        let shares = self.clone();
+        Runtime::spawn(async move {
+            let result = match shares.get(&share) {
+                Some(local) => pull(control, peer, &share, local)
+                    .await
+                    .map_err(|e| e.into()),
+                None => Err(format!("Unknown share {share}").into()),
+            };
+            let _ = command
+                .respond::<SyncReport, Box<dyn std::error::Error + Send + Sync>>(result)
+                .await;
+        });
+    }
+}
+
+async fn pull(
+    mut control: Control,
+    peer: PeerId,
+    share: &str,
+    local: Arc<dyn SyncSource>,
+) -> io::Result<SyncReport> {
+    let ours = Manifest::build(&*local)?;
+    let mut stream = control
+        .open_stream(peer, SYNC_PROTOCOL)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::ConnectionRefused, e))?;
+    let request = Frame::SyncRequest {
+        share: share.to_string(),
+        root: ours.root.clone(),
+    };
+    write_frame(&mut stream, &request).await?;
+
+    let theirs = match read_frame(&mut stream).await? {
+        Frame::SyncManifest(manifest) => manifest,
+        Frame::SyncUpToDate => {
+            let _ = stream.close().await;
+            return Ok(SyncReport {
+                root: ours.root,
+                unchanged: ours.files.len(),
+                ..SyncReport::default()
+            });
+        }
+        Frame::SyncError(e) => return Err(io::Error::other(e)),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unexpected sync reply",
+            ))
+        }
+    };
+
+    let local_files: HashMap<&str, &FileEntry> = ours
+        .files
+        .iter()
+        .map(|file| (file.path.as_str(), file))
+        .collect();
+    let mut report = SyncReport {
+        root: theirs.root.clone(),
+        stale: ours
+            .files
+            .iter()
+            .filter(|file| !theirs.files.iter().any(|remote| remote.path == file.path))
+            .map(|file| file.path.clone())
+            .collect(),
+        ..SyncReport::default()
+    };
+
+    for remote in theirs.files.iter() {
+        let existing = local_files.get(remote.path.as_str());
+        if existing.is_some_and(|file| file.hash == remote.hash) {
+            report.unchanged += 1;
+            continue;
+        }
+
+        let mut known: HashMap<&str, Vec<u8>> = HashMap::new();
+        if let Some(existing) = existing {
+            let data = local.read(&existing.path)?;
+            for (hash, chunk) in existing.chunks.iter().zip(data.chunks(CHUNK_SIZE)) {
+                known.insert(hash, chunk.to_vec());
+            }
+        }
+
+        let missing = remote.missing_from(existing.copied());
+        let chunks = Frame::SyncChunks {
+            path: remote.path.clone(),
+            indexes: missing.clone(),
+        };
+        write_frame(&mut stream, &chunks).await?;
+
+        let mut fetched: HashMap<usize, Bytes> = HashMap::new();
+        for _ in missing.iter() {
+            match read_frame(&mut stream).await? {
+                Frame::SyncChunk { index, data } => {
+                    report.chunks_fetched += 1;
+                    report.bytes_fetched += data.len() as u64;
+                    fetched.insert(index, data);
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "Unexpected sync chunk",
+                    ))
+                }
+            }
+        }
+
+        let mut assembled = Vec::with_capacity(remote.size as usize);
+        for (index, hash) in remote.chunks.iter().enumerate() {
+            let chunk = match fetched.get(&index) {
+                Some(chunk) => &chunk[..],
+                None => known
+                    .get(hash.as_str())
+                    .map(|chunk| &chunk[..])
+                    .unwrap_or(&[]),
+            };
+            if digest(chunk) != *hash {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Chunk {index} of {} failed verification", remote.path),
+                ));
+            }
+            assembled.extend_from_slice(chunk);
+        }
+
+        local.write(&remote.path, &assembled)?;
+        report.updated.push(remote.path.clone());
+    }
+
+    write_frame(&mut stream, &Frame::SyncDone).await?;
+    let _ = read_frame(&mut stream).await;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunked(fill: &[u8]) -> Vec<u8> {
+        fill.iter()
+            .flat_map(|byte| std::iter::repeat_n(*byte, CHUNK_SIZE))
+            .collect()
+    }
+
+    fn file<'a>(manifest: &'a Manifest, path: &str) -> Option<&'a FileEntry> {
+        manifest.files.iter().find(|file| file.path == path)
+    }
+
+    #[test]
+    fn identical_sources_share_a_root() {
+        let ours = MemorySource::new();
+        let theirs = MemorySource::new();
+        for source in [&ours, &theirs] {
+            source.write("a.txt", b"alpha").unwrap();
+            source.write("b.bin", &chunked(b"xyz")).unwrap();
+        }
+
+        let ours = Manifest::build(&ours).unwrap();
+        let theirs = Manifest::build(&theirs).unwrap();
+        assert_eq!(ours.root, theirs.root);
+        assert_eq!(ours.files, theirs.files);
+    }
+
+    #[test]
+    fn only_changed_chunks_are_requested() {
+        let ours = MemorySource::new();
+        let theirs = MemorySource::new();
+        ours.write("same.txt", b"unchanged").unwrap();
+        theirs.write("same.txt", b"unchanged").unwrap();
+        ours.write("data.bin", &chunked(b"abcd")).unwrap();
+        theirs.write("data.bin", &chunked(b"abXd")).unwrap();
+        theirs.write("new.bin", &chunked(b"pq")).unwrap();
+
+        let ours = Manifest::build(&ours).unwrap();
+        let theirs = Manifest::build(&theirs).unwrap();
+        assert_ne!(ours.root, theirs.root);
+
+        let same = file(&theirs, "same.txt").unwrap();
+        assert_eq!(file(&ours, "same.txt").unwrap().hash, same.hash);
+        assert!(same.missing_from(file(&ours, "same.txt")).is_empty());
+
+        let data = file(&theirs, "data.bin").unwrap();
+        assert_eq!(data.chunks.len(), 4);
+        assert_ne!(file(&ours, "data.bin").unwrap().hash, data.hash);
+        assert_eq!(data.missing_from(file(&ours, "data.bin")), vec![2]);
+
+        let new = file(&theirs, "new.bin").unwrap();
+        assert!(file(&ours, "new.bin").is_none());
+        assert_eq!(new.missing_from(None), vec![0, 1]);
+    }
+
+    #[test]
+    fn moved_chunks_are_reused() {
+        let ours = FileEntry::build(String::from("f"), &chunked(b"abc"));
+        let theirs = FileEntry::build(String::from("f"), &chunked(b"cab"));
+        assert_ne!(ours.hash, theirs.hash);
+        assert!(theirs.missing_from(Some(&ours)).is_empty());
+
+        let grown = FileEntry::build(String::from("f"), &chunked(b"abcz"));
+        assert_eq!(grown.missing_from(Some(&ours)), vec![3]);
+    }
+}
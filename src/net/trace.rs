@@ -0,0 +1,26 @@
+use std::collections::HashMap;
+
+use opentelemetry::{global, Context};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TraceContext(pub HashMap<String, String>);
+
+impl TraceContext {
+    pub fn inject(context: &Context) -> Option<Self> {
+        let mut carrier = HashMap::new();
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(context, &mut carrier)
+        });
+
+        (!carrier.is_empty()).then_some(TraceContext(carrier))
+    }
+
+    pub fn current() -> Option<Self> {
+        TraceContext::inject(&Context::current())
+    }
+
+    pub fn extract(&self) -> Context {
+        global::get_text_map_propagator(|propagator| propagator.extract(&self.0))
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use async_channel::Sender;
+use libp2p::{
+    core::transport::ListenerId,
+    swarm::{ConnectionId, SwarmEvent},
+    Multiaddr, PeerId,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{command::CommandWrapper, event::Event};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum TraceStep {
+    Received { kind: String },
+    Dialing { peer: Option<PeerId> },
+    Connected { peer: PeerId, address: Multiaddr },
+    DialFailed { peer: Option<PeerId>, error: String },
+    Listening { address: Multiaddr },
+    ListenerFailed { error: String },
+    ListenerClosed { error: Option<String> },
+    Responded { error: Option<String> },
+}
+
+pub struct CommandTracer {
+    events: Option<Sender<Event>>,
+    current: Option<Uuid>,
+    dials: HashMap<ConnectionId, Uuid>,
+    listeners: HashMap<ListenerId, Uuid>,
+}
+
+impl CommandTracer {
+    pub fn new(events: Sender<Event>, enabled: bool) -> Self {
+        CommandTracer {
+            events: enabled.then_some(events),
+            current: None,
+            dials: HashMap::new(),
+            listeners: HashMap::new(),
+        }
+    }
+
+    fn emit(&self, command: Uuid, step: TraceStep) {
+        if let Some(events) = &self.events {
+            let _ = events.try_send(Event::CommandTrace { command, step });
+        }
+    }
+
+    pub fn begin(&mut self, command: &mut CommandWrapper) {
+        let Some(events) = &self.events else {
+            return;
+        };
+
+        command.tracer = Some(events.clone());
+        self.current = Some(command.id);
+        self.emit(
+            command.id,
+            TraceStep::Received {
+                kind: command.command.name().to_string(),
+            },
+        );
+    }
+
+    pub fn end(&mut self) {
+        self.current = None;
+    }
+
+    pub fn dialing(&mut self, connection: ConnectionId, peer: Option<PeerId>) {
+        if let Some(command) = self.current {
+            self.dials.insert(connection, command);
+            self.emit(command, TraceStep::Dialing { peer });
+        }
+    }
+
+    pub fn listening(&mut self, listener: ListenerId) {
+        if let Some(command) = self.current {
+            self.listeners.insert(listener, command);
+        }
+    }
+
+    pub fn observe<T>(&mut self, event: &SwarmEvent<T>) {
+        if self.events.is_none() {
+            return;
+        }
+
+        match event {
+            SwarmEvent::ConnectionEstablished {
+                peer_id,
+                connection_id,
+                endpoint,
+                ..
+            } => {
+                if let Some(command) = self.dials.remove(connection_id) {
+                    self.emit(
+                        command,
+                        TraceStep::Connected {
+                            peer: *peer_id,
+                            address: endpoint.get_remote_address().clone(),
+                        },
+                    );
+                }
+            }
+            SwarmEvent::OutgoingConnectionError {
+                peer_id,
+                connection_id,
+                error,
+            } => {
+                if let Some(command) = self.dials.remove(connection_id) {
+                    self.emit(
+                        command,
+                        TraceStep::DialFailed {
+                            peer: *peer_id,
+                            error: error.to_string(),
+                        },
+                    );
+                }
+            }
+            SwarmEvent::NewListenAddr {
+                listener_id,
+                address,
+            } => {
+                if let Some(command) = self.listeners.get(listener_id) {
+                    self.emit(
+                        *command,
+                        TraceStep::Listening {
+                            address: address.clone(),
+                        },
+                    );
+                }
+            }
+            SwarmEvent::ListenerError { listener_id, error } => {
+                if let Some(command) = self.listeners.get(listener_id) {
+                    self.emit(
+                        *command,
+                        TraceStep::ListenerFailed {
+                            error: error.to_string(),
+                        },
+                    );
+                }
+            }
+            SwarmEvent::ListenerClosed {
+                listener_id,
+                reason,
+                ..
+            } => {
+                if let Some(command) = self.listeners.remove(listener_id) {
+                    self.emit(
+                        command,
+                        TraceStep::ListenerClosed {
+                            error: reason.as_ref().err().map(|e| e.to_string()),
+                        },
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
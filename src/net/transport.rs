@@ -0,0 +1,303 @@
+use std::{error::Error, fmt::Debug, net::IpAddr, sync::Arc};
+
+use libp2p::{
+    core::{
+        muxing::StreamMuxerBox,
+        transport::{Boxed, OptionalTransport},
+        Transport,
+    },
+    identity::Keypair,
+    noise, yamux, Multiaddr, PeerId,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(target_arch = "wasm32"))]
+use hickory_resolver::{
+    config::{NameServerConfigGroup, ResolverConfig, ResolverOpts},
+    system_conf::read_system_conf,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use libp2p::{
+    core::{
+        upgrade::{InboundConnectionUpgrade, OutboundConnectionUpgrade},
+        UpgradeInfo,
+    },
+    futures::{
+        future::{self, BoxFuture, Either},
+        AsyncRead, AsyncWrite, FutureExt, TryFutureExt,
+    },
+    tls,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::io;
+
+pub type BoxedTransport = Boxed<(PeerId, StreamMuxerBox)>;
+
+pub trait CustomTransport: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    fn build(&self, key: &Keypair) -> Result<BoxedTransport, Box<dyn Error + Send + Sync>>;
+
+    fn listen_addresses(&self) -> Vec<Multiaddr> {
+        Vec::new()
+    }
+}
+
+pub fn custom(
+    transports: &[Arc<dyn CustomTransport>],
+    key: &Keypair,
+) -> Result<OptionalTransport<BoxedTransport>, Box<dyn Error + Send + Sync>> {
+    let mut combined: Option<BoxedTransport> = None;
+    for transport in transports {
+        let built = transport
+            .build(key)
+            .map_err(|e| format!("Unable to build transport {}: {e}", transport.name()))?;
+        combined = Some(match combined {
+            Some(existing) => existing
+                .or_transport(built)
+                .map(|output, _| output.into_inner())
+                .boxed(),
+            None => built,
+        });
+    }
+
+    Ok(match combined {
+        Some(transport) => OptionalTransport::some(transport),
+        None => OptionalTransport::none(),
+    })
+}
+
+const HIGH_BDP_WINDOW: u32 = 16 * 1024 * 1024;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MuxerConfig {
+    #[serde(default)]
+    pub receive_window: Option<u32>,
+    #[serde(default)]
+    pub max_buffer_size: Option<usize>,
+    #[serde(default)]
+    pub max_streams: Option<usize>,
+}
+
+impl MuxerConfig {
+    pub fn high_bdp() -> Self {
+        MuxerConfig {
+            receive_window: Some(HIGH_BDP_WINDOW),
+            max_buffer_size: Some(HIGH_BDP_WINDOW as usize),
+            max_streams: None,
+        }
+    }
+
+    #[allow(deprecated)]
+    pub fn yamux(&self) -> yamux::Config {
+        let mut config = yamux::Config::default();
+        if let Some(window) = self.receive_window {
+            config.set_receive_window_size(window);
+        }
+        if let Some(size) = self.max_buffer_size {
+            config.set_max_buffer_size(size);
+        }
+        if let Some(streams) = self.max_streams {
+            config.set_max_num_streams(streams);
+        }
+        config
+    }
+}
+
+pub fn noise(key: &Keypair, prologue: &[u8]) -> Result<noise::Config, noise::Error> {
+    Ok(noise::Config::new(key)?.with_prologue(prologue.to_vec()))
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Security {
+    #[default]
+    Noise,
+    Tls,
+    Both,
+}
+
+impl Security {
+    pub fn uses_noise(&self) -> bool {
+        matches!(self, Security::Noise | Security::Both)
+    }
+
+    pub fn uses_tls(&self) -> bool {
+        matches!(self, Security::Tls | Security::Both)
+    }
+}
+
+#[derive(Debug)]
+pub enum SecurityError {
+    Noise(noise::Error),
+    #[cfg(not(target_arch = "wasm32"))]
+    Tls(tls::UpgradeError),
+    Unsupported(String),
+}
+
+impl std::fmt::Display for SecurityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecurityError::Noise(e) => write!(f, "Noise handshake failed: {e}"),
+            #[cfg(not(target_arch = "wasm32"))]
+            SecurityError::Tls(e) => write!(f, "TLS handshake failed: {e}"),
+            SecurityError::Unsupported(protocol) => {
+                write!(f, "Unsupported security protocol {protocol}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SecurityError {}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+pub struct SecurityUpgrade {
+    noise: Option<noise::Config>,
+    tls: Option<tls::Config>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SecurityUpgrade {
+    pub fn new(
+        key: &Keypair,
+        security: Security,
+        prologue: &[u8],
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(SecurityUpgrade {
+            noise: security
+                .uses_noise()
+                .then(|| noise(key, prologue))
+                .transpose()?,
+            tls: security
+                .uses_tls()
+                .then(|| tls::Config::new(key))
+                .transpose()?,
+        })
+    }
+
+    fn select(self, info: &str) -> Option<Selected> {
+        match (self.noise, self.tls) {
+            (Some(config), _) if config.protocol_info().any(|protocol| protocol == info) => {
+                Some(Selected::Noise(config))
+            }
+            (_, Some(config)) if config.protocol_info().any(|protocol| protocol == info) => {
+                Some(Selected::Tls(config))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+enum Selected {
+    Noise(noise::Config),
+    Tls(tls::Config),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+type SecureOutput<C> = (PeerId, Either<noise::Output<C>, tls::TlsStream<C>>);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl UpgradeInfo for SecurityUpgrade {
+    type Info = &'static str;
+    type InfoIter = std::vec::IntoIter<&'static str>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        let mut protocols = Vec::new();
+        if let Some(noise) = &self.noise {
+            protocols.extend(noise.protocol_info());
+        }
+        if let Some(tls) = &self.tls {
+            protocols.extend(tls.protocol_info());
+        }
+        protocols.into_iter()
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<C> InboundConnectionUpgrade<C> for SecurityUpgrade
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    type Output = SecureOutput<C>;
+    type Error = SecurityError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, socket: C, info: Self::Info) -> Self::Future {
+        match self.select(info) {
+            Some(Selected::Noise(config)) => config
+                .upgrade_inbound(socket, info)
+                .map_ok(|(peer, stream)| (peer, Either::Left(stream)))
+                .map_err(SecurityError::Noise)
+                .boxed(),
+            Some(Selected::Tls(config)) => config
+                .upgrade_inbound(socket, info)
+                .map_ok(|(peer, stream)| (peer, Either::Right(stream)))
+                .map_err(SecurityError::Tls)
+                .boxed(),
+            None => future::ready(Err(SecurityError::Unsupported(info.to_string()))).boxed(),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<C> OutboundConnectionUpgrade<C> for SecurityUpgrade
+where
+    C: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    type Output = SecureOutput<C>;
+    type Error = SecurityError;
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, socket: C, info: Self::Info) -> Self::Future {
+        match self.select(info) {
+            Some(Selected::Noise(config)) => config
+                .upgrade_outbound(socket, info)
+                .map_ok(|(peer, stream)| (peer, Either::Left(stream)))
+                .map_err(SecurityError::Noise)
+                .boxed(),
+            Some(Selected::Tls(config)) => config
+                .upgrade_outbound(socket, info)
+                .map_ok(|(peer, stream)| (peer, Either::Right(stream)))
+                .map_err(SecurityError::Tls)
+                .boxed(),
+            None => future::ready(Err(SecurityError::Unsupported(info.to_string()))).boxed(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DnsResolver {
+    #[default]
+    System,
+    Cloudflare,
+    Google,
+    Quad9,
+    Https {
+        servers: Vec<IpAddr>,
+        port: u16,
+        tls_name: String,
+    },
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DnsResolver {
+    pub fn config(&self) -> io::Result<(ResolverConfig, ResolverOpts)> {
+        let config = match self {
+            DnsResolver::System => return Ok(read_system_conf()?),
+            DnsResolver::Cloudflare => ResolverConfig::cloudflare_https(),
+            DnsResolver::Google => ResolverConfig::google_https(),
+            DnsResolver::Quad9 => ResolverConfig::quad9_https(),
+            DnsResolver::Https {
+                servers,
+                port,
+                tls_name,
+            } => ResolverConfig::from_parts(
+                None,
+                Vec::new(),
+                NameServerConfigGroup::from_ips_https(servers, *port, tls_name.clone(), true),
+            ),
+        };
+        Ok((config, ResolverOpts::default()))
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    os::unix::fs::FileTypeExt,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{ready, Context, Poll, Waker},
+};
+
+use libp2p::{
+    core::{
+        multiaddr::Protocol,
+        transport::{DialOpts, ListenerId, TransportError, TransportEvent},
+        Transport,
+    },
+    futures::{
+        future::{self, BoxFuture, Ready},
+        AsyncRead, AsyncWrite, FutureExt, TryFutureExt,
+    },
+    Multiaddr,
+};
+use tokio::{
+    io::ReadBuf,
+    net::{UnixListener, UnixStream},
+};
+
+#[derive(Debug)]
+pub struct UnixConnection(UnixStream);
+
+impl AsyncRead for UnixConnection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        ready!(tokio::io::AsyncRead::poll_read(
+            Pin::new(&mut self.get_mut().0),
+            cx,
+            &mut buf
+        ))?;
+        Poll::Ready(Ok(buf.filled().len()))
+    }
+}
+
+impl AsyncWrite for UnixConnection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        tokio::io::AsyncWrite::poll_write(Pin::new(&mut self.get_mut().0), cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        tokio::io::AsyncWrite::poll_flush(Pin::new(&mut self.get_mut().0), cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        tokio::io::AsyncWrite::poll_shutdown(Pin::new(&mut self.get_mut().0), cx)
+    }
+}
+
+pub fn address<P: AsRef<Path>>(path: P) -> Multiaddr {
+    Multiaddr::empty().with(Protocol::Unix(
+        path.as_ref().to_string_lossy().into_owned().into(),
+    ))
+}
+
+fn socket_path(address: &Multiaddr) -> Option<PathBuf> {
+    let mut protocols = address.iter();
+    let path = match protocols.next()? {
+        Protocol::Unix(path) => PathBuf::from(path.as_ref()),
+        _ => return None,
+    };
+    protocols
+        .all(|protocol| matches!(protocol, Protocol::P2p(_)))
+        .then_some(path)
+}
+
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    match UnixListener::bind(path) {
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            let stale = std::fs::symlink_metadata(path)
+                .is_ok_and(|metadata| metadata.file_type().is_socket())
+                && std::os::unix::net::UnixStream::connect(path).is_err();
+            if !stale {
+                return Err(e);
+            }
+            std::fs::remove_file(path)?;
+            UnixListener::bind(path)
+        }
+        result => result,
+    }
+}
+
+struct Listener {
+    socket: UnixListener,
+    address: Multiaddr,
+    path: PathBuf,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Default)]
+pub struct UnixTransport {
+    listeners: HashMap<ListenerId, Listener>,
+    pending: VecDeque<TransportEvent<Ready<io::Result<UnixConnection>>, io::Error>>,
+    waker: Option<Waker>,
+}
+
+impl UnixTransport {
+    pub fn new() -> Self {
+        UnixTransport::default()
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Transport for UnixTransport {
+    type Output = UnixConnection;
+    type Error = io::Error;
+    type ListenerUpgrade = Ready<io::Result<UnixConnection>>;
+    type Dial = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn listen_on(
+        &mut self,
+        id: ListenerId,
+        addr: Multiaddr,
+    ) -> Result<(), TransportError<Self::Error>> {
+        let Some(path) = socket_path(&addr) else {
+            return Err(TransportError::MultiaddrNotSupported(addr));
+        };
+        let socket = bind(&path).map_err(TransportError::Other)?;
+        let address = address(&path);
+        self.pending.push_back(TransportEvent::NewAddress {
+            listener_id: id,
+            listen_addr: address.clone(),
+        });
+        self.listeners.insert(
+            id,
+            Listener {
+                socket,
+                address,
+                path,
+            },
+        );
+        self.wake();
+        Ok(())
+    }
+
+    fn remove_listener(&mut self, id: ListenerId) -> bool {
+        let Some(listener) = self.listeners.remove(&id) else {
+            return false;
+        };
+        self.pending.push_back(TransportEvent::AddressExpired {
+            listener_id: id,
+            listen_addr: listener.address.clone(),
+        });
+        self.pending.push_back(TransportEvent::ListenerClosed {
+            listener_id: id,
+            reason: Ok(()),
+        });
+        self.wake();
+        true
+    }
+
+    fn dial(
+        &mut self,
+        addr: Multiaddr,
+        _opts: DialOpts,
+    ) -> Result<Self::Dial, TransportError<Self::Error>> {
+        let Some(path) = socket_path(&addr) else {
+            return Err(TransportError::MultiaddrNotSupported(addr));
+        };
+        Ok(UnixStream::connect(path).map_ok(UnixConnection).boxed())
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<TransportEvent<Self::ListenerUpgrade, Self::Error>> {
+        let this = self.get_mut();
+        if let Some(event) = this.pending.pop_front() {
+            return Poll::Ready(event);
+        }
+
+        let mut failed = None;
+        for (id, listener) in this.listeners.iter() {
+            match listener.socket.poll_accept(cx) {
+                Poll::Ready(Ok((stream, _))) => {
+                    return Poll::Ready(TransportEvent::Incoming {
+                        listener_id: *id,
+                        upgrade: future::ready(Ok(UnixConnection(stream))),
+                        local_addr: listener.address.clone(),
+                        send_back_addr: listener.address.clone(),
+                    });
+                }
+                Poll::Ready(Err(error)) => {
+                    failed = Some((*id, error));
+                    break;
+                }
+                Poll::Pending => {}
+            }
+        }
+        if let Some((listener_id, error)) = failed {
+            return Poll::Ready(TransportEvent::ListenerError { listener_id, error });
+        }
+
+        this.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
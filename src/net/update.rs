@@ -0,0 +1,98 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use libp2p::{
+    gossipsub::IdentTopic,
+    identity::{Keypair, PublicKey},
+    PeerId,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UpdateManifest {
+    pub version: String,
+    pub url: String,
+    pub hash: String,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignedManifest {
+    pub manifest: UpdateManifest,
+    pub published: DateTime<Utc>,
+    pub public_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+fn message(manifest: &UpdateManifest, published: &DateTime<Utc>) -> Option<Vec<u8>> {
+    let mut message = serde_json::to_vec(manifest).ok()?;
+    message.extend_from_slice(format!(":{}", published.timestamp_micros()).as_bytes());
+    Some(message)
+}
+
+impl SignedManifest {
+    pub fn sign(key: &Keypair, manifest: UpdateManifest) -> Option<Self> {
+        let published = Utc::now();
+        let signature = key.sign(&message(&manifest, &published)?).ok()?;
+        Some(SignedManifest {
+            manifest,
+            published,
+            public_key: key.public().encode_protobuf(),
+            signature,
+        })
+    }
+
+    pub fn publisher(&self) -> Option<PeerId> {
+        let key = PublicKey::try_decode_protobuf(&self.public_key).ok()?;
+        let message = message(&self.manifest, &self.published)?;
+        key.verify(&message, &self.signature)
+            .then(|| key.to_peer_id())
+    }
+}
+
+pub struct UpdateChannel {
+    topic: IdentTopic,
+    publishers: HashSet<PeerId>,
+    latest: HashMap<PeerId, DateTime<Utc>>,
+}
+
+impl UpdateChannel {
+    pub fn new(group: &str, publishers: Vec<PeerId>) -> Self {
+        UpdateChannel {
+            topic: IdentTopic::new(format!("/modius/{group}/updates")),
+            publishers: publishers.into_iter().collect(),
+            latest: HashMap::new(),
+        }
+    }
+
+    pub fn topic(&self) -> &IdentTopic {
+        &self.topic
+    }
+
+    pub fn set_group(&mut self, group: &str) {
+        self.topic = IdentTopic::new(format!("/modius/{group}/updates"));
+        self.latest.clear();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.publishers.is_empty()
+    }
+
+    pub fn accept(&mut self, data: &[u8]) -> Option<(PeerId, UpdateManifest)> {
+        let signed: SignedManifest = serde_json::from_slice(data).ok()?;
+        let publisher = signed
+            .publisher()
+            .filter(|publisher| self.publishers.contains(publisher))?;
+        if self
+            .latest
+            .get(&publisher)
+            .is_some_and(|latest| *latest >= signed.published)
+        {
+            return None;
+        }
+
+        self.latest.insert(publisher, signed.published);
+        Some((publisher, signed.manifest))
+    }
+}
@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+pub const WIRE_VERSION: &str = "1.0";
+const PREFIX: &str = "/modius/";
+const MAX_MESSAGE: &str = ";max=";
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProtocolVersion {
+    pub wire: String,
+    pub app: Option<String>,
+    #[serde(default)]
+    pub max_message: Option<usize>,
+}
+
+fn major(version: &str) -> &str {
+    version.split('.').next().unwrap_or(version)
+}
+
+impl ProtocolVersion {
+    pub fn local(app: &str, max_message: usize) -> Self {
+        ProtocolVersion {
+            wire: WIRE_VERSION.to_string(),
+            app: Some(app.to_string()),
+            max_message: Some(max_message),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let version = match &self.app {
+            Some(app) => format!("{PREFIX}{}/{app}", self.wire),
+            None => format!("{PREFIX}{}", self.wire),
+        };
+        match self.max_message {
+            Some(max) => format!("{version}{MAX_MESSAGE}{max}"),
+            None => version,
+        }
+    }
+
+    pub fn parse(version: &str) -> Option<Self> {
+        let rest = version.strip_prefix(PREFIX)?;
+        let (rest, max_message) = match rest.rsplit_once(MAX_MESSAGE) {
+            Some((rest, max)) => (rest, max.parse::<usize>().ok()),
+            None => (rest, None),
+        };
+        let (wire, app) = match rest.split_once('/') {
+            Some((wire, app)) => (wire, Some(app.to_string())),
+            None => (rest, None),
+        };
+        Some(ProtocolVersion {
+            wire: wire.to_string(),
+            app,
+            max_message,
+        })
+    }
+
+    pub fn compatible(&self, other: &ProtocolVersion) -> bool {
+        if major(&self.wire) != major(&other.wire) {
+            return false;
+        }
+
+        match (&self.app, &other.app) {
+            (Some(ours), Some(theirs)) => major(ours) == major(theirs),
+            _ => true,
+        }
+    }
+}
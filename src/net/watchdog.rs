@@ -0,0 +1,161 @@
+use std::{
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use async_channel::{Receiver, Sender};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_arch = "wasm32")]
+use crate::runtime::{sleep, Executor, Runtime};
+
+use super::event::Event;
+
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum StallAction {
+    #[default]
+    Notify,
+    Abort,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WatchdogConfig {
+    pub timeout: Duration,
+    pub action: StallAction,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            timeout: Duration::from_secs(10),
+            action: StallAction::Notify,
+        }
+    }
+}
+
+struct Pulse {
+    last_tick: Option<DateTime<Utc>>,
+    last_item: &'static str,
+    stalled: bool,
+}
+
+#[derive(Clone)]
+pub struct Heartbeat {
+    pulse: Arc<Mutex<Pulse>>,
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Heartbeat {
+            pulse: Arc::new(Mutex::new(Pulse {
+                last_tick: None,
+                last_item: "Startup",
+                stalled: false,
+            })),
+        }
+    }
+
+    pub fn processing(&self, item: &'static str) {
+        self.pulse
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .last_item = item;
+    }
+
+    pub fn tick(&self) {
+        let mut pulse = self.pulse.lock().unwrap_or_else(|e| e.into_inner());
+        pulse.last_tick = Some(Utc::now());
+        pulse.stalled = false;
+    }
+
+    pub fn watch(&self, config: WatchdogConfig, events: Sender<Event>) -> Receiver<()> {
+        let (abort, aborted) = async_channel::bounded::<()>(1);
+        Watch {
+            pulse: Arc::downgrade(&self.pulse),
+            config,
+            events,
+            abort,
+        }
+        .spawn();
+        aborted
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Heartbeat::new()
+    }
+}
+
+struct Watch {
+    pulse: Weak<Mutex<Pulse>>,
+    config: WatchdogConfig,
+    events: Sender<Event>,
+    abort: Sender<()>,
+}
+
+impl Watch {
+    fn interval(&self) -> Duration {
+        (self.config.timeout / 4).clamp(Duration::from_millis(10), MAX_CHECK_INTERVAL)
+    }
+
+    fn check(&self) -> bool {
+        let Some(pulse) = self.pulse.upgrade() else {
+            return false;
+        };
+
+        let stall = {
+            let mut pulse = pulse.lock().unwrap_or_else(|e| e.into_inner());
+            let stalled_for = pulse
+                .last_tick
+                .and_then(|last_tick| (Utc::now() - last_tick).to_std().ok())
+                .unwrap_or_default();
+            if pulse.stalled || stalled_for < self.config.timeout {
+                None
+            } else {
+                pulse.stalled = true;
+                Some((stalled_for, pulse.last_item))
+            }
+        };
+        let Some((stalled_for, last_item)) = stall else {
+            return true;
+        };
+
+        let _ = self.events.try_send(Event::EventLoopStalled {
+            stalled_for,
+            last_item: last_item.to_string(),
+        });
+        if self.config.action == StallAction::Abort {
+            let _ = self.abort.try_send(());
+            return false;
+        }
+        true
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn spawn(self) {
+        let _ = std::thread::Builder::new()
+            .name("modius-watchdog".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(self.interval());
+                if !self.check() {
+                    return;
+                }
+            });
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn spawn(self) {
+        Runtime::spawn(async move {
+            loop {
+                sleep(self.interval()).await;
+                if !self.check() {
+                    return;
+                }
+            }
+        });
+    }
+}
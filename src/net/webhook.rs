@@ -0,0 +1,176 @@
+use std::{error::Error, time::Duration};
+
+use async_channel::{Receiver, Sender};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{header::CONTENT_TYPE, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::runtime::{sleep, Executor, Runtime};
+
+use super::event::Event;
+
+const EVENT_HEADER: &str = "X-Modius-Event";
+const DELIVERY_HEADER: &str = "X-Modius-Delivery";
+const TIMESTAMP_HEADER: &str = "X-Modius-Timestamp";
+const SIGNATURE_HEADER: &str = "X-Modius-Signature";
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub max_attempts: u32,
+    pub backoff: Duration,
+    pub timeout: Duration,
+}
+
+#[derive(Serialize)]
+struct Delivery<'a> {
+    id: Uuid,
+    timestamp: DateTime<Utc>,
+    event: &'a Event,
+}
+
+pub fn sign(secret: &[u8], timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        <Hmac<Sha256> as Mac>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(format!("{timestamp}.").as_bytes());
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+impl Webhook {
+    pub fn new<U: Into<String>>(url: U) -> Self {
+        Webhook {
+            url: url.into(),
+            secret: None,
+            events: Vec::new(),
+            max_attempts: 5,
+            backoff: Duration::from_secs(1),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    pub fn with_secret<S: Into<String>>(mut self, secret: S) -> Self {
+        self.secret = Some(secret.into());
+        self
+    }
+
+    pub fn on(mut self, event: &str) -> Self {
+        self.events.push(event.to_string());
+        self
+    }
+
+    pub fn matches(&self, event: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|class| class == event)
+    }
+
+    async fn post(
+        &self,
+        client: &reqwest::Client,
+        id: Uuid,
+        event: &str,
+        body: Vec<u8>,
+    ) -> Result<StatusCode, reqwest::Error> {
+        let timestamp = Utc::now().timestamp();
+        let mut request = client
+            .post(&self.url)
+            .timeout(self.timeout)
+            .header(CONTENT_TYPE, "application/json")
+            .header(EVENT_HEADER, event)
+            .header(DELIVERY_HEADER, id.to_string())
+            .header(TIMESTAMP_HEADER, timestamp.to_string());
+        if let Some(secret) = &self.secret {
+            let signature = sign(secret.as_bytes(), timestamp, &body);
+            request = request.header(SIGNATURE_HEADER, format!("sha256={signature}"));
+        }
+        Ok(request.body(body).send().await?.status())
+    }
+
+    async fn deliver(&self, client: reqwest::Client, id: Uuid, event: &str, body: Vec<u8>) {
+        let mut backoff = self.backoff;
+        for attempt in 1..=self.max_attempts.max(1) {
+            match self.post(&client, id, event, body.clone()).await {
+                Ok(status) if status.is_success() => return,
+                Ok(status) if !retryable(status) => return,
+                _ if attempt == self.max_attempts => return,
+                _ => {
+                    sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_BACKOFF);
+                }
+            }
+        }
+    }
+}
+
+pub struct Webhooks {
+    hooks: Vec<Webhook>,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    pub fn new(hooks: Vec<Webhook>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(Webhooks {
+            hooks,
+            client: reqwest::Client::builder().build()?,
+        })
+    }
+
+    fn dispatch(&self, event: &Event) {
+        let name = event.name();
+        let matching: Vec<Webhook> = self
+            .hooks
+            .iter()
+            .filter(|hook| hook.matches(name))
+            .cloned()
+            .collect();
+        if matching.is_empty() {
+            return;
+        }
+
+        let id = Uuid::new_v4();
+        let delivery = Delivery {
+            id,
+            timestamp: Utc::now(),
+            event,
+        };
+        let Ok(body) = serde_json::to_vec(&delivery) else {
+            return;
+        };
+        for hook in matching {
+            let client = self.client.clone();
+            let body = body.clone();
+            Runtime::spawn(async move {
+                hook.deliver(client, id, name, body).await;
+            });
+        }
+    }
+
+    pub fn spawn(self, events: Receiver<Event>, forward: Sender<Event>) {
+        Runtime::spawn(async move {
+            while let Ok(event) = events.recv().await {
+                self.dispatch(&event);
+                if forward.send(event).await.is_err() {
+                    break;
+                }
+            }
+            forward.close();
+        });
+    }
+}
@@ -0,0 +1,71 @@
+use std::{error::Error, sync::Arc};
+
+use libp2p::{identity::Keypair, multiaddr::Protocol, Multiaddr};
+use libp2p_webrtc::tokio::{Certificate, Transport};
+
+use crate::storage::Storage;
+
+const NAMESPACE: &str = "webrtc";
+const CERTIFICATE: &[u8] = b"certificate";
+
+pub fn address(port: u16) -> Multiaddr {
+    Multiaddr::empty()
+        .with(Protocol::Ip4([0, 0, 0, 0].into()))
+        .with(Protocol::Udp(port))
+        .with(Protocol::WebRTCDirect)
+}
+
+pub fn certificate(
+    storage: &Arc<dyn Storage>,
+) -> Result<Certificate, Box<dyn Error + Send + Sync>> {
+    let stored = storage
+        .get(NAMESPACE, CERTIFICATE)?
+        .and_then(|pem| String::from_utf8(pem).ok())
+        .and_then(|pem| Certificate::from_pem(&pem).ok());
+    if let Some(certificate) = stored {
+        return Ok(certificate);
+    }
+
+    let certificate = Certificate::generate(&mut rand::thread_rng())?;
+    storage.put(
+        NAMESPACE,
+        CERTIFICATE,
+        certificate.serialize_pem().as_bytes(),
+    )?;
+    Ok(certificate)
+}
+
+pub fn transport(
+    key: &Keypair,
+    storage: &Arc<dyn Storage>,
+) -> Result<Transport, Box<dyn Error + Send + Sync>> {
+    Ok(Transport::new(key.clone(), certificate(storage)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    #[test]
+    fn certificate_is_reused_across_restarts() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let first = certificate(&storage).unwrap();
+        let second = certificate(&storage).unwrap();
+        assert_eq!(first.fingerprint(), second.fingerprint());
+    }
+
+    #[test]
+    fn unreadable_certificates_are_replaced() {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        storage
+            .put(NAMESPACE, CERTIFICATE, b"not a certificate")
+            .unwrap();
+        let replaced = certificate(&storage).unwrap();
+        assert_eq!(
+            certificate(&storage).unwrap().fingerprint(),
+            replaced.fingerprint()
+        );
+    }
+}
@@ -0,0 +1,434 @@
+use std::{io, ops::RangeInclusive};
+
+use bytes::{Bytes, BytesMut};
+use libp2p::{
+    futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    PeerId,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+#[cfg(feature = "ratchet")]
+use super::ratchet::RatchetHeader;
+#[cfg(feature = "opentelemetry")]
+use super::trace::TraceContext;
+use super::{
+    admin::{AdminCommand, AdminReply},
+    causal::CausalMessage,
+    fairness::FairScheduler,
+    feed::{FeedEntry, FeedHead},
+    groupkey::GroupKey,
+    history::HistoryEntry,
+    leave::Departure,
+    rpc::RpcResult,
+    schema::MessageTag,
+    sync::Manifest,
+};
+
+pub const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Envelope {
+    pub session: u64,
+    pub seq: u64,
+    #[serde(default)]
+    pub id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<MessageTag>,
+    #[cfg(feature = "opentelemetry")]
+    #[serde(default)]
+    pub trace: Option<TraceContext>,
+    #[cfg(feature = "ratchet")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ratchet: Option<RatchetHeader>,
+    #[serde(skip)]
+    pub payload: Bytes,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Frame {
+    Message(Envelope),
+    Ack {
+        session: u64,
+        seq: u64,
+    },
+    HistoryRequest {
+        room: String,
+        before: Option<u64>,
+        limit: usize,
+    },
+    HistoryPage {
+        entries: Vec<HistoryEntry>,
+        next: Option<u64>,
+    },
+    Departure(Departure),
+    GroupKey(GroupKey),
+    AdminCommand(AdminCommand),
+    AdminReply(AdminReply),
+    Shard {
+        blob: String,
+        index: usize,
+        #[serde(skip)]
+        data: Bytes,
+    },
+    ShardRequest {
+        blob: String,
+        index: usize,
+    },
+    ShardStored {
+        blob: String,
+        index: usize,
+    },
+    ShardMissing {
+        blob: String,
+        index: usize,
+    },
+    SyncRequest {
+        share: String,
+        root: String,
+    },
+    SyncManifest(Manifest),
+    SyncUpToDate,
+    SyncError(String),
+    SyncChunks {
+        path: String,
+        indexes: Vec<usize>,
+    },
+    SyncChunk {
+        index: usize,
+        #[serde(skip)]
+        data: Bytes,
+    },
+    SyncDone,
+    FeedHeadsRequest,
+    FeedHeads(Vec<FeedHead>),
+    FeedRequest {
+        feed: String,
+        from: u64,
+    },
+    FeedEntries(Vec<FeedEntry>),
+    CausalRequest {
+        room: String,
+        sender: PeerId,
+        range: RangeInclusive<u64>,
+    },
+    CausalMessages(Vec<CausalMessage>),
+    ProbeData {
+        #[serde(skip)]
+        data: Bytes,
+    },
+    ProbeEcho {
+        nonce: u64,
+    },
+    ProbeEnd,
+    ProbeReceived {
+        bytes: u64,
+    },
+    RpcRequest {
+        method: String,
+        params: Value,
+        #[serde(default)]
+        callbacks: Vec<String>,
+    },
+    RpcResponse(RpcResult),
+    RpcCallback {
+        name: String,
+        params: Value,
+    },
+    RpcCallbackResult(RpcResult),
+    RpcStreamRequest {
+        method: String,
+        params: Value,
+        window: u32,
+    },
+    RpcItem(RpcResult),
+    RpcCredit(u32),
+    RpcCancel,
+    RpcEnd,
+    #[cfg(feature = "ratchet")]
+    RatchetHello([u8; 32]),
+}
+
+impl Frame {
+    fn payload(&self) -> &[u8] {
+        match self {
+            Frame::Message(envelope) => &envelope.payload,
+            Frame::Shard { data, .. }
+            | Frame::SyncChunk { data, .. }
+            | Frame::ProbeData { data } => data,
+            _ => &[],
+        }
+    }
+}
+
+pub const FRAME_OVERHEAD: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub struct Oversized;
+
+impl std::fmt::Display for Oversized {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Frame exceeds the maximum size")
+    }
+}
+
+impl std::error::Error for Oversized {}
+
+fn oversized(kind: io::ErrorKind) -> io::Error {
+    io::Error::new(kind, Oversized)
+}
+
+pub fn is_oversized(error: &io::Error) -> bool {
+    error.get_ref().is_some_and(|inner| inner.is::<Oversized>())
+}
+
+pub fn frame_limit(max_message: usize) -> usize {
+    max_message.saturating_add(FRAME_OVERHEAD).min(MAX_FRAME)
+}
+
+pub fn encode(frame: &Frame) -> io::Result<Vec<u8>> {
+    let header = serde_json::to_vec(frame)?;
+    let payload = frame.payload();
+    let length = 4 + header.len() + payload.len();
+    if length > MAX_FRAME {
+        return Err(oversized(io::ErrorKind::InvalidInput));
+    }
+
+    let mut out = Vec::with_capacity(4 + length);
+    out.extend_from_slice(&(length as u32).to_be_bytes());
+    out.extend_from_slice(&(header.len() as u32).to_be_bytes());
+    out.extend_from_slice(&header);
+    out.extend_from_slice(payload);
+    Ok(out)
+}
+
+pub fn decode(data: &[u8]) -> io::Result<Frame> {
+    let Some((length, body)) = data.split_first_chunk::<4>() else {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "Frame length is truncated",
+        ));
+    };
+    let length = frame_length(*length, MAX_FRAME)?;
+    if body.len() != length {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Frame length does not match the data",
+        ));
+    }
+    decode_body(Bytes::copy_from_slice(body))
+}
+
+fn frame_length(prefix: [u8; 4], limit: usize) -> io::Result<usize> {
+    let length = u32::from_be_bytes(prefix) as usize;
+    if !(4..=limit.min(MAX_FRAME)).contains(&length) {
+        return Err(oversized(io::ErrorKind::InvalidData));
+    }
+    Ok(length)
+}
+
+fn decode_body(mut data: Bytes) -> io::Result<Frame> {
+    let header_length = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if header_length > data.len() - 4 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Frame header exceeds the frame",
+        ));
+    }
+
+    let payload = data.split_off(4 + header_length);
+    let mut frame: Frame = serde_json::from_slice(&data[4..])?;
+    match &mut frame {
+        Frame::Message(envelope) => envelope.payload = payload,
+        Frame::Shard { data, .. } | Frame::SyncChunk { data, .. } | Frame::ProbeData { data } => {
+            *data = payload
+        }
+        _ => {}
+    }
+    Ok(frame)
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(io: &mut W, frame: &Frame) -> io::Result<()> {
+    io.write_all(&encode(frame)?).await?;
+    io.flush().await?;
+    #[cfg(all(feature = "capture", not(target_arch = "wasm32")))]
+    super::capture::record(super::capture::Direction::Sent, frame, frame.payload());
+    Ok(())
+}
+
+pub async fn write_frame_fair<W: AsyncWrite + Unpin>(
+    io: &mut W,
+    frame: &Frame,
+    scheduler: &FairScheduler,
+) -> io::Result<()> {
+    scheduler.write_all(io, &encode(frame)?).await?;
+    io.flush().await?;
+    #[cfg(all(feature = "capture", not(target_arch = "wasm32")))]
+    super::capture::record(super::capture::Direction::Sent, frame, frame.payload());
+    Ok(())
+}
+
+pub async fn read_frame<R: AsyncRead + Unpin>(io: &mut R) -> io::Result<Frame> {
+    read_frame_limited(io, MAX_FRAME).await
+}
+
+pub async fn read_frame_limited<R: AsyncRead + Unpin>(
+    io: &mut R,
+    limit: usize,
+) -> io::Result<Frame> {
+    let mut length = [0u8; 4];
+    io.read_exact(&mut length).await?;
+    let length = frame_length(length, limit)?;
+
+    let mut data = BytesMut::zeroed(length);
+    io.read_exact(&mut data).await?;
+    let frame = decode_body(data.freeze())?;
+    #[cfg(all(feature = "capture", not(target_arch = "wasm32")))]
+    super::capture::record(super::capture::Direction::Received, &frame, frame.payload());
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use libp2p::futures::{executor::block_on, io::Cursor};
+
+    use super::*;
+
+    fn envelope(payload: &'static [u8]) -> Envelope {
+        Envelope {
+            session: 7,
+            seq: 3,
+            id: Some(Uuid::new_v4()),
+            nonce: Some(42),
+            channel: Some(String::from("chat")),
+            tag: None,
+            #[cfg(feature = "opentelemetry")]
+            trace: None,
+            #[cfg(feature = "ratchet")]
+            ratchet: None,
+            payload: Bytes::from_static(payload),
+        }
+    }
+
+    #[test]
+    fn payload_frames_round_trip() {
+        let frames = [
+            Frame::Message(envelope(b"hello")),
+            Frame::Shard {
+                blob: String::from("blob"),
+                index: 2,
+                data: Bytes::from_static(b"shard"),
+            },
+            Frame::SyncChunk {
+                index: 9,
+                data: Bytes::from_static(&[0, 1, 2, 255]),
+            },
+            Frame::ProbeData {
+                data: Bytes::from_static(b""),
+            },
+        ];
+        for frame in frames {
+            let decoded = decode(&encode(&frame).unwrap()).unwrap();
+            assert_eq!(decoded.payload(), frame.payload());
+            assert_eq!(
+                serde_json::to_value(&decoded).unwrap(),
+                serde_json::to_value(&frame).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn frames_round_trip_through_a_stream() {
+        let mut buffer = Vec::new();
+        block_on(write_frame(
+            &mut buffer,
+            &Frame::Message(envelope(b"streamed")),
+        ))
+        .unwrap();
+        block_on(write_frame(&mut buffer, &Frame::Ack { session: 7, seq: 3 })).unwrap();
+
+        let mut reader = Cursor::new(buffer);
+        let Frame::Message(message) = block_on(read_frame(&mut reader)).unwrap() else {
+            panic!("Expected a message frame");
+        };
+        assert_eq!(message.payload.as_ref(), b"streamed");
+        assert_eq!(message.nonce, Some(42));
+        assert!(matches!(
+            block_on(read_frame(&mut reader)).unwrap(),
+            Frame::Ack { session: 7, seq: 3 }
+        ));
+    }
+
+    #[test]
+    fn truncated_length_is_rejected() {
+        let error = decode(&[0, 0]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let error = block_on(read_frame(&mut Cursor::new(vec![0, 0, 1]))).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut data = encode(&Frame::SyncDone).unwrap();
+        data.pop();
+        assert_eq!(
+            decode(&data).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn header_longer_than_frame_is_rejected() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&8u32.to_be_bytes());
+        data.extend_from_slice(&100u32.to_be_bytes());
+        data.extend_from_slice(b"{}{}");
+
+        let error = decode(&data).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(!is_oversized(&error));
+    }
+
+    #[test]
+    fn frames_at_the_limit_are_accepted() {
+        let limit = 1024;
+        assert_eq!(
+            frame_length((limit as u32).to_be_bytes(), limit).unwrap(),
+            limit
+        );
+
+        let error = frame_length((limit as u32 + 1).to_be_bytes(), limit).unwrap_err();
+        assert!(is_oversized(&error));
+
+        let error = frame_length(((MAX_FRAME + 1) as u32).to_be_bytes(), usize::MAX).unwrap_err();
+        assert!(is_oversized(&error));
+    }
+
+    #[test]
+    fn oversized_frames_are_refused() {
+        let header = serde_json::to_vec(&Frame::ProbeData { data: Bytes::new() })
+            .unwrap()
+            .len();
+        let fits = MAX_FRAME - 4 - header;
+        let frame = Frame::ProbeData {
+            data: Bytes::from(vec![0; fits]),
+        };
+        let encoded = encode(&frame).unwrap();
+        assert_eq!(encoded.len(), 4 + MAX_FRAME);
+        assert_eq!(decode(&encoded).unwrap().payload().len(), fits);
+
+        let frame = Frame::ProbeData {
+            data: Bytes::from(vec![0; fits + 1]),
+        };
+        let error = encode(&frame).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(is_oversized(&error));
+
+        let error =
+            block_on(read_frame_limited(&mut Cursor::new(encoded), MAX_FRAME - 1)).unwrap_err();
+        assert!(is_oversized(&error));
+    }
+}
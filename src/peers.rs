@@ -0,0 +1,376 @@
+use std::{cmp::Reverse, collections::BTreeSet, error::Error, sync::Arc, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    storage::Storage,
+    util::{Peer, PeerType},
+};
+
+const NAMESPACE: &str = "peers";
+const SETS: &str = "peer_sets";
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PeerSet {
+    pub name: String,
+    pub members: BTreeSet<PeerId>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PeerSort {
+    LastSeen,
+    Latency,
+    Name,
+    Liveness,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PeerFilter {
+    pub tag: Option<String>,
+    pub connected: bool,
+    pub group: Option<String>,
+    pub kind: Option<PeerType>,
+    pub seen_within: Option<Duration>,
+    pub max_rtt_ms: Option<f64>,
+    pub min_liveness: Option<f64>,
+    pub sort: Option<PeerSort>,
+    pub limit: Option<usize>,
+}
+
+impl PeerFilter {
+    pub fn matches(&self, peer: &Peer, connected: bool) -> bool {
+        if self.connected && !connected {
+            return false;
+        }
+
+        if let Some(tag) = &self.tag {
+            if !peer.has_tag(tag) {
+                return false;
+            }
+        }
+
+        if self.group.is_some() && peer.group != self.group {
+            return false;
+        }
+
+        if self.kind.as_ref().is_some_and(|kind| *kind != peer.kind) {
+            return false;
+        }
+
+        if let Some(window) = self.seen_within {
+            let cutoff = TimeDelta::from_std(window)
+                .ok()
+                .and_then(|window| Utc::now().checked_sub_signed(window));
+            if peer
+                .last_seen
+                .is_none_or(|seen| cutoff.is_some_and(|cutoff| seen < cutoff))
+            {
+                return false;
+            }
+        }
+
+        if let Some(bound) = self.max_rtt_ms {
+            if peer.stats.rtt_ms.is_none_or(|rtt| rtt > bound) {
+                return false;
+            }
+        }
+
+        if self
+            .min_liveness
+            .is_some_and(|bound| peer.liveness.estimate < bound)
+        {
+            return false;
+        }
+
+        true
+    }
+
+    pub fn apply<F: Fn(&PeerId) -> bool>(&self, peers: Vec<Peer>, is_connected: F) -> Vec<Peer> {
+        let mut peers: Vec<Peer> = peers
+            .into_iter()
+            .map(|mut peer| {
+                peer.liveness.refresh();
+                peer
+            })
+            .filter(|peer| self.matches(peer, is_connected(&peer.id)))
+            .collect();
+
+        match self.sort {
+            Some(PeerSort::LastSeen) => peers.sort_by_key(|peer| Reverse(peer.last_seen)),
+            Some(PeerSort::Latency) => peers.sort_by(|a, b| {
+                let a = a.stats.rtt_ms.unwrap_or(f64::INFINITY);
+                let b = b.stats.rtt_ms.unwrap_or(f64::INFINITY);
+                a.total_cmp(&b)
+            }),
+            Some(PeerSort::Name) => peers.sort_by(|a, b| a.name.cmp(&b.name)),
+            Some(PeerSort::Liveness) => {
+                peers.sort_by(|a, b| b.liveness.estimate.total_cmp(&a.liveness.estimate))
+            }
+            None => {}
+        }
+
+        if let Some(limit) = self.limit {
+            peers.truncate(limit);
+        }
+        peers
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PrunePolicy {
+    pub max_age: Option<Duration>,
+    pub max_peers: Option<usize>,
+}
+
+impl PrunePolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.max_age.is_some() || self.max_peers.is_some()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PeerStore {
+    storage: Arc<dyn Storage>,
+}
+
+impl PeerStore {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        PeerStore { storage }
+    }
+
+    pub fn get(&self, id: &PeerId) -> Result<Option<Peer>, Box<dyn Error + Send + Sync>> {
+        self.storage.get_value::<Peer>(NAMESPACE, &id.to_bytes())
+    }
+
+    fn put(&self, peer: &Peer) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.storage.put_value(NAMESPACE, &peer.id.to_bytes(), peer)
+    }
+
+    pub fn insert(&self, mut peer: Peer) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(previous) = self.get(&peer.id)? {
+            peer.merge(previous);
+        }
+        self.put(&peer)
+    }
+
+    pub fn remove(&self, id: &PeerId) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.storage.delete(NAMESPACE, &id.to_bytes())
+    }
+
+    pub fn contains(&self, id: &PeerId) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(self.get(id)?.is_some())
+    }
+
+    pub fn list(&self) -> Result<Vec<Peer>, Box<dyn Error + Send + Sync>> {
+        self.storage.values::<Peer>(NAMESPACE)
+    }
+
+    pub fn update<F: FnOnce(&mut Peer)>(
+        &self,
+        id: &PeerId,
+        f: F,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        match self.get(id)? {
+            Some(mut peer) => {
+                f(&mut peer);
+                self.put(&peer)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn record_rtt(
+        &self,
+        id: &PeerId,
+        rtt: Duration,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.update(id, |peer| {
+            peer.stats.record_rtt(rtt);
+            peer.liveness.observe();
+        })
+        .map(|_| ())
+    }
+
+    pub fn record_throughput(
+        &self,
+        id: &PeerId,
+        bytes: usize,
+        elapsed: Duration,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.update(id, |peer| peer.stats.record_throughput(bytes, elapsed))
+            .map(|_| ())
+    }
+
+    pub fn report_sighting(
+        &self,
+        id: &PeerId,
+        at: DateTime<Utc>,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.update(id, |peer| peer.liveness.report(at)).map(|_| ())
+    }
+
+    pub fn seen(&self, id: &PeerId) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.update(id, |peer| peer.last_seen = Some(Utc::now()))
+            .map(|_| ())
+    }
+
+    pub fn tag(
+        &self,
+        id: &PeerId,
+        key: String,
+        value: String,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.update(id, |peer| {
+            peer.tags.insert(key, value);
+        })? {
+            true => Ok(()),
+            false => Err("Unknown peer".into()),
+        }
+    }
+
+    pub fn add_addresses(
+        &self,
+        id: &PeerId,
+        addresses: &[Multiaddr],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.update(id, |peer| {
+            for address in addresses {
+                peer.add_address(address.clone());
+            }
+        })
+        .map(|_| ())
+    }
+
+    pub fn address_succeeded(
+        &self,
+        id: &PeerId,
+        address: &Multiaddr,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.update(id, |peer| peer.record_success(address))
+            .map(|_| ())
+    }
+
+    pub fn address_failed(
+        &self,
+        id: &PeerId,
+        address: &Multiaddr,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.update(id, |peer| peer.record_failure(address))
+            .map(|_| ())
+    }
+
+    pub fn pin(&self, id: &PeerId, pinned: bool) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.update(id, |peer| peer.pinned = pinned)? {
+            true => Ok(()),
+            false => Err("Unknown peer".into()),
+        }
+    }
+
+    pub fn prune<F: Fn(&PeerId) -> bool>(
+        &self,
+        policy: &PrunePolicy,
+        is_connected: F,
+    ) -> Result<Vec<PeerId>, Box<dyn Error + Send + Sync>> {
+        let peers = self.list()?;
+        let total = peers.len();
+        let mut candidates: Vec<Peer> = peers
+            .into_iter()
+            .filter(|peer| !peer.pinned && peer.kind != PeerType::Bootstrap)
+            .filter(|peer| !is_connected(&peer.id))
+            .collect();
+        candidates.sort_by_key(|peer| peer.last_seen);
+
+        let cutoff = policy
+            .max_age
+            .and_then(|age| TimeDelta::from_std(age).ok())
+            .and_then(|age| Utc::now().checked_sub_signed(age));
+        let excess = policy
+            .max_peers
+            .map(|max| total.saturating_sub(max))
+            .unwrap_or(0);
+
+        let mut pruned = Vec::new();
+        for peer in candidates {
+            let expired =
+                matches!((cutoff, peer.last_seen), (Some(cutoff), Some(seen)) if seen < cutoff);
+            if !expired && pruned.len() >= excess {
+                continue;
+            }
+            self.remove(&peer.id)?;
+            pruned.push(peer.id);
+        }
+        Ok(pruned)
+    }
+
+    pub fn set(&self, name: &str) -> Result<Option<PeerSet>, Box<dyn Error + Send + Sync>> {
+        self.storage.get_value::<PeerSet>(SETS, name.as_bytes())
+    }
+
+    pub fn sets(&self) -> Result<Vec<PeerSet>, Box<dyn Error + Send + Sync>> {
+        let mut sets = self.storage.values::<PeerSet>(SETS)?;
+        sets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(sets)
+    }
+
+    pub fn define_set(
+        &self,
+        name: &str,
+        members: Vec<PeerId>,
+    ) -> Result<PeerSet, Box<dyn Error + Send + Sync>> {
+        let set = PeerSet {
+            name: name.to_string(),
+            members: members.into_iter().collect(),
+        };
+        self.storage.put_value(SETS, name.as_bytes(), &set)?;
+        Ok(set)
+    }
+
+    pub fn update_set(
+        &self,
+        name: &str,
+        add: Vec<PeerId>,
+        remove: Vec<PeerId>,
+    ) -> Result<PeerSet, Box<dyn Error + Send + Sync>> {
+        let Some(mut set) = self.set(name)? else {
+            return Err(format!("Unknown peer set {name}").into());
+        };
+        set.members.extend(add);
+        for peer in remove.iter() {
+            set.members.remove(peer);
+        }
+        self.storage.put_value(SETS, name.as_bytes(), &set)?;
+        Ok(set)
+    }
+
+    pub fn delete_set(&self, name: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        if self.set(name)?.is_none() {
+            return Ok(false);
+        }
+        self.storage.delete(SETS, name.as_bytes())?;
+        Ok(true)
+    }
+
+    pub fn by_device(&self, device: &Uuid) -> Result<Vec<Peer>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|peer| peer.device.as_ref() == Some(device))
+            .collect())
+    }
+
+    pub fn best_for(&self, service: &str) -> Result<Option<Peer>, Box<dyn Error + Send + Sync>> {
+        let mut candidates: Vec<(f64, Peer)> = self
+            .list()?
+            .into_iter()
+            .filter(|peer| peer.services.iter().any(|s| s == service))
+            .map(|peer| (peer.stats.score().unwrap_or(f64::INFINITY), peer))
+            .collect();
+        candidates.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+
+        Ok(candidates.into_iter().next().map(|(_, peer)| peer))
+    }
+}
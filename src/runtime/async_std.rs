@@ -0,0 +1,34 @@
+use std::{future::Future, time::Duration};
+
+use crate::net::transport::DnsResolver;
+
+use super::Executor;
+
+pub(crate) type Mdns = libp2p::mdns::async_io::Behaviour;
+pub(crate) type Quic = libp2p::quic::async_std::Transport;
+pub(crate) type Tcp = libp2p::tcp::async_io::Transport;
+
+pub(crate) fn dns<T>(
+    transport: T,
+    resolver: &DnsResolver,
+) -> std::io::Result<libp2p::dns::async_std::Transport<T>> {
+    let (config, opts) = resolver.config()?;
+    Ok(libp2p::dns::async_std::Transport::custom2(
+        transport, config, opts,
+    ))
+}
+
+pub(crate) struct Runtime;
+
+impl Executor for Runtime {
+    fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        async_std::task::spawn(future);
+    }
+
+    fn sleep(duration: Duration) -> impl Future<Output = ()> + Send {
+        async_std::task::sleep(duration)
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_channel::{Receiver, RecvError};
+
+#[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+mod tokio;
+
+#[cfg(all(
+    feature = "async-std",
+    not(feature = "tokio"),
+    not(target_arch = "wasm32")
+))]
+mod async_std;
+
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+
+#[cfg(all(feature = "tokio", not(target_arch = "wasm32")))]
+pub(crate) use self::tokio::{dns, Mdns, Quic, Runtime, Tcp};
+
+#[cfg(all(
+    feature = "async-std",
+    not(feature = "tokio"),
+    not(target_arch = "wasm32")
+))]
+pub(crate) use self::async_std::{dns, Mdns, Quic, Runtime, Tcp};
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) use self::wasm::Runtime;
+
+#[cfg(not(any(feature = "tokio", feature = "async-std", target_arch = "wasm32")))]
+compile_error!("modius requires either the `tokio` or the `async-std` feature");
+
+pub(crate) trait Executor {
+    fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static;
+
+    fn sleep(duration: Duration) -> impl Future<Output = ()> + Send;
+}
+
+#[derive(Debug)]
+pub struct Task<T> {
+    finished: Arc<AtomicBool>,
+    output: Receiver<T>,
+}
+
+impl<T: Send + 'static> Task<T> {
+    pub(crate) fn spawn<F>(future: F) -> Self
+    where
+        F: Future<Output = T> + Send + 'static,
+    {
+        let finished = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = async_channel::bounded::<T>(1);
+        let flag = finished.clone();
+        Runtime::spawn(async move {
+            let output = future.await;
+            flag.store(true, Ordering::SeqCst);
+            let _ = tx.send(output).await;
+        });
+
+        Task {
+            finished,
+            output: rx,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::SeqCst)
+    }
+
+    pub async fn join(&self) -> Result<T, RecvError> {
+        self.output.recv().await
+    }
+}
+
+pub async fn sleep(duration: Duration) {
+    Runtime::sleep(duration).await
+}
@@ -0,0 +1,34 @@
+use std::{future::Future, time::Duration};
+
+use crate::net::transport::DnsResolver;
+
+use super::Executor;
+
+pub(crate) type Mdns = libp2p::mdns::tokio::Behaviour;
+pub(crate) type Quic = libp2p::quic::tokio::Transport;
+pub(crate) type Tcp = libp2p::tcp::tokio::Transport;
+
+pub(crate) fn dns<T>(
+    transport: T,
+    resolver: &DnsResolver,
+) -> std::io::Result<libp2p::dns::tokio::Transport<T>> {
+    let (config, opts) = resolver.config()?;
+    Ok(libp2p::dns::tokio::Transport::custom(
+        transport, config, opts,
+    ))
+}
+
+pub(crate) struct Runtime;
+
+impl Executor for Runtime {
+    fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(future);
+    }
+
+    fn sleep(duration: Duration) -> impl Future<Output = ()> + Send {
+        tokio::time::sleep(duration)
+    }
+}
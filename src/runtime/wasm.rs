@@ -0,0 +1,18 @@
+use std::{future::Future, time::Duration};
+
+use super::Executor;
+
+pub(crate) struct Runtime;
+
+impl Executor for Runtime {
+    fn spawn<F>(future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        wasm_bindgen_futures::spawn_local(future);
+    }
+
+    fn sleep(duration: Duration) -> impl Future<Output = ()> + Send {
+        futures_timer::Delay::new(duration)
+    }
+}
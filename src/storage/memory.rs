@@ -0,0 +1,72 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    sync::{Arc, Mutex},
+};
+
+use super::{Entry, Storage};
+
+type Entries = BTreeMap<Vec<u8>, Vec<u8>>;
+
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStorage {
+    namespaces: Arc<Mutex<HashMap<String, Entries>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        MemoryStorage::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(
+        &self,
+        namespace: &str,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, Box<dyn Error + Send + Sync>> {
+        let namespaces = self.namespaces.lock().or(Err("Failed to lock storage"))?;
+        Ok(namespaces
+            .get(namespace)
+            .and_then(|entries| entries.get(key).cloned()))
+    }
+
+    fn put(
+        &self,
+        namespace: &str,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut namespaces = self.namespaces.lock().or(Err("Failed to lock storage"))?;
+        namespaces
+            .entry(namespace.to_string())
+            .or_default()
+            .insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, namespace: &str, key: &[u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut namespaces = self.namespaces.lock().or(Err("Failed to lock storage"))?;
+        if let Some(entries) = namespaces.get_mut(namespace) {
+            entries.remove(key);
+        }
+        Ok(())
+    }
+
+    fn iterate(&self, namespace: &str) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+        let namespaces = self.namespaces.lock().or(Err("Failed to lock storage"))?;
+        Ok(namespaces
+            .get(namespace)
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn is_persistent(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,62 @@
+use std::{error::Error, fmt::Debug};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+mod memory;
+#[cfg(not(target_arch = "wasm32"))]
+mod sled;
+
+pub use self::memory::MemoryStorage;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::sled::SledStorage;
+
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+pub trait Storage: Debug + Send + Sync {
+    fn get(
+        &self,
+        namespace: &str,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, Box<dyn Error + Send + Sync>>;
+    fn put(
+        &self,
+        namespace: &str,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync>>;
+    fn delete(&self, namespace: &str, key: &[u8]) -> Result<(), Box<dyn Error + Send + Sync>>;
+    fn iterate(&self, namespace: &str) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>>;
+    fn is_persistent(&self) -> bool;
+}
+
+impl dyn Storage {
+    pub fn get_value<T: DeserializeOwned>(
+        &self,
+        namespace: &str,
+        key: &[u8],
+    ) -> Result<Option<T>, Box<dyn Error + Send + Sync>> {
+        match self.get(namespace, key)? {
+            Some(raw) => Ok(Some(serde_json::from_slice::<T>(&raw)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn put_value<T: Serialize>(
+        &self,
+        namespace: &str,
+        key: &[u8],
+        value: &T,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.put(namespace, key, &serde_json::to_vec(value)?)
+    }
+
+    pub fn values<T: DeserializeOwned>(
+        &self,
+        namespace: &str,
+    ) -> Result<Vec<T>, Box<dyn Error + Send + Sync>> {
+        self.iterate(namespace)?
+            .into_iter()
+            .map(|(_, raw)| serde_json::from_slice::<T>(&raw).map_err(|e| e.into()))
+            .collect()
+    }
+}
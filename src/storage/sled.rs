@@ -0,0 +1,65 @@
+use std::{error::Error, path::Path};
+
+use super::{Entry, Storage};
+
+#[derive(Clone, Debug)]
+pub struct SledStorage {
+    db: sled::Db,
+}
+
+impl SledStorage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(SledStorage {
+            db: sled::open(path)?,
+        })
+    }
+
+    pub fn from_db(db: sled::Db) -> Self {
+        SledStorage { db }
+    }
+}
+
+impl Storage for SledStorage {
+    fn get(
+        &self,
+        namespace: &str,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, Box<dyn Error + Send + Sync>> {
+        Ok(self
+            .db
+            .open_tree(namespace)?
+            .get(key)?
+            .map(|value| value.to_vec()))
+    }
+
+    fn put(
+        &self,
+        namespace: &str,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.db.open_tree(namespace)?.insert(key, value)?;
+        Ok(())
+    }
+
+    fn delete(&self, namespace: &str, key: &[u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.db.open_tree(namespace)?.remove(key)?;
+        Ok(())
+    }
+
+    fn iterate(&self, namespace: &str) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+        self.db
+            .open_tree(namespace)?
+            .iter()
+            .map(|entry| {
+                entry
+                    .map(|(k, v)| (k.to_vec(), v.to_vec()))
+                    .map_err(|e| e.into())
+            })
+            .collect()
+    }
+
+    fn is_persistent(&self) -> bool {
+        true
+    }
+}
@@ -1,14 +1,15 @@
-use std::{error::Error, str::FromStr};
+use std::{cmp::Reverse, collections::BTreeMap, error::Error, str::FromStr, time::Duration};
 
 use chrono::{DateTime, Utc};
 use libp2p::{Multiaddr, PeerId};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub enum PeerType {
     Bootstrap,
     Discovered,
-    Relay
+    Relay,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -18,6 +19,148 @@ pub struct Peer {
     pub last_seen: Option<DateTime<Utc>>,
     pub name: Option<String>,
     pub kind: PeerType,
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default)]
+    pub stats: PeerStats,
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default)]
+    pub addresses: Vec<PeerAddress>,
+    #[serde(default)]
+    pub liveness: Liveness,
+    #[serde(default)]
+    pub device: Option<Uuid>,
+}
+
+const MAX_ADDRESSES: usize = 16;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PeerAddress {
+    pub address: Multiaddr,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_failure: Option<DateTime<Utc>>,
+    pub failures: u32,
+}
+
+impl PeerAddress {
+    pub fn new(address: Multiaddr) -> Self {
+        PeerAddress {
+            address,
+            last_success: None,
+            last_failure: None,
+            failures: 0,
+        }
+    }
+}
+
+const ROLLING_WEIGHT: f64 = 0.2;
+const REFERENCE_TRANSFER: f64 = 1024.0 * 1024.0;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PeerStats {
+    pub rtt_ms: Option<f64>,
+    pub throughput: Option<f64>,
+}
+
+impl PeerStats {
+    pub fn record_rtt(&mut self, rtt: Duration) {
+        let sample = rtt.as_secs_f64() * 1000.0;
+        self.rtt_ms = Some(match self.rtt_ms {
+            Some(rolling) => rolling + ROLLING_WEIGHT * (sample - rolling),
+            None => sample,
+        });
+    }
+
+    pub fn record_throughput(&mut self, bytes: usize, elapsed: Duration) {
+        if elapsed.is_zero() {
+            return;
+        }
+
+        let sample = bytes as f64 / elapsed.as_secs_f64();
+        self.throughput = Some(match self.throughput {
+            Some(rolling) => rolling + ROLLING_WEIGHT * (sample - rolling),
+            None => sample,
+        });
+    }
+
+    pub fn score(&self) -> Option<f64> {
+        let rtt = self.rtt_ms?;
+        match self.throughput {
+            Some(throughput) if throughput > 0.0 => {
+                Some(rtt + REFERENCE_TRANSFER / throughput * 1000.0)
+            }
+            _ => Some(rtt),
+        }
+    }
+}
+
+const LIVENESS_HALF_LIFE: f64 = 60.0;
+const REPORTED_WEIGHT: f64 = 0.8;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Liveness {
+    pub last_observed: Option<DateTime<Utc>>,
+    pub last_reported: Option<DateTime<Utc>>,
+    pub reporters: u32,
+    pub estimate: f64,
+}
+
+impl Liveness {
+    fn decay(since: Option<DateTime<Utc>>, now: DateTime<Utc>) -> f64 {
+        let Some(since) = since else {
+            return 0.0;
+        };
+        let age = (now - since).num_milliseconds().max(0) as f64 / 1000.0;
+        0.5f64.powf(age / LIVENESS_HALF_LIFE)
+    }
+
+    pub fn observe(&mut self) {
+        self.last_observed = Some(Utc::now());
+        self.refresh();
+    }
+
+    pub fn report(&mut self, at: DateTime<Utc>) {
+        let at = at.min(Utc::now());
+        if self.last_reported.is_none_or(|reported| reported < at) {
+            self.last_reported = Some(at);
+            self.reporters = self.reporters.saturating_add(1);
+        }
+        self.refresh();
+    }
+
+    pub fn refresh(&mut self) {
+        let now = Utc::now();
+        let observed = Liveness::decay(self.last_observed, now);
+        let reported = Liveness::decay(self.last_reported, now) * REPORTED_WEIGHT;
+        self.estimate = observed.max(reported);
+    }
+
+    fn merge(&mut self, previous: Liveness) {
+        self.last_observed = self.last_observed.max(previous.last_observed);
+        self.last_reported = self.last_reported.max(previous.last_reported);
+        self.reporters = self.reporters.max(previous.reporters);
+        self.refresh();
+    }
+}
+
+pub fn agent_version(name: &str, device: &Uuid) -> String {
+    format!("{name} ({device})")
+}
+
+pub fn parse_agent_version(agent: &str) -> (String, Option<Uuid>) {
+    let parsed = agent
+        .strip_suffix(')')
+        .and_then(|agent| agent.rsplit_once(" ("))
+        .and_then(|(name, device)| Some((name, Uuid::parse_str(device).ok()?)));
+    match parsed {
+        Some((name, device)) => (name.to_string(), Some(device)),
+        None => (agent.to_string(), None),
+    }
 }
 
 impl Peer {
@@ -28,6 +171,89 @@ impl Peer {
             kind,
             last_seen: None,
             name: None,
+            group: None,
+            services: Vec::new(),
+            stats: PeerStats::default(),
+            tags: BTreeMap::new(),
+            pinned: false,
+            addresses: Vec::new(),
+            liveness: Liveness::default(),
+            device: None,
+        }
+    }
+
+    fn address_mut(&mut self, address: &Multiaddr) -> &mut PeerAddress {
+        let index = match self.addresses.iter().position(|a| &a.address == address) {
+            Some(index) => index,
+            None => {
+                self.addresses.push(PeerAddress::new(address.clone()));
+                self.addresses.len() - 1
+            }
+        };
+        &mut self.addresses[index]
+    }
+
+    pub fn add_address(&mut self, address: Multiaddr) {
+        self.address_mut(&address);
+        if self.addresses.len() > MAX_ADDRESSES {
+            self.rank();
+            self.addresses.truncate(MAX_ADDRESSES);
+        }
+    }
+
+    pub fn merge(&mut self, previous: Peer) {
+        for record in previous.addresses {
+            if !self.addresses.iter().any(|a| a.address == record.address) {
+                self.addresses.push(record);
+            }
+        }
+        self.add_address(previous.address);
+        self.last_seen = self.last_seen.max(previous.last_seen);
+        self.name = self.name.take().or(previous.name);
+        self.group = self.group.take().or(previous.group);
+        self.device = self.device.or(previous.device);
+        if self.services.is_empty() {
+            self.services = previous.services;
+        }
+        if self.stats.rtt_ms.is_none() && self.stats.throughput.is_none() {
+            self.stats = previous.stats;
+        }
+        for (key, value) in previous.tags {
+            self.tags.entry(key).or_insert(value);
+        }
+        self.pinned |= previous.pinned;
+        self.liveness.merge(previous.liveness);
+    }
+
+    pub fn record_success(&mut self, address: &Multiaddr) {
+        let record = self.address_mut(address);
+        record.last_success = Some(Utc::now());
+        record.failures = 0;
+        self.address = address.clone();
+    }
+
+    pub fn record_failure(&mut self, address: &Multiaddr) {
+        let record = self.address_mut(address);
+        record.last_failure = Some(Utc::now());
+        record.failures += 1;
+    }
+
+    fn rank(&mut self) {
+        self.addresses
+            .sort_by_key(|a| (a.failures, Reverse(a.last_success)));
+    }
+
+    pub fn dial_addresses(&self) -> Vec<Multiaddr> {
+        let mut ranked = self.clone();
+        ranked.address_mut(&self.address);
+        ranked.rank();
+        ranked.addresses.into_iter().map(|a| a.address).collect()
+    }
+
+    pub fn has_tag(&self, filter: &str) -> bool {
+        match filter.split_once('=') {
+            Some((key, value)) => self.tags.get(key).is_some_and(|v| v == value),
+            None => self.tags.contains_key(filter),
         }
     }
 
@@ -35,7 +261,7 @@ impl Peer {
         kind: PeerType,
         id: I,
         address: A,
-    ) -> Result<Self, Box<dyn Error>> {
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
         let peer_id = PeerId::from_str(id.as_ref())?;
         let multiaddr = Multiaddr::from_str(address.as_ref())?;
         Ok(Peer::new(kind, peer_id, multiaddr))
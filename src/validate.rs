@@ -0,0 +1,310 @@
+use std::{collections::HashSet, error::Error, fmt};
+
+use derive_builder::UninitializedFieldError;
+use libp2p::{multiaddr::Protocol, Multiaddr, PeerId};
+
+use crate::util::{Peer, PeerType};
+
+const MAX_PORT: usize = u16::MAX as usize;
+const MAX_GROUP_LENGTH: usize = 255;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationIssue {
+    InvalidPort(usize),
+    EmptyGroup,
+    InvalidGroup(String),
+    MissingPeerId { peer: PeerId, address: Multiaddr },
+    MismatchedPeerId { peer: PeerId, address: Multiaddr },
+    DuplicatePeer(PeerId),
+    EphemeralPersistentStorage,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::InvalidPort(port) => {
+                write!(f, "Port {port} is outside of 0-{MAX_PORT}")
+            }
+            ValidationIssue::EmptyGroup => write!(f, "Group must not be empty"),
+            ValidationIssue::InvalidGroup(group) => write!(
+                f,
+                "Group {group:?} must be at most {MAX_GROUP_LENGTH} characters of A-Z, a-z, 0-9, '.', '_' or '-'"
+            ),
+            ValidationIssue::MissingPeerId { peer, address } => {
+                write!(f, "Bootstrap address {address} of {peer} has no /p2p component")
+            }
+            ValidationIssue::MismatchedPeerId { peer, address } => {
+                write!(f, "Address {address} does not belong to {peer}")
+            }
+            ValidationIssue::DuplicatePeer(peer) => write!(f, "Peer {peer} is listed more than once"),
+            ValidationIssue::EphemeralPersistentStorage => {
+                write!(f, "Ephemeral nodes cannot use persistent storage")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NodeBuilderError {
+    UninitializedField(&'static str),
+    Invalid(Vec<ValidationIssue>),
+}
+
+impl fmt::Display for NodeBuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NodeBuilderError::UninitializedField(field) => {
+                write!(f, "`{field}` must be initialized")
+            }
+            NodeBuilderError::Invalid(issues) => {
+                write!(f, "Invalid node configuration: ")?;
+                for (index, issue) in issues.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{issue}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for NodeBuilderError {}
+
+impl From<UninitializedFieldError> for NodeBuilderError {
+    fn from(error: UninitializedFieldError) -> Self {
+        NodeBuilderError::UninitializedField(error.field_name())
+    }
+}
+
+pub fn valid_group(group: &str) -> bool {
+    group.len() <= MAX_GROUP_LENGTH
+        && group
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+pub fn complete_peers(peers: &[Peer]) -> Vec<Peer> {
+    peers
+        .iter()
+        .map(|peer| {
+            let mut peer = peer.clone();
+            if address_peer(&peer.address).is_none() {
+                peer.address.push(Protocol::P2p(peer.id));
+            }
+            peer
+        })
+        .collect()
+}
+
+pub fn address_peer(address: &Multiaddr) -> Option<PeerId> {
+    address.iter().find_map(|protocol| match protocol {
+        Protocol::P2p(peer) => Some(peer),
+        _ => None,
+    })
+}
+
+pub fn validate(
+    port: Option<usize>,
+    group: Option<&str>,
+    peers: &[Peer],
+    ephemeral: bool,
+    persistent: bool,
+) -> Result<(), NodeBuilderError> {
+    let mut issues = Vec::new();
+    if ephemeral && persistent {
+        issues.push(ValidationIssue::EphemeralPersistentStorage);
+    }
+    if let Some(port) = port.filter(|port| *port > MAX_PORT) {
+        issues.push(ValidationIssue::InvalidPort(port));
+    }
+    match group {
+        Some("") => issues.push(ValidationIssue::EmptyGroup),
+        Some(group) if !valid_group(group) => {
+            issues.push(ValidationIssue::InvalidGroup(group.to_string()))
+        }
+        _ => {}
+    }
+
+    let mut seen = HashSet::new();
+    for peer in peers {
+        if !seen.insert(peer.id) {
+            issues.push(ValidationIssue::DuplicatePeer(peer.id));
+        }
+        match address_peer(&peer.address) {
+            Some(id) if id != peer.id => issues.push(ValidationIssue::MismatchedPeerId {
+                peer: peer.id,
+                address: peer.address.clone(),
+            }),
+            None if matches!(peer.kind, PeerType::Bootstrap) => {
+                issues.push(ValidationIssue::MissingPeerId {
+                    peer: peer.id,
+                    address: peer.address.clone(),
+                })
+            }
+            _ => {}
+        }
+    }
+
+    match issues.is_empty() {
+        true => Ok(()),
+        false => Err(NodeBuilderError::Invalid(issues)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use libp2p::identity::Keypair;
+
+    use super::*;
+
+    fn peer(kind: PeerType, id: PeerId, address: &str) -> Peer {
+        Peer::new(kind, id, Multiaddr::from_str(address).unwrap())
+    }
+
+    fn issues(result: Result<(), NodeBuilderError>) -> Vec<ValidationIssue> {
+        match result {
+            Err(NodeBuilderError::Invalid(issues)) => issues,
+            other => panic!("expected validation issues, got {other:?}"),
+        }
+    }
+
+    fn random_peer() -> PeerId {
+        Keypair::generate_ed25519().public().to_peer_id()
+    }
+
+    #[test]
+    fn accepts_a_valid_configuration() {
+        let id = random_peer();
+        let peers = vec![
+            peer(
+                PeerType::Bootstrap,
+                id,
+                &format!("/ip4/10.0.0.1/tcp/8000/p2p/{id}"),
+            ),
+            peer(PeerType::Relay, random_peer(), "/ip4/10.0.0.2/tcp/8000"),
+        ];
+        assert_eq!(
+            validate(Some(8000), Some("modius.generic"), &peers, false, true),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_out_of_range_ports() {
+        assert_eq!(
+            issues(validate(Some(70000), None, &[], false, false)),
+            vec![ValidationIssue::InvalidPort(70000)]
+        );
+        assert_eq!(validate(Some(MAX_PORT), None, &[], false, false), Ok(()));
+    }
+
+    #[test]
+    fn rejects_empty_groups() {
+        assert_eq!(
+            issues(validate(None, Some(""), &[], false, false)),
+            vec![ValidationIssue::EmptyGroup]
+        );
+    }
+
+    #[test]
+    fn rejects_groups_outside_the_charset_or_length() {
+        assert_eq!(
+            issues(validate(None, Some("my group!"), &[], false, false)),
+            vec![ValidationIssue::InvalidGroup(String::from("my group!"))]
+        );
+        let long = "a".repeat(MAX_GROUP_LENGTH + 1);
+        assert_eq!(
+            issues(validate(None, Some(&long), &[], false, false)),
+            vec![ValidationIssue::InvalidGroup(long)]
+        );
+    }
+
+    #[test]
+    fn rejects_bootstrap_addresses_without_a_peer_id() {
+        let id = random_peer();
+        let bootstrap = peer(PeerType::Bootstrap, id, "/ip4/10.0.0.1/tcp/8000");
+        assert_eq!(
+            issues(validate(
+                None,
+                None,
+                std::slice::from_ref(&bootstrap),
+                false,
+                false
+            )),
+            vec![ValidationIssue::MissingPeerId {
+                peer: id,
+                address: bootstrap.address,
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_addresses_of_another_peer() {
+        let id = random_peer();
+        let other = random_peer();
+        let relay = peer(
+            PeerType::Relay,
+            id,
+            &format!("/ip4/10.0.0.1/tcp/8000/p2p/{other}"),
+        );
+        assert_eq!(
+            issues(validate(
+                None,
+                None,
+                std::slice::from_ref(&relay),
+                false,
+                false
+            )),
+            vec![ValidationIssue::MismatchedPeerId {
+                peer: id,
+                address: relay.address,
+            }]
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_peers() {
+        let id = random_peer();
+        let address = format!("/ip4/10.0.0.1/tcp/8000/p2p/{id}");
+        let peers = vec![
+            peer(PeerType::Bootstrap, id, &address),
+            peer(PeerType::Relay, id, &address),
+        ];
+        assert_eq!(
+            issues(validate(None, None, &peers, false, false)),
+            vec![ValidationIssue::DuplicatePeer(id)]
+        );
+    }
+
+    #[test]
+    fn rejects_persistent_storage_on_ephemeral_nodes() {
+        assert_eq!(
+            issues(validate(None, None, &[], true, true)),
+            vec![ValidationIssue::EphemeralPersistentStorage]
+        );
+        assert_eq!(validate(None, None, &[], true, false), Ok(()));
+    }
+
+    #[test]
+    fn completes_missing_peer_ids_only() {
+        let id = random_peer();
+        let other = random_peer();
+        let peers = complete_peers(&[
+            peer(PeerType::Bootstrap, id, "/ip4/10.0.0.1/tcp/8000"),
+            peer(
+                PeerType::Bootstrap,
+                id,
+                &format!("/ip4/10.0.0.2/tcp/8000/p2p/{other}"),
+            ),
+        ]);
+        assert_eq!(
+            peers[0].address,
+            Multiaddr::from_str(&format!("/ip4/10.0.0.1/tcp/8000/p2p/{id}")).unwrap()
+        );
+        assert_eq!(address_peer(&peers[1].address), Some(other));
+    }
+}